@@ -1,19 +1,28 @@
 use crate::{
-    signature::{identity::Identity, EcdsaSignature},
-    CeremoniesError, Contribution, Engine, Entropy, Tau, G2,
+    signature::{identity::Identity, ContributionTypedData, EcdsaSignature},
+    CeremoniesError, CeremonyError, Contribution, Engine, Entropy, Tau, G2,
 };
 use rand::{Rng, SeedableRng};
 use rand_chacha::ChaCha20Rng;
 use rayon::prelude::*;
 use secrecy::{ExposeSecret, Secret};
 use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
 use tracing::instrument;
 
+/// Maximum length in bytes of [`BatchContribution::entropy_attestation`].
+pub const MAX_ENTROPY_ATTESTATION_LEN: usize = 256;
+
 #[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 #[serde(deny_unknown_fields, rename_all = "camelCase")]
 pub struct BatchContribution {
     pub contributions: Vec<Contribution>,
     pub ecdsa_signature: EcdsaSignature,
+    /// Public statement about where the entropy came from, e.g. a randomness
+    /// beacon round or a hash of a hardware RNG output, recorded in the
+    /// transcript. It is published, so it must never be the entropy itself.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entropy_attestation: Option<String>,
 }
 
 impl BatchContribution {
@@ -55,6 +64,138 @@ impl BatchContribution {
                 });
         res
     }
+
+    /// Checks that there are at most `max` sub-contributions. This is cheap,
+    /// so it can run before anything is done with an untrusted batch.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CeremonyError::TooManyContributions`].
+    pub fn check_num_contributions(&self, max: usize) -> Result<(), CeremonyError> {
+        if self.contributions.len() > max {
+            return Err(CeremonyError::TooManyContributions(
+                max,
+                self.contributions.len(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks that the entropy attestation, if any, isn't longer than
+    /// [`MAX_ENTROPY_ATTESTATION_LEN`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CeremoniesError::EntropyAttestationTooLong`].
+    pub fn validate_entropy_attestation(&self) -> Result<(), CeremoniesError> {
+        match &self.entropy_attestation {
+            Some(attestation) if attestation.len() > MAX_ENTROPY_ATTESTATION_LEN => {
+                Err(CeremoniesError::EntropyAttestationTooLong(
+                    MAX_ENTROPY_ATTESTATION_LEN,
+                    attestation.len(),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Drops the ECDSA signature unless `identity` signed `typed_data` with it.
+    /// The signature covers the whole batch, so it is verified once rather
+    /// than per sub-contribution. Non-Ethereum identities always end up with
+    /// an empty signature.
+    pub fn prune_ecdsa_signature(
+        &mut self,
+        identity: &Identity,
+        typed_data: &ContributionTypedData,
+    ) {
+        self.ecdsa_signature = self.ecdsa_signature.prune(identity, typed_data);
+    }
+
+    /// Size in bytes of the JSON encoding, i.e. what a client downloads and
+    /// uploads.
+    #[allow(clippy::missing_panics_doc)] // Serialization does not fail.
+    #[must_use]
+    pub fn byte_size(&self) -> usize {
+        let mut counter = ByteCounter(0);
+        serde_json::to_writer(&mut counter, self).expect("serialization does not fail");
+        counter.0
+    }
+
+    /// Splits the contributions into at most `n` shards of consecutive
+    /// sub-contributions that can be verified independently, e.g. on
+    /// different machines.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is zero.
+    #[must_use]
+    pub fn split(&self, n: usize) -> Vec<ContributionShard> {
+        assert!(n > 0, "number of shards must be positive");
+        let shard_size = ((self.contributions.len() + n - 1) / n).max(1);
+        self.contributions
+            .chunks(shard_size)
+            .enumerate()
+            .map(|(i, contributions)| ContributionShard {
+                offset: i * shard_size,
+                contributions: contributions.to_vec(),
+            })
+            .collect()
+    }
+}
+
+/// A range of consecutive sub-contributions of a [`BatchContribution`].
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct ContributionShard {
+    /// Index of the first sub-contribution in the batch.
+    pub offset: usize,
+    pub contributions: Vec<Contribution>,
+}
+
+/// Outcome of verifying one or more [`ContributionShard`]s.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct VerificationResult(pub Result<(), CeremoniesError>);
+
+impl VerificationResult {
+    /// Combines per-shard outcomes. The merged result fails if any shard
+    /// failed, reporting the failure with the lowest global index.
+    #[must_use]
+    pub fn merge(results: impl IntoIterator<Item = Self>) -> Self {
+        let failure = results
+            .into_iter()
+            .filter_map(|result| result.0.err())
+            .min_by_key(|error| match error {
+                // Shape errors apply to the whole batch.
+                CeremoniesError::UnexpectedNumContributions(..)
+                | CeremoniesError::UnexpectedNumRecords(..)
+                | CeremoniesError::EntropyAttestationTooLong(..) => (0, 0),
+                CeremoniesError::InvalidCeremony(index, _) => (1, *index),
+            });
+        Self(failure.map_or(Ok(()), Err))
+    }
+
+    /// Returns the result of the verification.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first [`CeremoniesError`] encountered.
+    pub const fn into_result(self) -> Result<(), CeremoniesError> {
+        self.0
+    }
+}
+
+/// Counts the bytes written instead of storing them.
+struct ByteCounter(usize);
+
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
 }
 
 fn derive_taus<E: Engine>(entropy: &Entropy, size: usize) -> Vec<Tau> {
@@ -87,15 +228,18 @@ pub fn get_pot_pubkeys<E: Engine>(entropy: &Entropy) -> Vec<G2> {
 #[cfg(test)]
 pub mod tests {
     use crate::{
-        batch_contribution::derive_taus,
+        batch_contribution::{derive_taus, MAX_ENTROPY_ATTESTATION_LEN},
         contribution::test::{invalid_g2_contribution, valid_contribution},
         engine::tests::arb_entropy,
         get_pot_pubkeys,
-        signature::EcdsaSignature,
-        BatchContribution, CeremoniesError, DefaultEngine, G2,
+        signature::{identity::Identity, ContributionTypedData, EcdsaSignature},
+        BatchContribution, BatchTranscript, CeremoniesError, DefaultEngine, Entropy, EntropySource,
+        G2,
     };
     use ark_bls12_381::{Fr, G2Affine};
     use ark_ec::{AffineCurve, ProjectiveCurve};
+    use ethers_core::types::{transaction::eip712::Eip712, H256};
+    use ethers_signers::{LocalWallet, Signer};
     use proptest::proptest;
     use secrecy::{ExposeSecret, Secret};
 
@@ -108,6 +252,7 @@ pub mod tests {
                 valid_contribution(),
             ],
             ecdsa_signature: EcdsaSignature::empty(),
+            entropy_attestation: None,
         };
         assert!(matches!(
             invalid.validate::<DefaultEngine>(),
@@ -117,10 +262,54 @@ pub mod tests {
         let mut valid = BatchContribution {
             contributions: vec![valid_contribution(), valid_contribution()],
             ecdsa_signature: EcdsaSignature::empty(),
+            entropy_attestation: None,
         };
         assert!(valid.validate::<DefaultEngine>().is_ok());
     }
 
+    #[test]
+    fn test_byte_size() {
+        let contribution = BatchTranscript::new(&[(4, 2), (8, 3)]).contribution();
+        assert_eq!(
+            contribution.byte_size(),
+            serde_json::to_vec(&contribution).unwrap().len()
+        );
+    }
+
+    #[test]
+    fn test_entropy_attestation_round_trip() {
+        let mut contribution = BatchTranscript::new(&[(4, 2)]).contribution();
+        let json = serde_json::to_value(&contribution).unwrap();
+        assert!(json.get("entropyAttestation").is_none());
+        assert_eq!(
+            serde_json::from_value::<BatchContribution>(json).unwrap(),
+            contribution
+        );
+
+        contribution.entropy_attestation = Some("drand round 2716312".to_string());
+        let json = serde_json::to_value(&contribution).unwrap();
+        assert_eq!(json["entropyAttestation"], "drand round 2716312");
+        assert_eq!(
+            serde_json::from_value::<BatchContribution>(json).unwrap(),
+            contribution
+        );
+    }
+
+    #[test]
+    fn test_entropy_attestation_length() {
+        let mut contribution = BatchTranscript::new(&[(4, 2)]).contribution();
+        contribution.entropy_attestation = Some("a".repeat(MAX_ENTROPY_ATTESTATION_LEN));
+        assert_eq!(contribution.validate_entropy_attestation(), Ok(()));
+        contribution.entropy_attestation = Some("a".repeat(MAX_ENTROPY_ATTESTATION_LEN + 1));
+        assert_eq!(
+            contribution.validate_entropy_attestation(),
+            Err(CeremoniesError::EntropyAttestationTooLong(
+                MAX_ENTROPY_ATTESTATION_LEN,
+                MAX_ENTROPY_ATTESTATION_LEN + 1
+            ))
+        );
+    }
+
     #[test]
     fn test_get_pot_pubkeys() {
         proptest!(|(entropy in arb_entropy())| {
@@ -141,17 +330,68 @@ pub mod tests {
             assert_eq!(result, expected);
         });
     }
+
+    #[test]
+    fn test_seeded_entropy_is_reproducible() {
+        let pubkeys = |seed| get_pot_pubkeys::<DefaultEngine>(&Entropy::from_seed(seed));
+        assert_eq!(pubkeys([1; 32]), pubkeys([1; 32]));
+        assert_ne!(pubkeys([1; 32]), pubkeys([2; 32]));
+    }
+
+    #[test]
+    fn test_prune_ecdsa_signature() {
+        let wallet = LocalWallet::new(&mut rand::thread_rng());
+        let mut contribution = BatchTranscript::new(&[(4, 2), (8, 3), (16, 4)]).contribution();
+        let typed_data = ContributionTypedData::from(&contribution);
+        let hash = typed_data.encode_eip712().unwrap();
+        let signature = EcdsaSignature(Some(wallet.sign_hash(H256::from(hash))));
+        contribution.ecdsa_signature = signature.clone();
+
+        let mut signed = contribution.clone();
+        signed.prune_ecdsa_signature(
+            &Identity::Ethereum {
+                address: wallet.address().0,
+            },
+            &typed_data,
+        );
+        assert_eq!(signed.ecdsa_signature, signature);
+
+        let other_wallet = LocalWallet::new(&mut rand::thread_rng());
+        let mut wrong_address = contribution.clone();
+        wrong_address.prune_ecdsa_signature(
+            &Identity::Ethereum {
+                address: other_wallet.address().0,
+            },
+            &typed_data,
+        );
+        assert_eq!(wrong_address.ecdsa_signature, EcdsaSignature::empty());
+
+        contribution.prune_ecdsa_signature(
+            &Identity::Github {
+                id: 1234,
+                username: "user".to_string(),
+            },
+            &typed_data,
+        );
+        assert_eq!(contribution.ecdsa_signature, EcdsaSignature::empty());
+    }
 }
 
-#[cfg(feature = "bench")]
+#[cfg(all(feature = "bench", any(feature = "arkworks", feature = "blst")))]
 #[cfg(not(tarpaulin_include))]
 #[doc(hidden)]
 pub mod bench {
     use super::*;
+    #[cfg(feature = "arkworks")]
+    use crate::Arkworks;
+    #[cfg(all(feature = "arkworks", feature = "blst"))]
+    use crate::Both;
+    #[cfg(feature = "blst")]
+    use crate::BLST;
     use crate::{
         bench::{rand_entropy, BATCH_SIZE},
         signature::identity::Identity,
-        Arkworks, BatchTranscript, Both, BLST,
+        BatchTranscript,
     };
     use criterion::{BatchSize, Criterion};
 
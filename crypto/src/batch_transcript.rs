@@ -1,6 +1,9 @@
 use crate::{
-    signature::{identity::Identity, ContributionTypedData, EcdsaSignature},
-    BatchContribution, CeremoniesError, Engine, Transcript,
+    signature::{
+        identity::Identity, BlsSignature, ContributionDomain, ContributionTypedData, EcdsaSignature,
+    },
+    BatchContribution, CeremoniesError, CeremonyError, Contribution, ContributionShard, Engine,
+    Transcript, VerificationCache, VerificationResult, DEFAULT_SIGNATURE_BATCH_SIZE, G1, G2,
 };
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
@@ -12,6 +15,32 @@ pub struct BatchTranscript {
     pub transcripts: Vec<Transcript>,
     pub participant_ids: Vec<Identity>,
     pub participant_ecdsa_signatures: Vec<EcdsaSignature>,
+    /// Entropy attestations by participant index. Only as long as needed for
+    /// the last participant with one, and omitted if nobody attested.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub participant_entropy_attestations: Vec<Option<String>>,
+}
+
+/// What the transcript records about a single participant.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContributionRecord {
+    /// Position in the transcript, `0` is the initial state.
+    pub index: usize,
+    pub participant_id: Identity,
+    pub participant_ecdsa_signature: EcdsaSignature,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entropy_attestation: Option<String>,
+    /// The witness of each sub-ceremony.
+    pub witnesses: Vec<WitnessRecord>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WitnessRecord {
+    pub running_product: G1,
+    pub pot_pubkey: G2,
+    pub bls_signature: BlsSignature,
 }
 
 impl BatchTranscript {
@@ -26,6 +55,7 @@ impl BatchTranscript {
                 .collect(),
             participant_ids: vec![Identity::None],
             participant_ecdsa_signatures: vec![EcdsaSignature::empty()],
+            participant_entropy_attestations: Vec::new(),
         }
     }
 
@@ -35,6 +65,32 @@ impl BatchTranscript {
         self.participant_ids.len() - 1
     }
 
+    /// Returns the number of records, including the initial state.
+    #[must_use]
+    pub fn num_records(&self) -> usize {
+        self.participant_ids.len()
+    }
+
+    /// Returns the record at `index`, or `None` if it is out of range.
+    #[must_use]
+    pub fn record(&self, index: usize) -> Option<ContributionRecord> {
+        Some(ContributionRecord {
+            index,
+            participant_id: self.participant_ids.get(index)?.clone(),
+            participant_ecdsa_signature: self.participant_ecdsa_signatures.get(index)?.clone(),
+            entropy_attestation: self
+                .participant_entropy_attestations
+                .get(index)
+                .cloned()
+                .flatten(),
+            witnesses: self
+                .transcripts
+                .iter()
+                .map(|transcript| transcript.contribution_by_index(index))
+                .collect::<Option<_>>()?,
+        })
+    }
+
     /// Creates the start of a new batch contribution.
     #[must_use]
     pub fn contribution(&self) -> BatchContribution {
@@ -45,16 +101,75 @@ impl BatchTranscript {
                 .map(Transcript::contribution)
                 .collect(),
             ecdsa_signature: EcdsaSignature::empty(),
+            entropy_attestation: None,
         }
     }
 
+    /// Creates the start of a new contribution to the sub-ceremony at `index`,
+    /// or `None` if there is no such sub-ceremony.
+    #[must_use]
+    pub fn contribution_at(&self, index: usize) -> Option<Contribution> {
+        self.transcripts.get(index).map(Transcript::contribution)
+    }
+
     /// Adds a batch contribution to the transcript. The contribution must be
-    /// valid.
-    #[instrument(level = "info", skip_all, fields(n=contribution.contributions.len()))]
+    /// valid. ECDSA signatures are checked in the mainnet domain.
     pub fn verify_add<E: Engine>(
         &mut self,
-        mut contribution: BatchContribution,
+        contribution: BatchContribution,
         identity: Identity,
+    ) -> Result<(), CeremoniesError> {
+        self.verify_add_in_domain::<E>(contribution, identity, &ContributionDomain::default())
+    }
+
+    /// Like [`BatchTranscript::verify_add`], but checks the ECDSA signature
+    /// in the given EIP-712 `domain`.
+    pub fn verify_add_in_domain<E: Engine>(
+        &mut self,
+        contribution: BatchContribution,
+        identity: Identity,
+        domain: &ContributionDomain,
+    ) -> Result<(), CeremoniesError> {
+        self.verify_contribution::<E>(&contribution)?;
+        self.add_in_domain::<E>(contribution, identity, domain)
+    }
+
+    /// Verifies a batch contribution against the transcript without adding
+    /// it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CeremoniesError::UnexpectedNumContributions`] if the number
+    /// of sub-ceremonies differs, or the first failure of any sub-ceremony.
+    #[instrument(level = "info", skip_all, fields(n=contribution.contributions.len()))]
+    pub fn verify_contribution<E: Engine>(
+        &self,
+        contribution: &BatchContribution,
+    ) -> Result<(), CeremoniesError> {
+        self.verify_contribution_with(contribution, Transcript::verify::<E>)
+    }
+
+    /// Like [`BatchTranscript::verify_contribution`], but reuses the verdicts
+    /// of sub-contributions already verified against the same powers.
+    ///
+    /// # Errors
+    ///
+    /// See [`BatchTranscript::verify_contribution`].
+    #[instrument(level = "info", skip_all, fields(n=contribution.contributions.len()))]
+    pub fn verify_contribution_cached<E: Engine>(
+        &self,
+        contribution: &BatchContribution,
+        cache: &VerificationCache,
+    ) -> Result<(), CeremoniesError> {
+        self.verify_contribution_with(contribution, |transcript, contribution| {
+            cache.verify::<E>(transcript, contribution)
+        })
+    }
+
+    fn verify_contribution_with(
+        &self,
+        contribution: &BatchContribution,
+        verify: impl Fn(&Transcript, &Contribution) -> Result<(), CeremonyError> + Sync,
     ) -> Result<(), CeremoniesError> {
         // Verify contribution count
         if self.transcripts.len() != contribution.contributions.len() {
@@ -64,50 +179,195 @@ impl BatchTranscript {
             ));
         }
 
+        contribution.validate_entropy_attestation()?;
+
+        // Reject mismatched shapes before any of the expensive checks
+        Self::verify_shapes(&self.transcripts, &contribution.contributions, 0)?;
+
         // Verify contributions in parallel
         self.transcripts
-            .par_iter_mut()
+            .par_iter()
             .zip(&contribution.contributions)
             .enumerate()
             .try_for_each(|(i, (transcript, contribution))| {
-                transcript
-                    .verify::<E>(contribution)
-                    .map_err(|e| CeremoniesError::InvalidCeremony(i, e))
-            })?;
+                verify(transcript, contribution).map_err(|e| CeremoniesError::InvalidCeremony(i, e))
+            })
+    }
 
-        self.participant_ecdsa_signatures.push(
-            contribution
-                .ecdsa_signature
-                .prune(&identity, &ContributionTypedData::from(&contribution)),
-        );
+    /// Adds a batch contribution that passed
+    /// [`BatchTranscript::verify_contribution`], pruning signatures that
+    /// don't verify against `identity`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CeremonyError::EntropyNotAdvanced`] for the first
+    /// sub-contribution that would not advance its transcript's accumulated
+    /// entropy, see [`Transcript::advanced_by`]. Nothing is added then.
+    #[instrument(level = "info", skip_all, fields(n=contribution.contributions.len()))]
+    pub fn add_in_domain<E: Engine>(
+        &mut self,
+        mut contribution: BatchContribution,
+        identity: Identity,
+        domain: &ContributionDomain,
+    ) -> Result<(), CeremoniesError> {
+        if let Some(i) = self
+            .transcripts
+            .iter()
+            .zip(&contribution.contributions)
+            .position(|(transcript, contribution)| !transcript.advanced_by(contribution))
+        {
+            return Err(CeremoniesError::InvalidCeremony(
+                i,
+                CeremonyError::EntropyNotAdvanced,
+            ));
+        }
+
+        let typed_data = ContributionTypedData::new(&contribution, domain.clone());
+        contribution.prune_ecdsa_signature(&identity, &typed_data);
+        self.participant_ecdsa_signatures
+            .push(contribution.ecdsa_signature.clone());
 
         // Prune BLS Signatures
-        contribution.contributions.iter_mut().for_each(|c| {
-            c.bls_signature = c
-                .bls_signature
-                .prune::<E>(identity.to_string().as_bytes(), c.pot_pubkey);
-        });
+        let message = identity.to_string();
+        let signatures = contribution
+            .contributions
+            .iter()
+            .filter_map(|c| Some((c.bls_signature.0?, message.as_bytes(), c.pot_pubkey)))
+            .collect::<Vec<_>>();
+        let mut verdicts =
+            E::verify_signatures(&signatures, DEFAULT_SIGNATURE_BATCH_SIZE).into_iter();
+        for c in &mut contribution.contributions {
+            if c.bls_signature.0.is_some() && !verdicts.next().unwrap_or(false) {
+                c.bls_signature = BlsSignature::empty();
+            }
+        }
 
         // Add contributions
-        for (transcript, contribution) in self
+        for (i, (transcript, contribution)) in self
             .transcripts
             .iter_mut()
             .zip(contribution.contributions.into_iter())
+            .enumerate()
         {
-            transcript.add(contribution);
+            transcript
+                .add(contribution)
+                .map_err(|e| CeremoniesError::InvalidCeremony(i, e))?;
         }
 
-        self.participant_ids.push(identity);
+        if let Some(attestation) = contribution.entropy_attestation {
+            self.participant_entropy_attestations
+                .resize(self.participant_ids.len(), None);
+            self.participant_entropy_attestations
+                .push(Some(attestation));
+        }
 
+        self.participant_ids.push(identity);
         Ok(())
     }
+
+    /// Verifies the whole transcript from scratch, as an auditor would after
+    /// the ceremony: the chain of every sub-ceremony, and every BLS signature
+    /// that was kept against the participant's identity. ECDSA signatures are
+    /// not checked, they sign powers the transcript no longer contains.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CeremoniesError::UnexpectedNumRecords`] if a witness doesn't
+    /// have one entry per participant, or the first failure of the
+    /// lowest-indexed sub-ceremony.
+    #[instrument(level = "info", skip_all, fields(n=self.participant_ids.len()))]
+    pub fn verify_history<E: Engine>(&self) -> Result<(), CeremoniesError> {
+        let num_records = self.participant_ids.len();
+        for (i, transcript) in self.transcripts.iter().enumerate() {
+            let witness = &transcript.witness;
+            for len in [
+                witness.products.len(),
+                witness.pubkeys.len(),
+                witness.signatures.len(),
+            ] {
+                if len != num_records {
+                    return Err(CeremoniesError::UnexpectedNumRecords(num_records, len, i));
+                }
+            }
+        }
+
+        let messages = self
+            .participant_ids
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        self.transcripts
+            .par_iter()
+            .enumerate()
+            .try_for_each(|(i, transcript)| {
+                transcript
+                    .verify_chain::<E>()
+                    .and_then(|()| transcript.verify_signatures::<E>(&messages))
+                    .map_err(|e| CeremoniesError::InvalidCeremony(i, e))
+            })
+    }
+
+    /// Checks the shape of every contribution against its transcript, so a
+    /// malformed batch fails fast with the index of the first mismatch.
+    fn verify_shapes(
+        transcripts: &[Transcript],
+        contributions: &[Contribution],
+        offset: usize,
+    ) -> Result<(), CeremoniesError> {
+        transcripts
+            .iter()
+            .zip(contributions)
+            .enumerate()
+            .try_for_each(|(i, (transcript, contribution))| {
+                transcript
+                    .verify_shape(&contribution.powers)
+                    .map_err(|e| CeremoniesError::InvalidCeremony(offset + i, e))
+            })
+    }
+
+    /// Verifies a shard of a batch contribution against the corresponding
+    /// transcripts without adding it. Results of all shards can be combined
+    /// using [`VerificationResult::merge`].
+    #[instrument(level = "info", skip_all, fields(offset=shard.offset, n=shard.contributions.len()))]
+    pub fn verify_shard<E: Engine>(&self, shard: &ContributionShard) -> VerificationResult {
+        let end = shard.offset + shard.contributions.len();
+        let Some(transcripts) = self.transcripts.get(shard.offset..end) else {
+            return VerificationResult(Err(CeremoniesError::UnexpectedNumContributions(
+                self.transcripts.len(),
+                end,
+            )));
+        };
+
+        if let Err(e) = Self::verify_shapes(transcripts, &shard.contributions, shard.offset) {
+            return VerificationResult(Err(e));
+        }
+
+        VerificationResult(
+            transcripts
+                .par_iter()
+                .zip(&shard.contributions)
+                .enumerate()
+                .try_for_each(|(i, (transcript, contribution))| {
+                    transcript
+                        .verify::<E>(contribution)
+                        .map_err(|e| CeremoniesError::InvalidCeremony(shard.offset + i, e))
+                }),
+        )
+    }
 }
 
 #[cfg(test)]
 pub mod tests {
     use crate::{
-        BatchTranscript, CeremoniesError::UnexpectedNumContributions, DefaultEngine, Identity,
+        BatchContribution, BatchTranscript,
+        CeremoniesError::{
+            EntropyAttestationTooLong, InvalidCeremony, UnexpectedNumContributions,
+            UnexpectedNumRecords,
+        },
+        CeremonyError, DefaultEngine, Identity, VerificationResult, G2,
+        MAX_ENTROPY_ATTESTATION_LEN,
     };
+    use secrecy::Secret;
 
     #[test]
     fn test_verify_add() {
@@ -120,17 +380,185 @@ pub mod tests {
             .unwrap();
         assert_eq!(result, UnexpectedNumContributions(2, 1));
     }
+
+    #[test]
+    fn test_entropy_attestation_recorded() {
+        let mut transcript = BatchTranscript::new([(4, 2)].iter());
+        let attest = |transcript: &mut BatchTranscript, attestation: Option<&str>| {
+            let mut contribution = transcript.contribution();
+            contribution
+                .add_entropy::<DefaultEngine>(&Secret::new([7; 32]), &Identity::None)
+                .unwrap();
+            contribution.entropy_attestation = attestation.map(ToString::to_string);
+            transcript.verify_add::<DefaultEngine>(contribution, Identity::None)
+        };
+
+        attest(&mut transcript, None).unwrap();
+        // Transcripts without attestations keep their layout.
+        let json = serde_json::to_value(&transcript).unwrap();
+        assert!(json.get("participantEntropyAttestations").is_none());
+
+        attest(&mut transcript, Some("beacon round 42")).unwrap();
+        assert_eq!(transcript.record(1).unwrap().entropy_attestation, None);
+        assert_eq!(
+            transcript.record(2).unwrap().entropy_attestation.as_deref(),
+            Some("beacon round 42")
+        );
+        let json = serde_json::to_string(&transcript).unwrap();
+        assert_eq!(
+            serde_json::from_str::<BatchTranscript>(&json).unwrap(),
+            transcript
+        );
+
+        let oversized = "a".repeat(MAX_ENTROPY_ATTESTATION_LEN + 1);
+        assert_eq!(
+            attest(&mut transcript, Some(&oversized)),
+            Err(EntropyAttestationTooLong(
+                MAX_ENTROPY_ATTESTATION_LEN,
+                MAX_ENTROPY_ATTESTATION_LEN + 1
+            ))
+        );
+        assert_eq!(transcript.num_participants(), 2);
+    }
+
+    #[test]
+    fn test_verify_add_rejects_shape_first() {
+        let mut transcript = BatchTranscript::new([(2, 2), (3, 3)].iter());
+        let mut contrib = transcript.contribution();
+        // The first contribution has no entropy, but the shape mismatch in
+        // the second one must be reported before any point is looked at.
+        contrib.contributions[1].powers.g1.pop();
+        let result = transcript
+            .verify_add::<DefaultEngine>(contrib, Identity::None)
+            .err()
+            .unwrap();
+        assert_eq!(
+            result,
+            InvalidCeremony(1, CeremonyError::UnexpectedNumG1Powers(3, 2))
+        );
+    }
+
+    #[test]
+    fn test_record() {
+        let mut transcript = BatchTranscript::new([(4, 2), (4, 2)].iter());
+        let mut contribution = transcript.contribution();
+        contribution
+            .add_entropy::<DefaultEngine>(&Secret::new([1; 32]), &Identity::None)
+            .unwrap();
+        let pubkeys = contribution
+            .contributions
+            .iter()
+            .map(|c| c.pot_pubkey)
+            .collect::<Vec<_>>();
+        transcript
+            .verify_add::<DefaultEngine>(contribution, Identity::Ethereum { address: [1; 20] })
+            .unwrap();
+
+        assert_eq!(transcript.num_records(), 2);
+        assert_eq!(transcript.record(0).unwrap().participant_id, Identity::None);
+        let record = transcript.record(1).unwrap();
+        assert_eq!(record.index, 1);
+        assert_eq!(
+            record.participant_id,
+            Identity::Ethereum { address: [1; 20] }
+        );
+        assert_eq!(
+            record
+                .witnesses
+                .iter()
+                .map(|w| w.pot_pubkey)
+                .collect::<Vec<_>>(),
+            pubkeys
+        );
+        assert!(transcript.record(2).is_none());
+    }
+
+    #[test]
+    fn test_verify_history() {
+        let mut transcript = BatchTranscript::new([(4, 2), (4, 2)].iter());
+        for no in 1..=2 {
+            let mut contribution = transcript.contribution();
+            contribution
+                .add_entropy::<DefaultEngine>(&Secret::new([no; 32]), &Identity::None)
+                .unwrap();
+            transcript
+                .verify_add::<DefaultEngine>(contribution, Identity::None)
+                .unwrap();
+        }
+        assert_eq!(transcript.verify_history::<DefaultEngine>(), Ok(()));
+
+        let mut tampered = transcript.clone();
+        tampered.transcripts[1].witness.pubkeys[2] = G2::one();
+        assert_eq!(
+            tampered.verify_history::<DefaultEngine>(),
+            Err(InvalidCeremony(1, CeremonyError::InvalidChainLink(2)))
+        );
+
+        let mut tampered = transcript.clone();
+        tampered.transcripts[0].witness.signatures[1] =
+            tampered.transcripts[0].witness.signatures[2].clone();
+        assert_eq!(
+            tampered.verify_history::<DefaultEngine>(),
+            Err(InvalidCeremony(
+                0,
+                CeremonyError::InvalidWitnessSignature(1)
+            ))
+        );
+
+        let mut tampered = transcript;
+        tampered.participant_ids.push(Identity::None);
+        assert_eq!(
+            tampered.verify_history::<DefaultEngine>(),
+            Err(UnexpectedNumRecords(4, 3, 0))
+        );
+    }
+
+    #[test]
+    fn test_verify_shards() {
+        let transcript = BatchTranscript::new([(4, 2), (4, 2), (4, 2)].iter());
+        let mut contribution = transcript.contribution();
+        contribution
+            .add_entropy::<DefaultEngine>(&Secret::new([1; 32]), &Identity::None)
+            .unwrap();
+
+        let verify_sharded = |contribution: &BatchContribution| {
+            VerificationResult::merge(
+                contribution
+                    .split(2)
+                    .iter()
+                    .map(|shard| transcript.verify_shard::<DefaultEngine>(shard)),
+            )
+            .into_result()
+        };
+        let verify_whole = |contribution: &BatchContribution| {
+            transcript
+                .clone()
+                .verify_add::<DefaultEngine>(contribution.clone(), Identity::None)
+        };
+
+        assert_eq!(contribution.split(2).len(), 2);
+        assert!(verify_sharded(&contribution).is_ok());
+        assert_eq!(verify_sharded(&contribution), verify_whole(&contribution));
+
+        // Break the last sub-contribution, which ends up in the second shard.
+        contribution.contributions[2].pot_pubkey = G2::one();
+        assert!(verify_sharded(&contribution).is_err());
+        assert_eq!(verify_sharded(&contribution), verify_whole(&contribution));
+    }
 }
 
-#[cfg(feature = "bench")]
+#[cfg(all(feature = "bench", any(feature = "arkworks", feature = "blst")))]
 #[cfg(not(tarpaulin_include))]
 #[doc(hidden)]
 pub mod bench {
     use super::*;
-    use crate::{
-        bench::{rand_entropy, BATCH_SIZE},
-        Arkworks, Both, BLST,
-    };
+    use crate::bench::{rand_entropy, BATCH_SIZE};
+    #[cfg(feature = "arkworks")]
+    use crate::Arkworks;
+    #[cfg(all(feature = "arkworks", feature = "blst"))]
+    use crate::Both;
+    #[cfg(feature = "blst")]
+    use crate::BLST;
     use criterion::{BatchSize, Criterion};
 
     pub fn group(criterion: &mut Criterion) {
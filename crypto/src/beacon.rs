@@ -0,0 +1,245 @@
+//! Publicly verifiable entropy from a [drand](https://drand.love) randomness
+//! beacon, for a final "nothing-up-my-sleeve" contribution that anyone can
+//! reproduce from the beacon round.
+//!
+//! Only chains with unchained G1 signatures, like drand's `quicknet`, are
+//! supported: round `r` is the BLS signature of `sha256(r)` (big-endian
+//! `u64`), verified against the chain's G2 public key.
+
+use crate::{Entropy, ErrorCode, G1, G2};
+use hex_literal::hex;
+use hkdf::Hkdf;
+use secrecy::Secret;
+use sha2::{Digest, Sha256};
+use strum::IntoStaticStr;
+use thiserror::Error;
+
+/// Hash-to-curve domain separation tag of drand's G1 signature schemes.
+pub const DRAND_DST: &[u8] = b"BLS_SIG_BLS12381G1_XMD:SHA-256_SSWU_RO_NUL_";
+
+/// Salt of the KDF from a beacon signature to entropy, so that the derived
+/// tau is unrelated to any other use of the same round.
+const TAU_KDF_SALT: &[u8] = b"Ethereum KZG Ceremony drand tau v1";
+
+#[derive(Clone, PartialEq, Eq, Debug, Error, IntoStaticStr)]
+pub enum BeaconError {
+    #[error("Could not fetch beacon round: {0}")]
+    Fetch(String),
+    #[error("Unexpected beacon round: requested {0}, got {1}")]
+    UnexpectedRound(u64, u64),
+    #[error("Invalid beacon signature for round {0}")]
+    InvalidSignature(u64),
+}
+
+impl ErrorCode for BeaconError {
+    fn to_error_code(&self) -> String {
+        format!("BeaconError::{}", <&str>::from(self))
+    }
+}
+
+/// A drand chain, identified by its public key.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DrandBeacon {
+    pub public_key: G2,
+}
+
+impl DrandBeacon {
+    /// drand's mainnet `quicknet` chain, with chain hash
+    /// `52db9ba70e0cc0f6eaf7803dd07447a1f5477735fd3f661792ba94600c84e971`.
+    pub const QUICKNET: Self = Self {
+        public_key: G2(hex!("83cf0f2896adee7eb8b5f01fcad3912212c437e0073e911fb90022d3e760183c8c4b450b6a0a6c3ac6a5776a2d1064510d1fec758c921cc22b0e17e63aaf4bcb5ed66304de9cf809bd274ca73bab4af5a6e9c76a4bc09e76eae8991ef5ece45a")),
+    };
+}
+
+/// A round as published by the beacon.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BeaconRound {
+    pub round: u64,
+    pub signature: G1,
+}
+
+impl BeaconRound {
+    /// The message signed in `round`.
+    #[must_use]
+    pub fn message(round: u64) -> [u8; 32] {
+        Sha256::digest(round.to_be_bytes()).into()
+    }
+
+    /// Entropy derived from the round's signature with HKDF-SHA256.
+    #[allow(clippy::missing_panics_doc)] // 32 bytes is a valid HKDF length.
+    #[must_use]
+    pub fn entropy(&self) -> Entropy {
+        let mut okm = [0_u8; 32];
+        Hkdf::<Sha256>::new(Some(TAU_KDF_SALT), &self.signature.0)
+            .expand(&self.round.to_be_bytes(), &mut okm)
+            .expect("32 bytes is a valid HKDF output length");
+        Secret::new(okm)
+    }
+}
+
+/// Where beacon rounds come from, usually a drand HTTP endpoint. Tests can
+/// supply fixed rounds instead.
+pub trait BeaconSource {
+    /// Fetches round `round`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BeaconError::Fetch`] if the round can't be retrieved.
+    fn fetch(&self, round: u64) -> Result<BeaconRound, BeaconError>;
+}
+
+impl<F> BeaconSource for F
+where
+    F: Fn(u64) -> Result<BeaconRound, BeaconError>,
+{
+    fn fetch(&self, round: u64) -> Result<BeaconRound, BeaconError> {
+        self(round)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::{DefaultEngine, Engine, EntropySource, Tau};
+    use secrecy::ExposeSecret;
+
+    /// A beacon with a fixed key, so rounds can be signed in the drand
+    /// `quicknet` scheme without network access.
+    fn test_beacon() -> (DrandBeacon, Tau) {
+        let secret = DefaultEngine::generate_tau(&Entropy::from_seed([42; 32]));
+        let mut public_key = [G2::one(), G2::one()];
+        DefaultEngine::add_tau_g2(&secret, &mut public_key).unwrap();
+        (
+            DrandBeacon {
+                public_key: public_key[1],
+            },
+            secret,
+        )
+    }
+
+    fn signed_round(secret: &Tau, round: u64) -> BeaconRound {
+        BeaconRound {
+            round,
+            signature: DefaultEngine::sign_message_with_dst(
+                secret,
+                &BeaconRound::message(round),
+                DRAND_DST,
+            )
+            .unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_tau_from_fixed_round() {
+        let (beacon, secret) = test_beacon();
+        let fixed = signed_round(&secret, 1000);
+        let source = |round| {
+            if round == 1000 {
+                Ok(fixed)
+            } else {
+                Err(BeaconError::Fetch("unknown round".to_string()))
+            }
+        };
+
+        let tau = DefaultEngine::random_tau_from_beacon(&beacon, &source, 1000).unwrap();
+        // Reproducible by anyone with the round.
+        let again = DefaultEngine::random_tau_from_beacon(&beacon, &source, 1000).unwrap();
+        assert_eq!(tau.expose_secret(), again.expose_secret());
+        assert_eq!(
+            tau.expose_secret(),
+            DefaultEngine::generate_tau(&fixed.entropy()).expose_secret()
+        );
+
+        assert_eq!(
+            DefaultEngine::random_tau_from_beacon(&beacon, &source, 1001).err(),
+            Some(BeaconError::Fetch("unknown round".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_quicknet_public_key() {
+        DefaultEngine::validate_g2(&[DrandBeacon::QUICKNET.public_key]).unwrap();
+    }
+
+    /// A `quicknet` round as served by `GET /<chain hash>/public/<round>` of
+    /// the drand HTTP API, stored in `test_data/drand_quicknet_round.json`.
+    #[derive(serde::Deserialize)]
+    struct PublishedRound {
+        round: u64,
+        randomness: String,
+        signature: String,
+    }
+
+    #[test]
+    #[ignore = "needs test_data/drand_quicknet_round.json, a round fetched from the drand API"]
+    fn test_tau_from_published_round() {
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/test_data/drand_quicknet_round.json"
+        );
+        let json = std::fs::read_to_string(path).expect("published round fixture is missing");
+        let published: PublishedRound = serde_json::from_str(&json).unwrap();
+        let signature = G1(hex::decode(&published.signature)
+            .unwrap()
+            .try_into()
+            .unwrap());
+        // drand publishes the hash of the signature as the round's randomness.
+        assert_eq!(
+            hex::encode(Sha256::digest(signature.0)),
+            published.randomness
+        );
+
+        let fixed = BeaconRound {
+            round: published.round,
+            signature,
+        };
+        let source = |_| Ok(fixed);
+        let tau =
+            DefaultEngine::random_tau_from_beacon(&DrandBeacon::QUICKNET, &source, published.round)
+                .unwrap();
+        assert_eq!(
+            tau.expose_secret(),
+            DefaultEngine::generate_tau(&fixed.entropy()).expose_secret()
+        );
+
+        // Any other round number doesn't verify against the chain's key.
+        let renumbered = BeaconRound {
+            round: published.round + 1,
+            signature,
+        };
+        let source = |_| Ok(renumbered);
+        assert_eq!(
+            DefaultEngine::random_tau_from_beacon(
+                &DrandBeacon::QUICKNET,
+                &source,
+                published.round + 1
+            )
+            .err(),
+            Some(BeaconError::InvalidSignature(published.round + 1))
+        );
+    }
+
+    #[test]
+    fn test_rejects_forged_round() {
+        let (beacon, secret) = test_beacon();
+
+        // A round served for the wrong number.
+        let other = signed_round(&secret, 999);
+        let source = |_| Ok(other);
+        assert_eq!(
+            DefaultEngine::random_tau_from_beacon(&beacon, &source, 1000).err(),
+            Some(BeaconError::UnexpectedRound(1000, 999))
+        );
+
+        // A signature in the ceremony's own scheme instead of drand's.
+        let forged = BeaconRound {
+            round: 1000,
+            signature: DefaultEngine::sign_message(&secret, &BeaconRound::message(1000)).unwrap(),
+        };
+        let source = |_| Ok(forged);
+        assert_eq!(
+            DefaultEngine::random_tau_from_beacon(&beacon, &source, 1000).err(),
+            Some(BeaconError::InvalidSignature(1000))
+        );
+    }
+}
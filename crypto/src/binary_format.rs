@@ -0,0 +1,278 @@
+//! Compact binary encoding of a [`BatchContribution`], for storage that
+//! doesn't need the JSON of the API. Points are stored as raw compressed
+//! bytes, so the encoding is about half the size of the hex JSON and needs no
+//! parsing besides the point checks done on verification anyway.
+//!
+//! The layout, with all integers big-endian `u32`:
+//!
+//! - version byte, currently [`BINARY_FORMAT_VERSION`],
+//! - number of sub-contributions, then their number of G1 and G2 powers,
+//! - per sub-contribution the G1 powers, G2 powers, the pubkey and the
+//!   optional BLS signature,
+//! - the optional ECDSA signature and the optional entropy attestation as a
+//!   length-prefixed UTF-8 string.
+//!
+//! Optional values are prefixed with a byte that is `0` if absent and `1` if
+//! present.
+
+use crate::{
+    signature::{BlsSignature, EcdsaSignature},
+    BatchContribution, Contribution, ErrorCode, Powers, G1, G2,
+};
+use ethers_core::types::Signature as EthSignature;
+use strum::IntoStaticStr;
+use thiserror::Error;
+
+/// Version written by [`BatchContribution::to_bytes`]. Other versions are
+/// rejected by [`BatchContribution::from_bytes`].
+pub const BINARY_FORMAT_VERSION: u8 = 1;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Error, IntoStaticStr)]
+pub enum BinaryFormatError {
+    #[error("Unsupported binary format version {0}, expected {1}")]
+    UnsupportedVersion(u8, u8),
+    #[error("Unexpected end of data")]
+    UnexpectedEnd,
+    #[error("{0} trailing bytes after the contribution")]
+    TrailingBytes(usize),
+    #[error("Invalid presence byte {0}")]
+    InvalidPresence(u8),
+    #[error("Invalid ECDSA signature")]
+    InvalidEcdsaSignature,
+    #[error("Entropy attestation is not valid UTF-8")]
+    InvalidEntropyAttestation,
+}
+
+impl ErrorCode for BinaryFormatError {
+    fn to_error_code(&self) -> String {
+        format!("BinaryFormatError::{}", <&str>::from(self))
+    }
+}
+
+impl BatchContribution {
+    /// Encodes the contribution in the compact binary format.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a length does not fit in a `u32`, which no ceremony comes
+    /// close to.
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::default();
+        writer.bytes(&[BINARY_FORMAT_VERSION]);
+        writer.len(self.contributions.len());
+        for contribution in &self.contributions {
+            writer.len(contribution.powers.g1.len());
+            writer.len(contribution.powers.g2.len());
+        }
+        for contribution in &self.contributions {
+            for point in &contribution.powers.g1 {
+                writer.bytes(&point.0);
+            }
+            for point in &contribution.powers.g2 {
+                writer.bytes(&point.0);
+            }
+            writer.bytes(&contribution.pot_pubkey.0);
+            writer.optional(contribution.bls_signature.0.map(|sig| sig.0));
+        }
+        writer.optional(self.ecdsa_signature.0.map(<[u8; 65]>::from));
+        match &self.entropy_attestation {
+            Some(attestation) => {
+                writer.bytes(&[1]);
+                writer.len(attestation.len());
+                writer.bytes(attestation.as_bytes());
+            }
+            None => writer.bytes(&[0]),
+        }
+        writer.0
+    }
+
+    /// Decodes a contribution in the compact binary format. The points are
+    /// not validated, just like when parsing JSON.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the version is not [`BINARY_FORMAT_VERSION`], or if
+    /// `bytes` is truncated or malformed.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BinaryFormatError> {
+        let mut reader = Reader(bytes);
+        let [version] = reader.array()?;
+        if version != BINARY_FORMAT_VERSION {
+            return Err(BinaryFormatError::UnsupportedVersion(
+                version,
+                BINARY_FORMAT_VERSION,
+            ));
+        }
+        let num_contributions = reader.len()?;
+        let mut shapes = Vec::new();
+        for _ in 0..num_contributions {
+            shapes.push((reader.len()?, reader.len()?));
+        }
+        let mut contributions = Vec::new();
+        for (num_g1, num_g2) in shapes {
+            let g1 = (0..num_g1)
+                .map(|_| reader.array().map(G1))
+                .collect::<Result<_, _>>()?;
+            let g2 = (0..num_g2)
+                .map(|_| reader.array().map(G2))
+                .collect::<Result<_, _>>()?;
+            contributions.push(Contribution {
+                powers: Powers { g1, g2 },
+                pot_pubkey: G2(reader.array()?),
+                bls_signature: BlsSignature(reader.optional()?.map(G1)),
+            });
+        }
+        let ecdsa_signature = reader
+            .optional::<65>()?
+            .map(|sig| {
+                EthSignature::try_from(&sig[..])
+                    .map_err(|_| BinaryFormatError::InvalidEcdsaSignature)
+            })
+            .transpose()?;
+        let entropy_attestation = if reader.presence()? {
+            let len = reader.len()?;
+            let bytes = reader.take(len)?;
+            Some(
+                String::from_utf8(bytes.to_vec())
+                    .map_err(|_| BinaryFormatError::InvalidEntropyAttestation)?,
+            )
+        } else {
+            None
+        };
+        if !reader.0.is_empty() {
+            return Err(BinaryFormatError::TrailingBytes(reader.0.len()));
+        }
+        Ok(Self {
+            contributions,
+            ecdsa_signature: EcdsaSignature(ecdsa_signature),
+            entropy_attestation,
+        })
+    }
+}
+
+#[derive(Default)]
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn len(&mut self, len: usize) {
+        let len = u32::try_from(len).expect("length fits in a u32");
+        self.bytes(&len.to_be_bytes());
+    }
+
+    fn optional<const N: usize>(&mut self, value: Option<[u8; N]>) {
+        match value {
+            Some(value) => {
+                self.bytes(&[1]);
+                self.bytes(&value);
+            }
+            None => self.bytes(&[0]),
+        }
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], BinaryFormatError> {
+        if self.0.len() < len {
+            return Err(BinaryFormatError::UnexpectedEnd);
+        }
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], BinaryFormatError> {
+        Ok(self.take(N)?.try_into().expect("slice has length N"))
+    }
+
+    fn len(&mut self) -> Result<usize, BinaryFormatError> {
+        let len = u32::from_be_bytes(self.array()?);
+        // A length beyond the address space can't be backed by the data.
+        usize::try_from(len).map_err(|_| BinaryFormatError::UnexpectedEnd)
+    }
+
+    fn presence(&mut self) -> Result<bool, BinaryFormatError> {
+        match self.array()? {
+            [0] => Ok(false),
+            [1] => Ok(true),
+            [byte] => Err(BinaryFormatError::InvalidPresence(byte)),
+        }
+    }
+
+    fn optional<const N: usize>(&mut self) -> Result<Option<[u8; N]>, BinaryFormatError> {
+        self.presence()?.then(|| self.array()).transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BatchTranscript, DefaultEngine, Identity};
+    use ethers_core::types::H256;
+    use ethers_signers::LocalWallet;
+    use secrecy::Secret;
+
+    fn signed_contribution() -> BatchContribution {
+        let transcript = BatchTranscript::new(&[(4, 2), (8, 3)]);
+        let mut contribution = transcript.contribution();
+        contribution
+            .add_entropy::<DefaultEngine>(
+                &Secret::new([7; 32]),
+                &Identity::Github {
+                    id: 1234,
+                    username: "test_user".to_string(),
+                },
+            )
+            .unwrap();
+        let wallet = LocalWallet::new(&mut rand::thread_rng());
+        contribution.ecdsa_signature = EcdsaSignature(Some(wallet.sign_hash(H256::repeat_byte(1))));
+        contribution.entropy_attestation = Some("drand quicknet round 1000".to_string());
+        contribution
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let contribution = signed_contribution();
+        assert!(contribution.contributions[0].bls_signature.0.is_some());
+        assert!(contribution.ecdsa_signature.0.is_some());
+        let bytes = contribution.to_bytes();
+        assert_eq!(BatchContribution::from_bytes(&bytes), Ok(contribution));
+
+        // Absent optional values.
+        let base = BatchTranscript::new(&[(4, 2)]).contribution();
+        assert_eq!(BatchContribution::from_bytes(&base.to_bytes()), Ok(base));
+    }
+
+    #[test]
+    fn test_version_mismatch() {
+        let mut bytes = signed_contribution().to_bytes();
+        bytes[0] = BINARY_FORMAT_VERSION + 1;
+        assert_eq!(
+            BatchContribution::from_bytes(&bytes),
+            Err(BinaryFormatError::UnsupportedVersion(
+                BINARY_FORMAT_VERSION + 1,
+                BINARY_FORMAT_VERSION
+            ))
+        );
+    }
+
+    #[test]
+    fn test_malformed() {
+        let bytes = signed_contribution().to_bytes();
+        assert_eq!(
+            BatchContribution::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(BinaryFormatError::UnexpectedEnd)
+        );
+        let mut trailing = bytes;
+        trailing.push(0);
+        assert_eq!(
+            BatchContribution::from_bytes(&trailing),
+            Err(BinaryFormatError::TrailingBytes(1))
+        );
+    }
+}
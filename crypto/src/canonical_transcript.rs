@@ -0,0 +1,125 @@
+//! The `transcript.json` layout of the Ethereum KZG ceremony.
+//! <https://github.com/ethereum/kzg-ceremony-specs/blob/master/docs/sequencer/sequencer.md#transcript>
+//!
+//! The structs borrow from a [`BatchTranscript`] and only exist to pin the
+//! field names and their order, so the reference verifier can consume the
+//! output directly.
+
+use crate::{
+    signature::{identity::Identity, BlsSignature, EcdsaSignature},
+    BatchTranscript, Transcript, G1, G2,
+};
+use serde::Serialize;
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CanonicalTranscript<'a> {
+    pub transcripts: Vec<CanonicalSubTranscript<'a>>,
+    pub participant_ids: &'a [Identity],
+    pub participant_ecdsa_signatures: &'a [EcdsaSignature],
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CanonicalSubTranscript<'a> {
+    pub num_g1_powers: usize,
+    pub num_g2_powers: usize,
+    pub powers_of_tau: CanonicalPowersOfTau<'a>,
+    pub witness: CanonicalWitness<'a>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CanonicalPowersOfTau<'a> {
+    pub g1_powers: &'a [G1],
+    pub g2_powers: &'a [G2],
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CanonicalWitness<'a> {
+    pub running_products: &'a [G1],
+    pub pot_pubkeys: &'a [G2],
+    pub bls_signatures: &'a [BlsSignature],
+}
+
+impl<'a> From<&'a Transcript> for CanonicalSubTranscript<'a> {
+    fn from(transcript: &'a Transcript) -> Self {
+        Self {
+            num_g1_powers: transcript.powers.g1.len(),
+            num_g2_powers: transcript.powers.g2.len(),
+            powers_of_tau: CanonicalPowersOfTau {
+                g1_powers: &transcript.powers.g1,
+                g2_powers: &transcript.powers.g2,
+            },
+            witness: CanonicalWitness {
+                running_products: &transcript.witness.products,
+                pot_pubkeys: &transcript.witness.pubkeys,
+                bls_signatures: &transcript.witness.signatures,
+            },
+        }
+    }
+}
+
+impl BatchTranscript {
+    /// Borrows the transcript in the canonical `transcript.json` layout.
+    #[must_use]
+    pub fn to_canonical_transcript(&self) -> CanonicalTranscript<'_> {
+        CanonicalTranscript {
+            transcripts: self.transcripts.iter().map(Into::into).collect(),
+            participant_ids: &self.participant_ids,
+            participant_ecdsa_signatures: &self.participant_ecdsa_signatures,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{transcript::Witness, Powers};
+
+    /// A transcript with one participant, in the layout of the ceremony's
+    /// published `transcript.json`.
+    const GOLDEN: &str = include_str!("../test_data/canonical_transcript.json");
+
+    fn sub_transcript(num_g1: usize, num_g2: usize) -> Transcript {
+        Transcript {
+            powers: Powers {
+                g1: vec![G1::one(); num_g1],
+                g2: vec![G2::one(); num_g2],
+            },
+            witness: Witness {
+                products: vec![G1::one(); 2],
+                pubkeys: vec![G2::one(); 2],
+                signatures: vec![BlsSignature::empty(), BlsSignature(Some(G1::one()))],
+            },
+        }
+    }
+
+    #[test]
+    fn test_golden_transcript() {
+        let transcript = BatchTranscript {
+            transcripts: vec![sub_transcript(4, 2), sub_transcript(2, 2)],
+            participant_ids: vec![
+                Identity::None,
+                Identity::Github {
+                    id: 1234,
+                    username: "alice".to_string(),
+                },
+            ],
+            participant_ecdsa_signatures: vec![EcdsaSignature::empty(); 2],
+            participant_entropy_attestations: Vec::new(),
+        };
+
+        // Compare the text, so that field order and hex formatting matter.
+        let canonical =
+            serde_json::to_string_pretty(&transcript.to_canonical_transcript()).unwrap();
+        assert_eq!(canonical, GOLDEN.trim_end());
+
+        // The internal representation reads the canonical layout.
+        assert_eq!(
+            serde_json::from_str::<BatchTranscript>(GOLDEN).unwrap(),
+            transcript
+        );
+    }
+}
@@ -1,5 +1,5 @@
 use crate::{
-    signature::{identity::Identity, BlsSignature},
+    signature::{identity::Identity, BlsSignature, SigningContext},
     CeremonyError, Engine, Powers, Tau, G2,
 };
 use serde::{Deserialize, Serialize};
@@ -21,13 +21,68 @@ impl Contribution {
         self.pot_pubkey != G2::one()
     }
 
+    /// Check if the powers are unchanged from `prior`, i.e. $τ = 1$. Unlike
+    /// [`Contribution::has_entropy`], this looks at the powers themselves
+    /// instead of the pubkey.
+    #[must_use]
+    pub fn is_identity(&self, prior: &Self) -> bool {
+        self.powers == prior.powers
+    }
+
+    /// Copy of the powers and pubkey without the BLS signature, which only
+    /// means something to the contributor that made it.
+    #[must_use]
+    pub fn clone_base_without_signature(&self) -> Self {
+        Self {
+            powers: self.powers.clone(),
+            pot_pubkey: self.pot_pubkey,
+            bls_signature: BlsSignature::empty(),
+        }
+    }
+
+    /// Checks that this contribution is a base entropy can be added to,
+    /// without touching any secret. Lets a client reject a malformed base
+    /// before asking for entropy.
+    #[instrument(level = "info", skip_all, fields(n1=self.powers.g1.len(), n2=self.powers.g2.len()))]
+    pub fn precheck<E: Engine>(&self) -> Result<(), CeremonyError> {
+        let num_g1 = self.powers.g1.len();
+        let num_g2 = self.powers.g2.len();
+        if num_g1 < 2 {
+            return Err(CeremonyError::UnsupportedNumG1Powers(num_g1));
+        }
+        if num_g2 < 2 {
+            return Err(CeremonyError::UnsupportedNumG2Powers(num_g2));
+        }
+        if num_g2 > num_g1 {
+            return Err(CeremonyError::UnsupportedMoreG2Powers(num_g1, num_g2));
+        }
+        E::validate_g1(&self.powers.g1)?;
+        E::validate_g2(&self.powers.g2)?;
+        E::validate_g2(&[self.pot_pubkey]).map_err(|error| match error {
+            CeremonyError::InvalidG2Power(_, error) => CeremonyError::InvalidPubKey(error),
+            CeremonyError::ZeroG2(_) => CeremonyError::ZeroPubkey,
+            error => error,
+        })
+    }
+
     /// Adds entropy to this contribution. Can be called multiple times.
     /// The entropy is consumed and the blob is zeroized after use.
-    #[instrument(level = "info", skip_all, , fields(n1=self.powers.g1.len(), n2=self.powers.g2.len()))]
     pub fn add_tau<E: Engine>(
         &mut self,
         tau: &Tau,
         identity: &Identity,
+    ) -> Result<(), CeremonyError> {
+        self.add_tau_with_context::<E>(tau, identity, &SigningContext::default())
+    }
+
+    /// Adds entropy to this contribution, binding the BLS signature to
+    /// `context` in addition to the identity.
+    #[instrument(level = "info", skip_all, , fields(n1=self.powers.g1.len(), n2=self.powers.g2.len()))]
+    pub fn add_tau_with_context<E: Engine>(
+        &mut self,
+        tau: &Tau,
+        identity: &Identity,
+        context: &SigningContext,
     ) -> Result<(), CeremonyError> {
         // Validate points after computation to contribute faster
 
@@ -36,12 +91,123 @@ impl Contribution {
         E::add_tau_g2(tau, &mut self.powers.g2)?;
         let mut temp = [G2::one(), self.pot_pubkey];
         E::add_tau_g2(tau, &mut temp)?;
-        self.bls_signature = BlsSignature::sign::<E>(identity.to_string().as_bytes(), tau);
+        self.bls_signature = BlsSignature::sign::<E>(&context.message(identity), tau);
         self.pot_pubkey = temp[1];
 
         Ok(())
     }
 
+    /// Replaces the BLS signature with one for `identity`, leaving the powers
+    /// untouched. Lets a client fix the binding if its identity changed after
+    /// it added `tau`, without computing the powers again.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CeremonyError::TauPubkeyMismatch`] if `tau` is not the
+    /// secret of the pot pubkey, i.e. the signature would be pruned. The
+    /// signature is left as is then.
+    pub fn resign<E: Engine>(
+        &mut self,
+        tau: &Tau,
+        identity: &Identity,
+    ) -> Result<(), CeremonyError> {
+        self.resign_with_context::<E>(tau, identity, &SigningContext::default())
+    }
+
+    /// Like [`Contribution::resign`], binding the signature to `context` in
+    /// addition to the identity.
+    ///
+    /// # Errors
+    ///
+    /// See [`Contribution::resign`].
+    pub fn resign_with_context<E: Engine>(
+        &mut self,
+        tau: &Tau,
+        identity: &Identity,
+        context: &SigningContext,
+    ) -> Result<(), CeremonyError> {
+        let message = context.message(identity);
+        let signature: BlsSignature = BlsSignature::sign::<E>(&message, tau);
+        if !signature.verify::<E>(&message, self.pot_pubkey) {
+            return Err(CeremonyError::TauPubkeyMismatch);
+        }
+        self.bls_signature = signature;
+        Ok(())
+    }
+
+    /// Checks that no power repeats its predecessor. Equal consecutive powers
+    /// mean $τ = 1$, which only the generator prefix of a fresh transcript
+    /// may have, never a contribution that claims entropy.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CeremonyError::DegeneratePowers`] if two consecutive G1 or
+    /// G2 powers are equal.
+    pub fn check_distinct_powers(&self) -> Result<(), CeremonyError> {
+        let g1_repeats = self.powers.g1.windows(2).any(|pair| pair[0] == pair[1]);
+        let g2_repeats = self.powers.g2.windows(2).any(|pair| pair[0] == pair[1]);
+        if g1_repeats || g2_repeats {
+            return Err(CeremonyError::DegeneratePowers);
+        }
+        Ok(())
+    }
+
+    /// Checks that the G1 and G2 powers were raised to the same $τ$: the G1
+    /// powers are a sequence of powers of $τ$ = `g2[1]`, starting with
+    /// $e(g1_1, g2_0) = e(g1_0, g2_1)$, and every G2 power matches the G1
+    /// power with the same exponent. Points must be validated already.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CeremonyError::G1PairingFailed`] or
+    /// [`CeremonyError::G2PairingFailed`] if the powers are inconsistent, or
+    /// an unsupported number of powers error if there are too few to check.
+    pub fn verify_tau_consistency<E: Engine>(&self) -> Result<(), CeremonyError> {
+        let num_g1 = self.powers.g1.len();
+        let num_g2 = self.powers.g2.len();
+        if num_g1 < 2 {
+            return Err(CeremonyError::UnsupportedNumG1Powers(num_g1));
+        }
+        if num_g2 < 2 {
+            return Err(CeremonyError::UnsupportedNumG2Powers(num_g2));
+        }
+        if num_g2 > num_g1 {
+            return Err(CeremonyError::UnsupportedMoreG2Powers(num_g1, num_g2));
+        }
+        E::verify_g1(&self.powers.g1, self.powers.g2[1])?;
+        E::verify_g2(&self.powers.g1[..num_g2], &self.powers.g2)
+    }
+
+    /// Checks that `next` built directly on `prev`: the first G1 power of
+    /// `next` is the one of `prev` raised to the $τ$ of `next`'s pubkey,
+    /// $e(next.g1_1, g2_0) = e(prev.g1_1, next.pubkey)$, and the powers of
+    /// `next` are consistent with each other, so every power of `prev` was
+    /// raised to that $τ$. Unlike the transcript's chain verification, this
+    /// works on any pair of contributions, e.g. two consecutive ones an
+    /// auditor kept. Points must be validated already.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CeremonyError::PubKeyPairingFailed`] if `next` did not build
+    /// on `prev`, a pairing error if the powers of `next` are inconsistent,
+    /// or a shape error if the two have a different number of powers.
+    pub fn verify_succession<E: Engine>(prev: &Self, next: &Self) -> Result<(), CeremonyError> {
+        if prev.powers.g1.len() != next.powers.g1.len() {
+            return Err(CeremonyError::UnexpectedNumG1Powers(
+                prev.powers.g1.len(),
+                next.powers.g1.len(),
+            ));
+        }
+        if prev.powers.g2.len() != next.powers.g2.len() {
+            return Err(CeremonyError::UnexpectedNumG2Powers(
+                prev.powers.g2.len(),
+                next.powers.g2.len(),
+            ));
+        }
+        next.verify_tau_consistency::<E>()?;
+        E::verify_pubkey(next.powers.g1[1], prev.powers.g1[1], next.pot_pubkey)
+    }
+
     /// Performs validations in the contribution.
     #[instrument(level = "info", skip_all, , fields(n1=self.powers.g1.len(), n2=self.powers.g2.len()))]
     pub fn validate<E: Engine>(&mut self) -> Result<(), CeremonyError> {
@@ -58,8 +224,9 @@ pub mod test {
     use super::*;
     use crate::{
         group::tests::{invalid_g1, invalid_g2},
-        DefaultEngine, G1,
+        DefaultEngine, Transcript, F, G1,
     };
+    use secrecy::Secret;
 
     pub fn valid_contribution() -> Contribution {
         Contribution {
@@ -122,6 +289,277 @@ pub mod test {
         assert!(valid_contribution().validate::<DefaultEngine>().is_ok());
     }
 
+    #[test]
+    fn test_precheck() {
+        let base = Contribution {
+            powers: Powers::new(4, 2),
+            pot_pubkey: G2::one(),
+            bls_signature: BlsSignature::empty(),
+        };
+        assert_eq!(base.precheck::<DefaultEngine>(), Ok(()));
+
+        let mut malformed = base.clone();
+        malformed.powers.g1[2] = invalid_g1();
+        assert!(matches!(
+            malformed.precheck::<DefaultEngine>(),
+            Err(CeremonyError::InvalidG1Power(2, _))
+        ));
+
+        let mut malformed = base.clone();
+        malformed.powers.g2[1] = invalid_g2();
+        assert!(matches!(
+            malformed.precheck::<DefaultEngine>(),
+            Err(CeremonyError::InvalidG2Power(1, _))
+        ));
+
+        let mut malformed = base.clone();
+        malformed.pot_pubkey = invalid_g2();
+        assert!(matches!(
+            malformed.precheck::<DefaultEngine>(),
+            Err(CeremonyError::InvalidPubKey(_))
+        ));
+
+        let mut malformed = base.clone();
+        malformed.powers.g1.truncate(1);
+        assert_eq!(
+            malformed.precheck::<DefaultEngine>(),
+            Err(CeremonyError::UnsupportedNumG1Powers(1))
+        );
+
+        let mut malformed = base;
+        malformed.powers.g2.push(G2::one());
+        malformed.powers.g1.truncate(2);
+        assert_eq!(
+            malformed.precheck::<DefaultEngine>(),
+            Err(CeremonyError::UnsupportedMoreG2Powers(2, 3))
+        );
+    }
+
+    #[test]
+    fn test_is_identity() {
+        let prior = Contribution {
+            powers: Powers::new(4, 2),
+            pot_pubkey: G2::one(),
+            bls_signature: BlsSignature::empty(),
+        };
+        let mut noop = prior.clone();
+        noop.add_tau::<DefaultEngine>(&Secret::new(F::one()), &Identity::None)
+            .unwrap();
+        assert!(noop.is_identity(&prior));
+
+        let tau = DefaultEngine::generate_tau(&Secret::new([1; 32]));
+        let mut contribution = prior.clone();
+        contribution
+            .add_tau::<DefaultEngine>(&tau, &Identity::None)
+            .unwrap();
+        assert!(!contribution.is_identity(&prior));
+    }
+
+    #[test]
+    fn test_clone_base_without_signature() {
+        let signed = Contribution {
+            bls_signature: BlsSignature(Some(G1::one())),
+            ..valid_contribution()
+        };
+        let base = signed.clone_base_without_signature();
+        assert_eq!(base.bls_signature, BlsSignature::empty());
+        assert_eq!(base.powers, signed.powers);
+        assert_eq!(base.pot_pubkey, signed.pot_pubkey);
+
+        // The base handed out by the transcript carries no signature either.
+        let transcript = Transcript::new(4, 2);
+        assert_eq!(
+            transcript.contribution().bls_signature,
+            BlsSignature::empty()
+        );
+    }
+
+    #[test]
+    fn test_resign() {
+        let tau = DefaultEngine::generate_tau(&Secret::new([1; 32]));
+        let old_identity = Identity::Ethereum { address: [1; 20] };
+        let new_identity = Identity::Ethereum { address: [2; 20] };
+        let context = SigningContext::default();
+        let mut contribution = Contribution {
+            powers: Powers::new(4, 2),
+            pot_pubkey: G2::one(),
+            bls_signature: BlsSignature::empty(),
+        };
+        contribution
+            .add_tau::<DefaultEngine>(&tau, &old_identity)
+            .unwrap();
+        let signed = contribution.clone();
+
+        contribution
+            .resign::<DefaultEngine>(&tau, &new_identity)
+            .unwrap();
+        assert_eq!(contribution.powers, signed.powers);
+        assert_eq!(contribution.pot_pubkey, signed.pot_pubkey);
+        let pruned = contribution
+            .bls_signature
+            .prune::<DefaultEngine>(&context.message(&new_identity), contribution.pot_pubkey);
+        assert_eq!(pruned, contribution.bls_signature);
+        assert!(pruned.0.is_some());
+        assert!(!contribution
+            .bls_signature
+            .verify::<DefaultEngine>(&context.message(&old_identity), contribution.pot_pubkey));
+
+        // A different tau can't sign for the pubkey.
+        let other_tau = DefaultEngine::generate_tau(&Secret::new([2; 32]));
+        let mut unchanged = signed.clone();
+        assert_eq!(
+            unchanged.resign::<DefaultEngine>(&other_tau, &new_identity),
+            Err(CeremonyError::TauPubkeyMismatch)
+        );
+        assert_eq!(unchanged, signed);
+    }
+
+    fn contribute(prior: &Contribution, seed: u8) -> Contribution {
+        let tau = DefaultEngine::generate_tau(&Secret::new([seed; 32]));
+        let mut contribution = prior.clone_base_without_signature();
+        contribution.pot_pubkey = G2::one();
+        contribution
+            .add_tau::<DefaultEngine>(&tau, &Identity::None)
+            .unwrap();
+        contribution
+    }
+
+    #[test]
+    fn test_verify_succession() {
+        let base = Transcript::new(4, 3).contribution();
+        let first = contribute(&base, 1);
+        let second = contribute(&first, 2);
+        assert_eq!(
+            Contribution::verify_succession::<DefaultEngine>(&base, &first),
+            Ok(())
+        );
+        assert_eq!(
+            Contribution::verify_succession::<DefaultEngine>(&first, &second),
+            Ok(())
+        );
+
+        // Built on the base instead of on the first contribution.
+        let skipped = contribute(&base, 2);
+        assert_eq!(
+            Contribution::verify_succession::<DefaultEngine>(&first, &skipped),
+            Err(CeremonyError::PubKeyPairingFailed)
+        );
+
+        // Powers of the second contribution with the pubkey of another tau.
+        let mut forged = second.clone();
+        forged.pot_pubkey = contribute(&first, 3).pot_pubkey;
+        assert_eq!(
+            Contribution::verify_succession::<DefaultEngine>(&first, &forged),
+            Err(CeremonyError::PubKeyPairingFailed)
+        );
+
+        // The higher powers don't follow from the first one.
+        let mut forged = second;
+        forged.powers.g1[3] = first.powers.g1[3];
+        assert_eq!(
+            Contribution::verify_succession::<DefaultEngine>(&first, &forged),
+            Err(CeremonyError::G1PairingFailed)
+        );
+
+        assert_eq!(
+            Contribution::verify_succession::<DefaultEngine>(
+                &Transcript::new(5, 3).contribution(),
+                &first
+            ),
+            Err(CeremonyError::UnexpectedNumG1Powers(5, 4))
+        );
+    }
+
+    #[test]
+    fn test_verify_tau_consistency() {
+        let base = Contribution {
+            powers: Powers::new(4, 3),
+            pot_pubkey: G2::one(),
+            bls_signature: BlsSignature::empty(),
+        };
+        let tau = DefaultEngine::generate_tau(&Secret::new([1; 32]));
+        let other_tau = DefaultEngine::generate_tau(&Secret::new([2; 32]));
+
+        let mut consistent = base.clone();
+        consistent
+            .add_tau::<DefaultEngine>(&tau, &Identity::None)
+            .unwrap();
+        assert_eq!(consistent.verify_tau_consistency::<DefaultEngine>(), Ok(()));
+
+        // G1 and G2 raised to different taus.
+        let mut inconsistent = base.clone();
+        DefaultEngine::add_tau_g1(&tau, &mut inconsistent.powers.g1).unwrap();
+        DefaultEngine::add_tau_g2(&other_tau, &mut inconsistent.powers.g2).unwrap();
+        assert_eq!(
+            inconsistent.verify_tau_consistency::<DefaultEngine>(),
+            Err(CeremonyError::G1PairingFailed)
+        );
+
+        // Only the higher G2 powers are off.
+        let mut inconsistent = consistent.clone();
+        inconsistent.powers.g2[2] = consistent.powers.g2[1];
+        assert_eq!(
+            inconsistent.verify_tau_consistency::<DefaultEngine>(),
+            Err(CeremonyError::G2PairingFailed)
+        );
+
+        // Full verification rejects it as well.
+        let transcript = Transcript::new(4, 3);
+        let mut inconsistent = transcript.contribution();
+        DefaultEngine::add_tau_g1(&tau, &mut inconsistent.powers.g1).unwrap();
+        DefaultEngine::add_tau_g2(&other_tau, &mut inconsistent.powers.g2).unwrap();
+        let mut pubkey = [G2::one(), G2::one()];
+        DefaultEngine::add_tau_g2(&tau, &mut pubkey).unwrap();
+        inconsistent.pot_pubkey = pubkey[1];
+        assert_eq!(
+            transcript.verify::<DefaultEngine>(&inconsistent),
+            Err(CeremonyError::G1PairingFailed)
+        );
+    }
+
+    #[test]
+    fn test_add_tau_with_context() {
+        let tau = DefaultEngine::generate_tau(&Secret::new([1; 32]));
+        let identity = Identity::Github {
+            id: 1234,
+            username: "test_user".to_string(),
+        };
+        let context = SigningContext {
+            ceremony_id: Some("kzg".to_string()),
+            round: Some(2),
+        };
+        assert_eq!(context.message(&identity), b"kzg|2|git|1234|test_user");
+
+        let mut default = valid_contribution();
+        default.add_tau::<DefaultEngine>(&tau, &identity).unwrap();
+        let mut with_context = valid_contribution();
+        with_context
+            .add_tau_with_context::<DefaultEngine>(&tau, &identity, &context)
+            .unwrap();
+        assert_eq!(default.pot_pubkey, with_context.pot_pubkey);
+        assert_ne!(default.bls_signature, with_context.bls_signature);
+
+        // The default context signs the plain identity.
+        let plain = identity.to_string();
+        let pubkey = with_context.pot_pubkey;
+        assert!(default
+            .bls_signature
+            .prune::<DefaultEngine>(plain.as_bytes(), pubkey)
+            .0
+            .is_some());
+
+        // Verification succeeds only with the same context.
+        let signature = &with_context.bls_signature;
+        assert!(signature
+            .prune::<DefaultEngine>(&context.message(&identity), pubkey)
+            .0
+            .is_some());
+        assert!(signature
+            .prune::<DefaultEngine>(plain.as_bytes(), pubkey)
+            .0
+            .is_none());
+    }
+
     #[test]
     fn contribution_json() {
         let value = Contribution {
@@ -155,3 +593,80 @@ pub mod test {
         assert_eq!(deser, value);
     }
 }
+
+#[cfg(all(feature = "bench", any(feature = "arkworks", feature = "blst")))]
+#[cfg(not(tarpaulin_include))]
+#[doc(hidden)]
+pub mod bench {
+    use super::*;
+    #[cfg(feature = "arkworks")]
+    use crate::Arkworks;
+    #[cfg(all(feature = "arkworks", feature = "blst"))]
+    use crate::Both;
+    #[cfg(feature = "blst")]
+    use crate::BLST;
+    use crate::{bench::seeded_tau, Transcript};
+    use criterion::{BatchSize, BenchmarkId, Criterion};
+
+    const SIZES: [(usize, usize); 2] = [(4096, 65), (32768, 65)];
+
+    pub fn group(criterion: &mut Criterion) {
+        #[cfg(feature = "arkworks")]
+        bench_engine::<Arkworks>(criterion, "arkworks");
+        #[cfg(feature = "blst")]
+        bench_engine::<BLST>(criterion, "blst");
+        #[cfg(all(feature = "arkworks", feature = "blst"))]
+        bench_engine::<Both<Arkworks, BLST>>(criterion, "both");
+    }
+
+    fn bench_engine<E: Engine>(criterion: &mut Criterion, name: &str) {
+        bench_add_tau::<E>(criterion, name);
+        bench_validate::<E>(criterion, name);
+    }
+
+    /// A contribution with non-trivial points, derived from a fixed seed.
+    fn contribution<E: Engine>(num_g1: usize, num_g2: usize) -> Contribution {
+        let mut contribution = Transcript::new(num_g1, num_g2).contribution();
+        contribution
+            .add_tau::<E>(&seeded_tau(0), &Identity::None)
+            .unwrap();
+        contribution
+    }
+
+    fn bench_add_tau<E: Engine>(criterion: &mut Criterion, name: &str) {
+        let id = format!("contribution/{name}/add_tau");
+        for (num_g1, num_g2) in SIZES {
+            let contribution = contribution::<E>(num_g1, num_g2);
+            let tau = seeded_tau(1);
+            criterion.bench_with_input(
+                BenchmarkId::new(id.clone(), num_g1),
+                &contribution,
+                move |bencher, contribution| {
+                    bencher.iter_batched_ref(
+                        || contribution.clone(),
+                        |contribution| contribution.add_tau::<E>(&tau, &Identity::None).unwrap(),
+                        BatchSize::LargeInput,
+                    );
+                },
+            );
+        }
+    }
+
+    fn bench_validate<E: Engine>(criterion: &mut Criterion, name: &str) {
+        let id = format!("contribution/{name}/validate");
+        for (num_g1, num_g2) in SIZES {
+            let contribution = contribution::<E>(num_g1, num_g2);
+            criterion.bench_with_input(
+                BenchmarkId::new(id.clone(), num_g1),
+                &contribution,
+                move |bencher, contribution| {
+                    bencher.iter_batched_ref(
+                        || contribution.clone(),
+                        |contribution| contribution.validate::<E>().unwrap(),
+                        BatchSize::LargeInput,
+                    );
+                },
+            );
+        }
+    }
+}
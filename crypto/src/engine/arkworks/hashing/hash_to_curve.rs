@@ -34,7 +34,7 @@
 use crate::engine::arkworks::{
     ext_field::ToBasePrimeFieldIterator, hashing::hash_to_field::HashToField,
 };
-use ark_bls12_381::{g1::Parameters as G1Parameters, Fq, Fr};
+use ark_bls12_381::{g1::Parameters as G1Parameters, g2::Parameters as G2Parameters, Fq, Fq2, Fr};
 use ark_ec::{
     short_weierstrass_jacobian::{GroupAffine, GroupProjective},
     AffineCurve, ModelParameters, ProjectiveCurve, SWModelParameters,
@@ -65,6 +65,34 @@ impl ClearCofactor for GroupAffine<G1Parameters> {
     }
 }
 
+impl ClearCofactor for GroupAffine<G2Parameters> {
+    fn clear_cofactor(&self) -> Self {
+        let base = self.into_projective();
+        // h_eff from the IETF spec, section 8.8.2, equivalent to clearing the
+        // cofactor with the endomorphism ψ as BLST does.
+        let h_eff: &[u64] = &[
+            0xe8020005aaa95551,
+            0x59894c0adebbf6b4,
+            0xe954cbc06689f6a3,
+            0x2ec0ec69d7477c1a,
+            0x6d82bf015d1212b0,
+            0x329c2f178731db95,
+            0x9986ff031508ffe1,
+            0x88e2a8e9145ad768,
+            0x584c6a0ea91b3528,
+            0x0bc69f08f2ee75b3,
+        ];
+        let mut res = GroupProjective::<G2Parameters>::zero();
+        for b in BitIteratorBE::without_leading_zeros(h_eff) {
+            res.double_in_place();
+            if b {
+                res += &base;
+            }
+        }
+        res.into_affine()
+    }
+}
+
 /// Trait for hashing arbitrary data to a group element on an elliptic curve
 pub trait HashToCurve<T: SWModelParameters>: Sized {
     /// Create a new hash to curve instance, with a given domain.
@@ -198,6 +226,51 @@ impl SWUParams for G1SWUParameters {
     const ZETA: Fq = field_new!(Fq, "11");
 }
 
+// 8.8.2.  BLS12-381 G2
+// BLS12381G2_XMD:SHA-256_SSWU_RO_ is defined as follows:
+// * E': y'^2 = x'^3 + A' * x' + B', where
+//      -  A' = 240 * I
+//      -  B' = 1012 * (1 + I)
+//  * Z: -(2 + I)
+pub struct G2SWUParameters;
+
+impl ModelParameters for G2SWUParameters {
+    type BaseField = Fq2;
+    type ScalarField = Fr;
+}
+
+impl G2SWUParameters {
+    // Any point of E' will do, it is only used to sanity check the isogeny.
+    const GENERATOR_X: Fq2 = field_new!(
+        Fq2,
+        field_new!(Fq, "1013943753989489072999173422519762386327743647717881997617454727818088018124345592325480866046017301556266549048476"),
+        field_new!(Fq, "2228007985740061515669236336326319980483331436432714389501512362442377618773233077873096113548485386314427811724954")
+    );
+    const GENERATOR_Y: Fq2 = field_new!(
+        Fq2,
+        field_new!(Fq, "483950511133246063278500667890425269817047677648651481553368849123043384491524467549724515390600657308756785679369"),
+        field_new!(Fq, "3906985860187570787735561476856923467787148002658702404484402624719385166527484043253636273152595842569682378807146")
+    );
+}
+
+impl SWModelParameters for G2SWUParameters {
+    const AFFINE_GENERATOR_COEFFS: (Self::BaseField, Self::BaseField) =
+        (Self::GENERATOR_X, Self::GENERATOR_Y);
+    const COEFF_A: Fq2 = field_new!(Fq2, field_new!(Fq, "0"), field_new!(Fq, "240"));
+    const COEFF_B: Fq2 = field_new!(Fq2, field_new!(Fq, "1012"), field_new!(Fq, "1012"));
+    // E' is isogenous to E, so they have the same order.
+    const COFACTOR: &'static [u64] = <G2Parameters as SWModelParameters>::COFACTOR;
+    const COFACTOR_INV: Fr = <G2Parameters as SWModelParameters>::COFACTOR_INV;
+}
+
+impl SWUParams for G2SWUParameters {
+    const ZETA: Fq2 = field_new!(
+        Fq2,
+        field_new!(Fq, "4002409555221667393417789825735904156556882819939007885332058136124031650490837864442687629129015664037894272559785"),
+        field_new!(Fq, "4002409555221667393417789825735904156556882819939007885332058136124031650490837864442687629129015664037894272559786")
+    );
+}
+
 /// Represents the SWU hash-to-curve map defined by `P`.
 pub struct SWUMap<P: SWUParams> {
     curve_params: PhantomData<fn() -> P>,
@@ -449,6 +522,94 @@ impl WBParams for G1Parameters {
     ];
 }
 
+impl WBParams for G2Parameters {
+    type IsogenousCurve = G2SWUParameters;
+
+    const PHI_X_DEN: &'static [Fq2] = &[
+        field_new!(
+            Fq2,
+            field_new!(Fq, "0"),
+            field_new!(Fq, "4002409555221667393417789825735904156556882819939007885332058136124031650490837864442687629129015664037894272559715")
+        ),
+        field_new!(
+            Fq2,
+            field_new!(Fq, "12"),
+            field_new!(Fq, "4002409555221667393417789825735904156556882819939007885332058136124031650490837864442687629129015664037894272559775")
+        ),
+        field_new!(
+            Fq2,
+            field_new!(Fq, "1"),
+            field_new!(Fq, "0")
+        ),
+    ];
+    const PHI_X_NOM: &'static [Fq2] = &[
+        field_new!(
+            Fq2,
+            field_new!(Fq, "889424345604814976315064405719089812568196182208668418962679585805340366775741747653930584250892369786198727235542"),
+            field_new!(Fq, "889424345604814976315064405719089812568196182208668418962679585805340366775741747653930584250892369786198727235542")
+        ),
+        field_new!(
+            Fq2,
+            field_new!(Fq, "0"),
+            field_new!(Fq, "2668273036814444928945193217157269437704588546626005256888038757416021100327225242961791752752677109358596181706522")
+        ),
+        field_new!(
+            Fq2,
+            field_new!(Fq, "2668273036814444928945193217157269437704588546626005256888038757416021100327225242961791752752677109358596181706526"),
+            field_new!(Fq, "1334136518407222464472596608578634718852294273313002628444019378708010550163612621480895876376338554679298090853261")
+        ),
+        field_new!(
+            Fq2,
+            field_new!(Fq, "3557697382419259905260257622876359250272784728834673675850718343221361467102966990615722337003569479144794908942033"),
+            field_new!(Fq, "0")
+        ),
+    ];
+    const PHI_Y_DEN: &'static [Fq2] = &[
+        field_new!(
+            Fq2,
+            field_new!(Fq, "4002409555221667393417789825735904156556882819939007885332058136124031650490837864442687629129015664037894272559355"),
+            field_new!(Fq, "4002409555221667393417789825735904156556882819939007885332058136124031650490837864442687629129015664037894272559355")
+        ),
+        field_new!(
+            Fq2,
+            field_new!(Fq, "0"),
+            field_new!(Fq, "4002409555221667393417789825735904156556882819939007885332058136124031650490837864442687629129015664037894272559571")
+        ),
+        field_new!(
+            Fq2,
+            field_new!(Fq, "18"),
+            field_new!(Fq, "4002409555221667393417789825735904156556882819939007885332058136124031650490837864442687629129015664037894272559769")
+        ),
+        field_new!(
+            Fq2,
+            field_new!(Fq, "1"),
+            field_new!(Fq, "0")
+        ),
+    ];
+    const PHI_Y_NOM: &'static [Fq2] = &[
+        field_new!(
+            Fq2,
+            field_new!(Fq, "3261222600550988246488569487636662646083386001431784202863158481286248011511053074731078808919938689216061999863558"),
+            field_new!(Fq, "3261222600550988246488569487636662646083386001431784202863158481286248011511053074731078808919938689216061999863558")
+        ),
+        field_new!(
+            Fq2,
+            field_new!(Fq, "0"),
+            field_new!(Fq, "889424345604814976315064405719089812568196182208668418962679585805340366775741747653930584250892369786198727235518")
+        ),
+        field_new!(
+            Fq2,
+            field_new!(Fq, "2668273036814444928945193217157269437704588546626005256888038757416021100327225242961791752752677109358596181706524"),
+            field_new!(Fq, "1334136518407222464472596608578634718852294273313002628444019378708010550163612621480895876376338554679298090853263")
+        ),
+        field_new!(
+            Fq2,
+            field_new!(Fq, "2816510427748580758331037284777117739799287910327449993381818688383577828123182200904113516794492504322962636245776"),
+            field_new!(Fq, "0")
+        ),
+    ];
+}
+
 pub struct WBMap<P: WBParams> {
     swu_field_curve_hasher: SWUMap<P::IsogenousCurve>,
     curve_params: PhantomData<fn() -> P>,
@@ -9,10 +9,10 @@ mod tests {
             hash_to_curve::{HashToCurve, MapToCurveBasedHasher, WBMap},
             hash_to_field::DefaultFieldHasher,
         },
-        group::G1,
+        group::{G1, G2},
     };
-    use ark_bls12_381::g1::Parameters as G1Parameters;
-    use blst::{blst_hash_to_g1, blst_p1};
+    use ark_bls12_381::{g1::Parameters as G1Parameters, g2::Parameters as G2Parameters};
+    use blst::{blst_hash_to_g1, blst_hash_to_g2, blst_p1, blst_p2};
     use proptest::proptest;
     use sha2::Sha256;
 
@@ -45,4 +45,34 @@ mod tests {
             assert_eq!(g1_ark, g1_blst);
         });
     }
+
+    #[test]
+    fn test_g2() {
+        proptest!(|(msg in ".*")| {
+            let suite = b"BLS_SIG_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_";
+            let msg = msg.as_bytes();
+            let g2_mapper = MapToCurveBasedHasher::<
+                G2Parameters,
+                DefaultFieldHasher<Sha256, 128>,
+                WBMap<G2Parameters>,
+            >::new(suite)
+            .unwrap();
+
+            let g2_ark = G2::from(g2_mapper.hash(msg).unwrap());
+            let g2_blst = unsafe {
+                let mut out = blst_p2::default();
+                blst_hash_to_g2(
+                    &mut out,
+                    msg.as_ptr(),
+                    msg.len(),
+                    suite.as_ptr(),
+                    suite.len(),
+                    [0_u8; 0].as_ptr(),
+                    0,
+                );
+                G2::try_from(out).unwrap()
+            };
+            assert_eq!(g2_ark, g2_blst);
+        });
+    }
 }
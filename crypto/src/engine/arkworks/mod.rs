@@ -8,7 +8,7 @@ mod hashing;
 mod zcash_format;
 
 use self::endomorphism::{g1_mul_glv, g1_subgroup_check, g2_subgroup_check};
-use super::Engine;
+use super::{Engine, EngineCapabilities, SignedMessage, CHUNKED_TAU_THRESHOLD, MSM_CHUNK_SIZE};
 use crate::{
     engine::arkworks::hashing::{
         hash_to_curve::{HashToCurve, MapToCurveBasedHasher, WBMap},
@@ -17,12 +17,13 @@ use crate::{
     CeremonyError, Entropy, ParseError, Tau, F, G1, G2,
 };
 use ark_bls12_381::{
-    g1::Parameters as G1Parameters, Bls12_381, Fr, G1Affine, G1Projective, G2Affine, G2Projective,
+    g1::Parameters as G1Parameters, g2::Parameters as G2Parameters, Bls12_381, Fr, G1Affine,
+    G1Projective, G2Affine, G2Projective,
 };
 use ark_ec::{
     msm::VariableBaseMSM, wnaf::WnafContext, AffineCurve, PairingEngine, ProjectiveCurve,
 };
-use ark_ff::{BigInteger, One, PrimeField, UniformRand, Zero};
+use ark_ff::{BigInteger, Field, One, PrimeField, UniformRand, Zero};
 use digest::Digest;
 use hkdf::Hkdf;
 use rand::{Rng, SeedableRng};
@@ -38,10 +39,22 @@ use tracing::instrument;
 pub struct Arkworks;
 
 impl Engine for Arkworks {
+    fn capabilities() -> EngineCapabilities {
+        EngineCapabilities {
+            parallel_validation: true,
+            batch_verification: true,
+            fixed_base_tables: false,
+            gpu: false,
+        }
+    }
+
     #[instrument(level = "info", skip_all, fields(n=points.len()))]
     fn validate_g1(points: &[G1]) -> Result<(), CeremonyError> {
         points.into_par_iter().enumerate().try_for_each(|(i, p)| {
             let p = G1Affine::try_from(*p).map_err(|e| CeremonyError::InvalidG1Power(i, e))?;
+            if p.infinity {
+                return Err(CeremonyError::ZeroG1(i));
+            }
             if !g1_subgroup_check(&p) {
                 return Err(CeremonyError::InvalidG1Power(
                     i,
@@ -56,6 +69,9 @@ impl Engine for Arkworks {
     fn validate_g2(points: &[G2]) -> Result<(), CeremonyError> {
         points.into_par_iter().enumerate().try_for_each(|(i, p)| {
             let p = G2Affine::try_from(*p).map_err(|e| CeremonyError::InvalidG2Power(i, e))?;
+            if p.infinity {
+                return Err(CeremonyError::ZeroG2(i));
+            }
             if !g2_subgroup_check(&p) {
                 return Err(CeremonyError::InvalidG2Power(
                     i,
@@ -151,22 +167,13 @@ impl Engine for Arkworks {
 
     #[instrument(level = "info", skip_all, fields(n=powers.len()))]
     fn add_tau_g1(tau: &Tau, powers: &mut [G1]) -> Result<(), CeremonyError> {
-        let taus = powers_of_tau(tau, powers.len());
-        let mut projective = powers
-            .par_iter()
-            .zip(taus.expose_secret())
-            .map(|(p, tau)| G1Affine::try_from(*p).map(|p| g1_mul_glv(&p, *tau)))
-            .collect::<Result<Vec<_>, _>>()?;
-        G1Projective::batch_normalization(&mut projective);
-        for (p, a) in powers.iter_mut().zip(projective) {
-            *p = a.into_affine().into();
-        }
-        Ok(())
+        let taus = powers_of_tau_for(tau, powers.len());
+        mul_g1(powers, taus.expose_secret())
     }
 
     #[instrument(level = "info", skip_all, fields(n=powers.len()))]
     fn add_tau_g2(tau: &Tau, powers: &mut [G2]) -> Result<(), CeremonyError> {
-        let taus = powers_of_tau(tau, powers.len());
+        let taus = powers_of_tau_for(tau, powers.len());
         let mut projective = powers
             .par_iter()
             .zip(taus.expose_secret())
@@ -184,19 +191,25 @@ impl Engine for Arkworks {
         Ok(())
     }
 
-    fn sign_message(tau: &Tau, message: &[u8]) -> Option<G1> {
+    #[instrument(level = "info", skip_all, fields(n=powers.len()))]
+    fn msm_update(tau: &Tau, powers: &mut [G1]) -> Result<(), CeremonyError> {
+        let taus = powers_of_tau_chunked(tau, powers.len());
+        mul_g1(powers, taus.expose_secret())
+    }
+
+    fn sign_message_with_dst(tau: &Tau, message: &[u8], dst: &[u8]) -> Option<G1> {
         let mapper = MapToCurveBasedHasher::<
             G1Parameters,
             DefaultFieldHasher<Sha256, 128>,
             WBMap<G1Parameters>,
-        >::new(Self::CYPHER_SUITE.as_bytes())
+        >::new(dst)
         .ok()?;
         let point = mapper.hash(message).ok()?;
         let sig = point.mul(Fr::from(tau.expose_secret())).into_affine();
         Some(G1::from(sig))
     }
 
-    fn verify_signature(sig: G1, message: &[u8], pk: G2) -> bool {
+    fn verify_signature_with_dst(sig: G1, message: &[u8], pk: G2, dst: &[u8]) -> bool {
         let sig = match G1Affine::try_from(sig) {
             Ok(sig) => sig,
             _ => return false,
@@ -219,7 +232,7 @@ impl Engine for Arkworks {
             G1Parameters,
             DefaultFieldHasher<Sha256, 128>,
             WBMap<G1Parameters>,
-        >::new(Self::CYPHER_SUITE.as_bytes())
+        >::new(dst)
         {
             Ok(mapper) => mapper,
             _ => return false,
@@ -235,6 +248,97 @@ impl Engine for Arkworks {
 
         c1 == c2
     }
+
+    fn sign_message_g2_with_dst(tau: &Tau, message: &[u8], dst: &[u8]) -> Option<G2> {
+        let mapper = MapToCurveBasedHasher::<
+            G2Parameters,
+            DefaultFieldHasher<Sha256, 128>,
+            WBMap<G2Parameters>,
+        >::new(dst)
+        .ok()?;
+        let point = mapper.hash(message).ok()?;
+        let sig = point.mul(Fr::from(tau.expose_secret())).into_affine();
+        Some(G2::from(sig))
+    }
+
+    fn verify_signature_g2_with_dst(sig: G2, message: &[u8], pk: G1, dst: &[u8]) -> bool {
+        let sig = match G2Affine::try_from(sig) {
+            Ok(sig) => sig,
+            _ => return false,
+        };
+        if !g2_subgroup_check(&sig) {
+            return false;
+        }
+        let pk = match G1Affine::try_from(pk) {
+            Ok(pk) => pk,
+            _ => return false,
+        };
+        if !g1_subgroup_check(&pk) {
+            return false;
+        }
+        if pk.is_zero() {
+            // Rejecting infinite public keys to match BLST behavior
+            return false;
+        }
+        let mapper = match MapToCurveBasedHasher::<
+            G2Parameters,
+            DefaultFieldHasher<Sha256, 128>,
+            WBMap<G2Parameters>,
+        >::new(dst)
+        {
+            Ok(mapper) => mapper,
+            _ => return false,
+        };
+
+        let msg = match mapper.hash(message) {
+            Ok(msg) => msg,
+            _ => return false,
+        };
+
+        let c1 = Bls12_381::pairing(pk, msg);
+        let c2 = Bls12_381::pairing(G1Affine::prime_subgroup_generator(), sig);
+
+        c1 == c2
+    }
+
+    fn verify_signature_batch(signatures: &[SignedMessage]) -> bool {
+        let Ok(mapper) = MapToCurveBasedHasher::<
+            G1Parameters,
+            DefaultFieldHasher<Sha256, 128>,
+            WBMap<G1Parameters>,
+        >::new(Self::CYPHER_SUITE.as_bytes()) else {
+            return false;
+        };
+
+        // Checks e(Σ rᵢ⋅H(mᵢ), pkᵢ) = e(Σ rᵢ⋅sigᵢ, g₂) with random weights rᵢ.
+        let mut rng = rand::thread_rng();
+        let mut sig_sum = G1Projective::zero();
+        let mut pairs: Vec<(
+            <Bls12_381 as PairingEngine>::G1Prepared,
+            <Bls12_381 as PairingEngine>::G2Prepared,
+        )> = Vec::with_capacity(signatures.len() + 1);
+        for &(sig, message, pk) in signatures {
+            let (Ok(sig), Ok(pk), Ok(msg)) = (
+                G1Affine::try_from(sig),
+                G2Affine::try_from(pk),
+                mapper.hash(message),
+            ) else {
+                return false;
+            };
+            if !g1_subgroup_check(&sig) || !g2_subgroup_check(&pk) || pk.is_zero() {
+                return false;
+            }
+            let weight = Fr::from(rng.gen::<u64>());
+            sig_sum += sig.mul(weight);
+            pairs.push((msg.mul(weight).into_affine().into(), pk.into()));
+        }
+        pairs.push((
+            (-sig_sum).into_affine().into(),
+            G2Affine::prime_subgroup_generator().into(),
+        ));
+
+        Bls12_381::product_of_pairings(&pairs).is_one()
+    }
 }
 
 // Implementation of the KeyGen function as specified in
@@ -277,6 +381,47 @@ pub fn powers_of_tau(tau: &Tau, n: usize) -> SecretVec<Fr> {
     )
 }
 
+/// Computes the same values as [`powers_of_tau`], but splits the sequence in
+/// chunks of [`MSM_CHUNK_SIZE`] that are derived in parallel.
+pub fn powers_of_tau_chunked(tau: &Tau, n: usize) -> SecretVec<Fr> {
+    let tau = Secret::new(Fr::from(tau.expose_secret()));
+    let num_chunks = (n + MSM_CHUNK_SIZE - 1) / MSM_CHUNK_SIZE;
+    Secret::new(
+        (0..num_chunks)
+            .into_par_iter()
+            .flat_map_iter(|chunk| {
+                let start = tau.expose_secret().pow([(chunk * MSM_CHUNK_SIZE) as u64]);
+                iter::successors(Some(start), |x| Some(*x * tau.expose_secret()))
+                    .take(MSM_CHUNK_SIZE.min(n - chunk * MSM_CHUNK_SIZE))
+            })
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// The first `n` powers of $τ$, derived in chunks once `n` exceeds
+/// [`CHUNKED_TAU_THRESHOLD`].
+fn powers_of_tau_for(tau: &Tau, n: usize) -> SecretVec<Fr> {
+    if n > CHUNKED_TAU_THRESHOLD {
+        powers_of_tau_chunked(tau, n)
+    } else {
+        powers_of_tau(tau, n)
+    }
+}
+
+/// Multiplies each point in `powers` by the corresponding scalar in `taus`.
+fn mul_g1(powers: &mut [G1], taus: &[Fr]) -> Result<(), CeremonyError> {
+    let mut projective = powers
+        .par_iter()
+        .zip(taus)
+        .map(|(p, tau)| G1Affine::try_from(*p).map(|p| g1_mul_glv(&p, *tau)))
+        .collect::<Result<Vec<_>, _>>()?;
+    G1Projective::batch_normalization(&mut projective);
+    for (p, a) in powers.iter_mut().zip(projective) {
+        *p = a.into_affine().into();
+    }
+    Ok(())
+}
+
 fn random_factors(n: usize) -> (Vec<<Fr as PrimeField>::BigInt>, Fr) {
     let mut rng = rand::thread_rng();
     let mut sum = Fr::zero();
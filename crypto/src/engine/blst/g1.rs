@@ -1,8 +1,9 @@
 use crate::{ParseError, G1};
 use blst::{
-    blst_p1, blst_p1_affine, blst_p1_affine_compress, blst_p1_affine_in_g1, blst_p1_from_affine,
-    blst_p1_mult, blst_p1_to_affine, blst_p1_uncompress, blst_p1s_mult_pippenger,
-    blst_p1s_mult_pippenger_scratch_sizeof, blst_p1s_to_affine, blst_scalar, limb_t, BLST_ERROR,
+    blst_p1, blst_p1_affine, blst_p1_affine_compress, blst_p1_affine_in_g1, blst_p1_affine_is_inf,
+    blst_p1_from_affine, blst_p1_mult, blst_p1_to_affine, blst_p1_uncompress,
+    blst_p1s_mult_pippenger, blst_p1s_mult_pippenger_scratch_sizeof, blst_p1s_to_affine,
+    blst_scalar, limb_t, BLST_ERROR,
 };
 use std::{mem::size_of, ptr};
 
@@ -70,6 +71,10 @@ pub fn p1_affine_in_g1(p: &blst_p1_affine) -> bool {
     unsafe { blst_p1_affine_in_g1(p) }
 }
 
+pub fn p1_affine_is_inf(p: &blst_p1_affine) -> bool {
+    unsafe { blst_p1_affine_is_inf(p) }
+}
+
 pub fn p1s_to_affine(ps: &[blst_p1]) -> Vec<blst_p1_affine> {
     let input = ps.iter().map(|x| x as *const blst_p1).collect::<Vec<_>>();
     let mut out = Vec::<blst_p1_affine>::with_capacity(ps.len());
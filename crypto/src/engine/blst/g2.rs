@@ -1,8 +1,9 @@
 use crate::{ParseError, G2};
 use blst::{
-    blst_p2, blst_p2_affine, blst_p2_affine_compress, blst_p2_affine_in_g2, blst_p2_from_affine,
-    blst_p2_mult, blst_p2_to_affine, blst_p2_uncompress, blst_p2s_mult_pippenger,
-    blst_p2s_mult_pippenger_scratch_sizeof, blst_p2s_to_affine, blst_scalar, limb_t, BLST_ERROR,
+    blst_p2, blst_p2_affine, blst_p2_affine_compress, blst_p2_affine_in_g2, blst_p2_affine_is_inf,
+    blst_p2_from_affine, blst_p2_mult, blst_p2_to_affine, blst_p2_uncompress,
+    blst_p2s_mult_pippenger, blst_p2s_mult_pippenger_scratch_sizeof, blst_p2s_to_affine,
+    blst_scalar, limb_t, BLST_ERROR,
 };
 use std::{mem::size_of, ptr};
 
@@ -30,6 +31,14 @@ impl TryFrom<blst_p2_affine> for G2 {
         }
     }
 }
+
+impl TryFrom<blst_p2> for G2 {
+    type Error = ParseError;
+
+    fn try_from(g2: blst_p2) -> Result<Self, Self::Error> {
+        Self::try_from(p2_to_affine(&g2))
+    }
+}
 pub fn p2_from_affine(a: &blst_p2_affine) -> blst_p2 {
     unsafe {
         let mut p = blst_p2::default();
@@ -58,6 +67,10 @@ pub fn p2_affine_in_g2(p: &blst_p2_affine) -> bool {
     unsafe { blst_p2_affine_in_g2(p) }
 }
 
+pub fn p2_affine_is_inf(p: &blst_p2_affine) -> bool {
+    unsafe { blst_p2_affine_is_inf(p) }
+}
+
 pub fn p2s_to_affine(ps: &[blst_p2]) -> Vec<blst_p2_affine> {
     let input = ps.iter().map(|x| x as *const blst_p2).collect::<Vec<_>>();
     let mut out = Vec::<blst_p2_affine>::with_capacity(ps.len());
@@ -3,19 +3,27 @@ mod g2;
 mod scalar;
 
 use self::{
-    g1::{p1_affine_in_g1, p1_from_affine, p1_mult, p1s_mult_pippenger, p1s_to_affine},
-    g2::{p2_affine_in_g2, p2_from_affine, p2_mult, p2_to_affine, p2s_to_affine},
-    scalar::{fr_from_scalar, fr_mul, fr_one, random_fr, scalar_from_fr},
+    g1::{
+        p1_affine_in_g1, p1_affine_is_inf, p1_from_affine, p1_mult, p1s_mult_pippenger,
+        p1s_to_affine,
+    },
+    g2::{p2_affine_in_g2, p2_affine_is_inf, p2_from_affine, p2_mult, p2_to_affine, p2s_to_affine},
+    scalar::{fr_from_scalar, fr_mul, fr_one, fr_pow, random_fr, scalar_from_fr},
 };
 use crate::{
-    engine::blst::{g1::p1_to_affine, g2::p2s_mult_pippenger, scalar::Scalar},
-    CeremonyError, Engine, Entropy, ParseError, Tau, G1, G2,
+    engine::{
+        blst::{g1::p1_to_affine, g2::p2s_mult_pippenger, scalar::Scalar},
+        SignedMessage, CHUNKED_TAU_THRESHOLD, MSM_CHUNK_SIZE,
+    },
+    CeremonyError, Engine, EngineCapabilities, Entropy, ParseError, Tau, G1, G2,
 };
 use blst::{
-    blst_core_verify_pk_in_g2, blst_final_exp, blst_fp12, blst_fr, blst_fr_add, blst_hash_to_g1,
-    blst_miller_loop, blst_p1, blst_p1_affine, blst_p1_generator, blst_p2_affine,
-    blst_p2_affine_generator, blst_p2_generator, blst_scalar, blst_scalar_from_le_bytes,
-    blst_sign_pk_in_g2, BLST_ERROR,
+    blst_core_verify_pk_in_g1, blst_core_verify_pk_in_g2, blst_final_exp, blst_fp12, blst_fr,
+    blst_fr_add, blst_hash_to_g1, blst_hash_to_g2, blst_miller_loop, blst_p1, blst_p1_affine,
+    blst_p1_generator, blst_p2, blst_p2_affine, blst_p2_affine_generator, blst_p2_generator,
+    blst_scalar, blst_scalar_from_le_bytes, blst_sign_pk_in_g1, blst_sign_pk_in_g2,
+    min_sig::{PublicKey, Signature},
+    BLST_ERROR,
 };
 use rand::Rng;
 use rayon::prelude::{
@@ -28,37 +36,32 @@ use std::iter;
 pub struct BLST;
 
 impl Engine for BLST {
+    fn capabilities() -> EngineCapabilities {
+        EngineCapabilities {
+            parallel_validation: true,
+            batch_verification: true,
+            fixed_base_tables: false,
+            gpu: false,
+        }
+    }
+
     fn generate_tau(entropy: &Entropy) -> Tau {
         let fr = random_fr(*entropy.expose_secret());
         Secret::new((&fr).into())
     }
 
     fn add_tau_g1(tau: &Tau, powers: &mut [G1]) -> Result<(), CeremonyError> {
-        let taus = powers_of_tau(tau, powers.len());
-
-        let powers_projective = powers
-            .par_iter()
-            .zip(taus.expose_secret())
-            .map(|(&p, tau)| {
-                let p = blst_p1_affine::try_from(p)?;
-                let p = p1_from_affine(&p);
-                Ok(p1_mult(&p, tau))
-            })
-            .collect::<Result<Vec<_>, ParseError>>()?;
-
-        let powers_affine = p1s_to_affine(&powers_projective);
+        let taus = powers_of_tau_for(tau, powers.len());
+        mul_g1(powers, taus.expose_secret())
+    }
 
-        powers
-            .par_iter_mut()
-            .zip(powers_affine)
-            .try_for_each(|(p, p_affine)| {
-                *p = G1::try_from(p_affine)?;
-                Ok(())
-            })
+    fn msm_update(tau: &Tau, powers: &mut [G1]) -> Result<(), CeremonyError> {
+        let taus = powers_of_tau_chunked(tau, powers.len());
+        mul_g1(powers, taus.expose_secret())
     }
 
     fn add_tau_g2(tau: &Tau, powers: &mut [crate::G2]) -> Result<(), crate::CeremonyError> {
-        let taus = powers_of_tau(tau, powers.len());
+        let taus = powers_of_tau_for(tau, powers.len());
 
         let powers_projective = powers
             .par_iter()
@@ -84,6 +87,9 @@ impl Engine for BLST {
     fn validate_g1(points: &[crate::G1]) -> Result<(), crate::CeremonyError> {
         points.into_par_iter().enumerate().try_for_each(|(i, &p)| {
             let p = blst_p1_affine::try_from(p)?;
+            if p1_affine_is_inf(&p) {
+                return Err(CeremonyError::ZeroG1(i));
+            }
             if !p1_affine_in_g1(&p) {
                 return Err(CeremonyError::InvalidG1Power(
                     i,
@@ -97,6 +103,9 @@ impl Engine for BLST {
     fn validate_g2(points: &[crate::G2]) -> Result<(), crate::CeremonyError> {
         points.into_par_iter().enumerate().try_for_each(|(i, &p)| {
             let p = blst_p2_affine::try_from(p)?;
+            if p2_affine_is_inf(&p) {
+                return Err(CeremonyError::ZeroG2(i));
+            }
             if !p2_affine_in_g2(&p) {
                 return Err(CeremonyError::InvalidG2Power(
                     i,
@@ -185,7 +194,7 @@ impl Engine for BLST {
         Ok(())
     }
 
-    fn sign_message(tau: &Tau, message: &[u8]) -> Option<G1> {
+    fn sign_message_with_dst(tau: &Tau, message: &[u8], dst: &[u8]) -> Option<G1> {
         let mut hash = blst_p1::default();
         let mut sig = blst_p1::default();
         let sk = blst_scalar::from(tau.expose_secret());
@@ -194,8 +203,8 @@ impl Engine for BLST {
                 &mut hash,
                 message.as_ptr(),
                 message.len(),
-                Self::CYPHER_SUITE.as_ptr(),
-                Self::CYPHER_SUITE.len(),
+                dst.as_ptr(),
+                dst.len(),
                 [0; 0].as_ptr(),
                 0,
             );
@@ -204,7 +213,7 @@ impl Engine for BLST {
         G1::try_from(sig).ok()
     }
 
-    fn verify_signature(sig: G1, message: &[u8], pk: G2) -> bool {
+    fn verify_signature_with_dst(sig: G1, message: &[u8], pk: G2, dst: &[u8]) -> bool {
         let blst_pk = match blst_p2_affine::try_from(pk).ok() {
             Some(pk) => pk,
             _ => return false,
@@ -220,16 +229,104 @@ impl Engine for BLST {
                 true,
                 message.as_ptr(),
                 message.len(),
-                Self::CYPHER_SUITE.as_ptr(),
-                Self::CYPHER_SUITE.len(),
+                dst.as_ptr(),
+                dst.len(),
+                [0; 0].as_ptr(),
+                0,
+            )
+        };
+        result == BLST_ERROR::BLST_SUCCESS
+    }
+
+    fn sign_message_g2_with_dst(tau: &Tau, message: &[u8], dst: &[u8]) -> Option<G2> {
+        let mut hash = blst_p2::default();
+        let mut sig = blst_p2::default();
+        let sk = blst_scalar::from(tau.expose_secret());
+        unsafe {
+            blst_hash_to_g2(
+                &mut hash,
+                message.as_ptr(),
+                message.len(),
+                dst.as_ptr(),
+                dst.len(),
+                [0; 0].as_ptr(),
+                0,
+            );
+            blst_sign_pk_in_g1(&mut sig, &hash, &sk);
+        }
+        G2::try_from(sig).ok()
+    }
+
+    fn verify_signature_g2_with_dst(sig: G2, message: &[u8], pk: G1, dst: &[u8]) -> bool {
+        let blst_pk = match blst_p1_affine::try_from(pk).ok() {
+            Some(pk) => pk,
+            _ => return false,
+        };
+        let blst_sig = match blst_p2_affine::try_from(sig).ok() {
+            Some(sig) => sig,
+            _ => return false,
+        };
+        let result = unsafe {
+            blst_core_verify_pk_in_g1(
+                &blst_pk,
+                &blst_sig,
+                true,
+                message.as_ptr(),
+                message.len(),
+                dst.as_ptr(),
+                dst.len(),
                 [0; 0].as_ptr(),
                 0,
             )
         };
         result == BLST_ERROR::BLST_SUCCESS
     }
+
+    fn verify_signature_batch(signatures: &[SignedMessage]) -> bool {
+        let parsed = signatures
+            .iter()
+            .map(|(sig, _, pk)| {
+                Some((
+                    Signature::from_bytes(&sig.0).ok()?,
+                    PublicKey::from_bytes(&pk.0).ok()?,
+                ))
+            })
+            .collect::<Option<Vec<_>>>();
+        let Some(parsed) = parsed else {
+            return false;
+        };
+        let messages = signatures
+            .iter()
+            .map(|(_, message, _)| *message)
+            .collect::<Vec<_>>();
+        let sigs = parsed.iter().map(|(sig, _)| sig).collect::<Vec<_>>();
+        let pks = parsed.iter().map(|(_, pk)| pk).collect::<Vec<_>>();
+        let mut rng = rand::thread_rng();
+        let rands = signatures
+            .iter()
+            .map(|_| {
+                let mut b = [0; 32];
+                rng.fill(&mut b[..SIGNATURE_RAND_BITS / 8]);
+                blst_scalar { b }
+            })
+            .collect::<Vec<_>>();
+        let result = Signature::verify_multiple_aggregate_signatures(
+            &messages,
+            Self::CYPHER_SUITE.as_bytes(),
+            &pks,
+            true,
+            &sigs,
+            true,
+            &rands,
+            SIGNATURE_RAND_BITS,
+        );
+        result == BLST_ERROR::BLST_SUCCESS
+    }
 }
 
+/// Size of the random weights in [`BLST::verify_signature_batch`].
+const SIGNATURE_RAND_BITS: usize = 64;
+
 fn pairing(p: &blst_p1_affine, q: &blst_p2_affine) -> blst_fp12 {
     let mut tmp = blst_fp12::default();
     unsafe { blst_miller_loop(&mut tmp, q, p) };
@@ -249,6 +346,56 @@ fn powers_of_tau(tau: &Tau, n: usize) -> SecretVec<Scalar> {
     SecretVec::new(vec)
 }
 
+/// Computes the same values as [`powers_of_tau`], but splits the sequence in
+/// chunks of [`MSM_CHUNK_SIZE`] that are derived in parallel.
+fn powers_of_tau_chunked(tau: &Tau, n: usize) -> SecretVec<Scalar> {
+    let tau = tau.expose_secret().into();
+    let num_chunks = (n + MSM_CHUNK_SIZE - 1) / MSM_CHUNK_SIZE;
+    let vec = (0..num_chunks)
+        .into_par_iter()
+        .flat_map_iter(|chunk| {
+            let start = fr_pow(&tau, (chunk * MSM_CHUNK_SIZE) as u64);
+            iter::successors(Some(start), move |x| Some(fr_mul(x, &tau)))
+                .map(|n| Scalar::from(scalar_from_fr(&n)))
+                .take(MSM_CHUNK_SIZE.min(n - chunk * MSM_CHUNK_SIZE))
+        })
+        .collect();
+    SecretVec::new(vec)
+}
+
+/// The first `n` powers of $τ$, derived in chunks once `n` exceeds
+/// [`CHUNKED_TAU_THRESHOLD`].
+fn powers_of_tau_for(tau: &Tau, n: usize) -> SecretVec<Scalar> {
+    if n > CHUNKED_TAU_THRESHOLD {
+        powers_of_tau_chunked(tau, n)
+    } else {
+        powers_of_tau(tau, n)
+    }
+}
+
+/// Multiplies each point in `powers` by the corresponding scalar in `taus`.
+fn mul_g1(powers: &mut [G1], taus: &[Scalar]) -> Result<(), CeremonyError> {
+    let powers_projective = powers
+        .par_iter()
+        .zip(taus)
+        .map(|(&p, tau)| {
+            let p = blst_p1_affine::try_from(p)?;
+            let p = p1_from_affine(&p);
+            Ok(p1_mult(&p, tau))
+        })
+        .collect::<Result<Vec<_>, ParseError>>()?;
+
+    let powers_affine = p1s_to_affine(&powers_projective);
+
+    powers
+        .par_iter_mut()
+        .zip(powers_affine)
+        .try_for_each(|(p, p_affine)| {
+            *p = G1::try_from(p_affine)?;
+            Ok(())
+        })
+}
+
 fn random_factors(n: usize) -> (Vec<blst_scalar>, blst_scalar) {
     let mut rng = rand::thread_rng();
     let mut entropy = [0u8; 32];
@@ -50,6 +50,20 @@ pub fn fr_mul(a: &blst_fr, b: &blst_fr) -> blst_fr {
     out
 }
 
+/// Computes `base` raised to `exp` by square-and-multiply.
+pub fn fr_pow(base: &blst_fr, mut exp: u64) -> blst_fr {
+    let mut result = fr_one();
+    let mut square = *base;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = fr_mul(&result, &square);
+        }
+        square = fr_mul(&square, &square);
+        exp >>= 1;
+    }
+    result
+}
+
 #[allow(dead_code)] // Currently only used in tests
 pub fn fr_zero() -> blst_fr {
     fr_from_scalar(&scalar_from_u64(0u64))
@@ -1,4 +1,4 @@
-use super::Engine;
+use super::{Engine, EngineCapabilities, SignedMessage};
 use crate::{CeremonyError, Entropy, Tau, G1, G2};
 use rayon::join;
 use secrecy::ExposeSecret;
@@ -12,6 +12,10 @@ pub struct Both<A: Engine, B: Engine> {
 }
 
 impl<A: Engine, B: Engine> Engine for Both<A, B> {
+    fn capabilities() -> EngineCapabilities {
+        A::capabilities().intersect(B::capabilities())
+    }
+
     fn validate_g1(points: &[G1]) -> Result<(), CeremonyError> {
         let (a, b) = join(|| A::validate_g1(points), || B::validate_g1(points));
         a?;
@@ -74,19 +78,55 @@ impl<A: Engine, B: Engine> Engine for Both<A, B> {
         Ok(())
     }
 
-    fn sign_message(tau: &Tau, message: &[u8]) -> Option<G1> {
+    fn msm_update(tau: &Tau, powers: &mut [G1]) -> Result<(), CeremonyError> {
+        let mut b = powers.to_vec();
+        let (ra, rb) = join(|| A::msm_update(tau, powers), || B::msm_update(tau, &mut b));
+        ra?;
+        rb?;
+        assert_eq!(powers, &b[..]);
+        Ok(())
+    }
+
+    fn sign_message_with_dst(tau: &Tau, message: &[u8], dst: &[u8]) -> Option<G1> {
+        let (a, b) = join(
+            || A::sign_message_with_dst(tau, message, dst),
+            || B::sign_message_with_dst(tau, message, dst),
+        );
+        assert_eq!(a, b);
+        a
+    }
+
+    fn verify_signature_with_dst(sig: G1, message: &[u8], pk: G2, dst: &[u8]) -> bool {
+        let (a, b) = join(
+            || A::verify_signature_with_dst(sig, message, pk, dst),
+            || B::verify_signature_with_dst(sig, message, pk, dst),
+        );
+        assert_eq!(a, b);
+        a
+    }
+
+    fn sign_message_g2_with_dst(tau: &Tau, message: &[u8], dst: &[u8]) -> Option<G2> {
+        let (a, b) = join(
+            || A::sign_message_g2_with_dst(tau, message, dst),
+            || B::sign_message_g2_with_dst(tau, message, dst),
+        );
+        assert_eq!(a, b);
+        a
+    }
+
+    fn verify_signature_g2_with_dst(sig: G2, message: &[u8], pk: G1, dst: &[u8]) -> bool {
         let (a, b) = join(
-            || A::sign_message(tau, message),
-            || B::sign_message(tau, message),
+            || A::verify_signature_g2_with_dst(sig, message, pk, dst),
+            || B::verify_signature_g2_with_dst(sig, message, pk, dst),
         );
         assert_eq!(a, b);
         a
     }
 
-    fn verify_signature(sig: G1, message: &[u8], pk: G2) -> bool {
+    fn verify_signature_batch(signatures: &[SignedMessage]) -> bool {
         let (a, b) = join(
-            || A::verify_signature(sig, message, pk),
-            || B::verify_signature(sig, message, pk),
+            || A::verify_signature_batch(signatures),
+            || B::verify_signature_batch(signatures),
         );
         assert_eq!(a, b);
         a
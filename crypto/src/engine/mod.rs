@@ -11,8 +11,14 @@ mod arkworks;
 mod blst;
 mod both;
 
-use crate::{CeremonyError, F, G1, G2};
+use crate::{
+    beacon::{BeaconError, BeaconRound, BeaconSource, DrandBeacon, DRAND_DST},
+    CeremonyError, F, G1, G2,
+};
+use rand::{CryptoRng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
 pub use secrecy::Secret;
+use zeroize::Zeroizing;
 
 #[cfg(feature = "arkworks")]
 pub use self::arkworks::Arkworks;
@@ -23,29 +29,139 @@ pub use self::both::Both;
 pub type Entropy = Secret<[u8; 32]>;
 pub type Tau = Secret<F>;
 
+/// The operating system's RNG, the default source of entropy.
+pub type OsEntropy = rand::rngs::OsRng;
+
+/// Constructors for [`Entropy`].
+///
+/// Contributions should use [`EntropySource::random`], or
+/// [`EntropySource::from_source`] to draw from a hardware RNG or HSM. Such
+/// sources plug in by implementing [`RngCore`] and [`CryptoRng`]. The seeded
+/// and RNG-injected variants exist so that tests can reproduce exact taus and
+/// pot pubkeys.
+pub trait EntropySource: Sized {
+    /// Draws entropy from the provided cryptographically secure RNG.
+    fn from_rng<R: RngCore + CryptoRng>(rng: &mut R) -> Self;
+
+    /// Reads 256 bits from `source`. The read buffer is zeroized.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CeremonyError::InsufficientEntropy`] if the source fails
+    /// before providing 256 bits.
+    fn from_source<S: RngCore + CryptoRng>(source: &mut S) -> Result<Self, CeremonyError>;
+
+    /// Deterministic entropy derived from a fixed seed.
+    #[must_use]
+    fn from_seed(seed: [u8; 32]) -> Self {
+        Self::from_rng(&mut ChaCha20Rng::from_seed(seed))
+    }
+
+    /// Entropy from [`OsEntropy`].
+    #[must_use]
+    fn random() -> Self {
+        Self::from_rng(&mut OsEntropy::default())
+    }
+}
+
+impl EntropySource for Entropy {
+    fn from_rng<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+        let mut bytes = Zeroizing::new([0_u8; 32]);
+        rng.fill_bytes(&mut *bytes);
+        Secret::new(*bytes)
+    }
+
+    fn from_source<S: RngCore + CryptoRng>(source: &mut S) -> Result<Self, CeremonyError> {
+        let mut bytes = Zeroizing::new([0_u8; 32]);
+        source
+            .try_fill_bytes(&mut *bytes)
+            .map_err(|_| CeremonyError::InsufficientEntropy)?;
+        Ok(Secret::new(*bytes))
+    }
+}
+
+/// Optional features of an [`Engine`], so callers can pick a code path at
+/// runtime.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct EngineCapabilities {
+    /// Point validation is spread over multiple threads.
+    pub parallel_validation: bool,
+    /// Sequences of powers are verified with a single random linear
+    /// combination instead of a pairing per power.
+    pub batch_verification: bool,
+    /// Scalar multiplications use precomputed fixed-base tables.
+    pub fixed_base_tables: bool,
+    /// Work is offloaded to a GPU.
+    pub gpu: bool,
+}
+
+impl EngineCapabilities {
+    /// Capabilities supported by both `self` and `other`.
+    #[must_use]
+    pub const fn intersect(self, other: Self) -> Self {
+        Self {
+            parallel_validation: self.parallel_validation && other.parallel_validation,
+            batch_verification: self.batch_verification && other.batch_verification,
+            fixed_base_tables: self.fixed_base_tables && other.fixed_base_tables,
+            gpu: self.gpu && other.gpu,
+        }
+    }
+}
+
+/// Number of consecutive powers of $τ$ derived by the recurrence in
+/// [`Engine::msm_update`] before starting a new chunk.
+#[cfg(any(feature = "arkworks", feature = "blst"))]
+const MSM_CHUNK_SIZE: usize = 256;
+
+/// Number of powers above which [`Engine::add_tau_g1`] and
+/// [`Engine::add_tau_g2`] derive the powers of $τ$ in parallel chunks, like
+/// [`Engine::msm_update`]. Below it, the serial recurrence is cheaper than
+/// starting the chunks.
+#[cfg(any(feature = "arkworks", feature = "blst"))]
+const CHUNKED_TAU_THRESHOLD: usize = 4 * MSM_CHUNK_SIZE;
+
+/// Default number of signatures [`Engine::verify_signatures`] checks with a
+/// single multi-pairing when pruning a contribution's BLS signatures.
+pub const DEFAULT_SIGNATURE_BATCH_SIZE: usize = 16;
+
+/// A signature, the signed message and the public key to verify it with.
+pub type SignedMessage<'a> = (G1, &'a [u8], G2);
+
 pub trait Engine {
     const CYPHER_SUITE: &'static str = "BLS_SIG_BLS12381G1_XMD:SHA-256_SSWU_RO_POP_";
 
+    /// Cipher suite of the min-pubkey-size variant, with signatures in G2 and
+    /// public keys in G1.
+    const CYPHER_SUITE_G2: &'static str = "BLS_SIG_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_";
+
+    /// Reports the optional features this backend supports.
+    fn capabilities() -> EngineCapabilities;
+
     /// Verifies that the given G1 points are valid.
     ///
     /// Valid mean that they are uniquely encoded in compressed ZCash format and
-    /// represent curve points in the prime order subgroup.
+    /// represent curve points in the prime order subgroup other than the point
+    /// at infinity.
     ///
     /// # Errors
     /// Returns an error if any of the `points` is not a compressed ZCash format
     /// point on the curve, or if the point is not in the correct prime order
-    /// subgroup.
+    /// subgroup. The point at infinity is reported as
+    /// [`CeremonyError::ZeroG1`] with its index.
     fn validate_g1(points: &[G1]) -> Result<(), CeremonyError>;
 
     /// Verifies that the given G2 points are valid.
     ///
     /// Valid mean that they are uniquely encoded in compressed ZCash format and
-    /// represent curve points in the prime order subgroup.
+    /// represent curve points in the prime order subgroup other than the point
+    /// at infinity.
     ///
     /// # Errors
     /// Returns an error if any of the `points` is not a compressed ZCash format
     /// point on the curve, or if the point is not in the correct prime order
-    /// subgroup.
+    /// subgroup. The point at infinity is reported as
+    /// [`CeremonyError::ZeroG2`] with its index.
     fn validate_g2(points: &[G2]) -> Result<(), CeremonyError>;
 
     /// Verify that the pubkey contains the contribution added
@@ -74,34 +190,199 @@ pub trait Engine {
     /// Derive a secret scalar $τ$ from the given entropy.
     fn generate_tau(entropy: &Entropy) -> Tau;
 
+    /// Derive $τ$ from 256 bits read from `source`, e.g. a hardware RNG.
+    ///
+    /// # Errors
+    ///
+    /// See [`EntropySource::from_source`].
+    fn generate_tau_from<S: RngCore + CryptoRng>(source: &mut S) -> Result<Tau, CeremonyError> {
+        Ok(Self::generate_tau(&Entropy::from_source(source)?))
+    }
+
+    /// Derive $τ$ from round `round` of a drand beacon, fetched from
+    /// `source`. The round's signature is verified against the beacon's
+    /// public key and fed through a domain-separated KDF, so anyone with the
+    /// round can reproduce $τ$.
+    ///
+    /// # Errors
+    /// Returns an error if the round can't be fetched, is a different round,
+    /// or its signature doesn't verify.
+    fn random_tau_from_beacon(
+        beacon: &DrandBeacon,
+        source: &impl BeaconSource,
+        round: u64,
+    ) -> Result<Tau, BeaconError> {
+        let fetched = source.fetch(round)?;
+        if fetched.round != round {
+            return Err(BeaconError::UnexpectedRound(round, fetched.round));
+        }
+        let message = BeaconRound::message(round);
+        if !Self::verify_signature_with_dst(
+            fetched.signature,
+            &message,
+            beacon.public_key,
+            DRAND_DST,
+        ) {
+            return Err(BeaconError::InvalidSignature(round));
+        }
+        Ok(Self::generate_tau(&fetched.entropy()))
+    }
+
     /// Multiply elements of `powers` by powers of $τ$.
     ///
+    /// Large inputs derive the powers of $τ$ like [`Engine::msm_update`]. The
+    /// output does not depend on the path taken.
+    ///
     /// # Errors
     /// Returns an error if any of `powers` is not a valid curve point.
     fn add_tau_g1(tau: &Tau, powers: &mut [G1]) -> Result<(), CeremonyError>;
 
     /// Multiply elements of `powers` by powers of $τ$.
     ///
+    /// Large inputs derive the powers of $τ$ like [`Engine::msm_update`]. The
+    /// output does not depend on the path taken.
+    ///
     /// # Errors
     /// Returns an error if any of `powers` is not a valid curve point.
     fn add_tau_g2(tau: &Tau, powers: &mut [G2]) -> Result<(), CeremonyError>;
 
+    /// Multiply elements of `powers` by powers of $τ$, like
+    /// [`Engine::add_tau_g1`], but derive the powers of $τ$ in parallel chunks.
+    /// Each chunk starts from a single exponentiation and continues with the
+    /// recurrence $τ^{i+1} = τ^i ⋅ τ$. The output is identical to
+    /// [`Engine::add_tau_g1`].
+    ///
+    /// # Errors
+    /// Returns an error if any of `powers` is not a valid curve point.
+    fn msm_update(tau: &Tau, powers: &mut [G1]) -> Result<(), CeremonyError>;
+
     /// Sign a message with `CYPHER_SUITE`, using $τ$ as the secret key.
-    fn sign_message(tau: &Tau, message: &[u8]) -> Option<G1>;
+    fn sign_message(tau: &Tau, message: &[u8]) -> Option<G1> {
+        Self::sign_message_with_dst(tau, message, Self::CYPHER_SUITE.as_bytes())
+    }
+
+    /// Sign a message with a G1 signature, hashing to the curve with the
+    /// domain separation tag `dst`, using $τ$ as the secret key.
+    fn sign_message_with_dst(tau: &Tau, message: &[u8], dst: &[u8]) -> Option<G1>;
 
     /// Verify a `CYPHER_SUITE` signature.
-    fn verify_signature(sig: G1, message: &[u8], pk: G2) -> bool;
+    fn verify_signature(sig: G1, message: &[u8], pk: G2) -> bool {
+        Self::verify_signature_with_dst(sig, message, pk, Self::CYPHER_SUITE.as_bytes())
+    }
+
+    /// Verify a G1 signature made with the domain separation tag `dst`.
+    fn verify_signature_with_dst(sig: G1, message: &[u8], pk: G2, dst: &[u8]) -> bool;
+
+    /// Sign a message with `CYPHER_SUITE_G2`, using $τ$ as the secret key.
+    fn sign_message_g2(tau: &Tau, message: &[u8]) -> Option<G2> {
+        Self::sign_message_g2_with_dst(tau, message, Self::CYPHER_SUITE_G2.as_bytes())
+    }
+
+    /// Sign a message with a G2 signature, hashing to the curve with the
+    /// domain separation tag `dst`, using $τ$ as the secret key.
+    fn sign_message_g2_with_dst(tau: &Tau, message: &[u8], dst: &[u8]) -> Option<G2>;
+
+    /// Verify a `CYPHER_SUITE_G2` signature.
+    fn verify_signature_g2(sig: G2, message: &[u8], pk: G1) -> bool {
+        Self::verify_signature_g2_with_dst(sig, message, pk, Self::CYPHER_SUITE_G2.as_bytes())
+    }
+
+    /// Verify a G2 signature made with the domain separation tag `dst`.
+    fn verify_signature_g2_with_dst(sig: G2, message: &[u8], pk: G1, dst: &[u8]) -> bool;
+
+    /// Verify that all `signatures` are valid `CYPHER_SUITE` signatures with
+    /// a single multi-pairing. Each signature is weighted by a random scalar,
+    /// so invalid signatures can't cancel each other out.
+    fn verify_signature_batch(signatures: &[SignedMessage]) -> bool;
+
+    /// Verify `signatures` in groups of at most `batch_size` per
+    /// multi-pairing. A group that fails is re-checked one signature at a
+    /// time, so the verdicts match [`Engine::verify_signature`] for any
+    /// `batch_size`.
+    fn verify_signatures(signatures: &[SignedMessage], batch_size: usize) -> Vec<bool> {
+        signatures
+            .chunks(batch_size.max(1))
+            .flat_map(|group| {
+                if group.len() > 1 && Self::verify_signature_batch(group) {
+                    vec![true; group.len()]
+                } else {
+                    group
+                        .iter()
+                        .map(|&(sig, message, pk)| Self::verify_signature(sig, message, pk))
+                        .collect()
+                }
+            })
+            .collect()
+    }
 }
 
 #[cfg(all(test, feature = "arkworks", feature = "blst"))]
 pub mod tests {
     use super::*;
     use crate::DefaultEngine;
-    use ark_bls12_381::G1Affine;
+    use ark_bls12_381::{G1Affine, G2Affine};
+    use ark_ec::{AffineCurve, ProjectiveCurve};
     use hex_literal::hex;
-    use proptest::{arbitrary::any, proptest, strategy::Strategy};
+    use proptest::{arbitrary::any, proptest, strategy::Strategy, test_runner::Config};
     use secrecy::ExposeSecret;
 
+    /// Yields the bytes 0, 1, 2, … and fails once `remaining` runs out, like
+    /// an exhausted hardware RNG.
+    struct MockSource {
+        next: u8,
+        remaining: usize,
+    }
+
+    impl RngCore for MockSource {
+        fn next_u32(&mut self) -> u32 {
+            let mut bytes = [0; 4];
+            self.fill_bytes(&mut bytes);
+            u32::from_le_bytes(bytes)
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            let mut bytes = [0; 8];
+            self.fill_bytes(&mut bytes);
+            u64::from_le_bytes(bytes)
+        }
+
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            self.try_fill_bytes(dest).unwrap();
+        }
+
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+            if dest.len() > self.remaining {
+                return Err(rand::Error::new("mock source exhausted"));
+            }
+            for byte in dest {
+                *byte = self.next;
+                self.next = self.next.wrapping_add(1);
+            }
+            self.remaining -= dest.len();
+            Ok(())
+        }
+    }
+
+    impl CryptoRng for MockSource {}
+
+    #[test]
+    fn test_generate_tau_from_source() {
+        let source = |remaining| MockSource { next: 0, remaining };
+        let stream: [u8; 32] = core::array::from_fn(|i| u8::try_from(i).unwrap());
+
+        let tau = DefaultEngine::generate_tau_from(&mut source(32)).unwrap();
+        let again = DefaultEngine::generate_tau_from(&mut source(64)).unwrap();
+        let expected = DefaultEngine::generate_tau(&Secret::new(stream));
+        assert_eq!(tau.expose_secret(), again.expose_secret());
+        assert_eq!(tau.expose_secret(), expected.expose_secret());
+
+        // Less than 256 bits is refused rather than padded.
+        assert!(matches!(
+            DefaultEngine::generate_tau_from(&mut source(31)),
+            Err(CeremonyError::InsufficientEntropy)
+        ));
+    }
+
     pub fn arb_f() -> impl Strategy<Value = F> {
         arkworks::test::arb_fr().prop_map(F::from)
     }
@@ -118,6 +399,43 @@ pub mod tests {
         proptest::array::uniform32(any::<u8>())
     }
 
+    #[test]
+    fn test_verify_signatures_batch_size() {
+        let message = b"git|1234|foobar".as_slice();
+        let other = b"git|4567|bazbaz".as_slice();
+        let signatures = (0..20_u8)
+            .map(|i| {
+                let tau = DefaultEngine::generate_tau(&Entropy::from_seed([i; 32]));
+                let sig = DefaultEngine::sign_message(&tau, message).unwrap();
+                let mut pubkey = [G2::one(), G2::one()];
+                DefaultEngine::add_tau_g2(&tau, &mut pubkey).unwrap();
+                match i % 5 {
+                    1 => (sig, other, pubkey[1]),
+                    3 => (G1::zero(), message, pubkey[1]),
+                    _ => (sig, message, pubkey[1]),
+                }
+            })
+            .collect::<Vec<_>>();
+        let expected = (0..20)
+            .map(|i| i % 5 != 1 && i % 5 != 3)
+            .collect::<Vec<_>>();
+        let valid = signatures
+            .iter()
+            .zip(&expected)
+            .filter_map(|(signature, valid)| valid.then_some(*signature))
+            .collect::<Vec<_>>();
+
+        for batch_size in [1, 4, 16] {
+            assert_eq!(
+                DefaultEngine::verify_signatures(&signatures, batch_size),
+                expected
+            );
+            assert!(DefaultEngine::verify_signatures(&valid, batch_size)
+                .into_iter()
+                .all(|verdict| verdict));
+        }
+    }
+
     #[test]
     fn test_zeros_in_verify_signature() {
         let r1 = Arkworks::verify_signature(G1::zero(), b"hello", G2::zero());
@@ -176,6 +494,75 @@ pub mod tests {
         });
     }
 
+    #[test]
+    fn test_msm_update() {
+        let tau = Arkworks::generate_tau(&Secret::new([42; 32]));
+        let n = 2 * MSM_CHUNK_SIZE + 3;
+        let mut expected = vec![G1::one(); n];
+        let mut points1 = vec![G1::one(); n];
+        let mut points2 = vec![G1::one(); n];
+
+        Arkworks::add_tau_g1(&tau, &mut expected).unwrap();
+        BLST::msm_update(&tau, &mut points1).unwrap();
+        Arkworks::msm_update(&tau, &mut points2).unwrap();
+
+        assert_eq!(points1, expected);
+        assert_eq!(points2, expected);
+    }
+
+    /// Multiplies the powers one by one, without any of the engines' batching.
+    fn naive_add_tau_g1(tau: &Tau, powers: &mut [G1]) {
+        let taus = arkworks::powers_of_tau(tau, powers.len());
+        for (p, tau) in powers.iter_mut().zip(taus.expose_secret()) {
+            let affine = G1Affine::try_from(*p).unwrap();
+            *p = affine.mul(*tau).into_affine().into();
+        }
+    }
+
+    fn naive_add_tau_g2(tau: &Tau, powers: &mut [G2]) {
+        let taus = arkworks::powers_of_tau(tau, powers.len());
+        for (p, tau) in powers.iter_mut().zip(taus.expose_secret()) {
+            let affine = G2Affine::try_from(*p).unwrap();
+            *p = affine.mul(*tau).into_affine().into();
+        }
+    }
+
+    #[test]
+    fn test_add_tau_g1_above_threshold() {
+        let n = CHUNKED_TAU_THRESHOLD + 3;
+        proptest!(Config::with_cases(4), |(tau in arb_f(), p in arb_g1())| {
+            let tau = Secret::new(tau);
+            let mut expected = vec![p; n];
+            let mut points1 = vec![p; n];
+            let mut points2 = vec![p; n];
+
+            naive_add_tau_g1(&tau, &mut expected);
+            BLST::add_tau_g1(&tau, &mut points1).unwrap();
+            Arkworks::add_tau_g1(&tau, &mut points2).unwrap();
+
+            assert_eq!(points1, expected);
+            assert_eq!(points2, expected);
+        });
+    }
+
+    #[test]
+    fn test_add_tau_g2_above_threshold() {
+        let n = CHUNKED_TAU_THRESHOLD + 3;
+        proptest!(Config::with_cases(2), |(tau in arb_f(), p in arb_g2())| {
+            let tau = Secret::new(tau);
+            let mut expected = vec![p; n];
+            let mut points1 = vec![p; n];
+            let mut points2 = vec![p; n];
+
+            naive_add_tau_g2(&tau, &mut expected);
+            BLST::add_tau_g2(&tau, &mut points1).unwrap();
+            Arkworks::add_tau_g2(&tau, &mut points2).unwrap();
+
+            assert_eq!(points1, expected);
+            assert_eq!(points2, expected);
+        });
+    }
+
     #[test]
     fn test_tau_larger_than_modulus() {
         let f = F(hex!(
@@ -210,6 +597,44 @@ pub mod tests {
         assert!(BLST::validate_g2(&[g2]).is_err());
         assert!(Arkworks::validate_g2(&[g2]).is_err());
     }
+
+    #[test]
+    fn test_validate_point_at_infinity() {
+        let g1 = [G1::one(), G1::one(), G1::zero(), G1::one()];
+        assert_eq!(BLST::validate_g1(&g1), Err(CeremonyError::ZeroG1(2)));
+        assert_eq!(Arkworks::validate_g1(&g1), Err(CeremonyError::ZeroG1(2)));
+
+        let g2 = [G2::one(), G2::zero()];
+        assert_eq!(BLST::validate_g2(&g2), Err(CeremonyError::ZeroG2(1)));
+        assert_eq!(Arkworks::validate_g2(&g2), Err(CeremonyError::ZeroG2(1)));
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let expected = EngineCapabilities {
+            parallel_validation: true,
+            batch_verification: true,
+            fixed_base_tables: false,
+            gpu: false,
+        };
+        assert_eq!(Arkworks::capabilities(), expected);
+        assert_eq!(BLST::capabilities(), expected);
+        assert_eq!(Both::<Arkworks, BLST>::capabilities(), expected);
+    }
+
+    #[test]
+    fn test_capabilities_intersect() {
+        let all = EngineCapabilities {
+            parallel_validation: true,
+            batch_verification: true,
+            fixed_base_tables: true,
+            gpu: true,
+        };
+        let none = EngineCapabilities::default();
+        assert_eq!(all.intersect(all), all);
+        assert_eq!(all.intersect(none), none);
+        assert_eq!(none.intersect(all), none);
+    }
 }
 
 #[cfg(feature = "bench")]
@@ -238,7 +663,9 @@ pub mod bench {
         bench_verify_g2::<E>(criterion, name);
         bench_generate_tau::<E>(criterion, name);
         bench_add_tau_g1::<E>(criterion, name);
+        bench_add_tau_g1_threshold::<E>(criterion, name);
         bench_add_tau_g2::<E>(criterion, name);
+        bench_msm_update::<E>(criterion, name);
     }
 
     const G1_SIZES: [usize; 5] = [1, 4096, 8192, 16384, 32768];
@@ -383,6 +810,47 @@ pub mod bench {
         }
     }
 
+    /// Compares the serial and chunked derivation of the powers of $τ$ at the
+    /// size where [`Engine::add_tau_g1`] switches between them.
+    fn bench_add_tau_g1_threshold<E: Engine>(criterion: &mut Criterion, name: &str) {
+        let id = format!("engine/{name}/add_tau_g1_threshold");
+        for size in [CHUNKED_TAU_THRESHOLD, CHUNKED_TAU_THRESHOLD + 1] {
+            criterion.bench_with_input(
+                BenchmarkId::new(id.clone(), size),
+                &size,
+                move |bencher, &size| {
+                    bencher.iter_batched_ref(
+                        || {
+                            (
+                                rand_tau(),
+                                iter::repeat(rand_g1()).take(size).collect::<Vec<_>>(),
+                            )
+                        },
+                        |(tau, powers)| E::add_tau_g1(tau, powers).unwrap(),
+                        BatchSize::LargeInput,
+                    );
+                },
+            );
+        }
+    }
+
+    fn bench_msm_update<E: Engine>(criterion: &mut Criterion, name: &str) {
+        let id = format!("engine/{name}/msm_update");
+        let size = 4096;
+        criterion.bench_with_input(BenchmarkId::new(id, size), &size, move |bencher, &size| {
+            bencher.iter_batched_ref(
+                || {
+                    (
+                        rand_tau(),
+                        iter::repeat(rand_g1()).take(size).collect::<Vec<_>>(),
+                    )
+                },
+                |(tau, powers)| E::msm_update(tau, powers).unwrap(),
+                BatchSize::LargeInput,
+            );
+        });
+    }
+
     fn bench_add_tau_g2<E: Engine>(criterion: &mut Criterion, name: &str) {
         let id = format!("engine/{name}/add_tau_g2");
         for size in G2_SIZES {
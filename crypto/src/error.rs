@@ -11,6 +11,10 @@ pub enum CeremoniesError {
     UnexpectedNumContributions(usize, usize),
     #[error("Error in contribution {0}: {1}")]
     InvalidCeremony(usize, #[source] CeremonyError),
+    #[error("Unexpected number of records: {0} participants, but {1} in sub-ceremony {2}")]
+    UnexpectedNumRecords(usize, usize, usize),
+    #[error("Entropy attestation too long: at most {0} bytes, got {1}")]
+    EntropyAttestationTooLong(usize, usize),
 }
 
 impl ErrorCode for CeremoniesError {
@@ -37,7 +41,7 @@ pub enum CeremonyError {
     InconsistentNumG1Powers(usize, usize),
     #[error("Inconsistent number of G2 powers: numG2Powers = {0}, len = {1}")]
     InconsistentNumG2Powers(usize, usize),
-    #[error("Unsupported: more G2 than G1 powers: numG2Powers = {0}, numG2Powers = {1}")]
+    #[error("Unsupported: more G2 than G1 powers: numG1Powers = {0}, numG2Powers = {1}")]
     UnsupportedMoreG2Powers(usize, usize),
     #[error("Error parsing G1 power {0}: {1}")]
     InvalidG1Power(usize, #[source] ParseError),
@@ -79,8 +83,24 @@ pub enum CeremonyError {
     DuplicateG2(usize, usize),
     #[error("Contribution contains no entropy: pubkey equals generator")]
     ContributionNoEntropy,
+    #[error("Contribution does not advance the accumulated entropy")]
+    EntropyNotAdvanced,
+    #[error("Too many contributions: at most {0}, got {1}")]
+    TooManyContributions(usize, usize),
+    #[error("Entropy source did not provide 256 bits")]
+    InsufficientEntropy,
     #[error("Mismatch in witness length: {0} products and {1} pubkeys")]
     WitnessLengthMismatch(usize, usize),
+    #[error("Contribution {0} does not build on the previous contribution")]
+    InvalidChainLink(usize),
+    #[error("Transcript powers do not match the last running product")]
+    ChainPowersMismatch,
+    #[error("BLS signature of contribution {0} does not verify")]
+    InvalidWitnessSignature(usize),
+    #[error("Tau is not the secret of the potPubkey")]
+    TauPubkeyMismatch,
+    #[error("Consecutive powers are equal")]
+    DegeneratePowers,
 }
 
 impl ErrorCode for CeremonyError {
@@ -1,10 +1,16 @@
 use crate::hex_format::HexDecodingError::{
-    DecoderError, InvalidCharacter, InvalidLength, MissingPrefix,
+    DecoderError, InvalidCharacter, InvalidLength, OddLength,
 };
 use hex::FromHexError;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 use serde::{de, Deserializer, Serializer};
 use std::fmt;
 
+/// Number of points decoded by a single rayon task.
+#[cfg(feature = "parallel")]
+const DECODE_CHUNK_SIZE: usize = 1024;
+
 /// Allocation free hex serializer with `0x` prefix.
 ///
 /// Constant generic `N` is the length of the byte array. The value
@@ -24,7 +30,7 @@ pub fn bytes_to_hex<S: Serializer, const N: usize, const M: usize>(
     serializer.serialize_str(str)
 }
 
-/// Allocation free hex deserializer with `0x` prefix.
+/// Allocation free hex deserializer, the `0x` prefix is optional.
 pub fn hex_to_bytes<'de, D: Deserializer<'de>, const N: usize>(
     deserializer: D,
 ) -> Result<[u8; N], D::Error> {
@@ -41,8 +47,8 @@ pub fn optional_hex_to_bytes<'de, D: Deserializer<'de>, const N: usize>(
 pub enum HexDecodingError {
     #[error("input length must equal {0}")]
     InvalidLength(usize),
-    #[error("hex string must start with `0x`")]
-    MissingPrefix,
+    #[error("hex string must have an even number of digits")]
+    OddLength,
     #[error("hex string must contain only lower-case hex digits")]
     InvalidCharacter,
     #[error("hex decoding failed: {0}")]
@@ -58,33 +64,105 @@ impl HexDecodingError {
     }
 }
 
+/// Decodes lower-case hex digits with an optional `0x` prefix, as written by
+/// [`bytes_to_hex`].
 pub fn hex_str_to_bytes<const N: usize>(value: &str) -> Result<[u8; N], HexDecodingError> {
     let mut result = [0_u8; N];
-    if value.len() != 2 + 2 * N {
-        return Err(InvalidLength(2 + 2 * N));
-    }
-    if &value[..2] != "0x" {
-        return Err(MissingPrefix);
-    }
-    if !value[2..]
+    let digits = value.strip_prefix("0x").unwrap_or(value);
+    // Also rejects whitespace and non-ASCII, so the lengths below are in
+    // characters.
+    if !digits
         .chars()
         .all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase())
     {
         return Err(InvalidCharacter);
     }
-    hex::decode_to_slice(&value[2..], &mut result).map_err(DecoderError)?;
+    if digits.len() % 2 != 0 {
+        return Err(OddLength);
+    }
+    if digits.len() != 2 * N {
+        return Err(InvalidLength(2 * N));
+    }
+    hex::decode_to_slice(digits, &mut result).map_err(DecoderError)?;
     Ok(result)
 }
 
-/// Serde Visitor for human readable formats. Requires `0x` prefix, but is
-/// otherwise case insensitive.
+#[derive(Debug, thiserror::Error)]
+#[error("invalid point at index {index}: {error}")]
+pub struct PointDecodingError {
+    pub index: usize,
+    pub error: HexDecodingError,
+}
+
+/// Decodes a list of hex encoded points in parallel. On failure, reports the
+/// first invalid point.
+#[cfg(feature = "parallel")]
+pub fn decode_hex_points<const N: usize>(
+    hex: &[String],
+) -> Result<Vec<[u8; N]>, PointDecodingError> {
+    decode_hex_points_parallel(hex)
+}
+
+/// Decodes a list of hex encoded points. On failure, reports the first
+/// invalid point.
+#[cfg(not(feature = "parallel"))]
+pub fn decode_hex_points<const N: usize>(
+    hex: &[String],
+) -> Result<Vec<[u8; N]>, PointDecodingError> {
+    decode_hex_points_serial(hex)
+}
+
+pub fn decode_hex_points_serial<const N: usize>(
+    hex: &[String],
+) -> Result<Vec<[u8; N]>, PointDecodingError> {
+    let mut points = vec![[0_u8; N]; hex.len()];
+    decode_hex_points_into(&mut points, hex)?;
+    Ok(points)
+}
+
+/// Decodes chunks of points concurrently into a preallocated vector.
+#[cfg(feature = "parallel")]
+pub fn decode_hex_points_parallel<const N: usize>(
+    hex: &[String],
+) -> Result<Vec<[u8; N]>, PointDecodingError> {
+    let mut points = vec![[0_u8; N]; hex.len()];
+    // Every chunk runs to its first error, so that the lowest index is
+    // reported regardless of scheduling.
+    let first_error = points
+        .par_chunks_mut(DECODE_CHUNK_SIZE)
+        .zip(hex.par_chunks(DECODE_CHUNK_SIZE))
+        .enumerate()
+        .filter_map(|(chunk, (points, hex))| {
+            decode_hex_points_into(points, hex)
+                .err()
+                .map(|error| PointDecodingError {
+                    index: chunk * DECODE_CHUNK_SIZE + error.index,
+                    ..error
+                })
+        })
+        .min_by_key(|error| error.index);
+    first_error.map_or(Ok(points), Err)
+}
+
+fn decode_hex_points_into<const N: usize>(
+    points: &mut [[u8; N]],
+    hex: &[String],
+) -> Result<(), PointDecodingError> {
+    for (index, (point, hex)) in points.iter_mut().zip(hex).enumerate() {
+        *point = hex_str_to_bytes(hex).map_err(|error| PointDecodingError { index, error })?;
+    }
+    Ok(())
+}
+
+/// Serde Visitor for human readable formats. Accepts lower-case hex digits
+/// with an optional `0x` prefix.
 struct StrVisitor<const N: usize>;
 
 impl<'de, const N: usize> de::Visitor<'de> for StrVisitor<N> {
     type Value = [u8; N];
 
     fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        write!(formatter, "a {N} byte hex string stating with `0x`")
+        write!(formatter, "a {N} byte hex string")
     }
 
     fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
@@ -103,7 +181,7 @@ impl<'de, const N: usize> de::Visitor<'de> for OptionalHexStrVisitor<N> {
     fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
         write!(
             formatter,
-            "a {N} byte hex string starting with `0x`, an empty string, or no value"
+            "a {N} byte hex string, an empty string, or no value"
         )
     }
 
@@ -137,7 +215,10 @@ impl<'de, const N: usize> de::Visitor<'de> for OptionalHexStrVisitor<N> {
 
 #[cfg(test)]
 mod tests {
-    use crate::hex_format::{hex_to_bytes, optional_hex_to_bytes};
+    use crate::hex_format::{
+        decode_hex_points_serial, hex_str_to_bytes, hex_to_bytes, optional_hex_to_bytes,
+        HexDecodingError,
+    };
     use serde::Deserialize;
 
     #[derive(Eq, PartialEq, Debug)]
@@ -203,4 +284,86 @@ mod tests {
         let from_wrong_prefix = serde_json::from_str::<Bytes>(r#""0X1234""#);
         assert!(from_wrong_prefix.is_err());
     }
+
+    #[test]
+    fn test_hex_optional_prefix() {
+        assert_eq!(hex_str_to_bytes::<2>("0x1234").unwrap(), [0x12, 0x34]);
+        assert_eq!(hex_str_to_bytes::<2>("1234").unwrap(), [0x12, 0x34]);
+        let from_unprefixed = serde_json::from_str::<OptionalBytes>(r#""1234""#).unwrap();
+        assert_eq!(from_unprefixed, OptionalBytes(Some([0x12, 0x34])));
+    }
+
+    #[test]
+    fn test_hex_malformed() {
+        assert!(matches!(
+            hex_str_to_bytes::<2>("0x123"),
+            Err(HexDecodingError::OddLength)
+        ));
+        assert!(matches!(
+            hex_str_to_bytes::<2>("12345"),
+            Err(HexDecodingError::OddLength)
+        ));
+        assert!(matches!(
+            hex_str_to_bytes::<2>("0x123456"),
+            Err(HexDecodingError::InvalidLength(4))
+        ));
+        for whitespace in ["0x12 34", " 0x1234", "0x1234\n", "0x12\t34"] {
+            assert!(matches!(
+                hex_str_to_bytes::<2>(whitespace),
+                Err(HexDecodingError::InvalidCharacter)
+            ));
+        }
+        assert!(matches!(
+            hex_str_to_bytes::<2>("0xABCD"),
+            Err(HexDecodingError::InvalidCharacter)
+        ));
+        // Multi-byte characters used to panic when slicing off the prefix.
+        assert!(matches!(
+            hex_str_to_bytes::<2>("a\u{e9}234"),
+            Err(HexDecodingError::InvalidCharacter)
+        ));
+        assert!(serde_json::from_str::<Bytes>(r#""0x12 4""#).is_err());
+        assert!(serde_json::from_str::<OptionalBytes>(r#""0x123""#).is_err());
+    }
+
+    fn hex_points(count: usize) -> Vec<String> {
+        (0..count)
+            .map(|i| format!("0x{}", hex::encode((i as u64).to_be_bytes())))
+            .collect()
+    }
+
+    #[test]
+    fn test_decode_hex_points_locates_invalid_point() {
+        let mut points = hex_points(10);
+        points[7] = "0x00112233445566xx".to_string();
+        points[8] = "0x0011".to_string();
+        let error = decode_hex_points_serial::<8>(&points).unwrap_err();
+        assert_eq!(error.index, 7);
+        assert!(matches!(error.error, HexDecodingError::InvalidCharacter));
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_decode_hex_points_parallel() {
+        use crate::hex_format::decode_hex_points_parallel;
+
+        // Spans multiple chunks, with a partial last one.
+        let mut points = hex_points(5000);
+        assert_eq!(
+            decode_hex_points_parallel::<8>(&points).unwrap(),
+            decode_hex_points_serial::<8>(&points).unwrap()
+        );
+
+        // The first invalid point is reported, even if a later chunk fails
+        // first.
+        points[4500] = String::new();
+        points[3000] = "0x0011".to_string();
+        let error = decode_hex_points_parallel::<8>(&points).unwrap_err();
+        assert_eq!(error.index, 3000);
+        assert!(matches!(error.error, HexDecodingError::InvalidLength(16)));
+        assert_eq!(
+            decode_hex_points_serial::<8>(&points).unwrap_err().index,
+            3000
+        );
+    }
 }
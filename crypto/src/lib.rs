@@ -5,6 +5,9 @@
 
 mod batch_contribution;
 mod batch_transcript;
+mod beacon;
+mod binary_format;
+mod canonical_transcript;
 mod contribution;
 mod engine;
 mod error;
@@ -12,18 +15,33 @@ mod group;
 mod hex_format;
 mod powers;
 pub mod signature;
+mod streaming;
 mod transcript;
+mod verification_cache;
 
 pub use crate::{
-    batch_contribution::{get_pot_pubkeys, BatchContribution},
-    batch_transcript::BatchTranscript,
+    batch_contribution::{
+        get_pot_pubkeys, BatchContribution, ContributionShard, VerificationResult,
+        MAX_ENTROPY_ATTESTATION_LEN,
+    },
+    batch_transcript::{BatchTranscript, ContributionRecord, WitnessRecord},
+    beacon::{BeaconError, BeaconRound, BeaconSource, DrandBeacon, DRAND_DST},
+    binary_format::{BinaryFormatError, BINARY_FORMAT_VERSION},
+    canonical_transcript::{
+        CanonicalPowersOfTau, CanonicalSubTranscript, CanonicalTranscript, CanonicalWitness,
+    },
     contribution::Contribution,
-    engine::{Engine, Entropy, Secret, Tau},
+    engine::{
+        Engine, EngineCapabilities, Entropy, EntropySource, OsEntropy, Secret, SignedMessage, Tau,
+        DEFAULT_SIGNATURE_BATCH_SIZE,
+    },
     error::{CeremoniesError, CeremonyError, ErrorCode, ParseError},
     group::{F, G1, G2},
     powers::Powers,
-    signature::identity::Identity,
+    signature::identity::{Identity, IdentityProvider, StructuredIdentity},
+    streaming::{read_contribution, StreamingError},
     transcript::Transcript,
+    verification_cache::{VerificationCache, DEFAULT_VERIFICATION_CACHE_SIZE},
 };
 
 pub use crate::engine::Both;
@@ -49,25 +67,36 @@ pub type DefaultEngine = BLST;
 pub mod bench {
     use super::*;
     use criterion::Criterion;
-    use rand::Rng;
-    use secrecy::Secret;
 
     pub const BATCH_SIZE: [(usize, usize); 4] = [(4096, 65), (8192, 65), (16384, 65), (32768, 65)];
 
     pub fn group(criterion: &mut Criterion) {
         engine::bench::group(criterion);
-        batch_contribution::bench::group(criterion);
-        batch_transcript::bench::group(criterion);
+        // Each runs the benches of the enabled engines.
+        #[cfg(any(feature = "arkworks", feature = "blst"))]
+        {
+            contribution::bench::group(criterion);
+            signature::bench::group(criterion);
+            batch_contribution::bench::group(criterion);
+            batch_transcript::bench::group(criterion);
+        }
     }
 
     #[must_use]
     pub fn rand_entropy() -> Entropy {
-        let mut rng = rand::thread_rng();
-        Secret::new(rng.gen())
+        Entropy::random()
     }
 
+    #[cfg(any(feature = "arkworks", feature = "blst"))]
     #[must_use]
     pub fn rand_tau() -> Tau {
-        Arkworks::generate_tau(&rand_entropy())
+        DefaultEngine::generate_tau(&rand_entropy())
+    }
+
+    /// Deterministic tau, so that benchmark inputs are comparable between runs.
+    #[cfg(any(feature = "arkworks", feature = "blst"))]
+    #[must_use]
+    pub fn seeded_tau(seed: u8) -> Tau {
+        DefaultEngine::generate_tau(&Secret::new([seed; 32]))
     }
 }
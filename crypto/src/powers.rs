@@ -1,5 +1,10 @@
 use super::{CeremonyError, G1, G2};
-use serde::{Deserialize, Serialize};
+use crate::hex_format::decode_hex_points;
+use serde::{
+    de::{self, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
+use std::fmt;
 
 #[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 #[serde(try_from = "PowersJson", into = "PowersJson")]
@@ -8,10 +13,15 @@ pub struct Powers {
     pub g2: Vec<G2>,
 }
 
+/// The power counts are written as JSON numbers, as in the specification.
+/// Numeric strings are accepted too, since that is how EIP-712 tooling
+/// encodes the same `uint256` fields.
 #[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 struct PowersJson {
+    #[serde(deserialize_with = "deserialize_num_powers")]
     num_g1_powers: usize,
+    #[serde(deserialize_with = "deserialize_num_powers")]
     num_g2_powers: usize,
     powers_of_tau: PowersOfTau,
 }
@@ -19,10 +29,63 @@ struct PowersJson {
 #[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase", deny_unknown_fields)]
 struct PowersOfTau {
+    #[serde(deserialize_with = "deserialize_g1_powers")]
     g1_powers: Vec<G1>,
+    #[serde(deserialize_with = "deserialize_g2_powers")]
     g2_powers: Vec<G2>,
 }
 
+/// A `numG1Powers` or `numG2Powers` value, either a number or a numeric
+/// string.
+pub(crate) struct NumPowers(pub usize);
+
+impl<'de> Deserialize<'de> for NumPowers {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(NumPowersVisitor)
+    }
+}
+
+struct NumPowersVisitor;
+
+impl<'de> Visitor<'de> for NumPowersVisitor {
+    type Value = NumPowers;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("a number of powers, as a number or a numeric string")
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+        usize::try_from(value)
+            .map(NumPowers)
+            .map_err(|_| E::invalid_value(de::Unexpected::Unsigned(value), &self))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        value
+            .parse()
+            .map(NumPowers)
+            .map_err(|_| E::invalid_value(de::Unexpected::Str(value), &self))
+    }
+}
+
+fn deserialize_num_powers<'de, D: Deserializer<'de>>(deserializer: D) -> Result<usize, D::Error> {
+    NumPowers::deserialize(deserializer).map(|n| n.0)
+}
+
+// Contributions have tens of thousands of points, so the hex decoding is
+// split from the (serial) JSON parsing and done in parallel.
+fn deserialize_g1_powers<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<G1>, D::Error> {
+    let hex = Vec::<String>::deserialize(deserializer)?;
+    let points = decode_hex_points(&hex).map_err(de::Error::custom)?;
+    Ok(points.into_iter().map(G1).collect())
+}
+
+fn deserialize_g2_powers<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<G2>, D::Error> {
+    let hex = Vec::<String>::deserialize(deserializer)?;
+    let points = decode_hex_points(&hex).map_err(de::Error::custom)?;
+    Ok(points.into_iter().map(G2).collect())
+}
+
 impl From<Powers> for PowersJson {
     fn from(powers: Powers) -> Self {
         Self {
@@ -68,12 +131,53 @@ impl Powers {
             g2: vec![G2::one(); num_g2],
         }
     }
+
+    /// Returns true if both have the same number of G1 and G2 powers,
+    /// regardless of the points themselves.
+    #[must_use]
+    pub fn same_shape(&self, other: &Self) -> bool {
+        self.g1.len() == other.g1.len() && self.g2.len() == other.g2.len()
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::Powers;
     use serde_json::json;
 
+    #[test]
+    fn test_same_shape() {
+        let powers = Powers::new(4, 2);
+        let mut other = Powers::new(4, 2);
+        other.g1[1] = crate::G1::zero();
+        assert!(powers.same_shape(&other));
+        assert!(!powers.same_shape(&Powers::new(3, 2)));
+        assert!(!powers.same_shape(&Powers::new(4, 3)));
+    }
+
+    #[test]
+    fn test_num_powers_as_number_or_string() {
+        let powers = Powers::new(2, 1);
+        let numbers = serde_json::to_value(&powers).unwrap();
+        // Counts are always written as numbers.
+        assert_eq!(numbers["numG1Powers"], json!(2));
+        assert_eq!(numbers["numG2Powers"], json!(1));
+
+        let mut strings = numbers.clone();
+        strings["numG1Powers"] = json!("2");
+        strings["numG2Powers"] = json!("1");
+        assert_eq!(serde_json::from_value::<Powers>(numbers).unwrap(), powers);
+        assert_eq!(
+            serde_json::from_value::<Powers>(strings.clone()).unwrap(),
+            powers
+        );
+
+        strings["numG1Powers"] = json!("two");
+        assert!(serde_json::from_value::<Powers>(strings.clone()).is_err());
+        strings["numG1Powers"] = json!(-2);
+        assert!(serde_json::from_value::<Powers>(strings).is_err());
+    }
+
     #[test]
     fn test_invalid_powers_json() {
         let g1 = "0xc00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000";
@@ -104,4 +208,22 @@ mod tests {
             .unwrap();
         assert!(format!("{result}").contains("Inconsistent number of G2 powers"));
     }
+
+    #[test]
+    fn test_invalid_point_located() {
+        let g1 = "0xc00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000";
+        let g2 = "0xc00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000";
+        let powers = json!({
+            "numG1Powers": 3,
+            "numG2Powers": 1,
+            "powersOfTau": {
+                "G1Powers": [g1, g1, "0xc0"],
+                "G2Powers": [g2],
+            },
+        });
+        let result = serde_json::from_value::<super::Powers>(powers)
+            .err()
+            .unwrap();
+        assert!(format!("{result}").contains("invalid point at index 2"));
+    }
 }
@@ -1,3 +1,4 @@
+use ethers_core::{types::H160, utils::to_checksum};
 use serde::{Deserialize, Serialize};
 use std::{fmt, fmt::Display, str::FromStr};
 use thiserror::Error;
@@ -7,19 +8,50 @@ pub enum Identity {
     None,
     Ethereum { address: [u8; 20] },
     Github { id: u64, username: String },
+    Twitter { id: u64, handle: String },
+}
+
+/// The kind of an [`Identity`], without the account details.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum IdentityProvider {
+    None,
+    Ethereum,
+    Github,
+    Twitter,
+}
+
+impl IdentityProvider {
+    /// Lowercase name that never changes, for metric labels and the like.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Ethereum => "ethereum",
+            Self::Github => "github",
+            Self::Twitter => "twitter",
+        }
+    }
+}
+
+impl Display for IdentityProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 impl Identity {
-    /// Parse Ethereum identity from address from hex string.
+    /// Parse Ethereum identity from address from hex string. The `0x` prefix
+    /// is optional and checksummed (mixed case) addresses are accepted.
     ///
     /// # Errors
     ///
     /// Returns [`IdentityError`] if the input is not a valid Ethereum address.
     pub fn eth_from_str(address: &str) -> Result<Self, IdentityError> {
-        if address.len() != 42 || &address[..2] != "0x" {
+        let digits = address.strip_prefix("0x").unwrap_or(address);
+        if digits.len() != 40 {
             return Err(IdentityError::InvalidEthereumAddress);
         }
-        let address = hex::decode(&address[2..])
+        let address = hex::decode(digits)
             .map_err(|_| IdentityError::InvalidEthereumAddress)?
             .try_into()
             .map_err(|_| IdentityError::InvalidEthereumAddress)?;
@@ -27,25 +59,68 @@ impl Identity {
         Ok(Self::Ethereum { address })
     }
 
+    /// The identity with provider-specific equivalences normalized away.
+    ///
+    /// GitHub usernames are case-insensitive, so they are lowercased. The
+    /// [`Display`] form is left as-is since it is what participants sign.
+    #[must_use]
+    pub fn canonical(&self) -> Self {
+        match self {
+            Self::Github { id, username } => Self::Github {
+                id: *id,
+                username: username.to_lowercase(),
+            },
+            other => other.clone(),
+        }
+    }
+
+    /// Key used to deduplicate participants. Twitter handles can be changed
+    /// at will, so Twitter identities are only keyed by their numeric id.
     #[must_use]
     pub fn unique_id(&self) -> String {
-        self.to_string()
+        match self {
+            Self::Twitter { id, .. } => format!("twt|{id}"),
+            other => other.canonical().to_string(),
+        }
+    }
+
+    /// Like [`Display`], but with Ethereum addresses EIP-55 checksummed, for
+    /// showing to users. [`Display`] stays lowercase, as it is the canonical
+    /// form used for equality and keys.
+    #[must_use]
+    pub fn display_checksummed(&self) -> String {
+        match self {
+            Self::Ethereum { address } => format!("eth|{}", to_checksum(&H160(*address), None)),
+            other => other.to_string(),
+        }
     }
 
     #[must_use]
     pub fn nickname(&self) -> String {
         match self {
-            Self::Ethereum { address } => format!("0x{}", hex::encode(address)),
+            Self::Ethereum { address } => to_checksum(&H160(*address), None),
             Self::Github { username, .. } => username.to_string(),
+            Self::Twitter { handle, .. } => handle.to_string(),
             Self::None => "<<unauthorized>>".to_string(),
         }
     }
 
+    #[must_use]
+    pub const fn provider(&self) -> IdentityProvider {
+        match self {
+            Self::None => IdentityProvider::None,
+            Self::Ethereum { .. } => IdentityProvider::Ethereum,
+            Self::Github { .. } => IdentityProvider::Github,
+            Self::Twitter { .. } => IdentityProvider::Twitter,
+        }
+    }
+
     #[must_use]
     pub fn provider_name(&self) -> String {
         match self {
             Self::Ethereum { .. } => "Ethereum",
             Self::Github { .. } => "Github",
+            Self::Twitter { .. } => "Twitter",
             Self::None => "None",
         }
         .to_string()
@@ -64,6 +139,8 @@ pub enum IdentityError {
     InvalidEthereumAddress,
     #[error("Invalid Github ID")]
     InvalidGithubId,
+    #[error("Invalid Twitter ID")]
+    InvalidTwitterId,
 }
 
 impl Display for Identity {
@@ -72,6 +149,7 @@ impl Display for Identity {
             Self::None => write!(f, ""),
             Self::Ethereum { address } => write!(f, "eth|0x{}", hex::encode(address)),
             Self::Github { id, username } => write!(f, "git|{id}|{username}"),
+            Self::Twitter { id, handle } => write!(f, "twt|{id}|{handle}"),
         }
     }
 }
@@ -87,16 +165,7 @@ impl FromStr for Identity {
                 if parts.next().is_some() {
                     return Err(IdentityError::TooManyFields);
                 }
-
-                if address.len() != 42 || &address[..2] != "0x" {
-                    return Err(IdentityError::InvalidEthereumAddress);
-                }
-                let address = hex::decode(&address[2..])
-                    .map_err(|_| IdentityError::InvalidEthereumAddress)?
-                    .try_into()
-                    .map_err(|_| IdentityError::InvalidEthereumAddress)?;
-
-                Ok(Self::Ethereum { address })
+                Self::eth_from_str(address)
             }
             Some("git") => {
                 let id = parts.next().ok_or(IdentityError::MissingField)?;
@@ -110,6 +179,18 @@ impl FromStr for Identity {
 
                 Ok(Self::Github { id, username })
             }
+            Some("twt") => {
+                let id = parts.next().ok_or(IdentityError::MissingField)?;
+                let handle = parts.next().ok_or(IdentityError::MissingField)?;
+                if parts.next().is_some() {
+                    return Err(IdentityError::TooManyFields);
+                }
+
+                let id = id.parse().map_err(|_| IdentityError::InvalidTwitterId)?;
+                let handle = handle.to_string();
+
+                Ok(Self::Twitter { id, handle })
+            }
             Some("") => {
                 if parts.next().is_some() {
                     return Err(IdentityError::TooManyFields);
@@ -140,9 +221,131 @@ impl<'de> Deserialize<'de> for Identity {
     }
 }
 
+/// Serializes an [`Identity`] as a tagged object, e.g.
+/// `{ "type": "github", "id": 123, "username": "user" }`, instead of the
+/// `git|123|user` string form used in transcripts and signatures.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct StructuredIdentity(pub Identity);
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum StructuredRepr {
+    None,
+    Ethereum { address: String },
+    Github { id: u64, username: String },
+    Twitter { id: u64, handle: String },
+}
+
+impl From<Identity> for StructuredIdentity {
+    fn from(identity: Identity) -> Self {
+        Self(identity)
+    }
+}
+
+impl From<StructuredIdentity> for Identity {
+    fn from(identity: StructuredIdentity) -> Self {
+        identity.0
+    }
+}
+
+impl Serialize for StructuredIdentity {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let repr = match &self.0 {
+            Identity::None => StructuredRepr::None,
+            Identity::Ethereum { address } => StructuredRepr::Ethereum {
+                address: format!("0x{}", hex::encode(address)),
+            },
+            Identity::Github { id, username } => StructuredRepr::Github {
+                id: *id,
+                username: username.clone(),
+            },
+            Identity::Twitter { id, handle } => StructuredRepr::Twitter {
+                id: *id,
+                handle: handle.clone(),
+            },
+        };
+        repr.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for StructuredIdentity {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let identity = match StructuredRepr::deserialize(deserializer)? {
+            StructuredRepr::None => Identity::None,
+            StructuredRepr::Ethereum { address } => {
+                Identity::eth_from_str(&address).map_err(serde::de::Error::custom)?
+            }
+            StructuredRepr::Github { id, username } => Identity::Github { id, username },
+            StructuredRepr::Twitter { id, handle } => Identity::Twitter { id, handle },
+        };
+        Ok(Self(identity))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serde_json::json;
+
+    fn assert_structured_round_trip(identity: Identity, expected: serde_json::Value) {
+        let structured = StructuredIdentity(identity);
+        let value = serde_json::to_value(&structured).unwrap();
+        assert_eq!(value, expected);
+        assert_eq!(
+            serde_json::from_value::<StructuredIdentity>(value).unwrap(),
+            structured
+        );
+    }
+
+    #[test]
+    fn test_structured() {
+        assert_structured_round_trip(Identity::None, json!({ "type": "none" }));
+        assert_structured_round_trip(
+            Identity::Ethereum {
+                address: [0xab; 20],
+            },
+            json!({
+                "type": "ethereum",
+                "address": "0xabababababababababababababababababababab"
+            }),
+        );
+        assert_structured_round_trip(
+            Identity::Github {
+                id: 123,
+                username: "user".to_string(),
+            },
+            json!({ "type": "github", "id": 123, "username": "user" }),
+        );
+        assert_structured_round_trip(
+            Identity::Twitter {
+                id: 456,
+                handle: "handle".to_string(),
+            },
+            json!({ "type": "twitter", "id": 456, "handle": "handle" }),
+        );
+        assert!(serde_json::from_value::<StructuredIdentity>(
+            json!({ "type": "ethereum", "address": "0x12" })
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_structured_leaves_string_form() {
+        let identity = Identity::Github {
+            id: 123,
+            username: "user".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_value(identity).unwrap(),
+            json!("git|123|user")
+        );
+    }
 
     #[test]
     fn test_none() {
@@ -183,6 +386,55 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_eth_checksummed() {
+        // From the EIP-55 test vectors.
+        let checksummed = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+        let identity = Identity::eth_from_str(&checksummed.to_lowercase()).unwrap();
+        assert_eq!(identity.display_checksummed(), format!("eth|{checksummed}"));
+        assert_eq!(identity.nickname(), checksummed);
+        assert_eq!(
+            identity.to_string(),
+            format!("eth|{}", checksummed.to_lowercase())
+        );
+        assert_eq!(identity.display_checksummed().parse(), Ok(identity));
+
+        let github = Identity::Github {
+            id: 1,
+            username: "Alice".to_string(),
+        };
+        assert_eq!(github.display_checksummed(), github.to_string());
+    }
+
+    #[test]
+    fn test_eth_malformed() {
+        let address = "0xAbCdEf0123456789aBcDeF0123456789ABCDEF01";
+        let expected = Identity::eth_from_str(address).unwrap();
+        assert_eq!(Identity::eth_from_str(&address[2..]).unwrap(), expected);
+        assert_eq!(format!("eth|{}", &address[2..]).parse(), Ok(expected));
+
+        for malformed in [
+            // Odd length.
+            "0x000000000000000000000000000000000000000",
+            // Embedded whitespace.
+            "0x00000000000000000000 0000000000000000000",
+            " 0x000000000000000000000000000000000000000",
+            // Multi-byte characters used to panic when slicing off the prefix.
+            "a\u{e9}000000000000000000000000000000000000000",
+            "0x\u{e9}00000000000000000000000000000000000000",
+        ] {
+            assert_eq!(
+                Identity::eth_from_str(malformed),
+                Err(IdentityError::InvalidEthereumAddress),
+                "{malformed:?}"
+            );
+            assert_eq!(
+                format!("eth|{malformed}").parse::<Identity>(),
+                Err(IdentityError::InvalidEthereumAddress),
+            );
+        }
+    }
+
     #[test]
     fn test_git() {
         let identity = Identity::Github {
@@ -197,6 +449,77 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_git_canonical() {
+        let upper: Identity = "git|1|Alice".parse().unwrap();
+        let lower: Identity = "git|1|alice".parse().unwrap();
+        assert_ne!(upper, lower);
+        assert_eq!(upper.to_string(), "git|1|Alice");
+        assert_eq!(upper.canonical(), lower);
+        assert_eq!(upper.unique_id(), lower.unique_id());
+        assert_eq!(upper.unique_id(), "git|1|alice");
+    }
+
+    #[test]
+    fn test_twt() {
+        let identity = Identity::Twitter {
+            id: 123,
+            handle: "handle".to_string(),
+        };
+        assert_eq!(identity.to_string(), "twt|123|handle");
+        assert_eq!(identity, "twt|123|handle".parse().unwrap());
+        assert_eq!(identity.provider_name(), "Twitter");
+        assert_eq!(identity.nickname(), "handle");
+        // Renaming the account doesn't make it a new participant.
+        let renamed = Identity::Twitter {
+            id: 123,
+            handle: "renamed".to_string(),
+        };
+        assert_eq!(identity.unique_id(), "twt|123");
+        assert_eq!(identity.unique_id(), renamed.unique_id());
+        assert_eq!(
+            "twt|abc|handle".parse::<Identity>().err().unwrap(),
+            IdentityError::InvalidTwitterId
+        );
+        assert_eq!(
+            "twt|123|handle|".parse::<Identity>().err().unwrap(),
+            IdentityError::TooManyFields
+        );
+    }
+
+    #[test]
+    fn test_provider() {
+        let cases = [
+            (Identity::None, IdentityProvider::None, "none"),
+            (
+                Identity::Ethereum { address: [0; 20] },
+                IdentityProvider::Ethereum,
+                "ethereum",
+            ),
+            (
+                Identity::Github {
+                    id: 123,
+                    username: "username".to_string(),
+                },
+                IdentityProvider::Github,
+                "github",
+            ),
+            (
+                Identity::Twitter {
+                    id: 123,
+                    handle: "handle".to_string(),
+                },
+                IdentityProvider::Twitter,
+                "twitter",
+            ),
+        ];
+        for (identity, provider, label) in cases {
+            assert_eq!(identity.provider(), provider);
+            assert_eq!(provider.as_str(), label);
+            assert_eq!(provider.to_string(), label);
+        }
+    }
+
     #[test]
     fn test_invalid() {
         assert_eq!(
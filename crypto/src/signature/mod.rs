@@ -9,26 +9,82 @@ use crate::{
     signature::identity::Identity,
     BatchContribution, Engine, Tau, G1, G2,
 };
-use ethers_core::types::{
-    transaction::eip712::{EIP712Domain, Eip712, Eip712Error, TypedData},
-    Signature as EthSignature,
+use ethers_core::{
+    types::{
+        transaction::eip712::{EIP712Domain, Eip712, Eip712Error, TypedData},
+        Signature as EthSignature,
+    },
+    utils::keccak256,
 };
-use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use lru::LruCache;
+use once_cell::sync::Lazy;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::json;
+use std::{
+    fmt::Debug,
+    sync::{Mutex, PoisonError},
+};
+
+/// One of the two variants of BLS signatures on BLS12-381, with the signature
+/// and the public key in opposite groups.
+pub trait BlsVariant {
+    type Signature: Copy + Eq + Debug;
+    type Pubkey: Copy + Eq + Debug;
+
+    fn sign<E: Engine>(sk: &Tau, message: &[u8]) -> Option<Self::Signature>;
+
+    fn verify<E: Engine>(sig: Self::Signature, message: &[u8], pk: Self::Pubkey) -> bool;
+}
+
+/// Signatures in G1 and public keys in G2. This is what the ceremony uses, the
+/// public key being the contribution's `pot_pubkey`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct MinSignature;
+
+impl BlsVariant for MinSignature {
+    type Signature = G1;
+    type Pubkey = G2;
+
+    fn sign<E: Engine>(sk: &Tau, message: &[u8]) -> Option<G1> {
+        E::sign_message(sk, message)
+    }
+
+    fn verify<E: Engine>(sig: G1, message: &[u8], pk: G2) -> bool {
+        E::verify_signature(sig, message, pk)
+    }
+}
+
+/// Signatures in G2 and public keys in G1, for interoperability with
+/// ecosystems that use this variant.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct MinPubkey;
+
+impl BlsVariant for MinPubkey {
+    type Signature = G2;
+    type Pubkey = G1;
+
+    fn sign<E: Engine>(sk: &Tau, message: &[u8]) -> Option<G2> {
+        E::sign_message_g2(sk, message)
+    }
+
+    fn verify<E: Engine>(sig: G2, message: &[u8], pk: G1) -> bool {
+        E::verify_signature_g2(sig, message, pk)
+    }
+}
 
 #[derive(Clone, PartialEq, Eq, Debug)]
-pub struct BlsSignature(pub Option<G1>);
+pub struct BlsSignature<V: BlsVariant = MinSignature>(pub Option<V::Signature>);
 
-impl BlsSignature {
+impl<V: BlsVariant> BlsSignature<V> {
     #[must_use]
     pub const fn empty() -> Self {
         Self(None)
     }
 
     #[must_use]
-    pub fn prune<E: Engine>(&self, message: &[u8], pk: G2) -> Self {
+    pub fn prune<E: Engine>(&self, message: &[u8], pk: V::Pubkey) -> Self {
         Self(self.0.and_then(|sig| {
-            if E::verify_signature(sig, message, pk) {
+            if V::verify::<E>(sig, message, pk) {
                 Some(sig)
             } else {
                 None
@@ -36,13 +92,22 @@ impl BlsSignature {
         }))
     }
 
+    /// True if this is a signature of `message` by the holder of `pk`.
+    #[must_use]
+    pub fn verify<E: Engine>(&self, message: &[u8], pk: V::Pubkey) -> bool {
+        self.prune::<E>(message, pk).0.is_some()
+    }
+
     #[must_use]
     pub fn sign<E: Engine>(message: &[u8], sk: &Tau) -> Self {
-        Self(E::sign_message(sk, message))
+        Self(V::sign::<E>(sk, message))
     }
 }
 
-impl Serialize for BlsSignature {
+impl<V: BlsVariant> Serialize for BlsSignature<V>
+where
+    V::Signature: Serialize,
+{
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
@@ -54,7 +119,7 @@ impl Serialize for BlsSignature {
     }
 }
 
-impl<'de> Deserialize<'de> for BlsSignature {
+impl<'de> Deserialize<'de> for BlsSignature<MinSignature> {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
@@ -63,6 +128,42 @@ impl<'de> Deserialize<'de> for BlsSignature {
     }
 }
 
+impl<'de> Deserialize<'de> for BlsSignature<MinPubkey> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        optional_hex_to_bytes::<_, 96>(deserializer).map(|bytes_opt| Self(bytes_opt.map(G2)))
+    }
+}
+
+/// Additional context bound into the BLS signature of a contribution.
+///
+/// The signed message is the `|`-separated list of the present fields followed
+/// by the participant identity, e.g. `ceremony_id|round|identity`. The default
+/// context has no fields and signs only the identity.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct SigningContext {
+    pub ceremony_id: Option<String>,
+    pub round: Option<u64>,
+}
+
+impl SigningContext {
+    /// Constructs the message to sign for `identity` in this context.
+    #[must_use]
+    pub fn message(&self, identity: &Identity) -> Vec<u8> {
+        let mut parts = Vec::new();
+        if let Some(ceremony_id) = &self.ceremony_id {
+            parts.push(ceremony_id.clone());
+        }
+        if let Some(round) = self.round {
+            parts.push(round.to_string());
+        }
+        parts.push(identity.to_string());
+        parts.join("|").into_bytes()
+    }
+}
+
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct EcdsaSignature(pub Option<EthSignature>);
 
@@ -83,6 +184,16 @@ impl EcdsaSignature {
             }
         }))
     }
+
+    /// True if the Ethereum `identity` signed `message` as a personal message
+    /// (EIP-191), as wallets do for plain text.
+    #[must_use]
+    pub fn verify_message(&self, identity: &Identity, message: &[u8]) -> bool {
+        match (&self.0, identity) {
+            (Some(sig), Identity::Ethereum { address }) => sig.verify(message, address).is_ok(),
+            _ => false,
+        }
+    }
 }
 
 impl Serialize for EcdsaSignature {
@@ -105,15 +216,14 @@ impl<'de> Deserialize<'de> for EcdsaSignature {
     where
         D: Deserializer<'de>,
     {
-        optional_hex_to_bytes::<_, 65>(deserializer).map(|bytes_opt| {
-            Self(bytes_opt.map(|bytes| {
-                EthSignature::try_from(&bytes[..]).expect("Impossible, input is guaranteed correct")
-            }))
-        })
+        optional_hex_to_bytes::<_, 65>(deserializer)?
+            .map(|bytes| EthSignature::try_from(&bytes[..]).map_err(de::Error::custom))
+            .transpose()
+            .map(Self)
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PubkeyTypedData {
     num_g1_powers: usize,
@@ -121,14 +231,37 @@ pub struct PubkeyTypedData {
     pot_pubkey: G2,
 }
 
+/// The EIP-712 domain contributions are signed in. Testnet ceremonies need
+/// the chain id of the testnet, or wallets sign a different message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContributionDomain {
+    pub name: String,
+    pub version: String,
+    pub chain_id: u64,
+}
+
+impl Default for ContributionDomain {
+    /// The mainnet ceremony domain.
+    fn default() -> Self {
+        Self {
+            name: "Ethereum KZG Ceremony".to_string(),
+            version: "1.0".to_string(),
+            chain_id: 1,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ContributionTypedData {
     pot_pubkeys: Vec<PubkeyTypedData>,
+    #[serde(skip)]
+    domain: ContributionDomain,
 }
 
-impl From<&BatchContribution> for ContributionTypedData {
-    fn from(contribution: &BatchContribution) -> Self {
+impl ContributionTypedData {
+    #[must_use]
+    pub fn new(contribution: &BatchContribution, domain: ContributionDomain) -> Self {
         Self {
             pot_pubkeys: contribution
                 .contributions
@@ -139,10 +272,17 @@ impl From<&BatchContribution> for ContributionTypedData {
                     pot_pubkey: c.pot_pubkey,
                 })
                 .collect(),
+            domain,
         }
     }
 }
 
+impl From<&BatchContribution> for ContributionTypedData {
+    fn from(contribution: &BatchContribution) -> Self {
+        Self::new(contribution, ContributionDomain::default())
+    }
+}
+
 impl From<ContributionTypedData> for TypedData {
     fn from(contrib: ContributionTypedData) -> Self {
         let json = json!({
@@ -163,9 +303,9 @@ impl From<ContributionTypedData> for TypedData {
             },
             "primaryType": "PoTPubkeys",
             "domain": {
-                "name": "Ethereum KZG Ceremony",
-                "version": "1.0",
-                "chainId": 1
+                "name": contrib.domain.name,
+                "version": contrib.domain.version,
+                "chainId": contrib.domain.chain_id
             },
             "message": contrib
         });
@@ -174,6 +314,21 @@ impl From<ContributionTypedData> for TypedData {
     }
 }
 
+/// The EIP-712 encoding of the `PoTPubkeys` type and its dependencies.
+const CONTRIBUTION_TYPE: &str = "PoTPubkeys(contributionPubkey[] potPubkeys)contributionPubkey(\
+                                 uint256 numG1Powers,uint256 numG2Powers,bytes potPubkey)";
+
+/// Number of distinct messages whose struct hash is memoized.
+const STRUCT_HASH_CACHE_SIZE: usize = 64;
+
+static TYPE_HASH: Lazy<[u8; 32]> = Lazy::new(|| keccak256(CONTRIBUTION_TYPE));
+
+/// Building the [`TypedData`] goes through JSON, so the struct hash is
+/// memoized. The key is the whole message, which is small, so entries can't
+/// collide.
+static STRUCT_HASH_CACHE: Lazy<Mutex<LruCache<Vec<PubkeyTypedData>, [u8; 32]>>> =
+    Lazy::new(|| Mutex::new(LruCache::new(STRUCT_HASH_CACHE_SIZE)));
+
 impl Eip712 for ContributionTypedData {
     type Error = Eip712Error;
 
@@ -182,19 +337,33 @@ impl Eip712 for ContributionTypedData {
     }
 
     fn type_hash() -> Result<[u8; 32], Self::Error> {
-        TypedData::type_hash()
+        Ok(*TYPE_HASH)
     }
 
     fn struct_hash(&self) -> Result<[u8; 32], Self::Error> {
-        TypedData::from(self.clone()).struct_hash()
+        let cached = STRUCT_HASH_CACHE
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&self.pot_pubkeys)
+            .copied();
+        if let Some(hash) = cached {
+            return Ok(hash);
+        }
+        let hash = TypedData::from(self.clone()).struct_hash()?;
+        STRUCT_HASH_CACHE
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .put(self.pot_pubkeys.clone(), hash);
+        Ok(hash)
     }
 }
 
 #[cfg(all(test, feature = "arkworks", feature = "blst"))]
 mod tests {
-    use crate::{
-        engine::tests::arb_f, signature::BlsSignature, Arkworks, Both, Engine, Entropy, BLST, F, G2,
-    };
+    use super::*;
+    use crate::{engine::tests::arb_f, Arkworks, BatchTranscript, Both, Entropy, BLST, F};
+    use ethers_core::{types::transaction::eip712::encode_type, utils::hash_message};
+    use ethers_signers::{LocalWallet, Signer};
     use proptest::proptest;
     use rand::{thread_rng, Rng};
     use secrecy::Secret;
@@ -206,18 +375,55 @@ mod tests {
         proptest!(|(f in arb_f(), msg in ".*")| {
             let bytes = msg.as_bytes();
             let tau = Secret::new(f);
-            let signed_blst = BlsSignature::sign::<BLST>(bytes, &tau);
-            let signed_ark = BlsSignature::sign::<Arkworks>(bytes, &tau);
+            let signed_blst = <BlsSignature>::sign::<BLST>(bytes, &tau);
+            let signed_ark = <BlsSignature>::sign::<Arkworks>(bytes, &tau);
             assert_eq!(signed_blst, signed_ark);
         });
     }
 
+    #[test]
+    fn test_verify_both_engines() {
+        proptest!(|(f in arb_f(), msg in ".*")| {
+            let bytes = msg.as_bytes();
+            let tau = Secret::new(f);
+            let sig = BLST::sign_message(&tau, bytes).unwrap();
+            let mut tmp = vec![G2::one(), G2::one()];
+            BLST::add_tau_g2(&tau, &mut tmp).unwrap();
+            let pubkey = tmp[1];
+            assert!(BothEngines::verify_signature(sig, bytes, pubkey));
+            assert!(!BothEngines::verify_signature(sig, b"other message", pubkey));
+        });
+    }
+
+    #[test]
+    fn test_min_pubkey_both_engines() {
+        proptest!(|(f in arb_f(), msg in ".*")| {
+            let bytes = msg.as_bytes();
+            let tau = Secret::new(f);
+            let signed_blst = BlsSignature::<MinPubkey>::sign::<BLST>(bytes, &tau);
+            let signed_ark = BlsSignature::<MinPubkey>::sign::<Arkworks>(bytes, &tau);
+            assert!(signed_blst.0.is_some());
+            assert_eq!(signed_blst, signed_ark);
+
+            let mut tmp = vec![G1::one(), G1::one()];
+            BLST::add_tau_g1(&tau, &mut tmp).unwrap();
+            let pubkey = tmp[1];
+            assert!(signed_blst.verify::<Arkworks>(bytes, pubkey));
+            assert!(signed_ark.verify::<BLST>(bytes, pubkey));
+            assert!(!signed_blst.verify::<BothEngines>(b"other message", pubkey));
+
+            let json = serde_json::to_string(&signed_blst).unwrap();
+            let decoded: BlsSignature<MinPubkey> = serde_json::from_str(&json).unwrap();
+            assert_eq!(decoded, signed_blst);
+        });
+    }
+
     #[test]
     fn test_bls_prune_after_encode() {
         proptest!(|(f in arb_f(), msg in ".*")| {
             let bytes = msg.as_bytes();
             let tau = Secret::new(f);
-            let signed = BlsSignature::sign::<BothEngines>(bytes, &tau);
+            let signed = <BlsSignature>::sign::<BothEngines>(bytes, &tau);
             assert!(signed.0.is_some());
             let mut tmp = vec![G2::one(), G2::one()];
             BothEngines::add_tau_g2(&tau, &mut tmp).unwrap();
@@ -232,7 +438,7 @@ mod tests {
         let message = b"git|1234|foobar";
         let wrong_msg = b"git|4567|bazbaz";
         let tau = Secret::new(F::one());
-        let signed = BlsSignature::sign::<BothEngines>(message, &tau);
+        let signed = <BlsSignature>::sign::<BothEngines>(message, &tau);
         assert!(signed.0.is_some());
         let mut tmp = vec![G2::one(), G2::one()];
         BothEngines::add_tau_g2(&tau, &mut tmp).unwrap();
@@ -246,7 +452,7 @@ mod tests {
         let message = b"git|1234|foobar";
         let tau = BothEngines::generate_tau(&Entropy::new(thread_rng().gen()));
         let wrong_tau = BothEngines::generate_tau(&Entropy::new(thread_rng().gen()));
-        let signed = BlsSignature::sign::<BothEngines>(message, &tau);
+        let signed = <BlsSignature>::sign::<BothEngines>(message, &tau);
         assert!(signed.0.is_some());
         let mut tmp = vec![G2::one(), G2::one()];
         BothEngines::add_tau_g2(&wrong_tau, &mut tmp).unwrap();
@@ -254,4 +460,103 @@ mod tests {
         let recovered = signed.prune::<BothEngines>(message, wrong_pubkey);
         assert_eq!(recovered, BlsSignature(None));
     }
+
+    #[test]
+    fn test_ecdsa_verify_message() {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let identity = Identity::Ethereum {
+            address: wallet.address().0,
+        };
+        let message = b"intent|1234";
+        let signature = EcdsaSignature(Some(wallet.sign_hash(hash_message(message))));
+
+        assert!(signature.verify_message(&identity, message));
+        assert!(!signature.verify_message(&identity, b"intent|4567"));
+        let other = Identity::Ethereum {
+            address: LocalWallet::new(&mut thread_rng()).address().0,
+        };
+        assert!(!signature.verify_message(&other, message));
+        assert!(!EcdsaSignature::empty().verify_message(&identity, message));
+        assert!(!signature.verify_message(&Identity::None, message));
+    }
+
+    fn typed_data(sizes: &[(usize, usize)]) -> ContributionTypedData {
+        ContributionTypedData::from(&BatchTranscript::new(sizes).contribution())
+    }
+
+    #[test]
+    fn test_type_hash() {
+        let typed_data = TypedData::from(typed_data(&[(4, 2)]));
+        let encoded = encode_type(&typed_data.primary_type, &typed_data.types).unwrap();
+        assert_eq!(encoded, CONTRIBUTION_TYPE);
+        assert_eq!(
+            ContributionTypedData::type_hash().unwrap(),
+            keccak256(encoded)
+        );
+    }
+
+    #[test]
+    fn test_struct_hash_cache() {
+        let data = typed_data(&[(8, 3), (16, 4)]);
+        let fresh = TypedData::from(data.clone()).struct_hash().unwrap();
+
+        assert_eq!(data.struct_hash().unwrap(), fresh);
+        assert!(STRUCT_HASH_CACHE
+            .lock()
+            .unwrap()
+            .contains(&data.pot_pubkeys));
+
+        // Repeated calls are served from the cache.
+        for _ in 0..1000 {
+            assert_eq!(data.struct_hash().unwrap(), fresh);
+        }
+
+        // Other messages hash differently, and the cache stays bounded.
+        for n in 0..2 * STRUCT_HASH_CACHE_SIZE {
+            let other = typed_data(&[(4 + n, 2)]);
+            assert_ne!(other.struct_hash().unwrap(), fresh);
+        }
+        assert!(STRUCT_HASH_CACHE.lock().unwrap().len() <= STRUCT_HASH_CACHE_SIZE);
+        assert_eq!(data.struct_hash().unwrap(), fresh);
+    }
+}
+
+#[cfg(all(feature = "bench", any(feature = "arkworks", feature = "blst")))]
+#[cfg(not(tarpaulin_include))]
+#[doc(hidden)]
+pub mod bench {
+    use super::*;
+    use crate::bench::seeded_tau;
+    #[cfg(feature = "arkworks")]
+    use crate::Arkworks;
+    #[cfg(all(feature = "arkworks", feature = "blst"))]
+    use crate::Both;
+    #[cfg(feature = "blst")]
+    use crate::BLST;
+    use criterion::Criterion;
+
+    const MESSAGE: &[u8] = b"git|1234|test_user";
+
+    pub fn group(criterion: &mut Criterion) {
+        #[cfg(feature = "arkworks")]
+        bench_engine::<Arkworks>(criterion, "arkworks");
+        #[cfg(feature = "blst")]
+        bench_engine::<BLST>(criterion, "blst");
+        #[cfg(all(feature = "arkworks", feature = "blst"))]
+        bench_engine::<Both<Arkworks, BLST>>(criterion, "both");
+    }
+
+    fn bench_engine<E: Engine>(criterion: &mut Criterion, name: &str) {
+        let tau = seeded_tau(0);
+        criterion.bench_function(&format!("signature/{name}/bls_sign"), |bencher| {
+            bencher.iter(|| <BlsSignature>::sign::<E>(MESSAGE, &tau));
+        });
+
+        let signature = <BlsSignature>::sign::<E>(MESSAGE, &tau);
+        let mut pubkey = [G2::one(), G2::one()];
+        E::add_tau_g2(&tau, &mut pubkey).unwrap();
+        criterion.bench_function(&format!("signature/{name}/bls_verify"), |bencher| {
+            bencher.iter(|| signature.prune::<E>(MESSAGE, pubkey[1]));
+        });
+    }
 }
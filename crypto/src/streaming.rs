@@ -0,0 +1,325 @@
+//! Streaming deserialization of contributions.
+//!
+//! Instead of building the whole [`Contribution`] in memory before validating
+//! it, every power is validated as soon as it is parsed, so invalid input is
+//! rejected at the first bad point.
+
+use crate::{
+    powers::NumPowers, signature::BlsSignature, CeremonyError, Contribution, Engine, ErrorCode,
+    Powers, G1, G2,
+};
+use serde::{
+    de::{self, DeserializeOwned, DeserializeSeed, MapAccess, SeqAccess, Visitor},
+    Deserializer,
+};
+use std::{cell::RefCell, fmt, io::Read, marker::PhantomData};
+use strum::IntoStaticStr;
+use thiserror::Error;
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum StreamingError {
+    #[error("Invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Ceremony(#[from] CeremonyError),
+}
+
+impl ErrorCode for StreamingError {
+    fn to_error_code(&self) -> String {
+        if let Self::Ceremony(inner) = self {
+            inner.to_error_code()
+        } else {
+            format!("StreamingError::{}", <&str>::from(self))
+        }
+    }
+}
+
+/// Reads a [`Contribution`] from `reader`, validating each power as soon as
+/// it is parsed.
+///
+/// # Errors
+///
+/// Returns [`StreamingError::Ceremony`] with the index of the first invalid
+/// power, or [`StreamingError::Json`] if the input is malformed.
+pub fn read_contribution<E: Engine, R: Read>(reader: R) -> Result<Contribution, StreamingError> {
+    let failure = RefCell::new(None);
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    let result = deserializer.deserialize_map(ContributionVisitor::<E> {
+        failure: &failure,
+        engine: PhantomData,
+    });
+    // The validation error is smuggled through serde as a custom error, report
+    // the original one.
+    if let Some(error) = failure.into_inner() {
+        return Err(error.into());
+    }
+    let contribution = result?;
+    deserializer.end()?;
+
+    E::validate_g2(&[contribution.pot_pubkey]).map_err(|error| match error {
+        CeremonyError::ZeroG2(_) => CeremonyError::ZeroPubkey,
+        error => error,
+    })?;
+    Ok(contribution)
+}
+
+trait Point: DeserializeOwned + Copy {
+    fn validate<E: Engine>(self, index: usize) -> Result<(), CeremonyError>;
+
+    fn inconsistent(expected: usize, actual: usize) -> CeremonyError;
+}
+
+impl Point for G1 {
+    fn validate<E: Engine>(self, index: usize) -> Result<(), CeremonyError> {
+        E::validate_g1(&[self]).map_err(|error| match error {
+            CeremonyError::InvalidG1Power(_, e) | CeremonyError::ParserError(e) => {
+                CeremonyError::InvalidG1Power(index, e)
+            }
+            CeremonyError::ZeroG1(_) => CeremonyError::ZeroG1(index),
+            error => error,
+        })
+    }
+
+    fn inconsistent(expected: usize, actual: usize) -> CeremonyError {
+        CeremonyError::InconsistentNumG1Powers(expected, actual)
+    }
+}
+
+impl Point for G2 {
+    fn validate<E: Engine>(self, index: usize) -> Result<(), CeremonyError> {
+        E::validate_g2(&[self]).map_err(|error| match error {
+            CeremonyError::InvalidG2Power(_, e) | CeremonyError::ParserError(e) => {
+                CeremonyError::InvalidG2Power(index, e)
+            }
+            CeremonyError::ZeroG2(_) => CeremonyError::ZeroG2(index),
+            error => error,
+        })
+    }
+
+    fn inconsistent(expected: usize, actual: usize) -> CeremonyError {
+        CeremonyError::InconsistentNumG2Powers(expected, actual)
+    }
+}
+
+/// Records `error` so it can be reported after serde unwinds.
+fn fail<D: de::Error>(failure: &RefCell<Option<CeremonyError>>, error: CeremonyError) -> D {
+    let message = error.to_string();
+    *failure.borrow_mut() = Some(error);
+    D::custom(message)
+}
+
+struct ContributionVisitor<'a, E> {
+    failure: &'a RefCell<Option<CeremonyError>>,
+    engine: PhantomData<E>,
+}
+
+impl<'de, E: Engine> Visitor<'de> for ContributionVisitor<'_, E> {
+    type Value = Contribution;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("a contribution")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut num_g1 = None;
+        let mut num_g2 = None;
+        let mut powers = None;
+        let mut pot_pubkey = None;
+        let mut bls_signature = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "numG1Powers" => num_g1 = Some(map.next_value::<NumPowers>()?.0),
+                "numG2Powers" => num_g2 = Some(map.next_value::<NumPowers>()?.0),
+                "powersOfTau" => {
+                    powers = Some(map.next_value_seed(PowersOfTauSeed::<E> {
+                        failure: self.failure,
+                        num_g1,
+                        num_g2,
+                        engine: PhantomData,
+                    })?);
+                }
+                "potPubkey" => pot_pubkey = Some(map.next_value::<G2>()?),
+                "blsSignature" => bls_signature = Some(map.next_value::<BlsSignature>()?),
+                other => return Err(de::Error::unknown_field(other, CONTRIBUTION_FIELDS)),
+            }
+        }
+        let num_g1 = num_g1.ok_or_else(|| de::Error::missing_field("numG1Powers"))?;
+        let num_g2 = num_g2.ok_or_else(|| de::Error::missing_field("numG2Powers"))?;
+        let powers: Powers = powers.ok_or_else(|| de::Error::missing_field("powersOfTau"))?;
+        if powers.g1.len() != num_g1 {
+            return Err(fail(
+                self.failure,
+                G1::inconsistent(num_g1, powers.g1.len()),
+            ));
+        }
+        if powers.g2.len() != num_g2 {
+            return Err(fail(
+                self.failure,
+                G2::inconsistent(num_g2, powers.g2.len()),
+            ));
+        }
+        Ok(Contribution {
+            powers,
+            pot_pubkey: pot_pubkey.ok_or_else(|| de::Error::missing_field("potPubkey"))?,
+            bls_signature: bls_signature.ok_or_else(|| de::Error::missing_field("blsSignature"))?,
+        })
+    }
+}
+
+const CONTRIBUTION_FIELDS: &[&str] = &[
+    "numG1Powers",
+    "numG2Powers",
+    "powersOfTau",
+    "potPubkey",
+    "blsSignature",
+];
+
+struct PowersOfTauSeed<'a, E> {
+    failure: &'a RefCell<Option<CeremonyError>>,
+    num_g1: Option<usize>,
+    num_g2: Option<usize>,
+    engine: PhantomData<E>,
+}
+
+impl<'de, E: Engine> DeserializeSeed<'de> for PowersOfTauSeed<'_, E> {
+    type Value = Powers;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, E: Engine> Visitor<'de> for PowersOfTauSeed<'_, E> {
+    type Value = Powers;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("powers of tau")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut g1 = None;
+        let mut g2 = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "G1Powers" => {
+                    g1 = Some(map.next_value_seed(PointsSeed::<G1, E> {
+                        failure: self.failure,
+                        expected: self.num_g1,
+                        point: PhantomData,
+                    })?);
+                }
+                "G2Powers" => {
+                    g2 = Some(map.next_value_seed(PointsSeed::<G2, E> {
+                        failure: self.failure,
+                        expected: self.num_g2,
+                        point: PhantomData,
+                    })?);
+                }
+                other => return Err(de::Error::unknown_field(other, &["G1Powers", "G2Powers"])),
+            }
+        }
+        Ok(Powers {
+            g1: g1.ok_or_else(|| de::Error::missing_field("G1Powers"))?,
+            g2: g2.ok_or_else(|| de::Error::missing_field("G2Powers"))?,
+        })
+    }
+}
+
+/// Deserializes a sequence of points, validating each one as it arrives. If
+/// the expected length is already known, longer sequences are rejected
+/// without reading them in full.
+struct PointsSeed<'a, P, E> {
+    failure: &'a RefCell<Option<CeremonyError>>,
+    expected: Option<usize>,
+    point: PhantomData<(P, E)>,
+}
+
+impl<'de, P: Point, E: Engine> DeserializeSeed<'de> for PointsSeed<'_, P, E> {
+    type Value = Vec<P>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, P: Point, E: Engine> Visitor<'de> for PointsSeed<'_, P, E> {
+    type Value = Vec<P>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("a sequence of points")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut points = Vec::new();
+        while let Some(point) = seq.next_element::<P>()? {
+            let index = points.len();
+            if let Some(expected) = self.expected {
+                if index >= expected {
+                    return Err(fail(self.failure, P::inconsistent(expected, index + 1)));
+                }
+            }
+            point
+                .validate::<E>(index)
+                .map_err(|error| fail(self.failure, error))?;
+            points.push(point);
+        }
+        Ok(points)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{group::tests::invalid_g1, DefaultEngine};
+
+    fn contribution_json(g1: Vec<G1>) -> String {
+        serde_json::to_string(&Contribution {
+            powers: Powers {
+                g1,
+                g2: vec![G2::one()],
+            },
+            pot_pubkey: G2::one(),
+            bls_signature: BlsSignature::empty(),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_read_valid() {
+        let json = contribution_json(vec![G1::one(); 4]);
+        let contribution = read_contribution::<DefaultEngine, _>(json.as_bytes()).unwrap();
+        assert_eq!(
+            contribution,
+            serde_json::from_str::<Contribution>(&json).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_fails_at_first_invalid_point() {
+        let json = contribution_json(vec![G1::one(), G1::one(), invalid_g1(), G1::one()]);
+        // Cut the stream right after the invalid point, validation must fail
+        // before the parser notices the truncated input.
+        let invalid = serde_json::to_string(&invalid_g1()).unwrap();
+        let end = json.find(&invalid).unwrap() + invalid.len();
+        let result = read_contribution::<DefaultEngine, _>(&json.as_bytes()[..end]);
+        assert!(matches!(
+            result,
+            Err(StreamingError::Ceremony(CeremonyError::InvalidG1Power(
+                2,
+                _
+            )))
+        ));
+    }
+
+    #[test]
+    fn test_rejects_too_many_powers() {
+        let json =
+            contribution_json(vec![G1::one(); 4]).replace("\"numG1Powers\":4", "\"numG1Powers\":2");
+        assert!(matches!(
+            read_contribution::<DefaultEngine, _>(json.as_bytes()),
+            Err(StreamingError::Ceremony(
+                CeremonyError::InconsistentNumG1Powers(2, 3)
+            ))
+        ));
+    }
+}
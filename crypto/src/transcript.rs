@@ -1,5 +1,5 @@
-use super::{CeremonyError, Contribution, Powers, G1, G2};
-use crate::{engine::Engine, signature::BlsSignature};
+use super::{CeremonyError, Contribution, ParseError, Powers, G1, G2};
+use crate::{engine::Engine, signature::BlsSignature, WitnessRecord, DEFAULT_SIGNATURE_BATCH_SIZE};
 use serde::{Deserialize, Serialize};
 use tracing::instrument;
 
@@ -57,6 +57,17 @@ impl Transcript {
         self.num_participants() > 0
     }
 
+    /// Returns the witness of the contribution at `index`, `0` being the
+    /// initial state, or `None` if it is out of range.
+    #[must_use]
+    pub fn contribution_by_index(&self, index: usize) -> Option<WitnessRecord> {
+        Some(WitnessRecord {
+            running_product: *self.witness.products.get(index)?,
+            pot_pubkey: *self.witness.pubkeys.get(index)?,
+            bls_signature: self.witness.signatures.get(index)?.clone(),
+        })
+    }
+
     /// Creates the start of a new contribution.
     #[must_use]
     pub fn contribution(&self) -> Contribution {
@@ -67,32 +78,53 @@ impl Transcript {
         }
     }
 
-    /// Verifies a contribution.
-    #[instrument(level = "info", skip_all, fields(n1=self.powers.g1.len(), n2=self.powers.g2.len()))]
-    pub fn verify<E: Engine>(&self, contribution: &Contribution) -> Result<(), CeremonyError> {
-        // Compatibility checks
-        if self.powers.g1.len() != contribution.powers.g1.len() {
+    /// Checks that `powers` has as many G1 and G2 powers as this transcript.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CeremonyError::UnexpectedNumG1Powers`] or
+    /// [`CeremonyError::UnexpectedNumG2Powers`] on a mismatch.
+    pub fn verify_shape(&self, powers: &Powers) -> Result<(), CeremonyError> {
+        if self.powers.same_shape(powers) {
+            return Ok(());
+        }
+        if self.powers.g1.len() != powers.g1.len() {
             return Err(CeremonyError::UnexpectedNumG1Powers(
                 self.powers.g1.len(),
-                contribution.powers.g1.len(),
+                powers.g1.len(),
             ));
         }
-        if self.powers.g2.len() != contribution.powers.g2.len() {
-            return Err(CeremonyError::UnexpectedNumG2Powers(
-                self.powers.g2.len(),
-                contribution.powers.g2.len(),
-            ));
+        Err(CeremonyError::UnexpectedNumG2Powers(
+            self.powers.g2.len(),
+            powers.g2.len(),
+        ))
+    }
+
+    /// Verifies a contribution.
+    #[instrument(level = "info", skip_all, fields(n1=self.powers.g1.len(), n2=self.powers.g2.len()))]
+    pub fn verify<E: Engine>(&self, contribution: &Contribution) -> Result<(), CeremonyError> {
+        self.verify_shape(&contribution.powers)?;
+
+        // Reject no-op contributions (τ = 1) before the expensive checks.
+        if contribution.powers == self.powers {
+            return Err(CeremonyError::ContributionNoEntropy);
         }
 
         // Verify the contribution points (encoding and subgroup checks).
         E::validate_g1(&contribution.powers.g1)?;
         E::validate_g2(&contribution.powers.g2)?;
-        E::validate_g2(&[contribution.pot_pubkey])?;
 
-        // Non-zero check
+        // Non-zero check, before validation reports it as a point at infinity.
         if contribution.pot_pubkey == G2::zero() {
             return Err(CeremonyError::ZeroPubkey);
         }
+        E::validate_g2(&[contribution.pot_pubkey])?;
+
+        // Repeated powers are only possible without entropy, which is
+        // reported below.
+        if contribution.has_entropy() {
+            contribution.check_distinct_powers()?;
+        }
 
         // Verify pairings.
         E::verify_pubkey(
@@ -100,23 +132,136 @@ impl Transcript {
             self.powers.g1[1],
             contribution.pot_pubkey,
         )?;
-        E::verify_g1(&contribution.powers.g1, contribution.powers.g2[1])?;
-        E::verify_g2(
-            &contribution.powers.g1[..contribution.powers.g2.len()],
-            &contribution.powers.g2,
-        )?;
+        contribution.verify_tau_consistency::<E>()?;
+
+        // Reject contributions that claim no entropy in the pubkey.
+        if !contribution.has_entropy() {
+            return Err(CeremonyError::ContributionNoEntropy);
+        }
 
         // Accept
         Ok(())
     }
 
+    /// Verifies the full history of the transcript.
+    ///
+    /// Checks that every running product builds on the previous one using the
+    /// corresponding pubkey, and that the current powers are a valid sequence
+    /// of powers whose $τ$ is the product of all contributed $τ$s.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CeremonyError::InvalidChainLink`] with the index of the first
+    /// contribution that does not build on its predecessor, or another
+    /// [`CeremonyError`] if the witness is malformed or the current powers are
+    /// not consistent with it.
+    #[instrument(level = "info", skip_all, fields(n=self.witness.products.len()))]
+    pub fn verify_chain<E: Engine>(&self) -> Result<(), CeremonyError> {
+        let products = &self.witness.products;
+        let pubkeys = &self.witness.pubkeys;
+        if products.len() != pubkeys.len() {
+            return Err(CeremonyError::WitnessLengthMismatch(
+                products.len(),
+                pubkeys.len(),
+            ));
+        }
+
+        // The chain starts at the generators.
+        if products.first() != Some(&G1::one()) || pubkeys.first() != Some(&G2::one()) {
+            return Err(CeremonyError::InvalidChainLink(0));
+        }
+
+        // Every running product must be the previous one raised to the
+        // contributed tau.
+        for i in 1..products.len() {
+            if products[i] == G1::zero() || pubkeys[i] == G2::zero() {
+                return Err(CeremonyError::InvalidChainLink(i));
+            }
+            E::validate_g1(&products[i..=i]).map_err(|e| {
+                witness_parse_error(e, |p| CeremonyError::InvalidWitnessProduct(i, p))
+            })?;
+            E::validate_g2(&pubkeys[i..=i]).map_err(|e| {
+                witness_parse_error(e, |p| CeremonyError::InvalidWitnessPubKey(i, p))
+            })?;
+            E::verify_pubkey(products[i], products[i - 1], pubkeys[i])
+                .map_err(|_| CeremonyError::InvalidChainLink(i))?;
+        }
+
+        // The current powers must continue from the last running product.
+        if Some(&self.powers.g1[1]) != products.last() {
+            return Err(CeremonyError::ChainPowersMismatch);
+        }
+        E::verify_pubkey(self.powers.g1[1], G1::one(), self.powers.g2[1])
+            .map_err(|_| CeremonyError::ChainPowersMismatch)?;
+        E::verify_g1(&self.powers.g1, self.powers.g2[1])?;
+        E::verify_g2(&self.powers.g1[..self.powers.g2.len()], &self.powers.g2)?;
+
+        Ok(())
+    }
+
+    /// Verifies the BLS signatures kept in the witness, where `messages[i]`
+    /// is what participant `i` signed. Pruned (empty) signatures are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CeremonyError::InvalidWitnessSignature`] with the index of
+    /// the first signature that does not verify.
+    pub fn verify_signatures<E: Engine>(&self, messages: &[String]) -> Result<(), CeremonyError> {
+        let (indices, signatures): (Vec<_>, Vec<_>) = self
+            .witness
+            .signatures
+            .iter()
+            .zip(&self.witness.pubkeys)
+            .zip(messages)
+            .enumerate()
+            .filter_map(|(i, ((signature, pubkey), message))| {
+                Some((i, (signature.0?, message.as_bytes(), *pubkey)))
+            })
+            .unzip();
+        let verdicts = E::verify_signatures(&signatures, DEFAULT_SIGNATURE_BATCH_SIZE);
+        match indices.into_iter().zip(verdicts).find(|(_, valid)| !valid) {
+            Some((i, _)) => Err(CeremonyError::InvalidWitnessSignature(i)),
+            None => Ok(()),
+        }
+    }
+
+    /// True if `contribution` moves the accumulated $τ$ on. This is
+    /// [`Contribution::has_entropy`] for the running powers: the new
+    /// $τ ⋅ g_2$ must differ from the one it builds on.
+    #[must_use]
+    pub fn advanced_by(&self, contribution: &Contribution) -> bool {
+        contribution.has_entropy() && contribution.powers.g2.get(1) != self.powers.g2.get(1)
+    }
+
     /// Adds a contribution to the transcript. The contribution must be
     /// verified.
-    pub fn add(&mut self, contribution: Contribution) {
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CeremonyError::EntropyNotAdvanced`] and leaves the transcript
+    /// unchanged if the contribution would not advance the accumulated
+    /// entropy, see [`Transcript::advanced_by`].
+    pub fn add(&mut self, contribution: Contribution) -> Result<(), CeremonyError> {
+        if !self.advanced_by(&contribution) {
+            return Err(CeremonyError::EntropyNotAdvanced);
+        }
         self.witness.products.push(contribution.powers.g1[1]);
         self.witness.pubkeys.push(contribution.pot_pubkey);
         self.witness.signatures.push(contribution.bls_signature);
         self.powers = contribution.powers;
+        Ok(())
+    }
+}
+
+fn witness_parse_error(
+    error: CeremonyError,
+    wrap: impl FnOnce(ParseError) -> CeremonyError,
+) -> CeremonyError {
+    match error {
+        CeremonyError::InvalidG1Power(_, e)
+        | CeremonyError::InvalidG2Power(_, e)
+        | CeremonyError::ParserError(e) => wrap(e),
+        e => e,
     }
 }
 
@@ -125,15 +270,28 @@ mod test {
     use super::*;
     use crate::{
         CeremonyError::{
-            G1PairingFailed, G2PairingFailed, InvalidG1Power, InvalidG2Power, PubKeyPairingFailed,
-            UnexpectedNumG1Powers, UnexpectedNumG2Powers,
+            ContributionNoEntropy, DegeneratePowers, EntropyNotAdvanced, G1PairingFailed,
+            G2PairingFailed, InvalidChainLink, InvalidG1Power, InvalidG2Power, PubKeyPairingFailed,
+            UnexpectedNumG1Powers, UnexpectedNumG2Powers, ZeroG1, ZeroG2,
         },
-        DefaultEngine,
+        DefaultEngine, Identity,
         ParseError::InvalidSubgroup,
+        F,
     };
     use ark_bls12_381::{Fr, G1Affine, G2Affine};
     use ark_ec::{AffineCurve, ProjectiveCurve};
     use hex_literal::hex;
+    use secrecy::Secret;
+
+    #[test]
+    fn contribution_by_index() {
+        let t = Transcript::new(4, 2);
+        let initial = t.contribution_by_index(0).unwrap();
+        assert_eq!(initial.running_product, G1::one());
+        assert_eq!(initial.pot_pubkey, G2::one());
+        assert_eq!(initial.bls_signature, BlsSignature::empty());
+        assert!(t.contribution_by_index(1).is_none());
+    }
 
     #[test]
     fn transcript_json() {
@@ -197,7 +355,7 @@ mod test {
 
         let bad_g2_contribution = Contribution {
             powers: Powers {
-                g1: vec![G1::zero(), G1::zero()],
+                g1: vec![G1::one(), G1::one()],
                 g2: vec![point_not_in_g2, point_not_in_g2],
             },
             pot_pubkey: G2::zero(),
@@ -210,6 +368,24 @@ mod test {
         assert!(matches!(result, InvalidG2Power(_, InvalidSubgroup)));
     }
 
+    #[test]
+    fn test_verify_point_at_infinity() {
+        let transcript = Transcript::new(4, 2);
+        let mut contribution = transcript.contribution();
+        contribution.powers.g1[2] = G1::zero();
+        assert_eq!(
+            transcript.verify::<DefaultEngine>(&contribution),
+            Err(ZeroG1(2))
+        );
+
+        let mut contribution = transcript.contribution();
+        contribution.powers.g2[1] = G2::zero();
+        assert_eq!(
+            transcript.verify::<DefaultEngine>(&contribution),
+            Err(ZeroG2(1))
+        );
+    }
+
     #[test]
     fn test_verify_wrong_pubkey() {
         let transcript = Transcript::new(2, 2);
@@ -340,4 +516,114 @@ mod test {
             .unwrap();
         assert_eq!(result, UnexpectedNumG2Powers(3, 2));
     }
+
+    #[test]
+    fn test_verify_no_entropy() {
+        let transcript = Transcript::new(3, 3);
+        let contribution = transcript.contribution();
+        let result = transcript
+            .verify::<DefaultEngine>(&contribution)
+            .err()
+            .unwrap();
+        assert_eq!(result, ContributionNoEntropy);
+    }
+
+    #[test]
+    fn test_verify_tau_one() {
+        let transcript = Transcript::new(3, 3);
+        let mut contribution = transcript.contribution();
+        contribution
+            .add_tau::<DefaultEngine>(&Secret::new(F::one()), &Identity::None)
+            .unwrap();
+        assert!(contribution.is_identity(&transcript.contribution()));
+        let result = transcript
+            .verify::<DefaultEngine>(&contribution)
+            .err()
+            .unwrap();
+        assert_eq!(result, ContributionNoEntropy);
+    }
+
+    #[test]
+    fn test_verify_degenerate_powers() {
+        let transcript = Transcript::new(4, 3);
+        let tau = DefaultEngine::generate_tau(&Secret::new([1; 32]));
+        let mut genuine = transcript.contribution();
+        genuine
+            .add_tau::<DefaultEngine>(&tau, &Identity::None)
+            .unwrap();
+        assert_eq!(genuine.check_distinct_powers(), Ok(()));
+        assert_eq!(transcript.verify::<DefaultEngine>(&genuine), Ok(()));
+
+        // The generator repeated in place of the first power.
+        let mut repeated = genuine.clone();
+        repeated.powers.g1[1] = repeated.powers.g1[0];
+        assert_eq!(
+            transcript.verify::<DefaultEngine>(&repeated),
+            Err(DegeneratePowers)
+        );
+
+        let mut repeated = genuine.clone();
+        repeated.powers.g1[3] = repeated.powers.g1[2];
+        assert_eq!(
+            transcript.verify::<DefaultEngine>(&repeated),
+            Err(DegeneratePowers)
+        );
+
+        let mut repeated = genuine;
+        repeated.powers.g2[2] = repeated.powers.g2[1];
+        assert_eq!(
+            transcript.verify::<DefaultEngine>(&repeated),
+            Err(DegeneratePowers)
+        );
+    }
+
+    fn chain_transcript(n: u8) -> Transcript {
+        let mut transcript = Transcript::new(4, 2);
+        for i in 1..=n {
+            let tau = DefaultEngine::generate_tau(&Secret::new([i; 32]));
+            let mut contribution = transcript.contribution();
+            contribution
+                .add_tau::<DefaultEngine>(&tau, &Identity::None)
+                .unwrap();
+            transcript.verify::<DefaultEngine>(&contribution).unwrap();
+            transcript.add(contribution).unwrap();
+        }
+        transcript
+    }
+
+    #[test]
+    fn test_add_requires_entropy_advance() {
+        let mut transcript = chain_transcript(1);
+        let before = transcript.clone();
+
+        // The previous powers under a pubkey that claims entropy.
+        let mut stale = transcript.contribution();
+        stale.pot_pubkey = transcript.witness.pubkeys[1];
+        assert!(stale.has_entropy());
+        assert_eq!(transcript.add(stale), Err(EntropyNotAdvanced));
+
+        let unchanged = transcript.contribution();
+        assert_eq!(transcript.add(unchanged), Err(EntropyNotAdvanced));
+        assert_eq!(transcript, before);
+    }
+
+    #[test]
+    fn test_verify_chain() {
+        let transcript = chain_transcript(3);
+        assert_eq!(transcript.verify_chain::<DefaultEngine>(), Ok(()));
+    }
+
+    #[test]
+    fn test_verify_chain_broken_link() {
+        let mut transcript = chain_transcript(3);
+        transcript.witness.products[2] = G1::from(
+            G1Affine::prime_subgroup_generator()
+                .mul(Fr::from(42))
+                .into_affine(),
+        );
+        assert_eq!(
+            transcript.verify_chain::<DefaultEngine>(),
+            Err(InvalidChainLink(2))
+        );
+    }
 }
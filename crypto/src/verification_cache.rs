@@ -0,0 +1,219 @@
+//! Memoized verification verdicts, so that verifying unchanged data again,
+//! e.g. the same contribution against the same transcript, doesn't repeat the
+//! pairings.
+//!
+//! Entries are keyed by a hash of everything the verdict depends on, so data
+//! that changed simply misses the cache and there is nothing to invalidate.
+//! Only the validity of points and powers is cached. The BLS signature is not
+//! part of the key, so checks that bind a contribution to an identity or
+//! another context must not go through the cache.
+
+use crate::{engine::Engine, CeremonyError, Contribution, Powers, Transcript, G2};
+use lru::LruCache;
+use sha2::{Digest, Sha256};
+use std::{
+    any::type_name,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, PoisonError,
+    },
+};
+
+/// Number of verdicts kept by [`VerificationCache::default`].
+pub const DEFAULT_VERIFICATION_CACHE_SIZE: usize = 256;
+
+pub struct VerificationCache {
+    verdicts: Mutex<LruCache<[u8; 32], Result<(), CeremonyError>>>,
+    hits: AtomicUsize,
+}
+
+impl Default for VerificationCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_VERIFICATION_CACHE_SIZE)
+    }
+}
+
+impl VerificationCache {
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            verdicts: Mutex::new(LruCache::new(capacity)),
+            hits: AtomicUsize::new(0),
+        }
+    }
+
+    /// Number of verdicts that were answered from the cache.
+    #[must_use]
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Returns the cached verdict for `key`, or runs `verify` and caches its
+    /// verdict.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `verify`, cached or not.
+    pub fn get_or_verify(
+        &self,
+        key: [u8; 32],
+        verify: impl FnOnce() -> Result<(), CeremonyError>,
+    ) -> Result<(), CeremonyError> {
+        let cached = self
+            .verdicts
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&key)
+            .copied();
+        if let Some(verdict) = cached {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return verdict;
+        }
+        // Not holding the lock while verifying, concurrent misses of the same
+        // key both do the work, which is harmless.
+        let verdict = verify();
+        self.verdicts
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .put(key, verdict);
+        verdict
+    }
+
+    /// Cached [`Contribution::validate`].
+    ///
+    /// # Errors
+    ///
+    /// See [`Contribution::validate`].
+    pub fn validate<E: Engine>(
+        &self,
+        contribution: &mut Contribution,
+    ) -> Result<(), CeremonyError> {
+        let mut hasher = KeyHasher::new::<E>("validate");
+        hasher.powers(&contribution.powers);
+        hasher.g2(contribution.pot_pubkey);
+        self.get_or_verify(hasher.finish(), || contribution.validate::<E>())
+    }
+
+    /// Cached [`Transcript::verify`].
+    ///
+    /// # Errors
+    ///
+    /// See [`Transcript::verify`].
+    pub fn verify<E: Engine>(
+        &self,
+        transcript: &Transcript,
+        contribution: &Contribution,
+    ) -> Result<(), CeremonyError> {
+        let mut hasher = KeyHasher::new::<E>("verify");
+        hasher.powers(&transcript.powers);
+        hasher.powers(&contribution.powers);
+        hasher.g2(contribution.pot_pubkey);
+        self.get_or_verify(hasher.finish(), || transcript.verify::<E>(contribution))
+    }
+}
+
+/// Hashes the raw point encodings. Lengths are included, so that different
+/// splits of the same points give different keys.
+struct KeyHasher(Sha256);
+
+impl KeyHasher {
+    fn new<E: Engine>(check: &str) -> Self {
+        let mut hasher = Self(Sha256::new());
+        hasher.bytes(check.as_bytes());
+        // Engines are meant to agree, but a verdict is only reused by the
+        // engine that produced it.
+        hasher.bytes(type_name::<E>().as_bytes());
+        hasher
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.len(bytes.len());
+        self.0.update(bytes);
+    }
+
+    fn len(&mut self, len: usize) {
+        self.0.update((len as u64).to_be_bytes());
+    }
+
+    fn powers(&mut self, powers: &Powers) {
+        self.len(powers.g1.len());
+        for point in &powers.g1 {
+            self.0.update(point.0);
+        }
+        self.len(powers.g2.len());
+        for point in &powers.g2 {
+            self.0.update(point.0);
+        }
+    }
+
+    fn g2(&mut self, point: G2) {
+        self.0.update(point.0);
+    }
+
+    fn finish(self) -> [u8; 32] {
+        self.0.finalize().into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DefaultEngine, Identity};
+    use secrecy::Secret;
+
+    fn contribution(transcript: &Transcript, entropy: u8) -> Contribution {
+        let mut contribution = transcript.contribution();
+        contribution
+            .add_tau::<DefaultEngine>(
+                &DefaultEngine::generate_tau(&Secret::new([entropy; 32])),
+                &Identity::None,
+            )
+            .unwrap();
+        contribution
+    }
+
+    #[test]
+    fn test_repeated_verify_is_cached() {
+        let cache = VerificationCache::default();
+        let transcript = Transcript::new(4, 2);
+        let contribution = contribution(&transcript, 1);
+        let verify = |transcript: &Transcript, contribution: &Contribution| {
+            cache.verify::<DefaultEngine>(transcript, contribution)
+        };
+
+        assert_eq!(verify(&transcript, &contribution), Ok(()));
+        assert_eq!(cache.hits(), 0);
+        assert_eq!(verify(&transcript, &contribution), Ok(()));
+        assert_eq!(cache.hits(), 1);
+
+        // Changed data misses the cache.
+        let mut tampered = contribution.clone();
+        tampered.powers.g1[2] = tampered.powers.g1[3];
+        assert!(verify(&transcript, &tampered).is_err());
+        assert_eq!(cache.hits(), 1);
+        assert!(verify(&transcript, &tampered).is_err());
+        assert_eq!(cache.hits(), 2);
+
+        // So does the same contribution against a different transcript.
+        let mut next = transcript.clone();
+        next.add(contribution.clone()).unwrap();
+        assert!(verify(&next, &contribution).is_err());
+        assert_eq!(cache.hits(), 2);
+    }
+
+    #[test]
+    fn test_cached_verdict_skips_work() {
+        let cache = VerificationCache::default();
+        let work = AtomicUsize::new(0);
+        let verify = || {
+            cache.get_or_verify([1; 32], || {
+                work.fetch_add(1, Ordering::SeqCst);
+                Err(CeremonyError::ContributionNoEntropy)
+            })
+        };
+
+        assert_eq!(verify(), Err(CeremonyError::ContributionNoEntropy));
+        assert_eq!(verify(), Err(CeremonyError::ContributionNoEntropy));
+        assert_eq!(work.load(Ordering::SeqCst), 1);
+    }
+}
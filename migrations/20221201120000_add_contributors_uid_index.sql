@@ -0,0 +1 @@
+CREATE INDEX IF NOT EXISTS contributors_uid ON contributors (uid);
@@ -0,0 +1 @@
+UPDATE contributors SET uid = LOWER(uid) WHERE uid LIKE 'git|%';
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS linked_identities (
+    uid       TEXT PRIMARY KEY NOT NULL,
+    principal TEXT             NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS linked_identities_principal ON linked_identities (principal);
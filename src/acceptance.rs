@@ -0,0 +1,40 @@
+//! Extension point to confirm a contribution with an external coordinator
+//! before it is added to the transcript, for deployments that run several
+//! sequencers.
+
+use axum::async_trait;
+use kzg_ceremony_crypto::{signature::identity::Identity, BatchContribution};
+use std::sync::Arc;
+use thiserror::Error;
+
+pub type SharedAcceptanceHook = Arc<dyn AcceptanceHook>;
+
+#[derive(Debug, Error)]
+#[error("{0}")]
+pub struct AcceptanceRejected(pub String);
+
+/// Called with every contribution that passed verification, right before it
+/// is added to the transcript. Returning an error aborts the contribution and
+/// frees the slot for the next participant.
+#[async_trait]
+pub trait AcceptanceHook: Send + Sync {
+    async fn accept(
+        &self,
+        identity: &Identity,
+        contribution: &BatchContribution,
+    ) -> Result<(), AcceptanceRejected>;
+}
+
+/// Accepts every contribution.
+pub struct NoopAcceptanceHook;
+
+#[async_trait]
+impl AcceptanceHook for NoopAcceptanceHook {
+    async fn accept(
+        &self,
+        _identity: &Identity,
+        _contribution: &BatchContribution,
+    ) -> Result<(), AcceptanceRejected> {
+        Ok(())
+    }
+}
@@ -0,0 +1,477 @@
+use crate::{
+    lobby::{SessionStatus, SharedLobbyState},
+    storage::{PersistentStorage, StorageError},
+    Options, SessionId,
+};
+use axum::{extract::Path, Extension, Json, TypedHeader};
+use headers::{authorization::Bearer, Authorization};
+use http::StatusCode;
+use kzg_ceremony_crypto::ErrorCode;
+use strum::IntoStaticStr;
+use thiserror::Error;
+use tokio::task::JoinError;
+use tracing::warn;
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum AdminError {
+    #[error("invalid admin token")]
+    Unauthorized,
+    #[error("session not found")]
+    SessionNotFound,
+    #[error("error in storage layer: {0}")]
+    StorageError(#[from] StorageError),
+    #[error("background task error: {0}")]
+    TaskError(#[from] JoinError),
+}
+
+impl ErrorCode for AdminError {
+    fn to_error_code(&self) -> String {
+        format!("AdminError::{}", <&str>::from(self))
+    }
+}
+
+fn authorize(
+    authorization: Option<TypedHeader<Authorization<Bearer>>>,
+    options: &Options,
+) -> Result<(), AdminError> {
+    match (authorization, &options.admin_token) {
+        (Some(TypedHeader(Authorization(bearer))), Some(admin_token))
+            if admin_token.matches(bearer.token()) =>
+        {
+            Ok(())
+        }
+        _ => Err(AdminError::Unauthorized),
+    }
+}
+
+/// Kicks a session out of the ceremony. If it is the active contributor, the
+/// contributor slot is freed. The session can not re-enter the lobby until the
+/// revocation expires.
+pub async fn revoke_session(
+    authorization: Option<TypedHeader<Authorization<Bearer>>>,
+    Path(session_id): Path<String>,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(storage): Extension<PersistentStorage>,
+    Extension(options): Extension<Options>,
+) -> Result<StatusCode, AdminError> {
+    authorize(authorization, &options)?;
+
+    let session_id = SessionId(session_id);
+    warn!(session_key = %session_id.key(), "revoking session");
+
+    // Revoke in the background, so that request cancelation doesn't interrupt
+    // it inbetween the lobby_state and storage calls.
+    tokio::spawn(async move {
+        if let Some(info) = lobby_state.revoke_session(&session_id).await {
+            storage
+                .expire_contribution(&info.token.unique_identifier())
+                .await?;
+        }
+        Ok(StatusCode::OK)
+    })
+    .await
+    .unwrap_or_else(|e| Err(AdminError::TaskError(e)))
+}
+
+/// Shows a session's identity, when it last pinged, its position in the lobby
+/// and whether it holds the contributor slot, for debugging stuck
+/// participants.
+pub async fn inspect_session(
+    authorization: Option<TypedHeader<Authorization<Bearer>>>,
+    Path(session_id): Path<String>,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(options): Extension<Options>,
+) -> Result<Json<SessionStatus>, AdminError> {
+    authorize(authorization, &options)?;
+
+    lobby_state
+        .session_status(&SessionId(session_id))
+        .await
+        .map(Json)
+        .ok_or(AdminError::SessionNotFound)
+}
+
+/// Removes a session from the lobby. If it is the active contributor, the
+/// contributor slot is freed. Unlike [`revoke_session`], the participant can
+/// sign in again right away.
+pub async fn evict_session(
+    authorization: Option<TypedHeader<Authorization<Bearer>>>,
+    Path(session_id): Path<String>,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(storage): Extension<PersistentStorage>,
+    Extension(options): Extension<Options>,
+) -> Result<StatusCode, AdminError> {
+    authorize(authorization, &options)?;
+
+    let session_id = SessionId(session_id);
+    warn!(session_key = %session_id.key(), "evicting session");
+
+    // Evict in the background, so that request cancelation doesn't interrupt
+    // it inbetween the lobby_state and storage calls.
+    tokio::spawn(async move {
+        match lobby_state.evict_session(&session_id).await {
+            None => Err(AdminError::SessionNotFound),
+            Some((info, true)) => {
+                storage
+                    .expire_contribution(&info.token.unique_identifier())
+                    .await?;
+                Ok(StatusCode::OK)
+            }
+            Some((_, false)) => Ok(StatusCode::OK),
+        }
+    })
+    .await
+    .unwrap_or_else(|e| Err(AdminError::TaskError(e)))
+}
+
+/// Stops new participants from claiming the contributor slot. The active
+/// contributor can still finish, status and transcript downloads stay live.
+/// The paused state survives restarts.
+pub async fn pause(
+    authorization: Option<TypedHeader<Authorization<Bearer>>>,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(storage): Extension<PersistentStorage>,
+    Extension(options): Extension<Options>,
+) -> Result<StatusCode, AdminError> {
+    set_paused(true, authorization, lobby_state, storage, &options).await
+}
+
+/// Lets participants claim the contributor slot again after [`pause`].
+pub async fn resume(
+    authorization: Option<TypedHeader<Authorization<Bearer>>>,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(storage): Extension<PersistentStorage>,
+    Extension(options): Extension<Options>,
+) -> Result<StatusCode, AdminError> {
+    set_paused(false, authorization, lobby_state, storage, &options).await
+}
+
+async fn set_paused(
+    paused: bool,
+    authorization: Option<TypedHeader<Authorization<Bearer>>>,
+    lobby_state: SharedLobbyState,
+    storage: PersistentStorage,
+    options: &Options,
+) -> Result<StatusCode, AdminError> {
+    authorize(authorization, options)?;
+    warn!(paused, "setting ceremony paused state");
+
+    // Persist first, so that a restart never resumes a paused ceremony.
+    tokio::spawn(async move {
+        storage.set_paused(paused).await?;
+        lobby_state.set_paused(paused).await;
+        Ok(StatusCode::OK)
+    })
+    .await
+    .unwrap_or_else(|e| Err(AdminError::TaskError(e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        api::v1::lobby::{try_contribute, TryContributeError},
+        sessions::SessionInfo,
+        storage::storage_client,
+        test_util::{create_test_session_info, test_options},
+        tests::test_transcript,
+    };
+    use kzg_ceremony_crypto::signature::identity::Identity;
+    use std::{sync::Arc, time::Duration};
+    use tokio::sync::RwLock;
+
+    #[tokio::test]
+    async fn revoke_active_contributor() {
+        let mut opts = test_options();
+        opts.admin_token = Some("admin".parse().unwrap());
+        let lobby_state = SharedLobbyState::new(opts.lobby.clone());
+        let transcript = Arc::new(RwLock::new(test_transcript()));
+        let db = storage_client(&opts.storage).await.unwrap();
+
+        let session_id = SessionId::new();
+        let other_session_id = SessionId::new();
+        lobby_state
+            .insert_session(session_id.clone(), create_test_session_info(100))
+            .await
+            .unwrap();
+        let mut other_session_info = create_test_session_info(100);
+        other_session_info.token.identity = Identity::Github {
+            id: 5678,
+            username: "other_user".to_string(),
+        };
+        lobby_state
+            .insert_session(other_session_id.clone(), other_session_info)
+            .await
+            .unwrap();
+
+        let try_contribute = |session_id: &SessionId| {
+            try_contribute(
+                session_id.clone(),
+                None,
+                Extension(lobby_state.clone()),
+                Extension(db.clone()),
+                Extension(transcript.clone()),
+                Extension(opts.clone()),
+            )
+        };
+
+        assert!(try_contribute(&session_id).await.is_ok());
+
+        let unauthorized = revoke_session(
+            None,
+            Path(session_id.0.clone()),
+            Extension(lobby_state.clone()),
+            Extension(db.clone()),
+            Extension(opts.clone()),
+        )
+        .await;
+        assert!(matches!(unauthorized, Err(AdminError::Unauthorized)));
+
+        let revoked = revoke_session(
+            Some(TypedHeader(Authorization::bearer("admin").unwrap())),
+            Path(session_id.0.clone()),
+            Extension(lobby_state.clone()),
+            Extension(db.clone()),
+            Extension(opts.clone()),
+        )
+        .await;
+        assert!(matches!(revoked, Ok(StatusCode::OK)));
+
+        // The contributor slot is free for the next participant.
+        assert!(try_contribute(&other_session_id).await.is_ok());
+
+        // The revoked session can not re-enter.
+        assert!(matches!(
+            try_contribute(&session_id).await,
+            Err(TryContributeError::SessionRevoked)
+        ));
+    }
+
+    #[tokio::test]
+    async fn pause_and_resume() {
+        let mut opts = test_options();
+        opts.admin_token = Some("admin".parse().unwrap());
+        opts.lobby.lobby_checkin_frequency = Duration::ZERO;
+        let lobby_state = SharedLobbyState::new(opts.lobby.clone());
+        let transcript = Arc::new(RwLock::new(test_transcript()));
+        let db = storage_client(&opts.storage).await.unwrap();
+
+        let session_id = SessionId::new();
+        lobby_state
+            .insert_session(session_id.clone(), create_test_session_info(100))
+            .await
+            .unwrap();
+
+        let admin = || Some(TypedHeader(Authorization::bearer("admin").unwrap()));
+        let try_contribute = || {
+            try_contribute(
+                session_id.clone(),
+                None,
+                Extension(lobby_state.clone()),
+                Extension(db.clone()),
+                Extension(transcript.clone()),
+                Extension(opts.clone()),
+            )
+        };
+
+        assert!(matches!(
+            pause(
+                None,
+                Extension(lobby_state.clone()),
+                Extension(db.clone()),
+                Extension(opts.clone()),
+            )
+            .await,
+            Err(AdminError::Unauthorized)
+        ));
+        assert!(matches!(
+            pause(
+                admin(),
+                Extension(lobby_state.clone()),
+                Extension(db.clone()),
+                Extension(opts.clone()),
+            )
+            .await,
+            Ok(StatusCode::OK)
+        ));
+        assert!(db.is_paused().await.unwrap());
+        assert!(matches!(
+            try_contribute().await,
+            Err(TryContributeError::CeremonyPaused)
+        ));
+
+        assert!(matches!(
+            resume(
+                admin(),
+                Extension(lobby_state.clone()),
+                Extension(db.clone()),
+                Extension(opts.clone()),
+            )
+            .await,
+            Ok(StatusCode::OK)
+        ));
+        assert!(!db.is_paused().await.unwrap());
+        assert!(try_contribute().await.is_ok());
+    }
+
+    fn session_info(id: u64) -> SessionInfo {
+        let mut info = create_test_session_info(100);
+        info.token.identity = Identity::Github {
+            id,
+            username: format!("user_{id}"),
+        };
+        info
+    }
+
+    #[tokio::test]
+    async fn inspect_sessions() {
+        let mut opts = test_options();
+        opts.admin_token = Some("admin".parse().unwrap());
+        let lobby_state = SharedLobbyState::new(opts.lobby.clone());
+        let transcript = Arc::new(RwLock::new(test_transcript()));
+        let db = storage_client(&opts.storage).await.unwrap();
+
+        let ids = [SessionId::new(), SessionId::new(), SessionId::new()];
+        for (id, session_id) in (1..).zip(&ids) {
+            lobby_state
+                .insert_session(session_id.clone(), session_info(id))
+                .await
+                .unwrap();
+        }
+        lobby_state.enter_lobby(&ids[0]).await.unwrap();
+        lobby_state.enter_lobby(&ids[1]).await.unwrap();
+
+        let inspect = |session_id: &SessionId| {
+            inspect_session(
+                Some(TypedHeader(Authorization::bearer("admin").unwrap())),
+                Path(session_id.0.clone()),
+                Extension(lobby_state.clone()),
+                Extension(opts.clone()),
+            )
+        };
+
+        let Json(second) = inspect(&ids[1]).await.unwrap();
+        assert_eq!(second.identity, session_info(2).token.identity);
+        assert_eq!(second.queue_position, Some(1));
+        assert!(!second.active_contributor);
+        let Json(outside) = inspect(&ids[2]).await.unwrap();
+        assert_eq!(outside.queue_position, None);
+
+        try_contribute(
+            ids[0].clone(),
+            None,
+            Extension(lobby_state.clone()),
+            Extension(db.clone()),
+            Extension(transcript.clone()),
+            Extension(opts.clone()),
+        )
+        .await
+        .unwrap();
+        let Json(active) = inspect(&ids[0]).await.unwrap();
+        assert!(active.active_contributor);
+        assert_eq!(active.queue_position, None);
+        let Json(second) = inspect(&ids[1]).await.unwrap();
+        assert_eq!(second.queue_position, Some(0));
+
+        assert!(matches!(
+            inspect(&SessionId::new()).await,
+            Err(AdminError::SessionNotFound)
+        ));
+        assert!(matches!(
+            inspect_session(
+                None,
+                Path(ids[0].0.clone()),
+                Extension(lobby_state.clone()),
+                Extension(opts.clone()),
+            )
+            .await,
+            Err(AdminError::Unauthorized)
+        ));
+    }
+
+    #[tokio::test]
+    async fn evict_idle_session() {
+        let mut opts = test_options();
+        opts.admin_token = Some("admin".parse().unwrap());
+        let lobby_state = SharedLobbyState::new(opts.lobby.clone());
+        let db = storage_client(&opts.storage).await.unwrap();
+
+        let session_id = SessionId::new();
+        lobby_state
+            .insert_session(session_id.clone(), session_info(1))
+            .await
+            .unwrap();
+        lobby_state.enter_lobby(&session_id).await.unwrap();
+
+        let evict = || {
+            evict_session(
+                Some(TypedHeader(Authorization::bearer("admin").unwrap())),
+                Path(session_id.0.clone()),
+                Extension(lobby_state.clone()),
+                Extension(db.clone()),
+                Extension(opts.clone()),
+            )
+        };
+
+        assert!(matches!(evict().await, Ok(StatusCode::OK)));
+        assert_eq!(lobby_state.get_lobby_size().await, 0);
+        assert_eq!(lobby_state.session_status(&session_id).await, None);
+        assert!(matches!(evict().await, Err(AdminError::SessionNotFound)));
+
+        // Unlike a revoked session, it can come back right away.
+        lobby_state
+            .insert_session(session_id.clone(), session_info(1))
+            .await
+            .unwrap();
+        assert!(lobby_state.ensure_not_revoked(&session_id).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn evict_active_contributor() {
+        let mut opts = test_options();
+        opts.admin_token = Some("admin".parse().unwrap());
+        opts.lobby.lobby_checkin_frequency = Duration::ZERO;
+        let lobby_state = SharedLobbyState::new(opts.lobby.clone());
+        let transcript = Arc::new(RwLock::new(test_transcript()));
+        let db = storage_client(&opts.storage).await.unwrap();
+
+        let session_id = SessionId::new();
+        let other_session_id = SessionId::new();
+        lobby_state
+            .insert_session(session_id.clone(), session_info(1))
+            .await
+            .unwrap();
+        lobby_state
+            .insert_session(other_session_id.clone(), session_info(2))
+            .await
+            .unwrap();
+
+        let try_contribute = |session_id: &SessionId| {
+            try_contribute(
+                session_id.clone(),
+                None,
+                Extension(lobby_state.clone()),
+                Extension(db.clone()),
+                Extension(transcript.clone()),
+                Extension(opts.clone()),
+            )
+        };
+
+        assert!(try_contribute(&session_id).await.is_ok());
+        assert!(try_contribute(&other_session_id).await.is_err());
+
+        let evicted = evict_session(
+            Some(TypedHeader(Authorization::bearer("admin").unwrap())),
+            Path(session_id.0.clone()),
+            Extension(lobby_state.clone()),
+            Extension(db.clone()),
+            Extension(opts.clone()),
+        )
+        .await;
+        assert!(matches!(evicted, Ok(StatusCode::OK)));
+        assert_eq!(lobby_state.session_status(&session_id).await, None);
+
+        // The contributor slot is free for the next participant.
+        assert!(try_contribute(&other_session_id).await.is_ok());
+    }
+}
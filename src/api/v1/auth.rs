@@ -1,26 +1,31 @@
 use crate::{
-    lobby::SharedLobbyState,
-    oauth::{EthOAuthClient, GithubOAuthClient, SharedAuthState},
+    keys::{Keys, SharedKeys},
+    lobby::{ActiveContributorError, SharedLobbyState},
+    lobby_token::LobbyEntryToken,
+    oauth::{
+        AuthRequestRefused, EthOAuthClient, GithubOAuthClient, SharedAuthState, TwitterOAuthClient,
+    },
     sessions::IdToken,
     storage::{PersistentStorage, StorageError},
     EthAuthOptions, Options, SessionId, SessionInfo,
 };
 use axum::{
     async_trait,
-    extract::{FromRequest, Query, RequestParts},
+    extract::{ConnectInfo, FromRequest, Query, RequestParts},
     response::{IntoResponse, Redirect, Response},
     Extension, Json,
 };
 use chrono::DateTime;
 use eyre::eyre;
-use http::StatusCode;
+use http::{header, HeaderMap};
 use kzg_ceremony_crypto::{signature::identity::Identity, ErrorCode};
 use oauth2::{
-    reqwest::async_http_client, AuthorizationCode, CsrfToken, RequestTokenError, Scope,
+    AuthorizationCode, CsrfToken, PkceCodeChallenge, PkceCodeVerifier, RequestTokenError, Scope,
     TokenResponse,
 };
 use serde::{Deserialize, Serialize};
-use serde_json::{json, Map, Value};
+use serde_json::json;
+use std::{net::SocketAddr, time::Duration};
 use strum::IntoStaticStr;
 use thiserror::Error;
 use tokio::time::Instant;
@@ -38,16 +43,32 @@ pub struct AuthError {
 pub enum AuthErrorPayload {
     #[error("lobby is full")]
     LobbyIsFull,
+    #[error("too many pending sign-in requests, try again later")]
+    TooManyAuthRequests,
+    #[error("too many sign-in requests from this address, try again later")]
+    RateLimited,
     #[error("user already contributed")]
     UserAlreadyContributed,
     #[error("invalid authorization code")]
     InvalidAuthCode,
+    #[error("unknown or expired state parameter")]
+    InvalidCsrfState,
+    #[error("invalid base64 data in state parameter")]
+    InvalidStateEncoding,
+    #[error("invalid json in state parameter")]
+    InvalidStateJson,
     #[error("could not fetch user data from auth server")]
     FetchUserDataError,
     #[error("could not extract user data from auth server")]
     CouldNotExtractUserData,
     #[error("user created after deadline")]
     UserCreatedAfterDeadline,
+    #[error("user does not have enough followers")]
+    NotEnoughFollowers,
+    #[error("user does not have enough public repositories")]
+    NotEnoughPublicRepos,
+    #[error("couldn't sign the lobby entry token")]
+    TokenSigning,
     #[error("storage error: {0}")]
     Storage(#[from] StorageError),
 }
@@ -61,21 +82,39 @@ impl ErrorCode for AuthErrorPayload {
 pub struct UserVerifiedResponse {
     id_token: IdToken,
     session_id: String,
+    lobby_entry_token: String,
+    lobby_entry_token_ttl: Duration,
+    pow_difficulty: u32,
     as_redirect_to: Option<String>,
 }
 
+/// Name of the cookie that binds an authorization request to the client that
+/// made it.
+pub const AUTH_BINDING_COOKIE: &str = "kzg_auth_binding";
+
+/// Name of the cookie that carries the lobby entry token, so that it never
+/// ends up in a redirect URL.
+pub const LOBBY_ENTRY_COOKIE: &str = "kzg_lobby_entry_token";
+
 pub struct AuthUrl {
     eth_auth_url: String,
     github_auth_url: String,
+    /// `None` if sign-in with Twitter is disabled.
+    twitter_auth_url: Option<String>,
+    binding_cookie: String,
 }
 
 impl IntoResponse for AuthUrl {
     fn into_response(self) -> Response {
-        Json(json!({
-            "eth_auth_url": self.eth_auth_url,
-            "github_auth_url": self.github_auth_url,
-        }))
-        .into_response()
+        (
+            [(header::SET_COOKIE, self.binding_cookie)],
+            Json(json!({
+                "eth_auth_url": self.eth_auth_url,
+                "github_auth_url": self.github_auth_url,
+                "twitter_auth_url": self.twitter_auth_url,
+            })),
+        )
+            .into_response()
     }
 }
 
@@ -84,7 +123,12 @@ impl IntoResponse for UserVerifiedResponse {
         // Handling URL parse error by ignoring it and returning without redirect – we
         // have no better option here, since we don't know the frontend that called us.
         let redirect_url = self.as_redirect_to.and_then(|r| Url::parse(&r).ok());
-        match redirect_url {
+        let lobby_entry_cookie = format!(
+            "{LOBBY_ENTRY_COOKIE}={}; Max-Age={}; Path=/; HttpOnly; Secure; SameSite=None",
+            self.lobby_entry_token,
+            self.lobby_entry_token_ttl.as_secs()
+        );
+        let response = match redirect_url {
             Some(mut redirect_url) => {
                 redirect_url
                     .query_pairs_mut()
@@ -92,7 +136,8 @@ impl IntoResponse for UserVerifiedResponse {
                     .append_pair("sub", &self.id_token.identity.unique_id())
                     .append_pair("nickname", &self.id_token.identity.nickname())
                     .append_pair("provider", &self.id_token.identity.provider_name())
-                    .append_pair("exp", &self.id_token.exp.to_string());
+                    .append_pair("exp", &self.id_token.exp.to_string())
+                    .append_pair("pow_difficulty", &self.pow_difficulty.to_string());
                 Redirect::to(redirect_url.as_str()).into_response()
             }
             None => Json(json!({
@@ -103,9 +148,12 @@ impl IntoResponse for UserVerifiedResponse {
                     "exp": &self.id_token.exp,
                 },
                 "session_id" : self.session_id,
+                "lobby_entry_token": self.lobby_entry_token,
+                "pow_difficulty": self.pow_difficulty,
             }))
             .into_response(),
-        }
+        };
+        ([(header::SET_COOKIE, lobby_entry_cookie)], response).into_response()
     }
 }
 
@@ -116,6 +164,8 @@ pub struct AuthClientLinkQueryParams {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CsrfWithRedirect {
+    // Random value, so that every state is unique and unguessable.
+    nonce: String,
     redirect: Option<String>,
 }
 
@@ -129,12 +179,17 @@ impl CsrfWithRedirect {
 
 // Returns the url that the user needs to call
 // in order to get an authorisation code
+#[allow(clippy::too_many_arguments)]
 pub async fn auth_client_link(
     Query(params): Query<AuthClientLinkQueryParams>,
+    ConnectInfo(socket): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Extension(options): Extension<Options>,
+    Extension(auth_state): Extension<SharedAuthState>,
     Extension(lobby_state): Extension<SharedLobbyState>,
     Extension(eth_client): Extension<EthOAuthClient>,
     Extension(gh_client): Extension<GithubOAuthClient>,
+    twitter_client: Option<Extension<TwitterOAuthClient>>,
 ) -> Result<AuthUrl, AuthErrorPayload> {
     let session_count = lobby_state.get_session_count().await;
 
@@ -143,23 +198,66 @@ pub async fn auth_client_link(
     }
 
     let csrf_with_redirect = CsrfWithRedirect {
+        nonce: CsrfToken::new_random().secret().clone(),
         redirect: params.redirect_to,
     }
     .encode_into_csrf();
 
+    // The same state and PKCE challenge is used for all providers, only one
+    // of them will call us back.
+    let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+    let binding = CsrfToken::new_random().secret().clone();
+    // `SameSite=None`, so that it is also set when the frontend is served
+    // from another origin.
+    let binding_cookie = format!(
+        "{AUTH_BINDING_COOKIE}={binding}; Max-Age={}; Path=/; HttpOnly; Secure; SameSite=None",
+        options.auth_request_ttl.as_secs()
+    );
+    auth_state
+        .write()
+        .await
+        .insert_auth_request(
+            csrf_with_redirect.secret().clone(),
+            pkce_verifier,
+            binding,
+            options.proxy.client_ip(&headers, socket),
+            options.max_auth_requests,
+            options.auth_requests_per_ip,
+        )
+        .map_err(|refused| match refused {
+            AuthRequestRefused::TooManyPending => AuthErrorPayload::TooManyAuthRequests,
+            AuthRequestRefused::RateLimited => AuthErrorPayload::RateLimited,
+        })?;
+
     let eth_auth_request = eth_client
         .authorize_url(|| csrf_with_redirect)
-        .add_scope(Scope::new("openid".to_string()));
+        .add_scope(Scope::new("openid".to_string()))
+        .set_pkce_challenge(pkce_challenge.clone());
 
     let (auth_url, csrf_with_redirect) = eth_auth_request.url();
 
-    let gh_auth_request = gh_client.client.authorize_url(|| csrf_with_redirect);
-
-    let (gh_url, _) = gh_auth_request.url();
+    let gh_auth_request = gh_client
+        .client
+        .authorize_url(|| csrf_with_redirect)
+        .set_pkce_challenge(pkce_challenge.clone());
+
+    let (gh_url, csrf_with_redirect) = gh_auth_request.url();
+
+    let twitter_url = twitter_client.map(|Extension(twitter_client)| {
+        let twitter_auth_request = twitter_client
+            .client
+            .authorize_url(|| csrf_with_redirect)
+            .set_pkce_challenge(pkce_challenge)
+            .add_scope(Scope::new("users.read".to_string()))
+            .add_scope(Scope::new("tweet.read".to_string()));
+        twitter_auth_request.url().0.to_string()
+    });
 
     Ok(AuthUrl {
         eth_auth_url: auth_url.to_string(),
         github_auth_url: gh_url.to_string(),
+        twitter_auth_url: twitter_url,
+        binding_cookie,
     })
 }
 
@@ -177,7 +275,34 @@ pub struct RawAuthPayload {
 #[derive(Debug)]
 pub struct AuthPayload {
     code: String,
+    state: String,
     redirect_to: Option<String>,
+    /// The [`AUTH_BINDING_COOKIE`] sent along with the callback.
+    binding: Option<String>,
+}
+
+impl AuthPayload {
+    /// Validates the state against the pending authorization requests and
+    /// returns the PKCE verifier for the code exchange. Every state can only
+    /// be used once, and only by the client it was issued to.
+    async fn take_pkce_verifier(
+        &self,
+        auth_state: &SharedAuthState,
+        options: &Options,
+    ) -> Result<PkceCodeVerifier, AuthError> {
+        auth_state
+            .write()
+            .await
+            .take_auth_request(
+                &self.state,
+                self.binding.as_deref(),
+                options.auth_request_ttl,
+            )
+            .ok_or_else(|| AuthError {
+                redirect: self.redirect_to.clone(),
+                payload: AuthErrorPayload::InvalidCsrfState,
+            })
+    }
 }
 
 #[async_trait]
@@ -191,40 +316,41 @@ where
         let Query(raw): Query<RawAuthPayload> = Query::from_request(req)
             .await
             .map_err(IntoResponse::into_response)?;
-        let decoded_state =
-            base64::decode_config(raw.state, base64::URL_SAFE_NO_PAD).map_err(|_| {
-                (
-                    StatusCode::BAD_REQUEST,
-                    Json(Value::Object(Map::from_iter([(
-                        "message".to_string(),
-                        Value::String("invalid base64 data in state parameter".to_string()),
-                    )]))),
-                )
-                    .into_response()
-            })?;
+        let decoded_state = base64::decode_config(&raw.state, base64::URL_SAFE_NO_PAD)
+            .map_err(|_| AuthErrorPayload::InvalidStateEncoding.into_response())?;
         let json_decoded_state =
-            serde_json::from_slice::<CsrfWithRedirect>(decoded_state.as_slice()).map_err(|_| {
-                (
-                    StatusCode::BAD_REQUEST,
-                    Json(Value::Object(Map::from_iter([(
-                        "message".to_string(),
-                        Value::String("invalid json in state parameter".to_string()),
-                    )]))),
-                )
-                    .into_response()
-            })?;
+            serde_json::from_slice::<CsrfWithRedirect>(decoded_state.as_slice())
+                .map_err(|_| AuthErrorPayload::InvalidStateJson.into_response())?;
         Ok(Self {
             code: raw.code,
+            state: raw.state,
             redirect_to: json_decoded_state.redirect,
+            binding: cookie(req.headers(), AUTH_BINDING_COOKIE),
         })
     }
 }
 
+/// The value of the cookie called `name`, if the request carries it.
+pub fn cookie(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value.to_string())
+}
+
 #[derive(Debug, Deserialize)]
 struct GhUserInfo {
     id: u64,
     login: String,
     created_at: String,
+    #[serde(default)]
+    public_repos: u64,
+    #[serde(default)]
+    followers: u64,
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -234,12 +360,19 @@ pub async fn github_callback(
     Extension(auth_state): Extension<SharedAuthState>,
     Extension(lobby_state): Extension<SharedLobbyState>,
     Extension(storage): Extension<PersistentStorage>,
+    Extension(keys): Extension<SharedKeys>,
     Extension(gh_oauth_client): Extension<GithubOAuthClient>,
     Extension(http_client): Extension<reqwest::Client>,
 ) -> Result<UserVerifiedResponse, AuthError> {
+    let pkce_verifier = payload.take_pkce_verifier(&auth_state, &options).await?;
     let token = gh_oauth_client
         .exchange_code(AuthorizationCode::new(payload.code))
-        .request_async(async_http_client)
+        .set_pkce_verifier(pkce_verifier)
+        .request_async(|request| {
+            options
+                .oauth_retry
+                .http_client(options.github.gh_request_timeout, request)
+        })
         .await
         .map_err(|e| {
             if let RequestTokenError::Parse(_, bytes) = e {
@@ -254,11 +387,14 @@ pub async fn github_callback(
             }
         })?;
 
-    let response = http_client
-        .get(options.github.gh_userinfo_url)
-        .bearer_auth(token.access_token().secret())
-        .header("User-Agent", "ethereum-kzg-ceremony-sequencer")
-        .send()
+    let response = options
+        .oauth_retry
+        .send("github_userinfo", options.github.gh_request_timeout, || {
+            http_client
+                .get(&options.github.gh_userinfo_url)
+                .bearer_auth(token.access_token().secret())
+                .header("User-Agent", "ethereum-kzg-ceremony-sequencer")
+        })
         .await
         .map_err(|_| AuthError {
             redirect: payload.redirect_to.clone(),
@@ -279,6 +415,18 @@ pub async fn github_callback(
             payload: AuthErrorPayload::UserCreatedAfterDeadline,
         });
     }
+    if matches!(options.github.gh_min_public_repos, Some(min) if gh_user_info.public_repos < min) {
+        return Err(AuthError {
+            redirect: payload.redirect_to.clone(),
+            payload: AuthErrorPayload::NotEnoughPublicRepos,
+        });
+    }
+    if matches!(options.github.gh_min_followers, Some(min) if gh_user_info.followers < min) {
+        return Err(AuthError {
+            redirect: payload.redirect_to.clone(),
+            payload: AuthErrorPayload::NotEnoughFollowers,
+        });
+    }
     let user = Identity::Github {
         id: gh_user_info.id,
         username: gh_user_info.login.clone(),
@@ -290,6 +438,107 @@ pub async fn github_callback(
         user,
         payload.redirect_to,
         options.multi_contribution,
+        options.lobby.pow_difficulty,
+        &keys,
+        options.lobby.lobby_entry_token_ttl,
+    )
+    .await
+}
+
+#[derive(Debug, Deserialize)]
+struct TwitterUserResponse {
+    data: TwitterUserInfo,
+}
+
+#[derive(Debug, Deserialize)]
+struct TwitterUserInfo {
+    id: String,
+    username: String,
+    public_metrics: TwitterPublicMetrics,
+}
+
+#[derive(Debug, Deserialize)]
+struct TwitterPublicMetrics {
+    followers_count: u64,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn twitter_callback(
+    payload: AuthPayload,
+    Extension(options): Extension<Options>,
+    Extension(auth_state): Extension<SharedAuthState>,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(storage): Extension<PersistentStorage>,
+    Extension(keys): Extension<SharedKeys>,
+    Extension(twitter_oauth_client): Extension<TwitterOAuthClient>,
+    Extension(http_client): Extension<reqwest::Client>,
+) -> Result<UserVerifiedResponse, AuthError> {
+    let pkce_verifier = payload.take_pkce_verifier(&auth_state, &options).await?;
+    let token = twitter_oauth_client
+        .exchange_code(AuthorizationCode::new(payload.code))
+        .set_pkce_verifier(pkce_verifier)
+        .request_async(|request| {
+            options
+                .oauth_retry
+                .http_client(options.twitter.tw_request_timeout, request)
+        })
+        .await
+        .map_err(|e| {
+            warn!("Twitter Token Exchange Error: {e}");
+            AuthError {
+                redirect: payload.redirect_to.clone(),
+                payload: AuthErrorPayload::InvalidAuthCode,
+            }
+        })?;
+
+    let response = options
+        .oauth_retry
+        .send(
+            "twitter_userinfo",
+            options.twitter.tw_request_timeout,
+            || {
+                http_client
+                    .get(&options.twitter.tw_userinfo_url)
+                    .bearer_auth(token.access_token().secret())
+            },
+        )
+        .await
+        .map_err(|_| AuthError {
+            redirect: payload.redirect_to.clone(),
+            payload: AuthErrorPayload::FetchUserDataError,
+        })?;
+    let twitter_user_info = response
+        .json::<TwitterUserResponse>()
+        .await
+        .map_err(|_| AuthError {
+            redirect: payload.redirect_to.clone(),
+            payload: AuthErrorPayload::CouldNotExtractUserData,
+        })?
+        .data;
+    if twitter_user_info.public_metrics.followers_count < options.twitter.tw_min_followers {
+        return Err(AuthError {
+            redirect: payload.redirect_to.clone(),
+            payload: AuthErrorPayload::NotEnoughFollowers,
+        });
+    }
+    let id = twitter_user_info.id.parse().map_err(|_| AuthError {
+        redirect: payload.redirect_to.clone(),
+        payload: AuthErrorPayload::CouldNotExtractUserData,
+    })?;
+    let user = Identity::Twitter {
+        id,
+        handle: twitter_user_info.username,
+    };
+    post_authenticate(
+        auth_state,
+        lobby_state,
+        storage,
+        user,
+        payload.redirect_to,
+        options.multi_contribution,
+        options.lobby.pow_difficulty,
+        &keys,
+        options.lobby.lobby_entry_token_ttl,
     )
     .await
 }
@@ -315,22 +564,32 @@ pub async fn eth_callback(
     Extension(auth_state): Extension<SharedAuthState>,
     Extension(lobby_state): Extension<SharedLobbyState>,
     Extension(storage): Extension<PersistentStorage>,
+    Extension(keys): Extension<SharedKeys>,
     Extension(oauth_client): Extension<EthOAuthClient>,
     Extension(http_client): Extension<reqwest::Client>,
 ) -> Result<UserVerifiedResponse, AuthError> {
+    let pkce_verifier = payload.take_pkce_verifier(&auth_state, &options).await?;
     let token = oauth_client
         .exchange_code(AuthorizationCode::new(payload.code))
-        .request_async(async_http_client)
+        .set_pkce_verifier(pkce_verifier)
+        .request_async(|request| {
+            options
+                .oauth_retry
+                .http_client(options.ethereum.eth_request_timeout, request)
+        })
         .await
         .map_err(|_| AuthError {
             redirect: payload.redirect_to.clone(),
             payload: AuthErrorPayload::InvalidAuthCode,
         })?;
 
-    let response = http_client
-        .get(&options.ethereum.eth_userinfo_url)
-        .bearer_auth(token.access_token().secret())
-        .send()
+    let response = options
+        .oauth_retry
+        .send("eth_userinfo", options.ethereum.eth_request_timeout, || {
+            http_client
+                .get(&options.ethereum.eth_userinfo_url)
+                .bearer_auth(token.access_token().secret())
+        })
         .await
         .map_err(|_| AuthError {
             redirect: payload.redirect_to.clone(),
@@ -386,10 +645,17 @@ pub async fn eth_callback(
         user_data,
         payload.redirect_to,
         options.multi_contribution,
+        options.lobby.pow_difficulty,
+        &keys,
+        options.lobby.lobby_entry_token_ttl,
     )
     .await
 }
 
+#[derive(Debug, Error)]
+#[error("JSON-RPC response exceeds {0} bytes")]
+struct RpcResponseTooLarge(usize);
+
 // TODO: This has many failure modes and should return and eyre::Result.
 async fn get_tx_count(
     address: &str,
@@ -404,13 +670,29 @@ async fn get_tx_count(
         "method": "eth_getTransactionCount"
     });
 
-    let rpc_response = client
+    // Errors are logged, strip the URL, it may contain an API key. The timeout
+    // covers connecting as well as reading the body.
+    let mut rpc_response = client
         .post(options.eth_rpc_url.get_secret())
         .json(&rpc_payload)
+        .timeout(options.eth_request_timeout)
         .send()
-        .await?;
+        .await
+        .map_err(reqwest::Error::without_url)?;
 
-    let rpc_response_json = rpc_response.json::<serde_json::Value>().await?;
+    // Read the body in chunks, so an oversized response is cut off early.
+    let mut body = Vec::new();
+    while let Some(chunk) = rpc_response
+        .chunk()
+        .await
+        .map_err(reqwest::Error::without_url)?
+    {
+        if body.len() + chunk.len() > options.eth_rpc_max_response_size {
+            return Err(RpcResponseTooLarge(options.eth_rpc_max_response_size).into());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    let rpc_response_json = serde_json::from_slice::<serde_json::Value>(&body)?;
 
     let rpc_result = rpc_response_json
         .get("result")
@@ -422,6 +704,7 @@ async fn get_tx_count(
     Ok(result)
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn post_authenticate(
     auth_state: SharedAuthState,
     lobby_state: SharedLobbyState,
@@ -429,9 +712,12 @@ async fn post_authenticate(
     user_data: Identity,
     redirect_to: Option<String>,
     multi_contribution: bool,
+    pow_difficulty: u32,
+    keys: &Keys,
+    lobby_entry_token_ttl: Duration,
 ) -> Result<UserVerifiedResponse, AuthError> {
     // Check if they have already contributed
-    match storage.has_contributed(&user_data.unique_id()).await {
+    match storage.has_contributed(&user_data).await {
         Err(error) => {
             return Err(AuthError {
                 redirect: redirect_to.clone(),
@@ -451,25 +737,57 @@ async fn post_authenticate(
         Ok(false) => (),
     }
 
+    let (session_id, id_token) = create_session(&auth_state, &lobby_state, user_data)
+        .await
+        .map_err(|_| AuthError {
+            redirect: redirect_to.clone(),
+            payload: AuthErrorPayload::LobbyIsFull,
+        })?;
+
+    let lobby_entry_token = LobbyEntryToken::new(id_token.identity.clone(), lobby_entry_token_ttl)
+        .sign(keys)
+        .await
+        .map_err(|_| AuthError {
+            redirect: redirect_to.clone(),
+            payload: AuthErrorPayload::TokenSigning,
+        })?;
+
+    Ok(UserVerifiedResponse {
+        id_token,
+        session_id: session_id.to_string(),
+        lobby_entry_token,
+        lobby_entry_token_ttl,
+        pow_difficulty,
+        as_redirect_to: redirect_to,
+    })
+}
+
+/// Returns the session of `identity`, creating a new one if it has none, and
+/// adds it to the sessions out of the lobby.
+pub async fn create_session(
+    auth_state: &SharedAuthState,
+    lobby_state: &SharedLobbyState,
+    identity: Identity,
+) -> Result<(SessionId, IdToken), ActiveContributorError> {
     // Check if this user is already in the lobby
     // If so, we send them back their session id
     let session_id = {
         let mut state = auth_state.write().await;
 
         #[allow(clippy::option_if_let_else)]
-        if let Some(session_id) = state.unique_id_session.get(&user_data.unique_id()) {
+        if let Some(session_id) = state.unique_id_session.get(&identity.unique_id()) {
             session_id.clone()
         } else {
             let id = SessionId::new();
             state
                 .unique_id_session
-                .insert(user_data.unique_id(), id.clone());
+                .insert(identity.unique_id(), id.clone());
             id
         }
     };
 
     let id_token = IdToken {
-        identity: user_data,
+        identity,
         exp: u64::MAX,
     };
 
@@ -480,25 +798,93 @@ async fn post_authenticate(
                 token: id_token.clone(),
                 last_ping_time: Instant::now(),
                 is_first_ping_attempt: true,
+                pow_challenge: None,
+                pow_solved: false,
+                intent_challenge: None,
+                intent_signed: false,
+                lobby_entry: 0,
             },
         )
-        .await
-        .map_err(|_| AuthError {
-            redirect: redirect_to.clone(),
-            payload: AuthErrorPayload::LobbyIsFull,
-        })?;
+        .await?;
 
-    Ok(UserVerifiedResponse {
-        id_token,
-        session_id: session_id.to_string(),
-        as_redirect_to: redirect_to,
-    })
+    Ok((session_id, id_token))
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::test_util::test_options;
+    use axum::{routing::post, Router, Server};
+    use tokio::time::sleep;
+
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+
+    #[test]
+    fn finds_cookie() {
+        let mut headers = HeaderMap::new();
+        headers.append(header::COOKIE, "a=1; kzg_auth_binding=xyz".parse().unwrap());
+        headers.append(header::COOKIE, "b=2".parse().unwrap());
+        assert_eq!(
+            cookie(&headers, AUTH_BINDING_COOKIE).as_deref(),
+            Some("xyz")
+        );
+        assert_eq!(cookie(&headers, "b").as_deref(), Some("2"));
+        assert_eq!(cookie(&headers, "c"), None);
+    }
+
+    /// Serves a JSON-RPC endpoint that takes `delay` to answer with `body`.
+    fn mock_rpc(delay: Duration, body: String) -> String {
+        let app = Router::new().route(
+            "/",
+            post(move || async move {
+                sleep(delay).await;
+                body
+            }),
+        );
+        let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(app.into_make_service());
+        let url = format!("http://{}/", server.local_addr());
+        tokio::spawn(server);
+        url
+    }
+
+    fn rpc_options(url: &str) -> EthAuthOptions {
+        let mut options = test_options().ethereum;
+        options.eth_rpc_url = url.parse().unwrap();
+        options.eth_request_timeout = Duration::from_millis(100);
+        options.eth_rpc_max_response_size = 1024;
+        options
+    }
+
+    async fn tx_count(options: &EthAuthOptions) -> eyre::Result<u64> {
+        get_tx_count("0x00", "0x01", &reqwest::Client::new(), options).await
+    }
+
+    #[tokio::test]
+    async fn rpc_tx_count() {
+        let url = mock_rpc(
+            Duration::ZERO,
+            json!({ "id": 1, "jsonrpc": "2.0", "result": "0x2a" }).to_string(),
+        );
+        assert_eq!(tx_count(&rpc_options(&url)).await.unwrap(), 42);
+    }
+
+    #[tokio::test]
+    async fn slow_rpc_times_out() {
+        let url = mock_rpc(Duration::from_secs(30), String::new());
+        let error = tx_count(&rpc_options(&url)).await.unwrap_err();
+        assert!(error.downcast_ref::<reqwest::Error>().unwrap().is_timeout());
+    }
+
+    #[tokio::test]
+    async fn oversized_rpc_response_is_rejected() {
+        let url = mock_rpc(Duration::ZERO, "0".repeat(1 << 20));
+        let error = tx_count(&rpc_options(&url)).await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<RpcResponseTooLarge>(),
+            Some(RpcResponseTooLarge(1024))
+        ));
+    }
 }
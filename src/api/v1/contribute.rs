@@ -1,10 +1,15 @@
 use crate::{
-    io::{write_json_file, TranscriptIoError},
+    acceptance::{AcceptanceRejected, SharedAcceptanceHook},
+    concurrency::ValidationLimiter,
+    idempotency::{request_hash, IdempotencyCache, IdempotencyError, IdempotencyKey},
+    io::{write_contribution_file, write_json_file, TranscriptIoError},
     keys::{SharedKeys, Signature, SignatureError},
     lobby::SharedLobbyState,
     receipt::Receipt,
+    sessions::{IdToken, SessionKey},
     storage::{PersistentStorage, StorageError},
-    Engine, Options, SessionId, SharedCeremonyStatus, SharedTranscript,
+    Options, SessionId, SharedCeremonyStatus, SharedTranscript, SharedTranscriptHash,
+    SharedVerificationCache,
 };
 use axum::{
     response::{IntoResponse, Response},
@@ -12,15 +17,19 @@ use axum::{
 };
 use axum_extra::response::ErasedJson;
 use http::StatusCode;
-use kzg_ceremony_crypto::{BatchContribution, CeremoniesError, ErrorCode};
-use serde::Serialize;
-use std::sync::atomic::Ordering;
+use kzg_ceremony_crypto::{BatchContribution, CeremoniesError, CeremonyError, ErrorCode};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
 use strum::IntoStaticStr;
 use thiserror::Error;
-use tokio::task::JoinError;
+use tokio::{sync::Mutex, task::JoinError};
 use tracing::error;
 
-#[derive(Serialize)]
+#[derive(Clone, Serialize)]
 pub struct ContributeReceipt {
     receipt: String,
     signature: Signature,
@@ -32,18 +41,56 @@ impl IntoResponse for ContributeReceipt {
     }
 }
 
+/// A verified contribution waiting for its confirmation.
+pub struct StagedContribution {
+    confirmation_token: String,
+    id_token: IdToken,
+    contribution: BatchContribution,
+    /// Number of participants of the transcript it was verified against.
+    verified_at: usize,
+}
+
+pub type SharedStagedContributions = Arc<Mutex<BTreeMap<SessionKey, StagedContribution>>>;
+
+#[derive(Debug, Serialize)]
+pub struct StageResponse {
+    confirmation_token: String,
+    expires_in_secs: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConfirmRequest {
+    confirmation_token: String,
+}
+
 #[derive(Debug, Error, IntoStaticStr)]
 pub enum ContributeError {
     #[error("not your turn to participate")]
     NotUsersTurn,
+    #[error("no staged contribution, it may have expired")]
+    NoStagedContribution,
+    #[error("invalid confirmation token")]
+    InvalidConfirmationToken,
     #[error("contribution invalid: {0}")]
     InvalidContribution(#[from] CeremoniesError),
+    #[error("contribution invalid: {0}")]
+    InvalidBatch(#[from] CeremonyError),
+    #[error("contribution rejected: {0}")]
+    Rejected(#[from] AcceptanceRejected),
+    #[error("contribution acceptance timed out")]
+    AcceptanceTimeout,
+    #[error("contribution slot was lost before the contribution was added")]
+    SlotLost,
+    #[error("transcript changed since the contribution was verified")]
+    TranscriptChanged,
     #[error("receipt signing error: {0}")]
     ReceiptSigning(SignatureError),
     #[error("storage error: {0}")]
     StorageError(#[from] StorageError),
     #[error("Transcript IO error: {0}")]
     TranscriptIOError(#[from] TranscriptIoError),
+    #[error("{0}")]
+    IdempotencyKey(#[from] IdempotencyError),
     #[error("background task error: {0}")]
     TaskError(#[from] JoinError),
 }
@@ -54,76 +101,390 @@ impl ErrorCode for ContributeError {
     }
 }
 
+/// Verifies the contribution of the current contributor and adds it to the
+/// transcript. Retries carrying the `Idempotency-Key` of a successful request
+/// get its receipt instead of being processed again.
 #[allow(clippy::too_many_arguments)]
 pub async fn contribute(
     session_id: SessionId,
+    IdempotencyKey(idempotency_key): IdempotencyKey,
     Json(contribution): Json<BatchContribution>,
     Extension(lobby_state): Extension<SharedLobbyState>,
     Extension(options): Extension<Options>,
     Extension(shared_transcript): Extension<SharedTranscript>,
     Extension(storage): Extension<PersistentStorage>,
     Extension(num_contributions): Extension<SharedCeremonyStatus>,
+    Extension(shared_hash): Extension<SharedTranscriptHash>,
     Extension(keys): Extension<SharedKeys>,
+    Extension(acceptance_hook): Extension<SharedAcceptanceHook>,
+    Extension(validation_limiter): Extension<ValidationLimiter>,
+    Extension(verification_cache): Extension<SharedVerificationCache>,
+    Extension(idempotency_cache): Extension<IdempotencyCache<ContributeReceipt>>,
 ) -> Result<ContributeReceipt, ContributeError> {
     // Handle the contribution in the background, so that request cancelation
     // doesn't interrupt it.
     let res = tokio::spawn(async move {
+        if let Some(key) = &idempotency_key {
+            let hash = request_hash(&contribution);
+            if let Some(receipt) = idempotency_cache.begin(&session_id, key, hash).await? {
+                return Ok(receipt);
+            }
+        }
+
+        let result: Result<_, ContributeError> = async {
+            let id_token = lobby_state
+                .begin_contributing(&session_id)
+                .await
+                .map_err(|_| ContributeError::NotUsersTurn)?
+                .token;
+
+            let (contribution, verified_at) = verify(
+                &session_id,
+                &id_token,
+                contribution,
+                &options,
+                &shared_transcript,
+                &lobby_state,
+                &storage,
+                &acceptance_hook,
+                &validation_limiter,
+                &verification_cache,
+            )
+            .await?;
+
+            apply(
+                &session_id,
+                id_token,
+                contribution,
+                verified_at,
+                options,
+                shared_transcript,
+                &lobby_state,
+                &storage,
+                &num_contributions,
+                &shared_hash,
+                &keys,
+            )
+            .await
+        }
+        .await;
+
+        if let Some(key) = &idempotency_key {
+            idempotency_cache
+                .finish(&session_id, key, result.as_ref().ok().cloned())
+                .await;
+        }
+        result
+    })
+    .await
+    .unwrap_or_else(|e| Err(ContributeError::TaskError(e)));
+    log_unexpected_error(&res);
+    res
+}
+
+/// Verifies the contribution of the current contributor like
+/// [`contribute`], but instead of adding it to the transcript, keeps it until
+/// it is confirmed through [`confirm_contribution`] with the returned token.
+/// Unconfirmed contributions are dropped after `contribution_confirm_window`
+/// and the slot is freed.
+#[allow(clippy::too_many_arguments)]
+pub async fn stage_contribution(
+    session_id: SessionId,
+    Json(contribution): Json<BatchContribution>,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(options): Extension<Options>,
+    Extension(shared_transcript): Extension<SharedTranscript>,
+    Extension(storage): Extension<PersistentStorage>,
+    Extension(acceptance_hook): Extension<SharedAcceptanceHook>,
+    Extension(validation_limiter): Extension<ValidationLimiter>,
+    Extension(verification_cache): Extension<SharedVerificationCache>,
+    Extension(staged): Extension<SharedStagedContributions>,
+) -> Result<Json<StageResponse>, ContributeError> {
+    tokio::spawn(async move {
         let id_token = lobby_state
             .begin_contributing(&session_id)
             .await
             .map_err(|_| ContributeError::NotUsersTurn)?
             .token;
 
-        let result = {
-            let mut transcript = shared_transcript.write().await;
-            transcript
-                .verify_add::<Engine>(contribution.clone(), id_token.identity.clone())
-                .map_err(ContributeError::InvalidContribution)
+        let (contribution, verified_at) = verify(
+            &session_id,
+            &id_token,
+            contribution,
+            &options,
+            &shared_transcript,
+            &lobby_state,
+            &storage,
+            &acceptance_hook,
+            &validation_limiter,
+            &verification_cache,
+        )
+        .await?;
+
+        let confirmation_token = hex::encode(rand::random::<[u8; 16]>());
+        let window = options.contribution_confirm_window;
+        staged.lock().await.insert(
+            session_id.key(),
+            StagedContribution {
+                confirmation_token: confirmation_token.clone(),
+                id_token,
+                contribution,
+                verified_at,
+            },
+        );
+        tokio::spawn(expire_staged_contribution(
+            staged,
+            session_id,
+            confirmation_token.clone(),
+            window,
+            lobby_state,
+            storage,
+        ));
+
+        Ok(Json(StageResponse {
+            confirmation_token,
+            expires_in_secs: window.as_secs(),
+        }))
+    })
+    .await
+    .unwrap_or_else(|e| Err(ContributeError::TaskError(e)))
+}
+
+/// Adds the contribution staged through [`stage_contribution`] to the
+/// transcript, unless the session lost the slot or the transcript changed in
+/// the meantime.
+#[allow(clippy::too_many_arguments)]
+pub async fn confirm_contribution(
+    session_id: SessionId,
+    Json(request): Json<ConfirmRequest>,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(options): Extension<Options>,
+    Extension(shared_transcript): Extension<SharedTranscript>,
+    Extension(storage): Extension<PersistentStorage>,
+    Extension(num_contributions): Extension<SharedCeremonyStatus>,
+    Extension(shared_hash): Extension<SharedTranscriptHash>,
+    Extension(keys): Extension<SharedKeys>,
+    Extension(staged): Extension<SharedStagedContributions>,
+) -> Result<ContributeReceipt, ContributeError> {
+    let res = tokio::spawn(async move {
+        let staged_contribution = {
+            let key = session_id.key();
+            let mut staged = staged.lock().await;
+            match staged.get(&key) {
+                None => return Err(ContributeError::NoStagedContribution),
+                Some(pending) if pending.confirmation_token != request.confirmation_token => {
+                    return Err(ContributeError::InvalidConfirmationToken)
+                }
+                Some(_) => staged.remove(&key).expect("checked above"),
+            }
         };
 
-        if let Err(e) = result {
-            lobby_state.clear_current_contributor().await;
-            storage
-                .expire_contribution(&id_token.unique_identifier())
-                .await?;
-            return Err(e);
+        apply(
+            &session_id,
+            staged_contribution.id_token,
+            staged_contribution.contribution,
+            staged_contribution.verified_at,
+            options,
+            shared_transcript,
+            &lobby_state,
+            &storage,
+            &num_contributions,
+            &shared_hash,
+            &keys,
+        )
+        .await
+    })
+    .await
+    .unwrap_or_else(|e| Err(ContributeError::TaskError(e)));
+    log_unexpected_error(&res);
+    res
+}
+
+/// Drops the staged contribution once `window` has passed, unless it was
+/// confirmed or replaced by then, frees the slot and puts the participant into
+/// the contribution cooldown.
+async fn expire_staged_contribution(
+    staged: SharedStagedContributions,
+    session_id: SessionId,
+    confirmation_token: String,
+    window: Duration,
+    lobby_state: SharedLobbyState,
+    storage: PersistentStorage,
+) {
+    tokio::time::sleep(window).await;
+
+    let expired = {
+        let key = session_id.key();
+        let mut staged = staged.lock().await;
+        match staged.get(&key) {
+            Some(pending) if pending.confirmation_token == confirmation_token => {
+                staged.remove(&key)
+            }
+            _ => None,
+        }
+    };
+    if let Some(expired) = expired {
+        if lobby_state.fail_contributor(&session_id).await {
+            if let Err(e) = storage
+                .expire_contribution(&expired.id_token.unique_identifier())
+                .await
+            {
+                error!("failed to expire staged contribution: {}", e);
+            }
         }
+    }
+}
 
-        let result = write_json_file(
-            options.transcript_file,
-            options.transcript_in_progress_file,
-            shared_transcript,
+/// Verifies the contribution and runs the acceptance hook. Frees the slot if
+/// the contribution is not accepted. Also returns the number of participants
+/// of the transcript it was verified against, see [`apply`].
+#[allow(clippy::too_many_arguments)]
+async fn verify(
+    session_id: &SessionId,
+    id_token: &IdToken,
+    contribution: BatchContribution,
+    options: &Options,
+    shared_transcript: &SharedTranscript,
+    lobby_state: &SharedLobbyState,
+    storage: &PersistentStorage,
+    acceptance_hook: &SharedAcceptanceHook,
+    validation_limiter: &ValidationLimiter,
+    verification_cache: &SharedVerificationCache,
+) -> Result<(BatchContribution, usize), ContributeError> {
+    // No lock is held while the hook runs, so the slot and the transcript can
+    // change in the meantime. `apply` checks both again before adding.
+    let result = async {
+        // Oversized batches are refused before any expensive work.
+        contribution.check_num_contributions(options.ceremony_sizes.num_ceremonies())?;
+        let (verified, contribution, verified_at) = {
+            let (engine, transcript, cache) = (
+                options.engine,
+                shared_transcript.clone(),
+                verification_cache.clone(),
+            );
+            validation_limiter
+                .run(move || {
+                    let transcript = transcript.blocking_read();
+                    let verified = engine.verify_contribution(&transcript, &contribution, &cache);
+                    (verified, contribution, transcript.num_participants())
+                })
+                .await?
+        };
+        verified?;
+        tokio::time::timeout(
+            options.acceptance_hook_timeout,
+            acceptance_hook.accept(&id_token.identity, &contribution),
         )
-        .await;
+        .await
+        .map_err(|_| ContributeError::AcceptanceTimeout)??;
+        Ok::<_, ContributeError>((contribution, verified_at))
+    }
+    .await;
+
+    if result.is_err() && lobby_state.fail_contributor(session_id).await {
+        storage
+            .expire_contribution(&id_token.unique_identifier())
+            .await?;
+    }
+    result
+}
+
+/// Adds a verified contribution to the transcript, persists it, frees the
+/// slot and signs the receipt. The contribution is only added if the session
+/// still holds the slot and the transcript still has the `verified_at`
+/// participants it was verified against.
+#[allow(clippy::too_many_arguments)]
+async fn apply(
+    session_id: &SessionId,
+    id_token: IdToken,
+    contribution: BatchContribution,
+    verified_at: usize,
+    options: Options,
+    shared_transcript: SharedTranscript,
+    lobby_state: &SharedLobbyState,
+    storage: &PersistentStorage,
+    num_contributions: &SharedCeremonyStatus,
+    shared_hash: &SharedTranscriptHash,
+    keys: &SharedKeys,
+) -> Result<ContributeReceipt, ContributeError> {
+    let added = {
+        // Nobody else can add while the write lock is held, so the checks
+        // still hold when the contribution is added.
+        let mut transcript = shared_transcript.write().await;
+        if !lobby_state.release_contributor(session_id).await {
+            // Whoever took the slot already expired the contribution.
+            return Err(ContributeError::SlotLost);
+        }
+        if transcript.num_participants() == verified_at {
+            options
+                .engine
+                .add_in_domain(
+                    &mut transcript,
+                    contribution.clone(),
+                    id_token.identity.clone(),
+                    &options.contribution_domain(),
+                )
+                .map_err(ContributeError::from)
+        } else {
+            Err(ContributeError::TranscriptChanged)
+        }
+    };
+    if let Err(e) = added {
+        storage
+            .expire_contribution(&id_token.unique_identifier())
+            .await?;
+        return Err(e);
+    }
 
-        lobby_state.clear_current_contributor().await;
-        storage.finish_contribution(&session_id.0).await?;
+    let result = write_json_file(
+        options.transcript_file,
+        options.transcript_in_progress_file,
+        shared_transcript,
+        options.transcript_zstd_level,
+    )
+    .await;
 
-        if let Err(e) = result {
+    storage
+        .finish_contribution(&id_token.unique_identifier())
+        .await?;
+
+    let hash = match result {
+        Ok(hash) => hash,
+        Err(e) => {
             error!("failed to write transcript: {}", e);
             return Err(ContributeError::TranscriptIOError(e));
         }
+    };
 
-        num_contributions.fetch_add(1, Ordering::Relaxed);
+    num_contributions.fetch_add(1, Ordering::Relaxed);
+    *shared_hash.write().await = hash;
 
-        let receipt = Receipt {
-            identity: id_token.identity,
-            witness: contribution.receipt(),
-        };
+    // The transcript is the record of the ceremony, a failed archive write
+    // doesn't fail the contribution.
+    if let Some(dir) = options.contribution_archive_dir {
+        if let Err(e) = write_contribution_file(dir, verified_at + 1, &contribution).await {
+            error!("failed to archive contribution: {}", e);
+        }
+    }
 
-        let (signed_msg, signature) = receipt
-            .sign(&keys)
-            .await
-            .map_err(ContributeError::ReceiptSigning)?;
+    let receipt = Receipt {
+        identity: id_token.identity,
+        witness: contribution.receipt(),
+    };
+
+    let (signed_msg, signature) = receipt
+        .sign(keys)
+        .await
+        .map_err(ContributeError::ReceiptSigning)?;
 
-        Ok(ContributeReceipt {
-            receipt: signed_msg,
-            signature,
-        })
+    Ok(ContributeReceipt {
+        receipt: signed_msg,
+        signature,
     })
-    .await
-    .unwrap_or_else(|e| Err(ContributeError::TaskError(e)));
-    if let Err(err) = &res {
+}
+
+fn log_unexpected_error(res: &Result<ContributeReceipt, ContributeError>) {
+    if let Err(err) = res {
         if matches!(
             err,
             ContributeError::ReceiptSigning(_)
@@ -133,7 +494,6 @@ pub async fn contribute(
             error!(?err, "unexpected error recording contribution");
         }
     }
-    res
 }
 
 pub async fn contribute_abort(
@@ -145,11 +505,11 @@ pub async fn contribute_abort(
     // so that request cancelation doesn't interrupt it inbetween the lobby_state
     // and storage calls.
     tokio::spawn(async move {
-        lobby_state
+        let identity = lobby_state
             .abort_contribution(&session_id)
             .await
             .map_err(|_| ContributeError::NotUsersTurn)?;
-        storage.expire_contribution(&session_id.0).await?;
+        storage.expire_contribution(&identity).await?;
         Ok(())
     })
     .await
@@ -160,23 +520,29 @@ pub async fn contribute_abort(
 mod tests {
     use super::*;
     use crate::{
+        acceptance::{AcceptanceHook, NoopAcceptanceHook},
         api::v1::{
             contribute::ContributeError,
             lobby::{try_contribute, TryContributeError, TryContributeResponse},
         },
         contribute,
-        io::read_json_file,
+        io::{contribution_path, read_contribution_file, read_json_file},
         keys,
         keys::SharedKeys,
-        lobby::SharedLobbyState,
+        lobby::{ActiveContributorError, SharedLobbyState},
         storage::storage_client,
         test_util::{create_test_session_info, test_options},
         tests::{invalid_contribution, test_transcript, valid_contribution},
-        Keys, SessionId,
+        Engine, Keys, SessionId,
     };
-    use axum::{Extension, Json};
+    use axum::{async_trait, Extension, Json};
     use clap::Parser;
-    use kzg_ceremony_crypto::{signature::identity::Identity, BatchTranscript};
+    use ethers_signers::{LocalWallet, Signer};
+    use kzg_ceremony_crypto::{
+        signature::{identity::Identity, ContributionTypedData, EcdsaSignature},
+        BatchTranscript, CeremonyError,
+    };
+    use rand::thread_rng;
     use std::{
         sync::{atomic::AtomicUsize, Arc},
         time::Duration,
@@ -188,6 +554,50 @@ mod tests {
         Arc::new(Keys::new(&options).unwrap())
     }
 
+    fn noop_hook() -> SharedAcceptanceHook {
+        Arc::new(NoopAcceptanceHook)
+    }
+
+    struct RejectingHook;
+
+    #[async_trait]
+    impl AcceptanceHook for RejectingHook {
+        async fn accept(
+            &self,
+            _identity: &Identity,
+            _contribution: &BatchContribution,
+        ) -> Result<(), AcceptanceRejected> {
+            Err(AcceptanceRejected("coordinator said no".to_string()))
+        }
+    }
+
+    /// Changes the state from under the contribution while it is being
+    /// accepted, like an operator and a later contributor would.
+    struct MeddlingHook {
+        lobby_state: SharedLobbyState,
+        transcript: SharedTranscript,
+        evict: Option<SessionId>,
+    }
+
+    #[async_trait]
+    impl AcceptanceHook for MeddlingHook {
+        async fn accept(
+            &self,
+            _identity: &Identity,
+            _contribution: &BatchContribution,
+        ) -> Result<(), AcceptanceRejected> {
+            if let Some(session_id) = &self.evict {
+                self.lobby_state.evict_session(session_id).await;
+            }
+            let mut transcript = self.transcript.write().await;
+            let other = valid_contribution(&transcript, 9);
+            transcript
+                .verify_add::<Engine>(other, Identity::None)
+                .unwrap();
+            Ok(())
+        }
+    }
+
     #[tokio::test]
     async fn rejects_out_of_turn_contribution() {
         let opts = test_options();
@@ -197,13 +607,19 @@ mod tests {
         let contrbution = valid_contribution(&transcript, 1);
         let result = contribute(
             SessionId::new(),
+            IdempotencyKey(None),
             Json(contrbution),
             Extension(lobby_state),
             Extension(opts),
             Extension(Arc::new(RwLock::new(transcript))),
             Extension(db),
             Extension(Arc::new(AtomicUsize::new(0))),
+            Extension(SharedTranscriptHash::default()),
             Extension(shared_keys()),
+            Extension(noop_hook()),
+            Extension(ValidationLimiter::new(1)),
+            Extension(SharedVerificationCache::default()),
+            Extension(IdempotencyCache::new(Duration::from_secs(600))),
         )
         .await;
         assert!(matches!(result, Err(ContributeError::NotUsersTurn)));
@@ -211,7 +627,8 @@ mod tests {
 
     #[tokio::test]
     async fn rejects_invalid_contribution() {
-        let opts = test_options();
+        let mut opts = test_options();
+        opts.lobby.contribution_cooldown = Duration::from_secs(60);
         let db = storage_client(&opts.storage).await.unwrap();
         let lobby_state = SharedLobbyState::new(opts.lobby.clone());
         let participant = SessionId::new();
@@ -226,26 +643,240 @@ mod tests {
             .unwrap();
         let transcript = test_transcript();
         let contribution = invalid_contribution(&transcript, 1);
+        let result = contribute(
+            participant.clone(),
+            IdempotencyKey(None),
+            Json(contribution),
+            Extension(lobby_state.clone()),
+            Extension(opts),
+            Extension(Arc::new(RwLock::new(transcript))),
+            Extension(db),
+            Extension(Arc::new(AtomicUsize::new(0))),
+            Extension(SharedTranscriptHash::default()),
+            Extension(shared_keys()),
+            Extension(noop_hook()),
+            Extension(ValidationLimiter::new(1)),
+            Extension(SharedVerificationCache::default()),
+            Extension(IdempotencyCache::new(Duration::from_secs(600))),
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(ContributeError::InvalidContribution(_))
+        ));
+
+        // A failed contribution frees the slot and starts the cooldown.
+        assert!(!lobby_state.release_contributor(&participant).await);
+        let rejoin = SessionId::new();
+        lobby_state
+            .insert_session(rejoin.clone(), create_test_session_info(100))
+            .await
+            .unwrap();
+        assert!(matches!(
+            lobby_state.enter_lobby(&rejoin).await,
+            Err(ActiveContributorError::CooldownActive(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_contribution_without_entropy() {
+        let opts = test_options();
+        let db = storage_client(&opts.storage).await.unwrap();
+        let lobby_state = SharedLobbyState::new(opts.lobby.clone());
+        let participant = SessionId::new();
+        lobby_state
+            .insert_session(participant.clone(), create_test_session_info(100))
+            .await
+            .unwrap();
+        lobby_state.enter_lobby(&participant).await.unwrap();
+        lobby_state
+            .set_current_contributor(&participant, opts.lobby.compute_deadline, db.clone())
+            .await
+            .unwrap();
+        let transcript = test_transcript();
+        let contribution = transcript.contribution();
         let result = contribute(
             participant,
+            IdempotencyKey(None),
             Json(contribution),
             Extension(lobby_state),
             Extension(opts),
             Extension(Arc::new(RwLock::new(transcript))),
             Extension(db),
             Extension(Arc::new(AtomicUsize::new(0))),
+            Extension(SharedTranscriptHash::default()),
             Extension(shared_keys()),
+            Extension(noop_hook()),
+            Extension(ValidationLimiter::new(1)),
+            Extension(SharedVerificationCache::default()),
+            Extension(IdempotencyCache::new(Duration::from_secs(600))),
         )
         .await;
         assert!(matches!(
             result,
-            Err(ContributeError::InvalidContribution(_))
+            Err(ContributeError::InvalidContribution(
+                CeremoniesError::InvalidCeremony(0, CeremonyError::ContributionNoEntropy)
+            ))
         ));
     }
 
+    #[tokio::test]
+    async fn rejects_oversized_batch_before_validation() {
+        let opts = test_options();
+        let db = storage_client(&opts.storage).await.unwrap();
+        let lobby_state = SharedLobbyState::new(opts.lobby.clone());
+        let participant = SessionId::new();
+        lobby_state
+            .insert_session(participant.clone(), create_test_session_info(100))
+            .await
+            .unwrap();
+        lobby_state.enter_lobby(&participant).await.unwrap();
+        lobby_state
+            .set_current_contributor(&participant, opts.lobby.compute_deadline, db.clone())
+            .await
+            .unwrap();
+        let transcript = test_transcript();
+        let mut contribution = valid_contribution(&transcript, 1);
+        let max = opts.ceremony_sizes.num_ceremonies();
+        let extra = contribution.contributions[0].clone();
+        contribution.contributions.resize(max + 1, extra);
+        let result = contribute(
+            participant,
+            IdempotencyKey(None),
+            Json(contribution),
+            Extension(lobby_state.clone()),
+            Extension(opts),
+            Extension(Arc::new(RwLock::new(transcript))),
+            Extension(db),
+            Extension(Arc::new(AtomicUsize::new(0))),
+            Extension(SharedTranscriptHash::default()),
+            Extension(shared_keys()),
+            Extension(noop_hook()),
+            Extension(ValidationLimiter::new(1)),
+            Extension(SharedVerificationCache::default()),
+            Extension(IdempotencyCache::new(Duration::from_secs(600))),
+        )
+        .await;
+        // Validation would have reported `UnexpectedNumContributions`.
+        assert!(matches!(
+            result,
+            Err(ContributeError::InvalidBatch(CeremonyError::TooManyContributions(limit, got)))
+                if limit == max && got == max + 1
+        ));
+        assert_eq!(lobby_state.snapshot().await.active_contributor, None);
+    }
+
+    #[tokio::test]
+    async fn hook_rejection_leaves_transcript_unchanged() {
+        let opts = test_options();
+        let db = storage_client(&opts.storage).await.unwrap();
+        let lobby_state = SharedLobbyState::new(opts.lobby.clone());
+        let participant = SessionId::new();
+        lobby_state
+            .insert_session(participant.clone(), create_test_session_info(100))
+            .await
+            .unwrap();
+        lobby_state.enter_lobby(&participant).await.unwrap();
+        lobby_state
+            .set_current_contributor(&participant, opts.lobby.compute_deadline, db.clone())
+            .await
+            .unwrap();
+        let transcript = test_transcript();
+        let contribution = valid_contribution(&transcript, 1);
+        let shared_transcript = Arc::new(RwLock::new(transcript.clone()));
+        let hook: SharedAcceptanceHook = Arc::new(RejectingHook);
+        let result = contribute(
+            participant,
+            IdempotencyKey(None),
+            Json(contribution),
+            Extension(lobby_state.clone()),
+            Extension(opts),
+            Extension(shared_transcript.clone()),
+            Extension(db),
+            Extension(Arc::new(AtomicUsize::new(0))),
+            Extension(SharedTranscriptHash::default()),
+            Extension(shared_keys()),
+            Extension(hook),
+            Extension(ValidationLimiter::new(1)),
+            Extension(SharedVerificationCache::default()),
+            Extension(IdempotencyCache::new(Duration::from_secs(600))),
+        )
+        .await;
+        assert!(matches!(result, Err(ContributeError::Rejected(_))));
+        assert_eq!(*shared_transcript.read().await, transcript);
+        // The slot is free again.
+        assert_eq!(lobby_state.snapshot().await.active_contributor, None);
+    }
+
+    async fn contribute_with_meddling_hook(
+        evict: bool,
+    ) -> (
+        Result<ContributeReceipt, ContributeError>,
+        SharedLobbyState,
+        SharedTranscript,
+    ) {
+        let opts = test_options();
+        let db = storage_client(&opts.storage).await.unwrap();
+        let lobby_state = SharedLobbyState::new(opts.lobby.clone());
+        let participant = SessionId::new();
+        lobby_state
+            .insert_session(participant.clone(), create_test_session_info(100))
+            .await
+            .unwrap();
+        lobby_state.enter_lobby(&participant).await.unwrap();
+        lobby_state
+            .set_current_contributor(&participant, opts.lobby.compute_deadline, db.clone())
+            .await
+            .unwrap();
+        let transcript = test_transcript();
+        let contribution = valid_contribution(&transcript, 1);
+        let shared_transcript = Arc::new(RwLock::new(transcript));
+        let hook: SharedAcceptanceHook = Arc::new(MeddlingHook {
+            lobby_state: lobby_state.clone(),
+            transcript: shared_transcript.clone(),
+            evict: evict.then(|| participant.clone()),
+        });
+        let result = contribute(
+            participant,
+            IdempotencyKey(None),
+            Json(contribution),
+            Extension(lobby_state.clone()),
+            Extension(opts),
+            Extension(shared_transcript.clone()),
+            Extension(db),
+            Extension(Arc::new(AtomicUsize::new(0))),
+            Extension(SharedTranscriptHash::default()),
+            Extension(shared_keys()),
+            Extension(hook),
+            Extension(ValidationLimiter::new(1)),
+            Extension(SharedVerificationCache::default()),
+            Extension(IdempotencyCache::new(Duration::from_secs(600))),
+        )
+        .await;
+        (result, lobby_state, shared_transcript)
+    }
+
+    #[tokio::test]
+    async fn evicted_contribution_is_not_added() {
+        let (result, _, shared_transcript) = contribute_with_meddling_hook(true).await;
+        assert!(matches!(result, Err(ContributeError::SlotLost)));
+        // Only the contribution made after the eviction is in the transcript.
+        assert_eq!(shared_transcript.read().await.num_participants(), 1);
+    }
+
+    #[tokio::test]
+    async fn stale_contribution_is_not_added() {
+        let (result, lobby_state, shared_transcript) = contribute_with_meddling_hook(false).await;
+        assert!(matches!(result, Err(ContributeError::TranscriptChanged)));
+        assert_eq!(shared_transcript.read().await.num_participants(), 1);
+        assert_eq!(lobby_state.snapshot().await.active_contributor, None);
+    }
+
     #[tokio::test]
     async fn accepts_valid_contribution() {
-        let cfg = test_options();
+        let mut cfg = test_options();
+        let archive = tempfile::tempdir().unwrap();
+        cfg.contribution_archive_dir = Some(archive.path().to_path_buf());
         let keys = shared_keys();
         let lobby_state = SharedLobbyState::new(cfg.lobby.clone());
         let participant = SessionId::new();
@@ -287,19 +918,27 @@ mod tests {
             .unwrap();
         lobby_state.enter_lobby(&participant).await.unwrap();
 
+        let identity = create_test_session_info(100).token.identity;
+        db.insert_contributor(&identity.unique_id()).await.unwrap();
         lobby_state
             .set_current_contributor(&participant, cfg.lobby.compute_deadline, db.clone())
             .await
             .unwrap();
         let result = contribute(
             participant.clone(),
-            Json(contribution_1),
+            IdempotencyKey(None),
+            Json(contribution_1.clone()),
             Extension(lobby_state.clone()),
             Extension(cfg.clone()),
             Extension(shared_transcript.clone()),
             Extension(db.clone()),
             Extension(Arc::new(AtomicUsize::new(0))),
+            Extension(SharedTranscriptHash::default()),
             Extension(keys.clone()),
+            Extension(noop_hook()),
+            Extension(ValidationLimiter::new(1)),
+            Extension(SharedVerificationCache::default()),
+            Extension(IdempotencyCache::new(Duration::from_secs(600))),
         )
         .await;
 
@@ -308,6 +947,12 @@ mod tests {
             .await
             .unwrap();
         assert_eq!(transcript, transcript_1);
+        let archived = read_contribution_file(contribution_path(archive.path(), 1))
+            .await
+            .unwrap();
+        assert_eq!(archived, contribution_1);
+        // The contribution is finished under the identity, not the session id.
+        assert_eq!(db.list_contributors(0, 10).await.unwrap(), vec![identity]);
         lobby_state
             .insert_session(participant.clone(), create_test_session_info(100))
             .await
@@ -319,13 +964,19 @@ mod tests {
             .unwrap();
         let result = contribute(
             participant.clone(),
+            IdempotencyKey(None),
             Json(contribution_2),
             Extension(lobby_state),
             Extension(cfg.clone()),
             Extension(shared_transcript.clone()),
             Extension(db.clone()),
             Extension(Arc::new(AtomicUsize::new(0))),
+            Extension(SharedTranscriptHash::default()),
             Extension(keys.clone()),
+            Extension(noop_hook()),
+            Extension(ValidationLimiter::new(1)),
+            Extension(SharedVerificationCache::default()),
+            Extension(IdempotencyCache::new(Duration::from_secs(600))),
         )
         .await;
 
@@ -336,6 +987,262 @@ mod tests {
         assert_eq!(transcript, transcript_2);
     }
 
+    async fn staged_contribution(
+        opts: &Options,
+        lobby_state: &SharedLobbyState,
+        db: &PersistentStorage,
+        shared_transcript: &SharedTranscript,
+        staged: &SharedStagedContributions,
+    ) -> (SessionId, StageResponse) {
+        let participant = SessionId::new();
+        lobby_state
+            .insert_session(participant.clone(), create_test_session_info(100))
+            .await
+            .unwrap();
+        lobby_state.enter_lobby(&participant).await.unwrap();
+        lobby_state
+            .set_current_contributor(&participant, opts.lobby.compute_deadline, db.clone())
+            .await
+            .unwrap();
+        let contribution = valid_contribution(&*shared_transcript.read().await, 1);
+        let Json(response) = stage_contribution(
+            participant.clone(),
+            Json(contribution),
+            Extension(lobby_state.clone()),
+            Extension(opts.clone()),
+            Extension(shared_transcript.clone()),
+            Extension(db.clone()),
+            Extension(noop_hook()),
+            Extension(ValidationLimiter::new(1)),
+            Extension(SharedVerificationCache::default()),
+            Extension(staged.clone()),
+        )
+        .await
+        .unwrap();
+        (participant, response)
+    }
+
+    async fn confirm(
+        participant: &SessionId,
+        confirmation_token: &str,
+        opts: &Options,
+        lobby_state: &SharedLobbyState,
+        db: &PersistentStorage,
+        shared_transcript: &SharedTranscript,
+        staged: &SharedStagedContributions,
+    ) -> Result<ContributeReceipt, ContributeError> {
+        confirm_contribution(
+            participant.clone(),
+            Json(ConfirmRequest {
+                confirmation_token: confirmation_token.to_string(),
+            }),
+            Extension(lobby_state.clone()),
+            Extension(opts.clone()),
+            Extension(shared_transcript.clone()),
+            Extension(db.clone()),
+            Extension(Arc::new(AtomicUsize::new(0))),
+            Extension(SharedTranscriptHash::default()),
+            Extension(shared_keys()),
+            Extension(staged.clone()),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn staged_contribution_confirmed_within_window() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut opts = test_options();
+        opts.transcript_file = dir.path().join("transcript.json");
+        opts.transcript_in_progress_file = dir.path().join("transcript.json.next");
+        let db = storage_client(&opts.storage).await.unwrap();
+        let lobby_state = SharedLobbyState::new(opts.lobby.clone());
+        let transcript = test_transcript();
+        let shared_transcript = Arc::new(RwLock::new(transcript.clone()));
+        let staged = SharedStagedContributions::default();
+
+        let (participant, response) =
+            staged_contribution(&opts, &lobby_state, &db, &shared_transcript, &staged).await;
+        assert_eq!(
+            response.expires_in_secs,
+            opts.contribution_confirm_window.as_secs()
+        );
+        // Nothing is applied before the confirmation.
+        assert_eq!(*shared_transcript.read().await, transcript);
+
+        let wrong_token = confirm(
+            &participant,
+            "wrong",
+            &opts,
+            &lobby_state,
+            &db,
+            &shared_transcript,
+            &staged,
+        )
+        .await;
+        assert!(matches!(
+            wrong_token,
+            Err(ContributeError::InvalidConfirmationToken)
+        ));
+
+        let result = confirm(
+            &participant,
+            &response.confirmation_token,
+            &opts,
+            &lobby_state,
+            &db,
+            &shared_transcript,
+            &staged,
+        )
+        .await;
+        assert!(result.is_ok());
+        assert_eq!(
+            shared_transcript.read().await.transcripts[0].num_participants(),
+            1
+        );
+        assert_eq!(lobby_state.snapshot().await.active_contributor, None);
+
+        // Each staged contribution is applied once.
+        let again = confirm(
+            &participant,
+            &response.confirmation_token,
+            &opts,
+            &lobby_state,
+            &db,
+            &shared_transcript,
+            &staged,
+        )
+        .await;
+        assert!(matches!(again, Err(ContributeError::NoStagedContribution)));
+    }
+
+    #[tokio::test]
+    async fn confirmation_after_eviction_is_refused() {
+        let opts = test_options();
+        let db = storage_client(&opts.storage).await.unwrap();
+        let lobby_state = SharedLobbyState::new(opts.lobby.clone());
+        let transcript = test_transcript();
+        let shared_transcript = Arc::new(RwLock::new(transcript.clone()));
+        let staged = SharedStagedContributions::default();
+
+        let (participant, response) =
+            staged_contribution(&opts, &lobby_state, &db, &shared_transcript, &staged).await;
+        lobby_state.evict_session(&participant).await.unwrap();
+
+        let result = confirm(
+            &participant,
+            &response.confirmation_token,
+            &opts,
+            &lobby_state,
+            &db,
+            &shared_transcript,
+            &staged,
+        )
+        .await;
+        assert!(matches!(result, Err(ContributeError::SlotLost)));
+        assert_eq!(*shared_transcript.read().await, transcript);
+    }
+
+    #[tokio::test]
+    async fn unconfirmed_staged_contribution_expires() {
+        let mut opts = test_options();
+        opts.lobby.contribution_cooldown = Duration::from_secs(600);
+        let db = storage_client(&opts.storage).await.unwrap();
+        let lobby_state = SharedLobbyState::new(opts.lobby.clone());
+        let transcript = test_transcript();
+        let shared_transcript = Arc::new(RwLock::new(transcript.clone()));
+        let staged = SharedStagedContributions::default();
+
+        tokio::time::pause();
+        let (participant, response) =
+            staged_contribution(&opts, &lobby_state, &db, &shared_transcript, &staged).await;
+        assert!(lobby_state.snapshot().await.active_contributor.is_some());
+
+        tokio::time::sleep(opts.contribution_confirm_window + Duration::from_secs(1)).await;
+        // The slot is free again.
+        assert_eq!(lobby_state.snapshot().await.active_contributor, None);
+
+        let result = confirm(
+            &participant,
+            &response.confirmation_token,
+            &opts,
+            &lobby_state,
+            &db,
+            &shared_transcript,
+            &staged,
+        )
+        .await;
+        assert!(matches!(result, Err(ContributeError::NoStagedContribution)));
+        assert_eq!(*shared_transcript.read().await, transcript);
+
+        // Expiring counts as not finishing.
+        lobby_state
+            .insert_session(participant.clone(), create_test_session_info(100))
+            .await
+            .unwrap();
+        assert!(matches!(
+            lobby_state.enter_lobby(&participant).await,
+            Err(ActiveContributorError::CooldownActive(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn retried_contribution_returns_cached_receipt() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut opts = test_options();
+        opts.transcript_file = dir.path().join("transcript.json");
+        opts.transcript_in_progress_file = dir.path().join("transcript.json.next");
+        let db = storage_client(&opts.storage).await.unwrap();
+        let lobby_state = SharedLobbyState::new(opts.lobby.clone());
+        let participant = SessionId::new();
+        lobby_state
+            .insert_session(participant.clone(), create_test_session_info(100))
+            .await
+            .unwrap();
+        lobby_state.enter_lobby(&participant).await.unwrap();
+        lobby_state
+            .set_current_contributor(&participant, opts.lobby.compute_deadline, db.clone())
+            .await
+            .unwrap();
+        let transcript = test_transcript();
+        let shared_transcript = Arc::new(RwLock::new(transcript.clone()));
+        let keys = shared_keys();
+        let cache = IdempotencyCache::new(Duration::from_secs(600));
+        let submit = |contribution: BatchContribution| {
+            contribute(
+                participant.clone(),
+                IdempotencyKey(Some("retry-key".to_string())),
+                Json(contribution),
+                Extension(lobby_state.clone()),
+                Extension(opts.clone()),
+                Extension(shared_transcript.clone()),
+                Extension(db.clone()),
+                Extension(Arc::new(AtomicUsize::new(0))),
+                Extension(SharedTranscriptHash::default()),
+                Extension(keys.clone()),
+                Extension(noop_hook()),
+                Extension(ValidationLimiter::new(1)),
+                Extension(SharedVerificationCache::default()),
+                Extension(cache.clone()),
+            )
+        };
+
+        let contribution = valid_contribution(&transcript, 1);
+        let first = submit(contribution.clone()).await.unwrap();
+        // The slot is already free, so a reprocessed retry would fail.
+        let retry = submit(contribution).await.unwrap();
+        assert_eq!(retry.receipt, first.receipt);
+        assert_eq!(
+            shared_transcript.read().await.transcripts[0].num_participants(),
+            1
+        );
+
+        let conflicting = submit(valid_contribution(&transcript, 2)).await;
+        assert!(matches!(
+            conflicting,
+            Err(ContributeError::IdempotencyKey(IdempotencyError::Conflict))
+        ));
+    }
+
     #[tokio::test]
     async fn aborts_contribution() {
         let opts = test_options();
@@ -364,6 +1271,7 @@ mod tests {
 
         let contribution_in_progress_response = try_contribute(
             other_session_id.clone(),
+            None,
             Extension(lobby_state.clone()),
             Extension(db.clone()),
             Extension(transcript.clone()),
@@ -389,6 +1297,7 @@ mod tests {
 
         let success_response = try_contribute(
             other_session_id.clone(),
+            None,
             Extension(lobby_state.clone()),
             Extension(db.clone()),
             Extension(transcript.clone()),
@@ -398,4 +1307,43 @@ mod tests {
 
         assert!(matches!(success_response, Ok(TryContributeResponse { .. })));
     }
+
+    #[tokio::test]
+    async fn ecdsa_signature_uses_configured_chain_id() {
+        let mut opts = test_options();
+        // Sepolia
+        opts.eip712_chain_id = 11_155_111;
+        let domain = opts.contribution_domain();
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let identity = Identity::Ethereum {
+            address: wallet.address().0,
+        };
+        let transcript = test_transcript();
+        let mut contribution = valid_contribution(&transcript, 1);
+        contribution.ecdsa_signature = EcdsaSignature(Some(
+            wallet
+                .sign_typed_data(&ContributionTypedData::new(&contribution, domain.clone()))
+                .await
+                .unwrap(),
+        ));
+
+        let mut testnet = transcript.clone();
+        testnet
+            .verify_add_in_domain::<Engine>(contribution.clone(), identity.clone(), &domain)
+            .unwrap();
+        assert_eq!(
+            testnet.participant_ecdsa_signatures[1],
+            contribution.ecdsa_signature
+        );
+
+        // The signature doesn't verify in the mainnet domain, so it is pruned.
+        let mut mainnet = transcript;
+        mainnet
+            .verify_add::<Engine>(contribution, identity)
+            .unwrap();
+        assert_eq!(
+            mainnet.participant_ecdsa_signatures[1],
+            EcdsaSignature::empty()
+        );
+    }
 }
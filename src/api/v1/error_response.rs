@@ -1,24 +1,51 @@
 use super::{
+    admin::AdminError,
     auth::{AuthError, AuthErrorPayload},
     contribute::ContributeError,
+    health::ReadinessError,
+    info::{ContributorsError, ExportError, InfoError},
+    link::LinkError,
     lobby::TryContributeError,
+    metrics::MetricsError,
+};
+use crate::{
+    concurrency::OverloadError, idempotency::IdempotencyError, keys::SignatureError,
+    sessions::SessionError, storage::StorageError, verify_only::VerifyOnlyError,
 };
-use crate::{keys::SignatureError, sessions::SessionError};
 use axum::{
     response::{IntoResponse, Redirect, Response},
     Json,
 };
-use http::StatusCode;
+use http::{header, StatusCode};
 use kzg_ceremony_crypto::{CeremoniesError, ErrorCode};
-use serde_json::json;
-use std::fmt::Display;
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, time::Duration};
 use url::Url;
 
-fn error_to_json<Err: Display + ErrorCode>(error: &Err) -> Json<serde_json::Value> {
-    Json(json!({
-        "code": error.to_error_code(),
-        "error": error.to_string()
-    }))
+/// The body of every error response. Clients should match on `code`, the
+/// `message` is for humans and may change.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiError {
+    pub code: String,
+    pub message: String,
+    /// For rate limited requests, how many seconds to wait before retrying.
+    /// Also sent as the `Retry-After` header.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_after_secs: Option<u64>,
+}
+
+impl ApiError {
+    pub fn new<Err: Display + ErrorCode>(error: &Err) -> Self {
+        Self {
+            code: error.to_error_code(),
+            message: error.to_string(),
+            retry_after_secs: None,
+        }
+    }
+}
+
+fn error_to_json<Err: Display + ErrorCode>(error: &Err) -> Json<ApiError> {
+    Json(ApiError::new(error))
 }
 
 impl IntoResponse for SignatureError {
@@ -35,10 +62,87 @@ impl IntoResponse for SignatureError {
     }
 }
 
+impl IntoResponse for AdminError {
+    fn into_response(self) -> Response {
+        let (status, body) = match self {
+            Self::Unauthorized => (StatusCode::UNAUTHORIZED, error_to_json(&self)),
+            Self::SessionNotFound => (StatusCode::NOT_FOUND, error_to_json(&self)),
+            Self::StorageError(err) => return err.into_response(),
+            Self::TaskError(_) => (StatusCode::INTERNAL_SERVER_ERROR, error_to_json(&self)),
+        };
+
+        (status, body).into_response()
+    }
+}
+
+impl IntoResponse for ExportError {
+    fn into_response(self) -> Response {
+        (StatusCode::BAD_REQUEST, error_to_json(&self)).into_response()
+    }
+}
+
+impl IntoResponse for ContributorsError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::InvalidLimit => (StatusCode::BAD_REQUEST, error_to_json(&self)).into_response(),
+            Self::StorageError(err) => err.into_response(),
+        }
+    }
+}
+
+impl IntoResponse for InfoError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::TranscriptUnavailable => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::ContributionNotFound | Self::RecordNotFound => StatusCode::NOT_FOUND,
+        };
+        (status, error_to_json(&self)).into_response()
+    }
+}
+
+impl IntoResponse for StorageError {
+    fn into_response(self) -> Response {
+        (StatusCode::INTERNAL_SERVER_ERROR, error_to_json(&self)).into_response()
+    }
+}
+
+impl IntoResponse for ReadinessError {
+    fn into_response(self) -> Response {
+        (StatusCode::SERVICE_UNAVAILABLE, error_to_json(&self)).into_response()
+    }
+}
+
+impl IntoResponse for OverloadError {
+    fn into_response(self) -> Response {
+        (StatusCode::SERVICE_UNAVAILABLE, error_to_json(&self)).into_response()
+    }
+}
+
+impl IntoResponse for VerifyOnlyError {
+    fn into_response(self) -> Response {
+        (StatusCode::METHOD_NOT_ALLOWED, error_to_json(&self)).into_response()
+    }
+}
+
+impl IntoResponse for LinkError {
+    fn into_response(self) -> Response {
+        let (status, body) = match self {
+            Self::UnknownSessionId => (StatusCode::UNAUTHORIZED, error_to_json(&self)),
+            Self::SameProvider | Self::AlreadyLinked => {
+                (StatusCode::BAD_REQUEST, error_to_json(&self))
+            }
+            Self::StorageError(err) => return err.into_response(),
+            Self::TaskError(_) => (StatusCode::INTERNAL_SERVER_ERROR, error_to_json(&self)),
+        };
+
+        (status, body).into_response()
+    }
+}
+
 impl IntoResponse for SessionError {
     fn into_response(self) -> Response {
         match self {
-            Self::InvalidSessionId => {
+            Self::UnknownSessionId => {
                 (StatusCode::BAD_REQUEST, error_to_json(&self)).into_response()
             }
         }
@@ -65,27 +169,53 @@ impl IntoResponse for AuthError {
 impl IntoResponse for AuthErrorPayload {
     fn into_response(self) -> Response {
         let (status, body) = match self {
-            Self::FetchUserDataError | Self::CouldNotExtractUserData => {
+            Self::FetchUserDataError | Self::CouldNotExtractUserData | Self::TokenSigning => {
                 (StatusCode::INTERNAL_SERVER_ERROR, error_to_json(&self))
             }
-            Self::LobbyIsFull => (StatusCode::SERVICE_UNAVAILABLE, error_to_json(&self)),
-            Self::InvalidAuthCode | Self::UserAlreadyContributed => {
-                (StatusCode::BAD_REQUEST, error_to_json(&self))
+            Self::LobbyIsFull | Self::TooManyAuthRequests => {
+                (StatusCode::SERVICE_UNAVAILABLE, error_to_json(&self))
             }
-            Self::UserCreatedAfterDeadline => (StatusCode::UNAUTHORIZED, error_to_json(&self)),
+            Self::RateLimited => (StatusCode::TOO_MANY_REQUESTS, error_to_json(&self)),
+            Self::InvalidAuthCode
+            | Self::InvalidCsrfState
+            | Self::InvalidStateEncoding
+            | Self::InvalidStateJson
+            | Self::UserAlreadyContributed => (StatusCode::BAD_REQUEST, error_to_json(&self)),
+            Self::UserCreatedAfterDeadline
+            | Self::NotEnoughFollowers
+            | Self::NotEnoughPublicRepos => (StatusCode::UNAUTHORIZED, error_to_json(&self)),
             Self::Storage(storage_error) => return storage_error.into_response(),
         };
         (status, body).into_response()
     }
 }
 
+impl IntoResponse for IdempotencyError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::InvalidKey => StatusCode::BAD_REQUEST,
+            Self::Conflict => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::InProgress => StatusCode::CONFLICT,
+        };
+        (status, error_to_json(&self)).into_response()
+    }
+}
+
 impl IntoResponse for ContributeError {
     fn into_response(self) -> Response {
         let (status, body) = match self {
-            Self::NotUsersTurn => (StatusCode::BAD_REQUEST, error_to_json(&self)),
+            Self::NotUsersTurn | Self::NoStagedContribution | Self::InvalidConfirmationToken => {
+                (StatusCode::BAD_REQUEST, error_to_json(&self))
+            }
+            Self::Rejected(_) | Self::SlotLost | Self::TranscriptChanged => {
+                (StatusCode::CONFLICT, error_to_json(&self))
+            }
+            Self::AcceptanceTimeout => (StatusCode::GATEWAY_TIMEOUT, error_to_json(&self)),
             Self::InvalidContribution(e) => return CeremoniesErrorFormatter(e).into_response(),
+            Self::InvalidBatch(e) => (StatusCode::BAD_REQUEST, error_to_json(&e)),
             Self::ReceiptSigning(err) => return err.into_response(),
             Self::StorageError(err) => return err.into_response(),
+            Self::IdempotencyKey(err) => return err.into_response(),
             Self::TaskError(_) | Self::TranscriptIOError(_) => {
                 (StatusCode::INTERNAL_SERVER_ERROR, error_to_json(&self))
             }
@@ -95,14 +225,44 @@ impl IntoResponse for ContributeError {
     }
 }
 
+impl IntoResponse for MetricsError {
+    fn into_response(self) -> Response {
+        let (status, body) = match self {
+            Self::Unauthorized => (StatusCode::UNAUTHORIZED, error_to_json(&self)),
+            Self::Encoding(_) => (StatusCode::INTERNAL_SERVER_ERROR, error_to_json(&self)),
+        };
+
+        (status, body).into_response()
+    }
+}
+
 impl IntoResponse for TryContributeError {
     fn into_response(self) -> Response {
         let (status, body) = match self {
-            Self::UnknownSessionId => (StatusCode::UNAUTHORIZED, error_to_json(&self)),
-            Self::RateLimited | Self::LobbyIsFull => {
-                (StatusCode::BAD_REQUEST, error_to_json(&self))
+            Self::UnknownSessionId | Self::SessionRevoked => {
+                (StatusCode::UNAUTHORIZED, error_to_json(&self))
             }
+            Self::RateLimited(retry_after) | Self::CooldownActive(retry_after) => {
+                return rate_limited(&self, retry_after)
+            }
+            Self::LobbyIsFull
+            | Self::UserAlreadyContributed
+            | Self::IdentityNotLinked
+            | Self::ProofOfWorkRequired
+            | Self::PowChallengeMissing
+            | Self::PowChallengeExpired
+            | Self::InvalidProofOfWork
+            | Self::IntentSignatureRequired
+            | Self::IntentChallengeMissing
+            | Self::IntentChallengeExpired
+            | Self::InvalidIntentSignature
+            | Self::IntentSignatureUnsupported
+            | Self::NotActiveContributor => (StatusCode::BAD_REQUEST, error_to_json(&self)),
+            Self::InvalidLobbyEntryToken
+            | Self::LobbyEntryTokenExpired
+            | Self::LobbyEntryTokenUsed => (StatusCode::UNAUTHORIZED, error_to_json(&self)),
             Self::AnotherContributionInProgress => (StatusCode::OK, error_to_json(&self)),
+            Self::CeremonyPaused => (StatusCode::SERVICE_UNAVAILABLE, error_to_json(&self)),
             Self::StorageError(err) => return err.into_response(),
             Self::TaskError(_) => (StatusCode::INTERNAL_SERVER_ERROR, error_to_json(&self)),
         };
@@ -111,15 +271,115 @@ impl IntoResponse for TryContributeError {
     }
 }
 
+/// Responds with how long to wait, rounded up to whole seconds, in both the
+/// `Retry-After` header and the body.
+fn rate_limited<Err: Display + ErrorCode>(error: &Err, retry_after: Duration) -> Response {
+    let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    let body = Json(ApiError {
+        retry_after_secs: Some(secs),
+        ..ApiError::new(error)
+    });
+    (
+        StatusCode::BAD_REQUEST,
+        [(header::RETRY_AFTER, secs.to_string())],
+        body,
+    )
+        .into_response()
+}
+
 struct CeremoniesErrorFormatter(CeremoniesError);
 
 impl IntoResponse for CeremoniesErrorFormatter {
     fn into_response(self) -> Response {
-        let body = Json(json!({
-            "code": self.0.to_error_code(),
-            "error" : format!("contribution invalid: {}", self.0)
-        }));
+        let body = Json(ApiError {
+            code: self.0.to_error_code(),
+            message: format!("contribution invalid: {}", self.0),
+            retry_after_secs: None,
+        });
 
         (StatusCode::BAD_REQUEST, body).into_response()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    async fn into_parts(response: impl IntoResponse) -> (StatusCode, Value) {
+        let response = response.into_response();
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn error_envelope() {
+        assert_eq!(
+            into_parts(TryContributeError::LobbyIsFull).await,
+            (
+                StatusCode::BAD_REQUEST,
+                json!({
+                    "code": "TryContributeError::LobbyIsFull",
+                    "message": "lobby is full",
+                })
+            )
+        );
+        assert_eq!(
+            into_parts(InfoError::RecordNotFound).await,
+            (
+                StatusCode::NOT_FOUND,
+                json!({
+                    "code": "InfoError::RecordNotFound",
+                    "message": "record index out of range",
+                })
+            )
+        );
+        assert_eq!(
+            into_parts(AuthError {
+                redirect: None,
+                payload: AuthErrorPayload::InvalidStateJson,
+            })
+            .await,
+            (
+                StatusCode::BAD_REQUEST,
+                json!({
+                    "code": "AuthErrorPayload::InvalidStateJson",
+                    "message": "invalid json in state parameter",
+                })
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn rate_limited_has_retry_after() {
+        let response =
+            TryContributeError::RateLimited(Duration::from_millis(12_300)).into_response();
+        assert_eq!(response.headers()[header::RETRY_AFTER], "13");
+        assert_eq!(
+            into_parts(response).await,
+            (
+                StatusCode::BAD_REQUEST,
+                json!({
+                    "code": "TryContributeError::RateLimited",
+                    "message": "call came too early. rate limited",
+                    "retry_after_secs": 13,
+                })
+            )
+        );
+
+        let response = TryContributeError::RateLimited(Duration::from_secs(5)).into_response();
+        assert_eq!(response.headers()[header::RETRY_AFTER], "5");
+    }
+
+    #[tokio::test]
+    async fn nested_errors_keep_their_code() {
+        let (status, body) = into_parts(ContributeError::StorageError(
+            StorageError::DatabaseError(sqlx::Error::PoolTimedOut),
+        ))
+        .await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        let error: ApiError = serde_json::from_value(body).unwrap();
+        assert_eq!(error.code, "StorageError::DatabaseError");
+    }
+}
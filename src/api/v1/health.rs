@@ -0,0 +1,118 @@
+use crate::{storage::PersistentStorage, Options, SharedTranscript};
+use axum::Extension;
+use http::StatusCode;
+use kzg_ceremony_crypto::ErrorCode;
+use std::time::Duration;
+use strum::IntoStaticStr;
+use thiserror::Error;
+use tokio::time::timeout;
+use tracing::warn;
+
+/// Probes should fail fast rather than hang on an unresponsive database.
+const STORAGE_PING_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum ReadinessError {
+    #[error("storage is unreachable")]
+    StorageUnreachable,
+    #[error("transcript is not initialized")]
+    TranscriptNotInitialized,
+}
+
+impl ErrorCode for ReadinessError {
+    fn to_error_code(&self) -> String {
+        format!("ReadinessError::{}", <&str>::from(self))
+    }
+}
+
+/// Liveness probe, succeeds as long as the server is listening.
+#[allow(clippy::unused_async)] // Required for axum function signature
+pub async fn livez() -> StatusCode {
+    StatusCode::OK
+}
+
+/// Readiness probe, succeeds once the sequencer can serve contributions.
+pub async fn readyz(
+    Extension(options): Extension<Options>,
+    Extension(storage): Extension<PersistentStorage>,
+    Extension(transcript): Extension<SharedTranscript>,
+) -> Result<StatusCode, ReadinessError> {
+    match timeout(STORAGE_PING_TIMEOUT, storage.ping()).await {
+        Ok(Ok(())) => {}
+        Ok(Err(error)) => {
+            warn!(?error, "readiness check failed to ping storage");
+            return Err(ReadinessError::StorageUnreachable);
+        }
+        Err(_) => {
+            warn!("readiness check timed out pinging storage");
+            return Err(ReadinessError::StorageUnreachable);
+        }
+    }
+
+    // A contribution holds the write lock while it is verified, which can take
+    // a while. The transcript is loaded by then, so don't wait for it.
+    if let Ok(transcript) = transcript.try_read() {
+        options
+            .ceremony_sizes
+            .validate_batch_transcript(&transcript)
+            .map_err(|_| ReadinessError::TranscriptNotInitialized)?;
+    }
+
+    Ok(StatusCode::OK)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        io::CeremonySizes, storage::storage_client, test_util::test_options, tests::test_transcript,
+    };
+    use axum::response::IntoResponse;
+    use kzg_ceremony_crypto::BatchTranscript;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    #[tokio::test]
+    async fn livez_always_succeeds() {
+        assert_eq!(livez().await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn readyz_checks_storage() {
+        let mut opts = test_options();
+        opts.ceremony_sizes = CeremonySizes::parse_from_cmd("4,2").unwrap();
+        let storage = storage_client(&opts.storage).await.unwrap();
+        let transcript = Arc::new(RwLock::new(test_transcript()));
+        let ready = || {
+            readyz(
+                Extension(opts.clone()),
+                Extension(storage.clone()),
+                Extension(transcript.clone()),
+            )
+        };
+
+        assert_eq!(ready().await.unwrap(), StatusCode::OK);
+
+        // Holding the connection makes the ping time out.
+        let connection = storage.lock_connection().await;
+        let response = ready().await.into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        drop(connection);
+        assert_eq!(ready().await.unwrap(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn readyz_checks_transcript() {
+        let mut opts = test_options();
+        opts.ceremony_sizes = CeremonySizes::parse_from_cmd("4,2").unwrap();
+        let storage = storage_client(&opts.storage).await.unwrap();
+        let transcript = Arc::new(RwLock::new(BatchTranscript::new(&[(8, 3)])));
+
+        let result = readyz(Extension(opts), Extension(storage), Extension(transcript)).await;
+        assert!(matches!(
+            result,
+            Err(ReadinessError::TranscriptNotInitialized)
+        ));
+    }
+}
@@ -1,16 +1,28 @@
+use super::json::JsonResponse;
 use crate::{
+    engine::ComputeBenchmark,
+    io::transcript_hash,
     keys::{Address, SharedKeys},
     lobby::SharedLobbyState,
-    Options, SharedCeremonyStatus,
+    storage::{PersistentStorage, StorageError},
+    Options, SharedCeremonyStatus, SharedTranscript, SharedTranscriptHash,
 };
 use axum::{
     body::StreamBody,
+    extract::{Path, Query},
     response::{IntoResponse, Response},
-    Extension, Json,
+    Extension, Json, TypedHeader,
 };
+use ethers_core::{types::H160, utils::to_checksum};
+use headers::{ETag, IfNoneMatch};
 use http::StatusCode;
-use serde::Serialize;
+use kzg_ceremony_crypto::{
+    BatchContribution, BatchTranscript, Contribution, ContributionRecord, ErrorCode, Identity,
+};
+use serde::{Deserialize, Serialize};
 use std::sync::atomic::Ordering;
+use strum::IntoStaticStr;
+use thiserror::Error;
 use tokio::fs::File;
 use tokio_util::io::ReaderStream;
 
@@ -21,41 +33,506 @@ pub struct StatusResponse {
     sequencer_address: Address,
 }
 
-impl IntoResponse for StatusResponse {
-    fn into_response(self) -> Response {
-        let status = StatusCode::OK;
-        (status, Json(self)).into_response()
-    }
-}
-
 pub async fn status(
+    Extension(options): Extension<Options>,
     Extension(lobby_state): Extension<SharedLobbyState>,
     Extension(ceremony_status): Extension<SharedCeremonyStatus>,
     Extension(keys): Extension<SharedKeys>,
-) -> StatusResponse {
+) -> JsonResponse<StatusResponse> {
     let lobby_size = lobby_state.get_lobby_size().await;
 
     let num_contributions = ceremony_status.load(Ordering::Relaxed);
     let sequencer_address = keys.address();
 
-    StatusResponse {
-        lobby_size,
-        num_contributions,
-        sequencer_address,
+    JsonResponse::new(
+        StatusResponse {
+            lobby_size,
+            num_contributions,
+            sequencer_address,
+        },
+        &options,
+    )
+}
+
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct CeremonyInfo {
+    sub_contributions: Vec<PowerCounts>,
+    /// Size of the contribution JSON in bytes, both downloaded and uploaded.
+    byte_size: usize,
+    /// Rough time to compute a contribution, from a benchmark of the
+    /// sequencer's own hardware.
+    estimated_compute_millis: u64,
+}
+
+impl CeremonyInfo {
+    /// Describes contributions shaped like `contribution`. The shape is fixed
+    /// by the ceremony sizes, so this is computed once at startup.
+    #[must_use]
+    pub fn new(contribution: &BatchContribution, benchmark: &ComputeBenchmark) -> Self {
+        let sub_contributions = contribution
+            .contributions
+            .iter()
+            .map(|contribution| PowerCounts {
+                num_g1_powers: contribution.powers.g1.len(),
+                num_g2_powers: contribution.powers.g2.len(),
+            })
+            .collect();
+        let estimated_compute_millis =
+            u64::try_from(benchmark.estimate(contribution).as_millis()).unwrap_or(u64::MAX);
+        Self {
+            sub_contributions,
+            byte_size: contribution.byte_size(),
+            estimated_compute_millis,
+        }
     }
 }
 
-pub async fn current_state(Extension(options): Extension<Options>) -> impl IntoResponse {
-    let f = match File::open(options.transcript_file).await {
-        Ok(file) => file,
-        Err(_) => {
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "could not open transcript file",
-            ))
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct PowerCounts {
+    num_g1_powers: usize,
+    num_g2_powers: usize,
+}
+
+/// Describes the work a contribution involves, so that clients can decide
+/// whether to join the lobby.
+pub async fn ceremony_info(Extension(info): Extension<CeremonyInfo>) -> Json<CeremonyInfo> {
+    Json(info)
+}
+
+/// Serves the transcript, with the hash as of its last write as `ETag`.
+pub async fn current_state(
+    if_none_match: Option<TypedHeader<IfNoneMatch>>,
+    Extension(options): Extension<Options>,
+    Extension(transcript): Extension<SharedTranscript>,
+    Extension(shared_hash): Extension<SharedTranscriptHash>,
+) -> Result<Response, InfoError> {
+    let etag = hash_etag(&shared_hash.read().await);
+    if let Some(TypedHeader(if_none_match)) = if_none_match {
+        if !if_none_match.precondition_passes(&etag) {
+            return Ok((StatusCode::NOT_MODIFIED, TypedHeader(etag)).into_response());
         }
-    };
+    }
+    // A compressed file can't be streamed as is. Serialize a copy, so that
+    // contributions aren't blocked while the response is built.
+    if options.transcript_zstd_level.is_some() {
+        let transcript = transcript.read().await.clone();
+        return Ok((StatusCode::OK, TypedHeader(etag), Json(transcript)).into_response());
+    }
+
+    let f = File::open(options.transcript_file)
+        .await
+        .map_err(|_| InfoError::TranscriptUnavailable)?;
     let stream = ReaderStream::new(f);
     let body = StreamBody::new(stream);
-    Ok((StatusCode::OK, body))
+    Ok((StatusCode::OK, TypedHeader(etag), body).into_response())
+}
+
+/// Returns the current state of a single sub-ceremony, so that clients don't
+/// have to download the whole batch.
+pub async fn contribution_at(
+    Path(index): Path<usize>,
+    Extension(transcript): Extension<SharedTranscript>,
+) -> Result<Json<Contribution>, InfoError> {
+    transcript
+        .read()
+        .await
+        .contribution_at(index)
+        .map(Json)
+        .ok_or(InfoError::ContributionNotFound)
+}
+
+/// Returns what the transcript records about the participant at `index`,
+/// `0` being the initial state. The witnesses come from
+/// [`kzg_ceremony_crypto::Transcript::contribution_by_index`].
+pub async fn record_at(
+    Path(index): Path<usize>,
+    Extension(transcript): Extension<SharedTranscript>,
+) -> Result<Json<ContributionRecord>, InfoError> {
+    let record = transcript.read().await.record(index);
+    record.map(Json).ok_or(InfoError::RecordNotFound)
+}
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum InfoError {
+    #[error("could not open transcript file")]
+    TranscriptUnavailable,
+    #[error("contribution index out of range")]
+    ContributionNotFound,
+    #[error("record index out of range")]
+    RecordNotFound,
+}
+
+impl ErrorCode for InfoError {
+    fn to_error_code(&self) -> String {
+        format!("InfoError::{}", <&str>::from(self))
+    }
+}
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum ExportError {
+    #[error("limit must be greater than zero")]
+    InvalidLimit,
+    #[error("offset is past the end of the transcript")]
+    OffsetOutOfRange,
+}
+
+impl ErrorCode for ExportError {
+    fn to_error_code(&self) -> String {
+        format!("ExportError::{}", <&str>::from(self))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportParams {
+    #[serde(default)]
+    offset: usize,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportPage {
+    total: usize,
+    offset: usize,
+    contributions: Vec<ContributionRecord>,
+    /// Offset of the next page, if there is one.
+    next: Option<usize>,
+}
+
+/// Returns a page of the contribution history. Only the records of the page
+/// are built, the limit is capped to `export_max_limit`.
+pub async fn export(
+    Query(params): Query<ExportParams>,
+    Extension(options): Extension<Options>,
+    Extension(transcript): Extension<SharedTranscript>,
+) -> Result<JsonResponse<ExportPage>, ExportError> {
+    let limit = params
+        .limit
+        .unwrap_or(options.export_max_limit)
+        .min(options.export_max_limit);
+    if limit == 0 {
+        return Err(ExportError::InvalidLimit);
+    }
+
+    let transcript = transcript.read().await;
+    let total = transcript.num_records();
+    if params.offset > total {
+        return Err(ExportError::OffsetOutOfRange);
+    }
+    let end = params.offset.saturating_add(limit).min(total);
+    let contributions = (params.offset..end)
+        .filter_map(|index| transcript.record(index))
+        .collect();
+
+    Ok(JsonResponse::new(
+        ExportPage {
+            total,
+            offset: params.offset,
+            contributions,
+            next: (end < total).then_some(end),
+        },
+        &options,
+    ))
+}
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum ContributorsError {
+    #[error("limit must be greater than zero")]
+    InvalidLimit,
+    #[error("storage error: {0}")]
+    StorageError(#[from] StorageError),
+}
+
+impl ErrorCode for ContributorsError {
+    fn to_error_code(&self) -> String {
+        format!("ContributorsError::{}", <&str>::from(self))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Contributor {
+    provider: String,
+    id: String,
+}
+
+impl Contributor {
+    /// Checksummed address for Ethereum, `id/username` for the social
+    /// providers.
+    fn new(identity: &Identity) -> Option<Self> {
+        let id = match identity {
+            Identity::None => return None,
+            Identity::Ethereum { address } => to_checksum(&H160(*address), None),
+            Identity::Github { id, username } => format!("{id}/{username}"),
+            Identity::Twitter { id, handle } => format!("{id}/{handle}"),
+        };
+        Some(Self {
+            provider: identity.provider_name(),
+            id,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ContributorsPage {
+    offset: usize,
+    contributors: Vec<Contributor>,
+    /// Offset of the next page, if there is one.
+    next: Option<usize>,
+}
+
+/// Returns a page of the identities that completed a contribution. The limit
+/// is capped to `export_max_limit`.
+pub async fn contributors(
+    Query(params): Query<ExportParams>,
+    Extension(options): Extension<Options>,
+    Extension(storage): Extension<PersistentStorage>,
+) -> Result<Json<ContributorsPage>, ContributorsError> {
+    let limit = params
+        .limit
+        .unwrap_or(options.export_max_limit)
+        .min(options.export_max_limit);
+    if limit == 0 {
+        return Err(ContributorsError::InvalidLimit);
+    }
+
+    // One extra row tells whether there is a next page.
+    let mut identities = storage
+        .list_contributors(params.offset, limit.saturating_add(1))
+        .await?;
+    let next = (identities.len() > limit).then_some(params.offset.saturating_add(limit));
+    identities.truncate(limit);
+
+    Ok(Json(ContributorsPage {
+        offset: params.offset,
+        contributors: identities.iter().filter_map(Contributor::new).collect(),
+        next,
+    }))
+}
+
+pub fn transcript_etag(transcript: &BatchTranscript) -> ETag {
+    hash_etag(&transcript_hash(transcript))
+}
+
+fn hash_etag(hash: &str) -> ETag {
+    format!("\"{hash}\"")
+        .parse()
+        .expect("Impossible, a quoted hex string is a valid ETag")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        storage::storage_client,
+        test_util::test_options,
+        tests::{test_transcript, valid_contribution},
+        Engine,
+    };
+    use std::{sync::Arc, time::Duration};
+    use tokio::sync::RwLock;
+
+    #[tokio::test]
+    async fn export_pages() {
+        let mut opts = test_options();
+        opts.export_max_limit = 2;
+        let mut transcript = test_transcript();
+        for no in 1..=4 {
+            let contribution = valid_contribution(&transcript, no);
+            transcript
+                .verify_add::<Engine>(contribution, Identity::None)
+                .unwrap();
+        }
+        let expected = (0..transcript.num_records())
+            .map(|index| transcript.record(index).unwrap())
+            .collect::<Vec<_>>();
+        let shared = Arc::new(RwLock::new(transcript));
+
+        let page = |offset, limit| {
+            export(
+                Query(ExportParams { offset, limit }),
+                Extension(opts.clone()),
+                Extension(shared.clone()),
+            )
+        };
+
+        // The limit is capped, so paging takes three requests.
+        let mut records = Vec::new();
+        let mut offset = Some(0);
+        let mut pages = 0;
+        while let Some(current) = offset {
+            let page = page(current, Some(10)).await.unwrap().value;
+            assert_eq!(page.total, expected.len());
+            assert!(page.contributions.len() <= 2);
+            records.extend(page.contributions);
+            offset = page.next;
+            pages += 1;
+        }
+        assert_eq!(pages, 3);
+        assert_eq!(records, expected);
+
+        let last = page(expected.len(), None).await.unwrap().value;
+        assert!(last.contributions.is_empty());
+        assert_eq!(last.next, None);
+        assert!(matches!(
+            page(expected.len() + 1, None).await,
+            Err(ExportError::OffsetOutOfRange)
+        ));
+        assert!(matches!(
+            page(0, Some(0)).await,
+            Err(ExportError::InvalidLimit)
+        ));
+    }
+
+    #[tokio::test]
+    async fn ceremony_info_matches_transcript() {
+        let transcript = BatchTranscript::new(&[(4, 2), (8, 3)]);
+        let contribution = transcript.contribution();
+        let benchmark = ComputeBenchmark {
+            per_g1_power: Duration::from_millis(1),
+            per_g2_power: Duration::from_millis(3),
+        };
+
+        let Json(info) =
+            ceremony_info(Extension(CeremonyInfo::new(&contribution, &benchmark))).await;
+        assert_eq!(
+            info.sub_contributions,
+            vec![
+                PowerCounts {
+                    num_g1_powers: 4,
+                    num_g2_powers: 2,
+                },
+                PowerCounts {
+                    num_g1_powers: 8,
+                    num_g2_powers: 3,
+                },
+            ]
+        );
+        assert_eq!(info.byte_size, contribution.byte_size());
+        assert_eq!(info.estimated_compute_millis, 12 + 3 * 5);
+    }
+
+    #[tokio::test]
+    async fn contributors_pages() {
+        let mut opts = test_options();
+        opts.export_max_limit = 2;
+        let storage = storage_client(&opts.storage).await.unwrap();
+        let identities = [
+            Identity::Ethereum {
+                address: hex::decode("5aaeb6053f3e94c9b9a09f33669435e7ef1beaed")
+                    .unwrap()
+                    .try_into()
+                    .unwrap(),
+            },
+            Identity::Github {
+                id: 1234,
+                username: "alice".to_string(),
+            },
+            Identity::Twitter {
+                id: 5678,
+                handle: "bob".to_string(),
+            },
+        ];
+        for identity in &identities {
+            storage
+                .insert_contributor(&identity.unique_id())
+                .await
+                .unwrap();
+            storage
+                .finish_contribution(&identity.unique_id())
+                .await
+                .unwrap();
+        }
+
+        let page = |offset, limit| {
+            contributors(
+                Query(ExportParams { offset, limit }),
+                Extension(opts.clone()),
+                Extension(storage.clone()),
+            )
+        };
+
+        let Json(first) = page(0, Some(10)).await.unwrap();
+        assert_eq!(first.next, Some(2));
+        assert_eq!(
+            first.contributors,
+            vec![
+                Contributor {
+                    provider: "Ethereum".to_string(),
+                    id: "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed".to_string(),
+                },
+                Contributor {
+                    provider: "Github".to_string(),
+                    id: "1234/alice".to_string(),
+                },
+            ]
+        );
+
+        let Json(second) = page(2, None).await.unwrap();
+        assert_eq!(second.next, None);
+        assert_eq!(
+            second.contributors,
+            vec![Contributor {
+                provider: "Twitter".to_string(),
+                id: "5678/bob".to_string(),
+            }]
+        );
+
+        assert!(page(3, None).await.unwrap().contributors.is_empty());
+        assert!(matches!(
+            page(0, Some(0)).await,
+            Err(ContributorsError::InvalidLimit)
+        ));
+    }
+
+    #[tokio::test]
+    async fn contribution_by_index() {
+        let transcript = test_transcript();
+        let expected = transcript.transcripts[0].contribution();
+        let shared = Arc::new(RwLock::new(transcript));
+
+        let response = contribution_at(Path(0), Extension(shared.clone()))
+            .await
+            .into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let contribution: Contribution = serde_json::from_slice(&body).unwrap();
+        assert_eq!(contribution, expected);
+
+        let response = contribution_at(Path(1), Extension(shared))
+            .await
+            .into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn record_by_index() {
+        let mut transcript = test_transcript();
+        let contribution = valid_contribution(&transcript, 1);
+        transcript
+            .verify_add::<Engine>(contribution, Identity::None)
+            .unwrap();
+        let expected = transcript.record(1).unwrap();
+        let witnesses = transcript
+            .transcripts
+            .iter()
+            .map(|transcript| transcript.contribution_by_index(1).unwrap())
+            .collect::<Vec<_>>();
+        let num_records = transcript.num_records();
+        let shared = Arc::new(RwLock::new(transcript));
+
+        let response = record_at(Path(1), Extension(shared.clone()))
+            .await
+            .into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let record: ContributionRecord = serde_json::from_slice(&body).unwrap();
+        assert_eq!(record, expected);
+        assert_eq!(record.index, 1);
+        assert_eq!(record.witnesses, witnesses);
+
+        let response = record_at(Path(num_records), Extension(shared))
+            .await
+            .into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
 }
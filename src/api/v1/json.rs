@@ -0,0 +1,78 @@
+use crate::Options;
+use axum::response::{IntoResponse, Response};
+use http::{header, HeaderValue, StatusCode};
+use serde::Serialize;
+
+/// Like [`axum::Json`], but pretty-printed if `--pretty-json` is set, for
+/// reading responses by hand. Compact otherwise.
+#[derive(Debug)]
+pub struct JsonResponse<T> {
+    pub value: T,
+    pub pretty: bool,
+}
+
+impl<T> JsonResponse<T> {
+    pub const fn new(value: T, options: &Options) -> Self {
+        Self {
+            value,
+            pretty: options.pretty_json,
+        }
+    }
+}
+
+impl<T: Serialize> IntoResponse for JsonResponse<T> {
+    fn into_response(self) -> Response {
+        let body = if self.pretty {
+            serde_json::to_string_pretty(&self.value)
+        } else {
+            serde_json::to_string(&self.value)
+        };
+        match body {
+            Ok(body) => (
+                [(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("application/json"),
+                )],
+                body,
+            )
+                .into_response(),
+            Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    async fn body(pretty: bool) -> String {
+        let response = JsonResponse {
+            value: json!({ "lobby_size": 1, "num_contributions": 2 }),
+            pretty,
+        }
+        .into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn compact_by_default() {
+        assert_eq!(
+            body(false).await,
+            r#"{"lobby_size":1,"num_contributions":2}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn pretty_when_enabled() {
+        let body = body(true).await;
+        assert!(body.lines().count() > 1);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+            json!({ "lobby_size": 1, "num_contributions": 2 })
+        );
+    }
+}
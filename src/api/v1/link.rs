@@ -0,0 +1,171 @@
+use crate::{
+    lobby::SharedLobbyState,
+    storage::{PersistentStorage, StorageError},
+    SessionId,
+};
+use axum::{Extension, Json};
+use http::StatusCode;
+use kzg_ceremony_crypto::ErrorCode;
+use serde::Deserialize;
+use strum::IntoStaticStr;
+use thiserror::Error;
+use tokio::task::JoinError;
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum LinkError {
+    #[error("unknown session id")]
+    UnknownSessionId,
+    #[error("identities must be from different providers")]
+    SameProvider,
+    #[error("identity is already linked")]
+    AlreadyLinked,
+    #[error("error in storage layer: {0}")]
+    StorageError(#[from] StorageError),
+    #[error("background task error: {0}")]
+    TaskError(#[from] JoinError),
+}
+
+impl ErrorCode for LinkError {
+    fn to_error_code(&self) -> String {
+        format!("LinkError::{}", <&str>::from(self))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LinkRequest {
+    session_id: String,
+}
+
+/// Records the identities of two sessions as a single principal, so that
+/// neither can contribute once the other has. Holding both session ids proves
+/// control of both identities, as each was obtained through its provider's
+/// sign-in flow.
+pub async fn link_identity(
+    session_id: SessionId,
+    Json(request): Json<LinkRequest>,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(storage): Extension<PersistentStorage>,
+) -> Result<StatusCode, LinkError> {
+    let identity = |session_id| {
+        let lobby_state = lobby_state.clone();
+        async move {
+            lobby_state
+                .modify_participant(&session_id, |info| info.token.identity.clone())
+                .await
+                .ok_or(LinkError::UnknownSessionId)
+        }
+    };
+    let first = identity(session_id).await?;
+    let second = identity(SessionId(request.session_id)).await?;
+
+    if first.provider() == second.provider() {
+        return Err(LinkError::SameProvider);
+    }
+
+    // Link in the background, so that request cancelation doesn't interrupt
+    // it inbetween the check and the inserts.
+    tokio::spawn(async move {
+        if storage.link_identities(&first, &second).await? {
+            Ok(StatusCode::OK)
+        } else {
+            Err(LinkError::AlreadyLinked)
+        }
+    })
+    .await
+    .unwrap_or_else(|e| Err(LinkError::TaskError(e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        api::v1::lobby::{try_contribute, TryContributeError},
+        storage::storage_client,
+        test_util::{create_test_session_info, test_options},
+        tests::test_transcript,
+    };
+    use kzg_ceremony_crypto::signature::identity::Identity;
+    use std::{sync::Arc, time::Duration};
+    use tokio::sync::RwLock;
+
+    #[tokio::test]
+    async fn linked_identity_can_not_contribute_twice() {
+        let mut opts = test_options();
+        opts.require_linked_identity = true;
+        opts.lobby.lobby_checkin_frequency = Duration::ZERO;
+        let lobby_state = SharedLobbyState::new(opts.lobby.clone());
+        let transcript = Arc::new(RwLock::new(test_transcript()));
+        let db = storage_client(&opts.storage).await.unwrap();
+
+        let github_session = SessionId::new();
+        let eth_session = SessionId::new();
+        let other_github_session = SessionId::new();
+        lobby_state
+            .insert_session(github_session.clone(), create_test_session_info(100))
+            .await
+            .unwrap();
+        let mut eth_session_info = create_test_session_info(100);
+        eth_session_info.token.identity = Identity::Ethereum { address: [1; 20] };
+        lobby_state
+            .insert_session(eth_session.clone(), eth_session_info)
+            .await
+            .unwrap();
+        let mut other_github_session_info = create_test_session_info(100);
+        other_github_session_info.token.identity = Identity::Github {
+            id: 5678,
+            username: "other_user".to_string(),
+        };
+        lobby_state
+            .insert_session(other_github_session.clone(), other_github_session_info)
+            .await
+            .unwrap();
+
+        let link = |session_id: &SessionId, other: &SessionId| {
+            link_identity(
+                session_id.clone(),
+                Json(LinkRequest {
+                    session_id: other.0.clone(),
+                }),
+                Extension(lobby_state.clone()),
+                Extension(db.clone()),
+            )
+        };
+        let try_contribute = |session_id: &SessionId| {
+            try_contribute(
+                session_id.clone(),
+                None,
+                Extension(lobby_state.clone()),
+                Extension(db.clone()),
+                Extension(transcript.clone()),
+                Extension(opts.clone()),
+            )
+        };
+
+        // Unlinked identities are refused when linking is required.
+        assert!(matches!(
+            try_contribute(&github_session).await,
+            Err(TryContributeError::IdentityNotLinked)
+        ));
+
+        assert!(matches!(
+            link(&github_session, &other_github_session).await,
+            Err(LinkError::SameProvider)
+        ));
+        assert!(matches!(
+            link(&github_session, &eth_session).await,
+            Ok(StatusCode::OK)
+        ));
+        assert!(matches!(
+            link(&other_github_session, &eth_session).await,
+            Err(LinkError::AlreadyLinked)
+        ));
+
+        // Contribute via GitHub, then the linked Ethereum identity is refused.
+        assert!(try_contribute(&github_session).await.is_ok());
+        lobby_state.clear_current_contributor().await;
+        assert!(matches!(
+            try_contribute(&eth_session).await,
+            Err(TryContributeError::UserAlreadyContributed)
+        ));
+    }
+}
@@ -1,15 +1,26 @@
 use crate::{
+    api::v1::{
+        auth::{cookie, create_session, LOBBY_ENTRY_COOKIE},
+        info::transcript_etag,
+    },
+    intent::{self, IntentChallenge, IntentSignature},
+    keys::SharedKeys,
     lobby::{ActiveContributorError, SharedLobbyState},
+    lobby_token::{LobbyEntryToken, LobbyTokenError},
+    oauth::SharedAuthState,
+    pow::PowChallenge,
     storage::{PersistentStorage, StorageError},
     SessionId, SharedTranscript,
 };
 use axum::{
     response::{IntoResponse, Response},
-    Extension, Json,
+    Extension, Json, TypedHeader,
 };
-use http::StatusCode;
+use headers::{ETag, IfNoneMatch};
+use http::{HeaderMap, StatusCode};
 use kzg_ceremony_crypto::{BatchContribution, ErrorCode};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
 use strum::IntoStaticStr;
 use thiserror::Error;
 use tokio::{task::JoinError, time::Instant};
@@ -18,12 +29,50 @@ use tokio::{task::JoinError, time::Instant};
 pub enum TryContributeError {
     #[error("unknown session id")]
     UnknownSessionId,
+    /// Carries how long the caller has to wait.
     #[error("call came too early. rate limited")]
-    RateLimited,
+    RateLimited(Duration),
     #[error("another contribution in progress")]
     AnotherContributionInProgress,
     #[error("lobby is full")]
     LobbyIsFull,
+    #[error("user already contributed")]
+    UserAlreadyContributed,
+    #[error("identity must be linked to another provider first")]
+    IdentityNotLinked,
+    #[error("session revoked")]
+    SessionRevoked,
+    #[error("proof of work not solved")]
+    ProofOfWorkRequired,
+    #[error("no proof of work challenge requested")]
+    PowChallengeMissing,
+    #[error("proof of work challenge expired")]
+    PowChallengeExpired,
+    #[error("invalid proof of work")]
+    InvalidProofOfWork,
+    #[error("intent to participate not signed")]
+    IntentSignatureRequired,
+    #[error("only Ethereum identities sign their intent")]
+    IntentSignatureUnsupported,
+    #[error("no intent challenge requested")]
+    IntentChallengeMissing,
+    #[error("intent challenge expired")]
+    IntentChallengeExpired,
+    #[error("invalid intent signature")]
+    InvalidIntentSignature,
+    #[error("invalid lobby entry token")]
+    InvalidLobbyEntryToken,
+    #[error("lobby entry token expired")]
+    LobbyEntryTokenExpired,
+    #[error("lobby entry token already used")]
+    LobbyEntryTokenUsed,
+    #[error("ceremony is paused")]
+    CeremonyPaused,
+    /// Carries how long the caller has to wait.
+    #[error("contribution cooldown active")]
+    CooldownActive(Duration),
+    #[error("user not active contributor")]
+    NotActiveContributor,
     #[error("error in storage layer: {0}")]
     StorageError(#[from] StorageError),
     #[error("background task error: {0}")]
@@ -45,44 +94,71 @@ impl From<ActiveContributorError> for TryContributeError {
             | ActiveContributorError::NotActiveContributor => Self::UnknownSessionId,
             ActiveContributorError::SessionCountLimitExceeded
             | ActiveContributorError::LobbySizeLimitExceeded => Self::LobbyIsFull,
-            ActiveContributorError::RateLimited => Self::RateLimited,
+            ActiveContributorError::RateLimited(retry_after) => Self::RateLimited(retry_after),
+            ActiveContributorError::SessionRevoked => Self::SessionRevoked,
+            ActiveContributorError::ProofOfWorkRequired => Self::ProofOfWorkRequired,
+            ActiveContributorError::IntentSignatureRequired => Self::IntentSignatureRequired,
+            ActiveContributorError::CeremonyPaused => Self::CeremonyPaused,
+            ActiveContributorError::CooldownActive(retry_after) => {
+                Self::CooldownActive(retry_after)
+            }
+        }
+    }
+}
+
+impl From<LobbyTokenError> for TryContributeError {
+    fn from(err: LobbyTokenError) -> Self {
+        match err {
+            LobbyTokenError::Invalid => Self::InvalidLobbyEntryToken,
+            LobbyTokenError::Expired => Self::LobbyEntryTokenExpired,
+            LobbyTokenError::Used => Self::LobbyEntryTokenUsed,
         }
     }
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct TryContributeResponse<C> {
-    contribution: C,
+    /// The contribution base, or `None` if the client already has the version
+    /// identified by `etag`.
+    contribution: Option<C>,
+    etag: ETag,
 }
 
 impl<C: Serialize> IntoResponse for TryContributeResponse<C> {
     fn into_response(self) -> Response {
-        (StatusCode::OK, Json(self.contribution)).into_response()
+        match self.contribution {
+            Some(contribution) => {
+                (StatusCode::OK, TypedHeader(self.etag), Json(contribution)).into_response()
+            }
+            None => (StatusCode::NOT_MODIFIED, TypedHeader(self.etag)).into_response(),
+        }
     }
 }
 
 pub async fn try_contribute(
     session_id: SessionId,
+    if_none_match: Option<TypedHeader<IfNoneMatch>>,
     Extension(lobby_state): Extension<SharedLobbyState>,
     Extension(storage): Extension<PersistentStorage>,
     Extension(transcript): Extension<SharedTranscript>,
     Extension(options): Extension<crate::Options>,
 ) -> Result<TryContributeResponse<BatchContribution>, TryContributeError> {
+    lobby_state.ensure_not_revoked(&session_id).await?;
+
     let res = lobby_state
         .modify_participant(&session_id, |mut info| {
             let now = Instant::now();
-            if !info.is_first_ping_attempt
-                && now < info.last_ping_time + options.lobby.min_checkin_delay()
-            {
-                return Err(TryContributeError::RateLimited);
+            let next_ping_time = info.last_ping_time + options.lobby.min_checkin_delay();
+            if !info.is_first_ping_attempt && now < next_ping_time {
+                return Err(TryContributeError::RateLimited(next_ping_time - now));
             }
             info.is_first_ping_attempt = false;
             info.last_ping_time = now;
-            Ok(info.token.unique_identifier())
+            Ok(info.token.identity.clone())
         })
         .await;
 
-    let uid = if let Some(inner) = res {
+    let identity = if let Some(inner) = res {
         inner?
     } else {
         // Session not found. Check if they're the active contributor, and
@@ -92,8 +168,18 @@ pub async fn try_contribute(
             .await?;
 
         let transcript = transcript.read().await;
+        let etag = transcript_etag(&transcript);
+        if let Some(TypedHeader(if_none_match)) = if_none_match {
+            if !if_none_match.precondition_passes(&etag) {
+                return Ok(TryContributeResponse {
+                    contribution: None,
+                    etag,
+                });
+            }
+        }
         return Ok(TryContributeResponse {
-            contribution: transcript.contribution(),
+            contribution: Some(transcript.contribution()),
+            etag,
         });
     };
 
@@ -101,6 +187,14 @@ pub async fn try_contribute(
     // so that request cancelation doesn't interrupt it inbetween the lobby_state
     // and storage calls.
     tokio::spawn(async move {
+        if options.require_linked_identity && !storage.is_linked(&identity).await? {
+            return Err(TryContributeError::IdentityNotLinked);
+        }
+
+        if !options.multi_contribution && storage.has_contributed(&identity).await? {
+            return Err(TryContributeError::UserAlreadyContributed);
+        }
+
         lobby_state.enter_lobby(&session_id).await?;
 
         lobby_state
@@ -108,27 +202,235 @@ pub async fn try_contribute(
             .await
             .map_err(TryContributeError::from)?;
 
-        storage.insert_contributor(&uid).await?;
+        storage.insert_contributor(&identity.unique_id()).await?;
         let transcript = transcript.read().await;
 
+        // The new active contributor always gets the contribution base.
         Ok(TryContributeResponse {
-            contribution: transcript.contribution(),
+            contribution: Some(transcript.contribution()),
+            etag: transcript_etag(&transcript),
         })
     })
     .await
     .unwrap_or_else(|e| Err(TryContributeError::TaskError(e)))
 }
 
+#[derive(Debug, Deserialize)]
+pub struct PowSolution {
+    nonce: u64,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct PowChallengeResponse {
+    challenge: String,
+    /// Number of leading zero bits the solution hash needs.
+    difficulty: u32,
+    expires_in_secs: u64,
+}
+
+/// Issues a new proof-of-work challenge to the session, replacing any previous
+/// one.
+pub async fn pow_challenge(
+    session_id: SessionId,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(options): Extension<crate::Options>,
+) -> Result<Json<PowChallengeResponse>, TryContributeError> {
+    let ttl = options.lobby.pow_challenge_ttl;
+    let challenge = PowChallenge::new(options.lobby.pow_difficulty, ttl);
+    let response = PowChallengeResponse {
+        challenge: challenge.challenge().to_owned(),
+        difficulty: challenge.difficulty(),
+        expires_in_secs: ttl.as_secs(),
+    };
+    lobby_state
+        .modify_participant(&session_id, |info| info.pow_challenge = Some(challenge))
+        .await
+        .ok_or(TryContributeError::UnknownSessionId)?;
+    Ok(Json(response))
+}
+
+/// Submits the solution to the proof-of-work challenge of the session. If
+/// a difficulty is configured, this must be done before entering the lobby.
+/// Each challenge can only be answered once.
+pub async fn submit_pow(
+    session_id: SessionId,
+    Json(solution): Json<PowSolution>,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+) -> Result<StatusCode, TryContributeError> {
+    let challenge = lobby_state
+        .modify_participant(&session_id, |info| info.pow_challenge.take())
+        .await
+        .ok_or(TryContributeError::UnknownSessionId)?
+        .ok_or(TryContributeError::PowChallengeMissing)?;
+    if challenge.is_expired() {
+        return Err(TryContributeError::PowChallengeExpired);
+    }
+    if !challenge.verify(solution.nonce) {
+        return Err(TryContributeError::InvalidProofOfWork);
+    }
+    lobby_state
+        .modify_participant(&session_id, |info| info.pow_solved = true)
+        .await
+        .ok_or(TryContributeError::UnknownSessionId)?;
+    Ok(StatusCode::OK)
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct IntentChallengeResponse {
+    /// The message to sign.
+    message: String,
+    expires_in_secs: u64,
+}
+
+/// Issues a new intent challenge to the session, replacing any previous one.
+/// Only Ethereum identities get one.
+pub async fn intent_challenge(
+    session_id: SessionId,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(options): Extension<crate::Options>,
+) -> Result<Json<IntentChallengeResponse>, TryContributeError> {
+    let ttl = options.lobby.intent_challenge_ttl;
+    let challenge = IntentChallenge::new(ttl);
+    let message = lobby_state
+        .modify_participant(&session_id, |info| {
+            if !intent::applies_to(&info.token.identity) {
+                return None;
+            }
+            let message = challenge.message(&info.token.identity);
+            info.intent_challenge = Some(challenge);
+            Some(message)
+        })
+        .await
+        .ok_or(TryContributeError::UnknownSessionId)?
+        .ok_or(TryContributeError::IntentSignatureUnsupported)?;
+    Ok(Json(IntentChallengeResponse {
+        message,
+        expires_in_secs: ttl.as_secs(),
+    }))
+}
+
+/// Submits the signed intent challenge of the session. Each challenge can only
+/// be answered once, a failed attempt needs a new challenge.
+pub async fn submit_intent(
+    session_id: SessionId,
+    Json(signature): Json<IntentSignature>,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+) -> Result<StatusCode, TryContributeError> {
+    let (challenge, identity) = lobby_state
+        .modify_participant(&session_id, |info| {
+            (info.intent_challenge.take(), info.token.identity.clone())
+        })
+        .await
+        .ok_or(TryContributeError::UnknownSessionId)?;
+    let challenge = challenge.ok_or(TryContributeError::IntentChallengeMissing)?;
+    if challenge.is_expired() {
+        return Err(TryContributeError::IntentChallengeExpired);
+    }
+
+    // Verify outside of the lobby lock, pairings are not free.
+    let message = challenge.message(&identity);
+    if !signature.verify(&identity, message.as_bytes()) {
+        return Err(TryContributeError::InvalidIntentSignature);
+    }
+    lobby_state
+        .modify_participant(&session_id, |info| info.intent_signed = true)
+        .await
+        .ok_or(TryContributeError::UnknownSessionId)?;
+    Ok(StatusCode::OK)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LobbyEntryRequest {
+    token: String,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct LobbyEntryResponse {
+    session_id: String,
+}
+
+/// Enters the lobby with a lobby entry token issued at sign-in, so that the
+/// sign-in session doesn't have to be kept alive until then. The token is
+/// taken from the request body or the cookie set at sign-in. Returns a fresh
+/// session to continue with, the sign-in session is dropped.
+pub async fn enter_with_token(
+    headers: HeaderMap,
+    request: Option<Json<LobbyEntryRequest>>,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(auth_state): Extension<SharedAuthState>,
+    Extension(storage): Extension<PersistentStorage>,
+    Extension(keys): Extension<SharedKeys>,
+    Extension(options): Extension<crate::Options>,
+) -> Result<Json<LobbyEntryResponse>, TryContributeError> {
+    let token = request
+        .map(|Json(request)| request.token)
+        .or_else(|| cookie(&headers, LOBBY_ENTRY_COOKIE))
+        .ok_or(TryContributeError::InvalidLobbyEntryToken)?;
+    let token = LobbyEntryToken::verify(&token, &keys)?;
+    if !options.multi_contribution && storage.has_contributed(&token.identity).await? {
+        return Err(TryContributeError::UserAlreadyContributed);
+    }
+    {
+        let mut auth_state = auth_state.write().await;
+        if !auth_state.redeem_lobby_token(&token.id, token.exp) {
+            return Err(LobbyTokenError::Used.into());
+        }
+        // Never hand out the sign-in session, whoever holds the token
+        // must not take over a session that is in use.
+        let unique_id = token.identity.unique_id();
+        if let Some(previous) = auth_state.unique_id_session.remove(&unique_id) {
+            if let Err(error) = lobby_state.remove_idle_session(&previous).await {
+                auth_state.unique_id_session.insert(unique_id, previous);
+                return Err(error.into());
+            }
+        }
+    }
+    let (session_id, _) = create_session(&auth_state, &lobby_state, token.identity).await?;
+    lobby_state.enter_lobby(&session_id).await?;
+    Ok(Json(LobbyEntryResponse {
+        session_id: session_id.to_string(),
+    }))
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct HeartbeatResponse {
+    /// Seconds left until the contributor slot expires.
+    remaining_seconds: u64,
+}
+
+/// Called periodically by the active contributor to extend its compute
+/// deadline while computing a contribution.
+pub async fn heartbeat(
+    session_id: SessionId,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+) -> Result<Json<HeartbeatResponse>, TryContributeError> {
+    let remaining = lobby_state
+        .heartbeat(&session_id)
+        .await
+        .map_err(|_| TryContributeError::NotActiveContributor)?;
+    Ok(Json(HeartbeatResponse {
+        remaining_seconds: remaining.as_secs(),
+    }))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{
         api::v1::lobby::TryContributeError,
+        intent::tests::ecdsa_intent,
+        keys::{self, Keys},
+        lobby::SelectionPolicy,
+        pow::solve_pow,
         storage::storage_client,
         test_util::{create_test_session_info, test_options},
         tests::test_transcript,
     };
-    use std::{sync::Arc, time::Duration};
+    use axum::extract::{FromRequest, RequestParts};
+    use clap::Parser;
+    use ethers_signers::{LocalWallet, Signer};
+    use kzg_ceremony_crypto::signature::identity::Identity;
+    use std::sync::Arc;
     use tokio::sync::RwLock;
 
     #[tokio::test]
@@ -145,6 +447,7 @@ mod tests {
         // no users in lobby
         let unknown_session_response = try_contribute(
             session_id.clone(),
+            None,
             Extension(lobby_state.clone()),
             Extension(db.clone()),
             Extension(transcript.clone()),
@@ -159,14 +462,21 @@ mod tests {
             .insert_session(session_id.clone(), create_test_session_info(100))
             .await
             .unwrap();
+        // a different user, who has not contributed yet
+        let mut other_session_info = create_test_session_info(100);
+        other_session_info.token.identity = Identity::Github {
+            id: 5678,
+            username: "other_user".to_string(),
+        };
         lobby_state
-            .insert_session(other_session_id.clone(), create_test_session_info(100))
+            .insert_session(other_session_id.clone(), other_session_info)
             .await
             .unwrap();
 
         // "other participant" is contributing
         try_contribute(
             other_session_id.clone(),
+            None,
             Extension(lobby_state.clone()),
             Extension(db.clone()),
             Extension(transcript.clone()),
@@ -176,6 +486,7 @@ mod tests {
         .unwrap();
         let contribution_in_progress_response = try_contribute(
             session_id.clone(),
+            None,
             Extension(lobby_state.clone()),
             Extension(db.clone()),
             Extension(transcript.clone()),
@@ -194,6 +505,7 @@ mod tests {
         tokio::time::advance(Duration::from_secs(5)).await;
         let too_soon_response = try_contribute(
             session_id.clone(),
+            None,
             Extension(lobby_state.clone()),
             Extension(db.clone()),
             Extension(transcript.clone()),
@@ -201,8 +513,15 @@ mod tests {
         )
         .await;
 
+        // The first ping was right before pausing the clock.
+        let min_checkin_delay = test_options().lobby.min_checkin_delay();
         assert!(
-            matches!(too_soon_response, Err(TryContributeError::RateLimited),),
+            matches!(
+                too_soon_response,
+                Err(TryContributeError::RateLimited(retry_after))
+                    if retry_after <= min_checkin_delay - Duration::from_secs(5)
+                        && retry_after > min_checkin_delay - Duration::from_secs(6)
+            ),
             "response expected: Err(TryContributeError::RateLimited) actual: {:?}",
             too_soon_response
         );
@@ -214,6 +533,7 @@ mod tests {
         tokio::time::advance(Duration::from_secs(5)).await;
         let too_soon_response = try_contribute(
             session_id.clone(),
+            None,
             Extension(lobby_state.clone()),
             Extension(db.clone()),
             Extension(transcript.clone()),
@@ -222,7 +542,9 @@ mod tests {
         .await;
         assert!(matches!(
             too_soon_response,
-            Err(TryContributeError::RateLimited)
+            Err(TryContributeError::RateLimited(retry_after))
+                if retry_after <= min_checkin_delay - Duration::from_secs(10)
+                    && retry_after > min_checkin_delay - Duration::from_secs(11)
         ));
 
         // wait enough time to be able to contribute
@@ -231,6 +553,7 @@ mod tests {
         tokio::time::resume();
         let success_response = try_contribute(
             session_id.clone(),
+            None,
             Extension(lobby_state.clone()),
             Extension(db.clone()),
             Extension(transcript.clone()),
@@ -242,13 +565,17 @@ mod tests {
         // if a user attempts to try_contribute again they should get rate limited
         let check_again = try_contribute(
             session_id.clone(),
+            None,
             Extension(lobby_state.clone()),
             Extension(db.clone()),
             Extension(transcript.clone()),
             Extension(test_options()),
         )
         .await;
-        assert!(matches!(check_again, Err(TryContributeError::RateLimited)));
+        assert!(matches!(
+            check_again,
+            Err(TryContributeError::RateLimited(_))
+        ));
 
         tokio::time::pause();
         tokio::time::advance(test_options().lobby.min_checkin_delay()).await;
@@ -257,6 +584,7 @@ mod tests {
         // but after waiting a bit they should be able to re-fetch their transcript
         let refetch_transcript = try_contribute(
             session_id.clone(),
+            None,
             Extension(lobby_state.clone()),
             Extension(db.clone()),
             Extension(transcript.clone()),
@@ -266,4 +594,459 @@ mod tests {
         .expect("re-fetching the transcript with try_contribute failed");
         assert_eq!(success_response, refetch_transcript);
     }
+
+    async fn issue_pow_challenge(
+        session_id: &SessionId,
+        lobby_state: &SharedLobbyState,
+        opts: &crate::Options,
+    ) -> PowChallenge {
+        pow_challenge(
+            session_id.clone(),
+            Extension(lobby_state.clone()),
+            Extension(opts.clone()),
+        )
+        .await
+        .unwrap();
+        lobby_state
+            .modify_participant(session_id, |info| info.pow_challenge.clone())
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn pow_required_before_entering_lobby() {
+        let mut opts = test_options();
+        opts.lobby.pow_difficulty = 8;
+        // Don't rate limit the repeated calls.
+        opts.lobby.lobby_checkin_frequency = Duration::ZERO;
+        let lobby_state = SharedLobbyState::new(opts.lobby.clone());
+        let transcript = Arc::new(RwLock::new(test_transcript()));
+        let db = storage_client(&opts.storage).await.unwrap();
+        let session_id = SessionId::new();
+        lobby_state
+            .insert_session(session_id.clone(), create_test_session_info(100))
+            .await
+            .unwrap();
+
+        let try_contribute = || {
+            try_contribute(
+                session_id.clone(),
+                None,
+                Extension(lobby_state.clone()),
+                Extension(db.clone()),
+                Extension(transcript.clone()),
+                Extension(opts.clone()),
+            )
+        };
+        let submit_pow = |nonce| {
+            submit_pow(
+                session_id.clone(),
+                Json(PowSolution { nonce }),
+                Extension(lobby_state.clone()),
+            )
+        };
+
+        assert!(matches!(
+            try_contribute().await,
+            Err(TryContributeError::ProofOfWorkRequired)
+        ));
+        assert!(matches!(
+            submit_pow(0).await,
+            Err(TryContributeError::PowChallengeMissing)
+        ));
+
+        let challenge = issue_pow_challenge(&session_id, &lobby_state, &opts).await;
+        let insufficient = (0..).find(|n| !challenge.verify(*n)).unwrap();
+        assert!(matches!(
+            submit_pow(insufficient).await,
+            Err(TryContributeError::InvalidProofOfWork)
+        ));
+        // A failed attempt needs a new challenge.
+        assert!(matches!(
+            submit_pow(solve_pow(&challenge)).await,
+            Err(TryContributeError::PowChallengeMissing)
+        ));
+        assert!(matches!(
+            try_contribute().await,
+            Err(TryContributeError::ProofOfWorkRequired)
+        ));
+
+        let challenge = issue_pow_challenge(&session_id, &lobby_state, &opts).await;
+        assert!(submit_pow(solve_pow(&challenge)).await.is_ok());
+        assert!(try_contribute().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn stale_pow_challenge_rejected() {
+        let mut opts = test_options();
+        opts.lobby.pow_difficulty = 4;
+        opts.lobby.pow_challenge_ttl = Duration::ZERO;
+        let lobby_state = SharedLobbyState::new(opts.lobby.clone());
+        let session_id = SessionId::new();
+        lobby_state
+            .insert_session(session_id.clone(), create_test_session_info(100))
+            .await
+            .unwrap();
+
+        let challenge = issue_pow_challenge(&session_id, &lobby_state, &opts).await;
+        assert!(matches!(
+            submit_pow(
+                session_id,
+                Json(PowSolution {
+                    nonce: solve_pow(&challenge)
+                }),
+                Extension(lobby_state),
+            )
+            .await,
+            Err(TryContributeError::PowChallengeExpired)
+        ));
+    }
+
+    #[tokio::test]
+    async fn pow_without_requested_challenge_rejected() {
+        let mut opts = test_options();
+        opts.lobby.pow_difficulty = 4;
+        let lobby_state = SharedLobbyState::new(opts.lobby.clone());
+        let session_id = SessionId::new();
+        lobby_state
+            .insert_session(session_id.clone(), create_test_session_info(100))
+            .await
+            .unwrap();
+
+        // Whatever nonce is sent, there is no challenge to check it against.
+        for nonce in 0..16 {
+            assert!(matches!(
+                submit_pow(
+                    session_id.clone(),
+                    Json(PowSolution { nonce }),
+                    Extension(lobby_state.clone()),
+                )
+                .await,
+                Err(TryContributeError::PowChallengeMissing)
+            ));
+        }
+        assert_eq!(
+            lobby_state
+                .modify_participant(&session_id, |info| info.pow_solved)
+                .await,
+            Some(false)
+        );
+    }
+
+    #[tokio::test]
+    async fn intent_required_before_entering_lobby() {
+        let mut opts = test_options();
+        opts.lobby.require_intent_signature = true;
+        // Don't rate limit the repeated calls.
+        opts.lobby.lobby_checkin_frequency = Duration::ZERO;
+        let lobby_state = SharedLobbyState::new(opts.lobby.clone());
+        let transcript = Arc::new(RwLock::new(test_transcript()));
+        let db = storage_client(&opts.storage).await.unwrap();
+        let session_id = SessionId::new();
+        let wallet = LocalWallet::new(&mut rand::thread_rng());
+        let identity = Identity::Ethereum {
+            address: wallet.address().0,
+        };
+        let mut session_info = create_test_session_info(100);
+        session_info.token.identity = identity.clone();
+        lobby_state
+            .insert_session(session_id.clone(), session_info)
+            .await
+            .unwrap();
+
+        let try_contribute = || {
+            try_contribute(
+                session_id.clone(),
+                None,
+                Extension(lobby_state.clone()),
+                Extension(db.clone()),
+                Extension(transcript.clone()),
+                Extension(opts.clone()),
+            )
+        };
+        let request_challenge = || {
+            intent_challenge(
+                session_id.clone(),
+                Extension(lobby_state.clone()),
+                Extension(opts.clone()),
+            )
+        };
+        let submit_intent = |signature| {
+            submit_intent(
+                session_id.clone(),
+                Json(signature),
+                Extension(lobby_state.clone()),
+            )
+        };
+
+        assert!(matches!(
+            submit_intent(IntentSignature::default()).await,
+            Err(TryContributeError::IntentChallengeMissing)
+        ));
+
+        // Unsigned and wrongly signed intents are rejected.
+        request_challenge().await.unwrap();
+        assert!(matches!(
+            submit_intent(IntentSignature::default()).await,
+            Err(TryContributeError::InvalidIntentSignature)
+        ));
+        request_challenge().await.unwrap();
+        assert!(matches!(
+            submit_intent(ecdsa_intent(&wallet, b"something else")).await,
+            Err(TryContributeError::InvalidIntentSignature)
+        ));
+        assert!(matches!(
+            try_contribute().await,
+            Err(TryContributeError::IntentSignatureRequired)
+        ));
+
+        // Challenges expire.
+        let message = request_challenge().await.unwrap().0.message;
+        tokio::time::pause();
+        tokio::time::advance(opts.lobby.intent_challenge_ttl).await;
+        tokio::time::resume();
+        assert!(matches!(
+            submit_intent(ecdsa_intent(&wallet, message.as_bytes())).await,
+            Err(TryContributeError::IntentChallengeExpired)
+        ));
+
+        let message = request_challenge().await.unwrap().0.message;
+        assert!(message.contains(&identity.to_string()));
+        assert_eq!(
+            submit_intent(ecdsa_intent(&wallet, message.as_bytes()))
+                .await
+                .unwrap(),
+            StatusCode::OK
+        );
+        assert!(try_contribute().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn intent_not_required_without_wallet() {
+        let mut opts = test_options();
+        opts.lobby.require_intent_signature = true;
+        let lobby_state = SharedLobbyState::new(opts.lobby.clone());
+        let session_id = SessionId::new();
+        lobby_state
+            .insert_session(session_id.clone(), create_test_session_info(100))
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            intent_challenge(
+                session_id.clone(),
+                Extension(lobby_state.clone()),
+                Extension(opts)
+            )
+            .await,
+            Err(TryContributeError::IntentSignatureUnsupported)
+        ));
+        lobby_state.enter_lobby(&session_id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn refetch_not_modified() {
+        let opts = test_options();
+        let lobby_state = SharedLobbyState::new(opts.lobby.clone());
+        let transcript = Arc::new(RwLock::new(test_transcript()));
+        let db = storage_client(&opts.storage).await.unwrap();
+        let session_id = SessionId::new();
+        lobby_state
+            .insert_session(session_id.clone(), create_test_session_info(100))
+            .await
+            .unwrap();
+
+        // the new active contributor gets the body even if they claim to have it
+        let response = try_contribute(
+            session_id.clone(),
+            Some(TypedHeader(IfNoneMatch::any())),
+            Extension(lobby_state.clone()),
+            Extension(db.clone()),
+            Extension(transcript.clone()),
+            Extension(test_options()),
+        )
+        .await
+        .unwrap();
+        assert!(response.contribution.is_some());
+
+        tokio::time::pause();
+        tokio::time::advance(opts.lobby.min_checkin_delay()).await;
+        tokio::time::resume();
+
+        // re-fetching with the received ETag doesn't send the body again
+        let refetch = try_contribute(
+            session_id.clone(),
+            Some(TypedHeader(IfNoneMatch::from(response.etag.clone()))),
+            Extension(lobby_state.clone()),
+            Extension(db.clone()),
+            Extension(transcript.clone()),
+            Extension(test_options()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(refetch.contribution, None);
+        assert_eq!(refetch.etag, response.etag);
+        assert_eq!(refetch.into_response().status(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[tokio::test]
+    async fn first_entered_admitted_first() {
+        let mut opts = test_options();
+        opts.lobby.selection_policy = SelectionPolicy::Fifo;
+        opts.lobby.lobby_checkin_frequency = Duration::ZERO;
+        let lobby_state = SharedLobbyState::new(opts.lobby.clone());
+        let transcript = Arc::new(RwLock::new(test_transcript()));
+        let db = storage_client(&opts.storage).await.unwrap();
+
+        let first = SessionId::new();
+        let second = SessionId::new();
+        let mut second_info = create_test_session_info(100);
+        second_info.token.identity = Identity::Github {
+            id: 5678,
+            username: "other_user".to_string(),
+        };
+        for (session_id, info) in [
+            (first.clone(), create_test_session_info(100)),
+            (second.clone(), second_info),
+        ] {
+            lobby_state
+                .insert_session(session_id.clone(), info)
+                .await
+                .unwrap();
+            lobby_state.enter_lobby(&session_id).await.unwrap();
+        }
+
+        let try_contribute = |session_id: &SessionId| {
+            try_contribute(
+                session_id.clone(),
+                None,
+                Extension(lobby_state.clone()),
+                Extension(db.clone()),
+                Extension(transcript.clone()),
+                Extension(opts.clone()),
+            )
+        };
+
+        // Polling eagerly doesn't let the later session jump the line.
+        for _ in 0..3 {
+            assert!(matches!(
+                try_contribute(&second).await,
+                Err(TryContributeError::AnotherContributionInProgress)
+            ));
+        }
+        assert!(try_contribute(&first).await.is_ok());
+        assert!(matches!(
+            try_contribute(&second).await,
+            Err(TryContributeError::AnotherContributionInProgress)
+        ));
+
+        lobby_state.clear_current_contributor().await;
+        assert!(try_contribute(&second).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn try_contribute_with_bearer_header() {
+        let opts = test_options();
+        let lobby_state = SharedLobbyState::new(opts.lobby.clone());
+        let transcript = Arc::new(RwLock::new(test_transcript()));
+        let db = storage_client(&opts.storage).await.unwrap();
+        let session_id = SessionId::new();
+        lobby_state
+            .insert_session(session_id.clone(), create_test_session_info(100))
+            .await
+            .unwrap();
+
+        let request = http::Request::builder()
+            .header(http::header::AUTHORIZATION, format!("Bearer {session_id}"))
+            .body(())
+            .unwrap();
+        let extracted = SessionId::from_request(&mut RequestParts::new(request))
+            .await
+            .unwrap();
+
+        assert_eq!(extracted, session_id);
+        try_contribute(
+            extracted,
+            None,
+            Extension(lobby_state),
+            Extension(db),
+            Extension(transcript),
+            Extension(opts),
+        )
+        .await
+        .expect("the bearer session should be able to contribute");
+    }
+
+    #[tokio::test]
+    async fn enter_lobby_with_token() {
+        let opts = test_options();
+        let lobby_state = SharedLobbyState::new(opts.lobby.clone());
+        let auth_state = SharedAuthState::default();
+        let db = storage_client(&opts.storage).await.unwrap();
+        let keys = Arc::new(Keys::new(&keys::Options::parse_from(Vec::<&str>::new())).unwrap());
+        let identity = Identity::Github {
+            id: 1234,
+            username: "test_user".to_string(),
+        };
+        let sign_token = || async {
+            LobbyEntryToken::new(identity.clone(), opts.lobby.lobby_entry_token_ttl)
+                .sign(&keys)
+                .await
+                .unwrap()
+        };
+        let enter = |headers: HeaderMap, token: Option<String>| {
+            enter_with_token(
+                headers,
+                token.map(|token| Json(LobbyEntryRequest { token })),
+                Extension(lobby_state.clone()),
+                Extension(auth_state.clone()),
+                Extension(db.clone()),
+                Extension(keys.clone()),
+                Extension(opts.clone()),
+            )
+        };
+        let (sign_in_session, _) = create_session(&auth_state, &lobby_state, identity.clone())
+            .await
+            .unwrap();
+
+        let token = sign_token().await;
+        assert!(matches!(
+            enter(HeaderMap::new(), Some(format!("{token}00"))).await,
+            Err(TryContributeError::InvalidLobbyEntryToken)
+        ));
+        assert!(matches!(
+            enter(HeaderMap::new(), None).await,
+            Err(TryContributeError::InvalidLobbyEntryToken)
+        ));
+        assert_eq!(lobby_state.get_lobby_size().await, 0);
+
+        let Json(response) = enter(HeaderMap::new(), Some(token.clone())).await.unwrap();
+        assert_eq!(lobby_state.get_lobby_size().await, 1);
+        let snapshot = lobby_state.snapshot().await;
+        let session_id = SessionId(response.session_id);
+        assert_eq!(snapshot.sessions_in_lobby[0].session_key, session_id.key());
+        assert_eq!(snapshot.sessions_in_lobby[0].identity, identity);
+        // A fresh session, the sign-in session is gone.
+        assert_ne!(session_id, sign_in_session);
+        assert!(lobby_state.session_status(&sign_in_session).await.is_none());
+
+        // Every token can only be used once.
+        assert!(matches!(
+            enter(HeaderMap::new(), Some(token)).await,
+            Err(TryContributeError::LobbyEntryTokenUsed)
+        ));
+
+        // The token is also taken from the cookie set at sign-in.
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            http::header::COOKIE,
+            format!("{LOBBY_ENTRY_COOKIE}={}", sign_token().await)
+                .parse()
+                .unwrap(),
+        );
+        let Json(response) = enter(headers, None).await.unwrap();
+        assert_ne!(SessionId(response.session_id), session_id);
+        assert!(lobby_state.session_status(&session_id).await.is_none());
+    }
 }
@@ -0,0 +1,94 @@
+use crate::Options;
+use axum::{
+    response::{IntoResponse, Response},
+    Extension, TypedHeader,
+};
+use headers::{authorization::Bearer, Authorization};
+use http::header;
+use kzg_ceremony_crypto::ErrorCode;
+use prometheus::{Encoder, TextEncoder};
+use strum::IntoStaticStr;
+use thiserror::Error;
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum MetricsError {
+    #[error("invalid metrics token")]
+    Unauthorized,
+    #[error("failed to encode metrics: {0}")]
+    Encoding(#[from] prometheus::Error),
+}
+
+impl ErrorCode for MetricsError {
+    fn to_error_code(&self) -> String {
+        format!("MetricsError::{}", <&str>::from(self))
+    }
+}
+
+/// Prometheus metrics in the text format. `metrics_auth` must be given as a
+/// bearer token, since lobby sizes and rejection rates are of use to
+/// attackers. The route is only mounted if it is set.
+pub async fn metrics(
+    authorization: Option<TypedHeader<Authorization<Bearer>>>,
+    Extension(options): Extension<Options>,
+) -> Result<Response, MetricsError> {
+    match (&options.metrics_auth, authorization) {
+        (Some(token), Some(TypedHeader(Authorization(bearer))))
+            if token.matches(bearer.token()) => {}
+        _ => return Err(MetricsError::Unauthorized),
+    }
+
+    let encoder = TextEncoder::new();
+    let mut body = Vec::new();
+    encoder.encode(&prometheus::gather(), &mut body)?;
+    Ok((
+        [(header::CONTENT_TYPE, encoder.format_type().to_owned())],
+        body,
+    )
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::test_options;
+    use http::StatusCode;
+
+    #[tokio::test]
+    async fn requires_configured_token() {
+        let mut opts = test_options();
+        opts.metrics_auth = Some("metrics".parse().unwrap());
+
+        let missing = metrics(None, Extension(opts.clone())).await;
+        assert!(matches!(missing, Err(MetricsError::Unauthorized)));
+        assert_eq!(missing.into_response().status(), StatusCode::UNAUTHORIZED);
+
+        let wrong = metrics(
+            Some(TypedHeader(Authorization::bearer("metric").unwrap())),
+            Extension(opts.clone()),
+        )
+        .await;
+        assert!(matches!(wrong, Err(MetricsError::Unauthorized)));
+
+        let authorized = metrics(
+            Some(TypedHeader(Authorization::bearer("metrics").unwrap())),
+            Extension(opts),
+        )
+        .await
+        .unwrap();
+        assert_eq!(authorized.status(), StatusCode::OK);
+        assert!(authorized.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .starts_with("text/plain"));
+    }
+
+    #[tokio::test]
+    async fn closed_without_token() {
+        let response = metrics(
+            Some(TypedHeader(Authorization::bearer("metrics").unwrap())),
+            Extension(test_options()),
+        )
+        .await;
+        assert!(matches!(response, Err(MetricsError::Unauthorized)));
+    }
+}
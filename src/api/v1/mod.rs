@@ -1,5 +1,10 @@
+pub mod admin;
 pub mod auth;
 pub mod contribute;
 pub mod error_response;
+pub mod health;
 pub mod info;
+pub mod json;
+pub mod link;
 pub mod lobby;
+pub mod metrics;
@@ -0,0 +1,7 @@
+use cli_batteries::version;
+use kzg_ceremony_sequencer::verify_transcript;
+
+#[allow(dead_code)] // Entry point
+fn main() {
+    cli_batteries::run(version!(crypto, small_powers_of_tau), verify_transcript::run);
+}
@@ -0,0 +1,146 @@
+//! Client IP extraction. Behind a reverse proxy the socket address is the
+//! proxy's, so the client IP is taken from a forwarded header the operator
+//! trusts instead.
+
+use clap::{Parser, ValueEnum};
+use http::HeaderMap;
+use std::net::{IpAddr, SocketAddr};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ForwardedHeader {
+    /// Not behind a proxy, use the socket address.
+    None,
+    /// `X-Forwarded-For`, as appended to by every proxy hop.
+    XForwardedFor,
+    /// `X-Real-IP`, as set by the outermost proxy.
+    XRealIp,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Forwarded header that carries the client IP. Only set this if the
+    /// proxy overwrites or appends to the header, otherwise clients can spoof
+    /// their IP.
+    #[clap(long, env, value_enum, default_value = "none")]
+    pub behind_proxy: ForwardedHeader,
+
+    /// Number of proxies in front of the sequencer that append to
+    /// `X-Forwarded-For`. Entries beyond that are set by the client and
+    /// ignored.
+    #[clap(long, env, default_value = "1")]
+    pub trusted_proxy_hops: usize,
+}
+
+impl Options {
+    /// The client IP of a request from `socket` with `headers`. Falls back to
+    /// the socket address if the trusted header is missing or malformed.
+    #[must_use]
+    pub fn client_ip(&self, headers: &HeaderMap, socket: SocketAddr) -> IpAddr {
+        match self.behind_proxy {
+            ForwardedHeader::None => None,
+            ForwardedHeader::XForwardedFor => self.forwarded_for(headers),
+            ForwardedHeader::XRealIp => headers
+                .get("x-real-ip")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse().ok()),
+        }
+        .unwrap_or_else(|| socket.ip())
+    }
+
+    /// Every trusted proxy appends the address it received the request from,
+    /// so the client is the entry `trusted_proxy_hops` from the right.
+    /// Anything further left came from the client.
+    fn forwarded_for(&self, headers: &HeaderMap) -> Option<IpAddr> {
+        if self.trusted_proxy_hops == 0 {
+            return None;
+        }
+        let entries = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .map(|value| value.to_str().ok())
+            .collect::<Option<Vec<_>>>()?
+            .into_iter()
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect::<Vec<_>>();
+        // Fewer entries than hops: all of them come from trusted proxies.
+        let index = entries.len().saturating_sub(self.trusted_proxy_hops);
+        entries.get(index)?.parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+
+    fn socket() -> SocketAddr {
+        "10.0.0.1:4321".parse().unwrap()
+    }
+
+    fn options(behind_proxy: ForwardedHeader, trusted_proxy_hops: usize) -> Options {
+        Options {
+            behind_proxy,
+            trusted_proxy_hops,
+        }
+    }
+
+    fn header_map(name: &'static str, values: &[&'static str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn not_behind_proxy() {
+        let options = options(ForwardedHeader::None, 1);
+        let headers = header_map("x-forwarded-for", &["1.2.3.4"]);
+        assert_eq!(options.client_ip(&headers, socket()), socket().ip());
+    }
+
+    #[test]
+    fn x_real_ip() {
+        let options = options(ForwardedHeader::XRealIp, 1);
+        let headers = header_map("x-real-ip", &["2001:db8::1"]);
+        assert_eq!(
+            options.client_ip(&headers, socket()),
+            "2001:db8::1".parse::<IpAddr>().unwrap()
+        );
+
+        let headers = header_map("x-real-ip", &["not an ip"]);
+        assert_eq!(options.client_ip(&headers, socket()), socket().ip());
+        assert_eq!(
+            options.client_ip(&HeaderMap::new(), socket()),
+            socket().ip()
+        );
+    }
+
+    #[test]
+    fn x_forwarded_for() {
+        let options = options(ForwardedHeader::XForwardedFor, 2);
+        let headers = header_map("x-forwarded-for", &["1.2.3.4, 5.6.7.8", "10.0.0.2"]);
+        assert_eq!(
+            options.client_ip(&headers, socket()),
+            "5.6.7.8".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(
+            options.client_ip(&HeaderMap::new(), socket()),
+            socket().ip()
+        );
+    }
+
+    #[test]
+    fn x_forwarded_for_spoofed() {
+        // The client prepends addresses of its choosing, the single trusted
+        // proxy appends the real one.
+        let options = options(ForwardedHeader::XForwardedFor, 1);
+        let headers = header_map("x-forwarded-for", &["6.6.6.6, 7.7.7.7, 5.6.7.8"]);
+        assert_eq!(
+            options.client_ip(&headers, socket()),
+            "5.6.7.8".parse::<IpAddr>().unwrap()
+        );
+    }
+}
@@ -0,0 +1,217 @@
+//! Global cap on the number of requests handled at once. Requests beyond the
+//! cap wait in a bounded queue, requests beyond the queue are shed with a 503,
+//! so the crypto-heavy endpoints don't collapse under load. Contribution
+//! validation has its own cap, see [`ValidationLimiter`].
+
+use axum::{
+    body::Body,
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Router,
+};
+use http::Request;
+use kzg_ceremony_crypto::ErrorCode;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::sync::Arc;
+use strum::IntoStaticStr;
+use thiserror::Error;
+use tokio::{sync::Semaphore, task::JoinError};
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum OverloadError {
+    #[error("too many requests in flight, try again later")]
+    Overloaded,
+}
+
+impl ErrorCode for OverloadError {
+    fn to_error_code(&self) -> String {
+        format!("OverloadError::{}", <&str>::from(self))
+    }
+}
+
+/// Shared by all routes, so the limits are global rather than per route.
+#[derive(Clone)]
+struct ConcurrencyLimiter {
+    // Requests running or waiting in the queue.
+    admitted: Arc<Semaphore>,
+    // Requests running.
+    running: Arc<Semaphore>,
+}
+
+impl ConcurrencyLimiter {
+    fn new(max_concurrent: usize, queue_depth: usize) -> Self {
+        Self {
+            admitted: Arc::new(Semaphore::new(max_concurrent + queue_depth)),
+            running: Arc::new(Semaphore::new(max_concurrent)),
+        }
+    }
+
+    async fn run<B: Send>(self, request: Request<B>, next: Next<B>) -> Response {
+        let _admitted = match self.admitted.try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => return OverloadError::Overloaded.into_response(),
+        };
+        let _running = self
+            .running
+            .acquire_owned()
+            .await
+            .expect("semaphore is never closed");
+        next.run(request).await
+    }
+}
+
+/// Limits all routes of `router` to `max_concurrent` requests at once, with up
+/// to `queue_depth` more waiting. A `max_concurrent` of zero disables the
+/// limit.
+pub fn limit_concurrency(router: Router, max_concurrent: usize, queue_depth: usize) -> Router {
+    if max_concurrent == 0 {
+        return router;
+    }
+    let limiter = ConcurrencyLimiter::new(max_concurrent, queue_depth);
+    router.layer(middleware::from_fn(
+        move |request: Request<Body>, next: Next<Body>| limiter.clone().run(request, next),
+    ))
+}
+
+/// Runs contribution validations on a dedicated thread pool. Validation is
+/// parallelized, so on the global pool a single validation occupies every
+/// core and starves the HTTP server. On this pool, validations and their
+/// parallel work share a fixed number of threads, further work waits.
+#[derive(Clone)]
+pub struct ValidationLimiter(Arc<ThreadPool>);
+
+impl ValidationLimiter {
+    /// Validates on `max_threads` threads, at least one.
+    ///
+    /// # Panics
+    ///
+    /// If the threads can't be spawned.
+    #[must_use]
+    pub fn new(max_threads: usize) -> Self {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(max_threads.max(1))
+            .thread_name(|index| format!("validation-{index}"))
+            .build()
+            .expect("failed to spawn validation threads");
+        Self(Arc::new(pool))
+    }
+
+    /// Runs `validate` on the validation pool, so that its parallel work is
+    /// bounded by the pool as well.
+    ///
+    /// # Errors
+    ///
+    /// If `validate` panics.
+    pub async fn run<R, F>(&self, validate: F) -> Result<R, JoinError>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let pool = self.0.clone();
+        tokio::task::spawn_blocking(move || pool.install(validate)).await
+    }
+}
+
+/// One less than the number of CPUs, so that a core stays free for the HTTP
+/// server.
+#[must_use]
+pub fn default_max_concurrent_validations() -> usize {
+    std::thread::available_parallelism().map_or(1, |cpus| cpus.get().saturating_sub(1).max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use http::StatusCode;
+    use rayon::iter::{IntoParallelIterator, ParallelIterator};
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+    use tokio::sync::Notify;
+    use tower::ServiceExt;
+
+    fn get_request(uri: &str) -> Request<Body> {
+        Request::builder().uri(uri).body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn sheds_requests_beyond_capacity() {
+        let release = Arc::new(Notify::new());
+        let slow = {
+            let release = release.clone();
+            move || async move { release.notified().await }
+        };
+        let app = limit_concurrency(Router::new().route("/slow", get(slow)), 1, 1)
+            .route("/livez", get(|| async {}));
+
+        // One request runs, one waits in the queue.
+        let running = tokio::spawn(app.clone().oneshot(get_request("/slow")));
+        let queued = tokio::spawn(app.clone().oneshot(get_request("/slow")));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let response = app.clone().oneshot(get_request("/slow")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let response = app.clone().oneshot(get_request("/livez")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        release.notify_waiters();
+        assert_eq!(running.await.unwrap().unwrap().status(), StatusCode::OK);
+        release.notify_one();
+        assert_eq!(queued.await.unwrap().unwrap().status(), StatusCode::OK);
+    }
+
+    fn track(running: &AtomicUsize, max_running: &AtomicUsize) {
+        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+        max_running.fetch_max(now, Ordering::SeqCst);
+        std::thread::sleep(Duration::from_millis(20));
+        running.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    #[tokio::test]
+    async fn validations_never_exceed_limit() {
+        let limiter = ValidationLimiter::new(2);
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+
+        let validations = (0..8)
+            .map(|_| {
+                let (limiter, running, max_running) =
+                    (limiter.clone(), running.clone(), max_running.clone());
+                tokio::spawn(
+                    async move { limiter.run(move || track(&running, &max_running)).await },
+                )
+            })
+            .collect::<Vec<_>>();
+        for validation in validations {
+            validation.await.unwrap().unwrap();
+        }
+
+        let max_running = max_running.load(Ordering::SeqCst);
+        assert!((1..=2).contains(&max_running), "{max_running} ran at once");
+    }
+
+    #[tokio::test]
+    async fn parallel_validation_is_bounded() {
+        let limiter = ValidationLimiter::new(2);
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+
+        let (threads, max_running) = limiter
+            .run(move || {
+                (0..16)
+                    .into_par_iter()
+                    .for_each(|_| track(&running, &max_running));
+                (
+                    rayon::current_num_threads(),
+                    max_running.load(Ordering::SeqCst),
+                )
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(threads, 2);
+        assert!((1..=2).contains(&max_running), "{max_running} ran at once");
+    }
+}
@@ -0,0 +1,229 @@
+//! Runtime selection of the cryptography backend, so that operators can
+//! compare backends without rebuilding.
+
+use crate::self_test;
+use clap::ValueEnum;
+use eyre::Result as EyreResult;
+use kzg_ceremony_crypto::{
+    signature::{identity::Identity, ContributionDomain},
+    Arkworks, BatchContribution, BatchTranscript, Both, CeremoniesError, Engine, Entropy,
+    EntropySource, VerificationCache, BLST, G1, G2,
+};
+use std::time::{Duration, Instant};
+
+/// Number of G1 and G2 powers timed by [`EngineKind::benchmark`].
+const BENCHMARK_POWERS: usize = 128;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum EngineKind {
+    /// Runs BLST and arkworks and checks that they agree.
+    Both,
+    Blst,
+    Arkworks,
+}
+
+impl EngineKind {
+    /// Like [`BatchTranscript::verify_add_in_domain`], with the selected
+    /// backend.
+    pub fn verify_add_in_domain(
+        self,
+        transcript: &mut BatchTranscript,
+        contribution: BatchContribution,
+        identity: Identity,
+        domain: &ContributionDomain,
+    ) -> Result<(), CeremoniesError> {
+        match self {
+            Self::Both => transcript.verify_add_in_domain::<Both<Arkworks, BLST>>(
+                contribution,
+                identity,
+                domain,
+            ),
+            Self::Blst => transcript.verify_add_in_domain::<BLST>(contribution, identity, domain),
+            Self::Arkworks => {
+                transcript.verify_add_in_domain::<Arkworks>(contribution, identity, domain)
+            }
+        }
+    }
+
+    /// Like [`BatchTranscript::verify_contribution_cached`], with the
+    /// selected backend.
+    pub fn verify_contribution(
+        self,
+        transcript: &BatchTranscript,
+        contribution: &BatchContribution,
+        cache: &VerificationCache,
+    ) -> Result<(), CeremoniesError> {
+        match self {
+            Self::Both => {
+                transcript.verify_contribution_cached::<Both<Arkworks, BLST>>(contribution, cache)
+            }
+            Self::Blst => transcript.verify_contribution_cached::<BLST>(contribution, cache),
+            Self::Arkworks => {
+                transcript.verify_contribution_cached::<Arkworks>(contribution, cache)
+            }
+        }
+    }
+
+    /// Like [`BatchTranscript::verify_history`], with the selected backend.
+    pub fn verify_history(self, transcript: &BatchTranscript) -> Result<(), CeremoniesError> {
+        match self {
+            Self::Both => transcript.verify_history::<Both<Arkworks, BLST>>(),
+            Self::Blst => transcript.verify_history::<BLST>(),
+            Self::Arkworks => transcript.verify_history::<Arkworks>(),
+        }
+    }
+
+    /// Like [`BatchTranscript::add_in_domain`], with the selected backend.
+    pub fn add_in_domain(
+        self,
+        transcript: &mut BatchTranscript,
+        contribution: BatchContribution,
+        identity: Identity,
+        domain: &ContributionDomain,
+    ) -> Result<(), CeremoniesError> {
+        match self {
+            Self::Both => {
+                transcript.add_in_domain::<Both<Arkworks, BLST>>(contribution, identity, domain)
+            }
+            Self::Blst => transcript.add_in_domain::<BLST>(contribution, identity, domain),
+            Self::Arkworks => transcript.add_in_domain::<Arkworks>(contribution, identity, domain),
+        }
+    }
+
+    /// Times adding a tau to a few powers with the selected backend.
+    pub fn benchmark(self) -> ComputeBenchmark {
+        match self {
+            Self::Both => ComputeBenchmark::measure::<Both<Arkworks, BLST>>(),
+            Self::Blst => ComputeBenchmark::measure::<BLST>(),
+            Self::Arkworks => ComputeBenchmark::measure::<Arkworks>(),
+        }
+    }
+
+    /// Runs the [`self_test`] with the selected backend.
+    pub fn self_test(self) -> EyreResult<()> {
+        match self {
+            Self::Both => self_test::run::<Both<Arkworks, BLST>>(),
+            Self::Blst => self_test::run::<BLST>(),
+            Self::Arkworks => self_test::run::<Arkworks>(),
+        }
+    }
+}
+
+/// How long adding a tau takes per power on this machine. Only a rough
+/// figure for clients, their hardware may be faster or slower.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ComputeBenchmark {
+    pub per_g1_power: Duration,
+    pub per_g2_power: Duration,
+}
+
+impl ComputeBenchmark {
+    fn measure<E: Engine>() -> Self {
+        let tau = E::generate_tau(&Entropy::random());
+        let mut g1 = vec![G1::one(); BENCHMARK_POWERS];
+        let mut g2 = vec![G2::one(); BENCHMARK_POWERS];
+
+        let start = Instant::now();
+        E::add_tau_g1(&tau, &mut g1).expect("generator powers are valid");
+        let per_g1_power = start.elapsed() / saturating_u32(BENCHMARK_POWERS);
+
+        let start = Instant::now();
+        E::add_tau_g2(&tau, &mut g2).expect("generator powers are valid");
+        let per_g2_power = start.elapsed() / saturating_u32(BENCHMARK_POWERS);
+
+        Self {
+            per_g1_power,
+            per_g2_power,
+        }
+    }
+
+    /// Rough time to add entropy to `contribution`.
+    pub fn estimate(&self, contribution: &BatchContribution) -> Duration {
+        contribution
+            .contributions
+            .iter()
+            .map(|contribution| {
+                self.per_g1_power * saturating_u32(contribution.powers.g1.len())
+                    + self.per_g2_power * saturating_u32(contribution.powers.g2.len())
+            })
+            .sum()
+    }
+}
+
+fn saturating_u32(n: usize) -> u32 {
+    u32::try_from(n).unwrap_or(u32::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{invalid_contribution, test_transcript, valid_contribution};
+
+    #[test]
+    fn engines_agree() {
+        let domain = ContributionDomain::default();
+        let verify = |contribution: &BatchContribution| {
+            [EngineKind::Both, EngineKind::Blst, EngineKind::Arkworks].map(|engine| {
+                let mut transcript = test_transcript();
+                engine
+                    .verify_add_in_domain(
+                        &mut transcript,
+                        contribution.clone(),
+                        Identity::None,
+                        &domain,
+                    )
+                    .map(|()| transcript)
+            })
+        };
+
+        let [both, blst, arkworks] = verify(&valid_contribution(&test_transcript(), 1));
+        assert!(both.is_ok());
+        assert_eq!(both, blst);
+        assert_eq!(both, arkworks);
+
+        let [both, blst, arkworks] = verify(&invalid_contribution(&test_transcript(), 1));
+        assert!(both.is_err());
+        assert_eq!(both, blst);
+        assert_eq!(both, arkworks);
+    }
+
+    #[test]
+    fn repeated_verification_is_cached() {
+        let cache = VerificationCache::default();
+        let transcript = test_transcript();
+        let contribution = valid_contribution(&transcript, 1);
+        let num_ceremonies = contribution.contributions.len();
+
+        for engine in [EngineKind::Both, EngineKind::Blst] {
+            engine
+                .verify_contribution(&transcript, &contribution, &cache)
+                .unwrap();
+        }
+        assert_eq!(cache.hits(), 0, "verdicts are kept per engine");
+
+        EngineKind::Both
+            .verify_contribution(&transcript, &contribution, &cache)
+            .unwrap();
+        assert_eq!(cache.hits(), num_ceremonies);
+    }
+
+    #[test]
+    fn estimate_scales_with_powers() {
+        let benchmark = ComputeBenchmark {
+            per_g1_power: Duration::from_millis(2),
+            per_g2_power: Duration::from_millis(5),
+        };
+        let contribution = BatchTranscript::new(&[(4, 2), (8, 3)]).contribution();
+        assert_eq!(
+            benchmark.estimate(&contribution),
+            Duration::from_millis(2 * 12 + 5 * 5)
+        );
+    }
+
+    #[test]
+    fn self_test_each_engine() {
+        for engine in [EngineKind::Both, EngineKind::Blst, EngineKind::Arkworks] {
+            engine.self_test().unwrap();
+        }
+    }
+}
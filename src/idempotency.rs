@@ -0,0 +1,197 @@
+//! Idempotency keys, so that a request retried after a client-side timeout
+//! returns the result of the original request instead of being processed
+//! again.
+//!
+//! Clients send the key in the `Idempotency-Key` header. Keys are scoped to
+//! the session and remembered for a limited time.
+
+use crate::{sessions::SessionKey, SessionId};
+use axum::{
+    async_trait,
+    extract::{FromRequest, RequestParts},
+};
+use kzg_ceremony_crypto::ErrorCode;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use strum::IntoStaticStr;
+use thiserror::Error;
+use tokio::{sync::Mutex, time::Instant};
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Longest key accepted, UUIDs and the like fit comfortably.
+const MAX_KEY_LENGTH: usize = 255;
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum IdempotencyError {
+    #[error("idempotency key must be printable ASCII of at most 255 characters")]
+    InvalidKey,
+    #[error("idempotency key was used for a different request")]
+    Conflict,
+    #[error("request with this idempotency key is still in progress")]
+    InProgress,
+}
+
+impl ErrorCode for IdempotencyError {
+    fn to_error_code(&self) -> String {
+        format!("IdempotencyError::{}", <&str>::from(self))
+    }
+}
+
+/// The `Idempotency-Key` header, if the client sent one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IdempotencyKey(pub Option<String>);
+
+#[async_trait]
+impl<B> FromRequest<B> for IdempotencyKey
+where
+    B: Send,
+{
+    type Rejection = IdempotencyError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        match req.headers().get(IDEMPOTENCY_KEY_HEADER) {
+            None => Ok(Self(None)),
+            Some(value) => value
+                .to_str()
+                .ok()
+                .filter(|key| !key.is_empty() && key.len() <= MAX_KEY_LENGTH)
+                .map(|key| Self(Some(key.to_owned())))
+                .ok_or(IdempotencyError::InvalidKey),
+        }
+    }
+}
+
+/// Hashes the JSON of the request, to tell a retry from a different request
+/// reusing the key.
+#[allow(clippy::missing_panics_doc)] // Does not panic.
+#[must_use]
+pub fn request_hash(request: &impl Serialize) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    serde_json::to_writer(&mut hasher, request)
+        .expect("Impossible, request serialization is infallible");
+    hasher.finalize().into()
+}
+
+enum Outcome<T> {
+    InProgress,
+    Done(T),
+}
+
+struct Entry<T> {
+    request_hash: [u8; 32],
+    created: Instant,
+    outcome: Outcome<T>,
+}
+
+/// Remembers the successful results of requests by session and key for
+/// `ttl`.
+#[derive(Clone)]
+pub struct IdempotencyCache<T> {
+    entries: Arc<Mutex<BTreeMap<(SessionKey, String), Entry<T>>>>,
+    ttl: Duration,
+}
+
+impl<T: Clone> IdempotencyCache<T> {
+    #[must_use]
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            entries: Arc::new(Mutex::new(BTreeMap::new())),
+            ttl,
+        }
+    }
+
+    /// Claims the key for a request. Returns the result of the original
+    /// request if this is a retry of a finished one, or `None` if the request
+    /// should be processed, followed by [`Self::finish`].
+    ///
+    /// # Errors
+    ///
+    /// If the key was used for a different request, or the original request is
+    /// still being processed.
+    pub async fn begin(
+        &self,
+        session_id: &SessionId,
+        key: &str,
+        request_hash: [u8; 32],
+    ) -> Result<Option<T>, IdempotencyError> {
+        let mut entries = self.entries.lock().await;
+        entries.retain(|_, entry| entry.created.elapsed() <= self.ttl);
+
+        let id = (session_id.key(), key.to_owned());
+        match entries.get(&id) {
+            Some(entry) if entry.request_hash != request_hash => Err(IdempotencyError::Conflict),
+            Some(Entry {
+                outcome: Outcome::InProgress,
+                ..
+            }) => Err(IdempotencyError::InProgress),
+            Some(Entry {
+                outcome: Outcome::Done(result),
+                ..
+            }) => Ok(Some(result.clone())),
+            None => {
+                entries.insert(
+                    id,
+                    Entry {
+                        request_hash,
+                        created: Instant::now(),
+                        outcome: Outcome::InProgress,
+                    },
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    /// Stores the result of a request claimed with [`Self::begin`]. Failed
+    /// requests release the key, so they can be retried.
+    pub async fn finish(&self, session_id: &SessionId, key: &str, result: Option<T>) {
+        let mut entries = self.entries.lock().await;
+        let id = (session_id.key(), key.to_owned());
+        match result {
+            Some(result) => {
+                if let Some(entry) = entries.get_mut(&id) {
+                    entry.outcome = Outcome::Done(result);
+                }
+            }
+            None => {
+                entries.remove(&id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn keys_expire() {
+        let cache = IdempotencyCache::new(Duration::from_secs(60));
+        let session_id = SessionId::new();
+        let hash = request_hash(&"body");
+
+        tokio::time::pause();
+        assert!(matches!(
+            cache.begin(&session_id, "key", hash).await,
+            Ok(None)
+        ));
+        cache.finish(&session_id, "key", Some(1)).await;
+        assert!(matches!(
+            cache.begin(&session_id, "key", hash).await,
+            Ok(Some(1))
+        ));
+        // Keys are scoped to the session.
+        assert!(matches!(
+            cache.begin(&SessionId::new(), "key", hash).await,
+            Ok(None)
+        ));
+
+        tokio::time::advance(Duration::from_secs(61)).await;
+        assert!(matches!(
+            cache.begin(&session_id, "key", hash).await,
+            Ok(None)
+        ));
+    }
+}
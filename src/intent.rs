@@ -0,0 +1,113 @@
+//! Signed intent to participate, which operators can require before a session
+//! enters the lobby, so that drive-by clients holding only a session can't.
+//!
+//! The server issues a random, expiring challenge per session, which
+//! Ethereum identities sign with their wallet (EIP-191). Other identities
+//! have no key the server knows of, so the requirement doesn't apply to them.
+
+use kzg_ceremony_crypto::{signature::EcdsaSignature, Identity};
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::time::Instant;
+
+#[derive(Clone, Debug)]
+pub struct IntentChallenge {
+    nonce: String,
+    expires: Instant,
+}
+
+impl IntentChallenge {
+    #[must_use]
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            nonce: hex::encode(rand::random::<[u8; 16]>()),
+            expires: Instant::now() + ttl,
+        }
+    }
+
+    /// The message `identity` has to sign.
+    #[must_use]
+    pub fn message(&self, identity: &Identity) -> String {
+        format!(
+            "I intend to participate in the KZG ceremony.\nIdentity: {identity}\nChallenge: {}",
+            self.nonce
+        )
+    }
+
+    #[must_use]
+    pub fn is_expired(&self) -> bool {
+        Instant::now() >= self.expires
+    }
+}
+
+/// Whether `identity` can sign an intent, see the module docs.
+#[must_use]
+pub const fn applies_to(identity: &Identity) -> bool {
+    matches!(identity, Identity::Ethereum { .. })
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct IntentSignature {
+    pub ecdsa_signature: Option<EcdsaSignature>,
+}
+
+impl IntentSignature {
+    /// Checks the signature by the address of `identity`, which has to be an
+    /// Ethereum identity.
+    #[must_use]
+    pub fn verify(&self, identity: &Identity, message: &[u8]) -> bool {
+        applies_to(identity)
+            && self.ecdsa_signature.as_ref().map_or(false, |signature| {
+                signature.verify_message(identity, message)
+            })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use ethers_core::utils::hash_message;
+    use ethers_signers::{LocalWallet, Signer};
+
+    /// Signs `message` with `wallet`.
+    pub fn ecdsa_intent(wallet: &LocalWallet, message: &[u8]) -> IntentSignature {
+        IntentSignature {
+            ecdsa_signature: Some(EcdsaSignature(Some(
+                wallet.sign_hash(hash_message(message)),
+            ))),
+        }
+    }
+
+    #[test]
+    fn verify_ecdsa_intent() {
+        let wallet = LocalWallet::new(&mut rand::thread_rng());
+        let identity = Identity::Ethereum {
+            address: wallet.address().0,
+        };
+        let message = IntentChallenge::new(Duration::from_secs(60)).message(&identity);
+        let signature = ecdsa_intent(&wallet, message.as_bytes());
+
+        assert!(signature.verify(&identity, message.as_bytes()));
+        let other = IntentChallenge::new(Duration::from_secs(60)).message(&identity);
+        assert!(!signature.verify(&identity, other.as_bytes()));
+        assert!(!IntentSignature::default().verify(&identity, message.as_bytes()));
+
+        // Signed by another wallet.
+        let other_wallet = LocalWallet::new(&mut rand::thread_rng());
+        assert!(
+            !ecdsa_intent(&other_wallet, message.as_bytes()).verify(&identity, message.as_bytes())
+        );
+    }
+
+    #[test]
+    fn only_ethereum_identities_sign() {
+        let wallet = LocalWallet::new(&mut rand::thread_rng());
+        let identity = Identity::Github {
+            id: 1,
+            username: "user".to_string(),
+        };
+        assert!(!applies_to(&identity));
+        let message = IntentChallenge::new(Duration::from_secs(60)).message(&identity);
+        assert!(!ecdsa_intent(&wallet, message.as_bytes()).verify(&identity, message.as_bytes()));
+    }
+}
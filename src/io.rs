@@ -1,10 +1,16 @@
-use crate::SharedTranscript;
+use crate::{SharedCeremonyStatus, SharedTranscript, SharedTranscriptHash};
 use eyre::eyre;
-use kzg_ceremony_crypto::BatchTranscript;
+use kzg_ceremony_crypto::{BatchContribution, BatchTranscript, BinaryFormatError};
 use serde::{de::DeserializeOwned, Serialize};
-use std::{path::PathBuf, sync::Arc};
+use sha2::{Digest, Sha256};
+use std::{
+    io::{BufRead, BufWriter, ErrorKind},
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, Arc},
+    time::{Duration, SystemTime},
+};
 use tokio::sync::RwLock;
-use tracing::{info, warn};
+use tracing::{error, info, warn};
 
 /// Represents a size constraint on a batch transcript
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -21,18 +27,25 @@ impl CeremonySizes {
     ///
     /// # Errors
     ///
-    /// Returns an error if there are no ceremonies specified, or if there is
-    /// an parse error.
+    /// Returns an error if there are no ceremonies specified, if there is
+    /// an parse error, or if a ceremony has an invalid shape. Every ceremony
+    /// needs at least two G1 and two G2 points, and no more G2 than G1 points.
     pub fn parse_from_cmd(cmd: &str) -> eyre::Result<Self> {
         let ceremonies = cmd.split(':');
         let parsed_ceremonies: Vec<_> = ceremonies
             .map(|ceremony| {
                 let parts = ceremony.split(',').collect::<Vec<_>>();
-                if parts.len() == 2 {
-                    Ok((parts[0].parse()?, parts[1].parse()?))
-                } else {
-                    Err(eyre!("Invalid ceremony sizes description {ceremony}"))
+                if parts.len() != 2 {
+                    return Err(eyre!("Invalid ceremony sizes description {ceremony}"));
                 }
+                let (num_g1, num_g2): (usize, usize) = (parts[0].parse()?, parts[1].parse()?);
+                if num_g1 < 2 || num_g2 < 2 || num_g2 > num_g1 {
+                    return Err(eyre!(
+                        "Invalid ceremony shape {ceremony}: need at least 2 G1 and 2 G2 points, \
+                         and no more G2 than G1 points"
+                    ));
+                }
+                Ok((num_g1, num_g2))
             })
             .collect::<eyre::Result<_>>()?;
         if parsed_ceremonies.is_empty() {
@@ -43,11 +56,26 @@ impl CeremonySizes {
         })
     }
 
+    /// Number of ceremonies, i.e. sub-contributions in a batch.
+    #[must_use]
+    pub fn num_ceremonies(&self) -> usize {
+        self.sizes.len()
+    }
+
+    /// Creates an empty batch transcript with this shape.
+    #[must_use]
+    pub fn base_transcript(&self) -> BatchTranscript {
+        BatchTranscript::new(&self.sizes)
+    }
+
     /// Validates a batch transcript against this shape description
     ///
     /// # Errors:
     /// - when the transcript does not conform to the required shape
-    fn validate_batch_transcript(&self, transcript: &BatchTranscript) -> eyre::Result<()> {
+    pub(crate) fn validate_batch_transcript(
+        &self,
+        transcript: &BatchTranscript,
+    ) -> eyre::Result<()> {
         let defined_ceremonies = transcript.transcripts.len();
         let expected_ceremonies = self.sizes.len();
         if defined_ceremonies != expected_ceremonies {
@@ -89,9 +117,17 @@ pub enum TranscriptIoError {
     SerializationError(serde_json::Error),
     #[error("Task error {0}")]
     TaskError(tokio::task::JoinError),
+    #[error("Failed to decode contribution {0}")]
+    BinaryFormatError(BinaryFormatError),
 }
 
-/// Reads a transcript file from disk, or creates it, if it doesn't exist.
+/// The first bytes of every zstd frame. JSON never starts with them, so
+/// [`read_json_file`] can tell compressed from plain files.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Reads a transcript file from disk, or creates it, if it doesn't exist. If
+/// the file is unreadable, e.g. because the process died while writing it, the
+/// previous version kept by [`write_json_file`] is used instead.
 ///
 /// # Errors
 ///
@@ -100,22 +136,119 @@ pub async fn read_or_create_transcript(
     path: PathBuf,
     work_path: PathBuf,
     ceremony_sizes: &CeremonySizes,
+    zstd_level: Option<i32>,
 ) -> eyre::Result<SharedTranscript> {
-    if path.exists() {
+    let backup = backup_path(&path);
+    if path.exists() || backup.exists() {
         info!(?path, "Opening transcript file");
-        let transcript = read_json_file::<BatchTranscript>(path).await?;
+        let transcript = match read_json_file::<BatchTranscript>(path.clone()).await {
+            Ok(transcript) => transcript,
+            Err(e) if backup.exists() => {
+                warn!(?path, ?backup, %e, "Transcript unreadable, falling back to previous version");
+                let transcript = read_json_file::<BatchTranscript>(backup.clone()).await?;
+                tokio::fs::copy(&backup, &path).await?;
+                transcript
+            }
+            Err(e) => return Err(e.into()),
+        };
         ceremony_sizes.validate_batch_transcript(&transcript)?;
         Ok(Arc::new(RwLock::new(transcript)))
     } else {
         warn!(?path, "No transcript found, creating new transcript file");
-        let transcript = BatchTranscript::new(&ceremony_sizes.sizes);
+        let transcript = ceremony_sizes.base_transcript();
         let shared_transcript = Arc::new(RwLock::new(transcript));
-        write_json_file(path, work_path, shared_transcript.clone()).await?;
+        write_json_file(path, work_path, shared_transcript.clone(), zstd_level).await?;
         Ok(shared_transcript)
     }
 }
 
-/// Asynchronously reads a JSON file from disk.
+/// Reads the transcript file written by another sequencer, without ever
+/// writing to it.
+///
+/// # Errors
+///
+/// - when the transcript can't be read or doesn't conform to the required
+///   shape.
+pub async fn read_transcript(
+    path: PathBuf,
+    ceremony_sizes: &CeremonySizes,
+) -> eyre::Result<SharedTranscript> {
+    let transcript = read_json_file::<BatchTranscript>(path).await?;
+    ceremony_sizes.validate_batch_transcript(&transcript)?;
+    Ok(Arc::new(RwLock::new(transcript)))
+}
+
+/// Reloads the transcript from `path` if the file was modified since
+/// `last_modified`, and updates its hash. Returns the modification time of
+/// the loaded file.
+///
+/// # Errors
+///
+/// - when the transcript can't be read or doesn't conform to the required
+///   shape. The transcript is left unchanged then.
+pub async fn reload_transcript(
+    transcript: &SharedTranscript,
+    num_contributions: &SharedCeremonyStatus,
+    shared_hash: &SharedTranscriptHash,
+    path: PathBuf,
+    ceremony_sizes: &CeremonySizes,
+    last_modified: Option<SystemTime>,
+) -> eyre::Result<Option<SystemTime>> {
+    let modified = tokio::fs::metadata(&path).await?.modified()?;
+    if last_modified == Some(modified) {
+        return Ok(last_modified);
+    }
+    let reloaded = read_json_file::<BatchTranscript>(path).await?;
+    ceremony_sizes.validate_batch_transcript(&reloaded)?;
+    let num_participants = reloaded.num_participants();
+    let hash = transcript_hash(&reloaded);
+    *transcript.write().await = reloaded;
+    num_contributions.store(num_participants, Ordering::Relaxed);
+    *shared_hash.write().await = hash;
+    Ok(Some(modified))
+}
+
+/// Calls [`reload_transcript`] every `interval`, for read replicas that serve
+/// the transcript of another sequencer.
+pub async fn reload_transcript_on_interval(
+    transcript: SharedTranscript,
+    num_contributions: SharedCeremonyStatus,
+    shared_hash: SharedTranscriptHash,
+    path: PathBuf,
+    ceremony_sizes: CeremonySizes,
+    interval: Duration,
+) {
+    let mut last_modified = None;
+    let mut interval = tokio::time::interval(interval);
+
+    loop {
+        interval.tick().await;
+
+        match reload_transcript(
+            &transcript,
+            &num_contributions,
+            &shared_hash,
+            path.clone(),
+            &ceremony_sizes,
+            last_modified,
+        )
+        .await
+        {
+            Ok(modified) => last_modified = modified,
+            Err(e) => error!("failed to reload transcript: {}", e),
+        }
+    }
+}
+
+/// Where [`write_json_file`] keeps the previous version of `path`.
+fn backup_path(path: &Path) -> PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(".bak");
+    backup.into()
+}
+
+/// Asynchronously reads a JSON file from disk. Files written with a
+/// `zstd_level` by [`write_json_file`] are decompressed.
 ///
 /// # Errors
 /// If the file does not exist, or if it does not contain correct transcript
@@ -125,13 +258,29 @@ pub async fn read_json_file<T: DeserializeOwned + Send + 'static>(
 ) -> Result<T, TranscriptIoError> {
     let handle = tokio::task::spawn_blocking(|| {
         let f = std::fs::File::open(path).map_err(TranscriptIoError::IoError)?;
-        let reader = std::io::BufReader::new(f);
-        serde_json::from_reader::<_, T>(reader).map_err(TranscriptIoError::SerializationError)
+        let mut reader = std::io::BufReader::new(f);
+        let compressed = reader
+            .fill_buf()
+            .map_err(TranscriptIoError::IoError)?
+            .starts_with(&ZSTD_MAGIC);
+        if compressed {
+            let decoder = zstd::Decoder::with_buffer(reader).map_err(TranscriptIoError::IoError)?;
+            serde_json::from_reader::<_, T>(decoder)
+        } else {
+            serde_json::from_reader::<_, T>(reader)
+        }
+        .map_err(TranscriptIoError::SerializationError)
     });
     handle.await.map_err(TranscriptIoError::TaskError)?
 }
 
-/// Asynchroniously writes a JSON file to disk using a tempfile.
+/// Asynchroniously writes a JSON file to disk using a tempfile. The file is
+/// synced before it replaces `target_path`, and the replaced version is kept
+/// at [`backup_path`], so a crash never leaves only a truncated file behind.
+///
+/// With a `zstd_level`, the JSON is compact and zstd compressed, otherwise it
+/// is pretty-printed. Returns the hash of the written data, see
+/// [`transcript_hash`].
 ///
 /// # Errors
 /// If either file cannot be written.
@@ -139,17 +288,446 @@ pub async fn write_json_file<T: Serialize + Send + Sync + 'static>(
     target_path: PathBuf,
     work_path: PathBuf,
     data: Arc<RwLock<T>>,
-) -> Result<(), TranscriptIoError> {
+    zstd_level: Option<i32>,
+) -> Result<String, TranscriptIoError> {
     let handle = tokio::task::spawn_blocking(move || {
         let f = std::fs::OpenOptions::new()
             .write(true)
             .create(true)
+            .truncate(true)
             .open(&work_path)
             .map_err(TranscriptIoError::IoError)?;
+        let mut writer = BufWriter::new(f);
         let guard = data.blocking_read();
-        serde_json::to_writer_pretty(&f, &*guard).map_err(TranscriptIoError::SerializationError)?;
+        if let Some(level) = zstd_level {
+            let mut encoder =
+                zstd::Encoder::new(writer, level).map_err(TranscriptIoError::IoError)?;
+            serde_json::to_writer(&mut encoder, &*guard)
+                .map_err(TranscriptIoError::SerializationError)?;
+            writer = encoder.finish().map_err(TranscriptIoError::IoError)?;
+        } else {
+            serde_json::to_writer_pretty(&mut writer, &*guard)
+                .map_err(TranscriptIoError::SerializationError)?;
+        }
+        let f = writer
+            .into_inner()
+            .map_err(|e| TranscriptIoError::IoError(e.into_error()))?;
+        f.sync_all().map_err(TranscriptIoError::IoError)?;
+
+        // Link rather than move the current version, so that `target_path`
+        // exists at all times. Concurrent writers may race for the link,
+        // either of them wins.
+        if target_path.exists() {
+            let backup = backup_path(&target_path);
+            ignore_io_error(std::fs::remove_file(&backup), ErrorKind::NotFound)?;
+            ignore_io_error(
+                std::fs::hard_link(&target_path, &backup),
+                ErrorKind::AlreadyExists,
+            )?;
+        }
         std::fs::rename(&work_path, &target_path).map_err(TranscriptIoError::IoError)?;
-        Ok(())
+        let hash = json_hash(&*guard);
+        // Only released after the rename: a newer version needs the write
+        // lock, so it can't be renamed over by this one. Concurrent writers
+        // all hold the same version.
+        drop(guard);
+        Ok(hash)
     });
     handle.await.map_err(TranscriptIoError::TaskError)?
 }
+
+/// Where [`write_contribution_file`] keeps the contribution of the
+/// participant at `index`.
+#[must_use]
+pub fn contribution_path(dir: &Path, index: usize) -> PathBuf {
+    dir.join(format!("{index:08}.bin"))
+}
+
+/// Writes a contribution to [`contribution_path`] in the compact binary
+/// format, replacing the file at once, so that it is never read truncated.
+///
+/// # Errors
+/// If the file cannot be written.
+pub async fn write_contribution_file(
+    dir: PathBuf,
+    index: usize,
+    contribution: &BatchContribution,
+) -> Result<(), TranscriptIoError> {
+    let bytes = contribution.to_bytes();
+    let handle = tokio::task::spawn_blocking(move || {
+        let target_path = contribution_path(&dir, index);
+        let mut work_path = target_path.as_os_str().to_owned();
+        work_path.push(".next");
+        std::fs::write(&work_path, bytes).map_err(TranscriptIoError::IoError)?;
+        std::fs::rename(&work_path, &target_path).map_err(TranscriptIoError::IoError)
+    });
+    handle.await.map_err(TranscriptIoError::TaskError)?
+}
+
+/// Reads a contribution written by [`write_contribution_file`].
+///
+/// # Errors
+/// If the file does not exist, or if it is not a contribution in a supported
+/// version of the binary format.
+pub async fn read_contribution_file(path: PathBuf) -> Result<BatchContribution, TranscriptIoError> {
+    let bytes = tokio::fs::read(path)
+        .await
+        .map_err(TranscriptIoError::IoError)?;
+    BatchContribution::from_bytes(&bytes).map_err(TranscriptIoError::BinaryFormatError)
+}
+
+fn ignore_io_error(result: std::io::Result<()>, kind: ErrorKind) -> Result<(), TranscriptIoError> {
+    match result {
+        Err(e) if e.kind() != kind => Err(TranscriptIoError::IoError(e)),
+        _ => Ok(()),
+    }
+}
+
+/// Writes the transcript to `target_path`, unless its hash equals
+/// `last_hash`. Returns the hash of the written transcript, or `None` if the
+/// write was skipped.
+///
+/// # Errors
+/// If either file cannot be written.
+pub async fn snapshot_transcript(
+    transcript: &SharedTranscript,
+    target_path: PathBuf,
+    work_path: PathBuf,
+    zstd_level: Option<i32>,
+    last_hash: &str,
+) -> Result<Option<String>, TranscriptIoError> {
+    let hash = transcript_hash(&*transcript.read().await);
+    if hash == last_hash {
+        return Ok(None);
+    }
+    write_json_file(target_path, work_path, transcript.clone(), zstd_level).await?;
+    Ok(Some(hash))
+}
+
+/// Persists the transcript every `interval`, independently of the
+/// contribution flow, skipping the write while the transcript is unchanged
+/// since it was last written.
+pub async fn persist_transcript_on_interval(
+    transcript: SharedTranscript,
+    shared_hash: SharedTranscriptHash,
+    target_path: PathBuf,
+    work_path: PathBuf,
+    zstd_level: Option<i32>,
+    interval: Duration,
+) {
+    let mut interval = tokio::time::interval(interval);
+
+    loop {
+        interval.tick().await;
+
+        // Also updated by the contribution flow when it writes the file.
+        let last_hash = shared_hash.read().await.clone();
+        match snapshot_transcript(
+            &transcript,
+            target_path.clone(),
+            work_path.clone(),
+            zstd_level,
+            &last_hash,
+        )
+        .await
+        {
+            Ok(Some(hash)) => {
+                info!(%hash, "Persisted transcript snapshot");
+                *shared_hash.write().await = hash;
+            }
+            Ok(None) => {}
+            Err(e) => error!("failed to snapshot transcript: {}", e),
+        }
+    }
+}
+
+/// Computes the hex encoded SHA-256 hash of the canonical JSON encoding of a
+/// transcript. The hash as of the last write is the `ETag` of transcript
+/// responses.
+#[must_use]
+pub fn transcript_hash(transcript: &BatchTranscript) -> String {
+    json_hash(transcript)
+}
+
+fn json_hash<T: Serialize>(data: &T) -> String {
+    let mut hasher = Sha256::new();
+    serde_json::to_writer(&mut hasher, data)
+        .expect("Impossible, transcript serialization is infallible");
+    hex::encode(hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tests::{test_transcript, valid_contribution},
+        Engine,
+    };
+    use kzg_ceremony_crypto::{signature::ContributionTypedData, Identity};
+    use serde_json::json;
+    use tempfile::tempdir;
+    use tokio::time::Instant;
+
+    #[test]
+    fn custom_ceremony_sizes() {
+        let sizes = CeremonySizes::parse_from_cmd("8,3:16,4").unwrap();
+        let contribution = sizes.base_transcript().contribution();
+        let typed_data = serde_json::to_value(ContributionTypedData::from(&contribution)).unwrap();
+        let shapes = typed_data["potPubkeys"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|pubkey| (pubkey["numG1Powers"].clone(), pubkey["numG2Powers"].clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(shapes, vec![(json!(8), json!(3)), (json!(16), json!(4))]);
+    }
+
+    #[test]
+    fn invalid_ceremony_sizes() {
+        assert!(CeremonySizes::parse_from_cmd("").is_err());
+        assert!(CeremonySizes::parse_from_cmd("8,3:").is_err());
+        assert!(CeremonySizes::parse_from_cmd("8").is_err());
+        assert!(CeremonySizes::parse_from_cmd("0,0").is_err());
+        assert!(CeremonySizes::parse_from_cmd("1,1").is_err());
+        assert!(CeremonySizes::parse_from_cmd("4,8").is_err());
+    }
+
+    #[tokio::test]
+    async fn truncated_transcript_falls_back_to_previous_version() {
+        let dir = tempdir().unwrap();
+        let target = dir.path().join("transcript.json");
+        let work = dir.path().join("transcript.json.next");
+        let sizes = CeremonySizes::parse_from_cmd("4,2").unwrap();
+
+        let transcript = read_or_create_transcript(target.clone(), work.clone(), &sizes, None)
+            .await
+            .unwrap();
+        let previous = transcript.read().await.clone();
+        {
+            let mut transcript = transcript.write().await;
+            let contribution = valid_contribution(&transcript, 1);
+            transcript
+                .verify_add::<Engine>(contribution, Identity::None)
+                .unwrap();
+        }
+        write_json_file(target.clone(), work.clone(), transcript, None)
+            .await
+            .unwrap();
+
+        // Simulate a crash halfway through writing the file.
+        let contents = std::fs::read(&target).unwrap();
+        std::fs::write(&target, &contents[..contents.len() / 2]).unwrap();
+
+        let restored = read_or_create_transcript(target.clone(), work, &sizes, None)
+            .await
+            .unwrap();
+        assert_eq!(*restored.read().await, previous);
+        // The complete version is back in place.
+        assert_eq!(
+            read_json_file::<BatchTranscript>(target).await.unwrap(),
+            previous
+        );
+    }
+
+    #[tokio::test]
+    async fn snapshot_skips_unchanged_transcript() {
+        let dir = tempdir().unwrap();
+        let target = dir.path().join("transcript.json");
+        let work = dir.path().join("transcript.json.snapshot");
+        let transcript = Arc::new(RwLock::new(test_transcript()));
+        let snapshot = |last_hash: String| {
+            let (transcript, target, work) = (transcript.clone(), target.clone(), work.clone());
+            async move {
+                snapshot_transcript(&transcript, target, work, None, &last_hash)
+                    .await
+                    .unwrap()
+            }
+        };
+
+        let initial = transcript_hash(&*transcript.read().await);
+        assert_eq!(snapshot(initial.clone()).await, None);
+        assert!(!target.exists());
+
+        {
+            let mut transcript = transcript.write().await;
+            let contribution = valid_contribution(&transcript, 1);
+            transcript
+                .verify_add::<Engine>(contribution, Identity::None)
+                .unwrap();
+        }
+        let hash = snapshot(initial)
+            .await
+            .expect("changed transcript must be written");
+        let persisted = read_json_file::<BatchTranscript>(target.clone())
+            .await
+            .unwrap();
+        assert_eq!(transcript_hash(&persisted), hash);
+
+        // Unchanged since the last snapshot, the file is left alone.
+        std::fs::remove_file(&target).unwrap();
+        assert_eq!(snapshot(hash).await, None);
+        assert!(!target.exists());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn transcript_persisted_on_interval() {
+        let dir = tempdir().unwrap();
+        let target = dir.path().join("transcript.json");
+        let work = dir.path().join("transcript.json.snapshot");
+        let transcript = Arc::new(RwLock::new(test_transcript()));
+        let interval = Duration::from_secs(60);
+        let shared_hash = Arc::new(RwLock::new(transcript_hash(&*transcript.read().await)));
+        tokio::spawn(persist_transcript_on_interval(
+            transcript.clone(),
+            shared_hash.clone(),
+            target.clone(),
+            work,
+            None,
+            interval,
+        ));
+
+        // Nothing changed since startup, so nothing is written.
+        tokio::time::sleep(interval * 2).await;
+        assert!(!target.exists());
+
+        let changed_at = Instant::now();
+        {
+            let mut transcript = transcript.write().await;
+            let contribution = valid_contribution(&transcript, 1);
+            transcript
+                .verify_add::<Engine>(contribution, Identity::None)
+                .unwrap();
+        }
+        // The write happens on a blocking thread, poll for it.
+        while !target.exists() {
+            assert!(changed_at.elapsed() <= interval * 2, "snapshot not written");
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+        let persisted = read_json_file::<BatchTranscript>(target.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            transcript_hash(&persisted),
+            transcript_hash(&*transcript.read().await)
+        );
+        assert_eq!(transcript_hash(&persisted), *shared_hash.read().await);
+
+        // Unchanged since the last snapshot, the file is not written again.
+        std::fs::remove_file(&target).unwrap();
+        tokio::time::sleep(interval * 3).await;
+        assert!(!target.exists());
+    }
+
+    #[tokio::test]
+    async fn transcript_reloaded_when_modified() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("transcript.json");
+        let work = dir.path().join("transcript.json.next");
+        let sizes = CeremonySizes::parse_from_cmd("4,2").unwrap();
+        let written = Arc::new(RwLock::new(test_transcript()));
+        write_json_file(path.clone(), work.clone(), written.clone(), None)
+            .await
+            .unwrap();
+
+        let transcript = read_transcript(path.clone(), &sizes).await.unwrap();
+        let status = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let hash = SharedTranscriptHash::default();
+        let modified = reload_transcript(&transcript, &status, &hash, path.clone(), &sizes, None)
+            .await
+            .unwrap();
+        assert!(modified.is_some());
+
+        {
+            let mut written = written.write().await;
+            let contribution = valid_contribution(&written, 1);
+            written
+                .verify_add::<Engine>(contribution, Identity::None)
+                .unwrap();
+        }
+        // Unchanged file, the transcript is left alone.
+        assert_eq!(
+            reload_transcript(&transcript, &status, &hash, path.clone(), &sizes, modified)
+                .await
+                .unwrap(),
+            modified
+        );
+        assert_eq!(transcript.read().await.num_participants(), 0);
+
+        let written_hash = write_json_file(path.clone(), work, written.clone(), None)
+            .await
+            .unwrap();
+        reload_transcript(&transcript, &status, &hash, path, &sizes, None)
+            .await
+            .unwrap();
+        assert_eq!(*transcript.read().await, *written.read().await);
+        assert_eq!(status.load(Ordering::Relaxed), 1);
+        assert_eq!(*hash.read().await, written_hash);
+    }
+
+    #[tokio::test]
+    async fn zstd_compressed_transcript_round_trip() {
+        let dir = tempdir().unwrap();
+        let plain = dir.path().join("plain.json");
+        let compressed = dir.path().join("transcript.json.zst");
+        let work = dir.path().join("transcript.json.next");
+        let mut transcript = test_transcript();
+        let contribution = valid_contribution(&transcript, 1);
+        transcript
+            .verify_add::<Engine>(contribution, Identity::None)
+            .unwrap();
+        let transcript = Arc::new(RwLock::new(transcript));
+
+        write_json_file(
+            compressed.clone(),
+            work.clone(),
+            transcript.clone(),
+            Some(3),
+        )
+        .await
+        .unwrap();
+        write_json_file(plain.clone(), work, transcript.clone(), None)
+            .await
+            .unwrap();
+
+        let bytes = std::fs::read(&compressed).unwrap();
+        assert!(bytes.starts_with(&ZSTD_MAGIC));
+        assert!(bytes.len() < std::fs::read(&plain).unwrap().len());
+        // Both formats are detected on read.
+        let expected = transcript.read().await.clone();
+        assert_eq!(
+            read_json_file::<BatchTranscript>(compressed).await.unwrap(),
+            expected
+        );
+        assert_eq!(
+            read_json_file::<BatchTranscript>(plain).await.unwrap(),
+            expected
+        );
+    }
+
+    #[tokio::test]
+    async fn contribution_file_round_trip() {
+        let dir = tempdir().unwrap();
+        let contribution = valid_contribution(&test_transcript(), 1);
+
+        write_contribution_file(dir.path().to_path_buf(), 1, &contribution)
+            .await
+            .unwrap();
+        let path = contribution_path(dir.path(), 1);
+        assert_eq!(
+            std::fs::read(&path).unwrap()[0],
+            kzg_ceremony_crypto::BINARY_FORMAT_VERSION
+        );
+        assert_eq!(read_contribution_file(path).await.unwrap(), contribution);
+
+        let other_version = dir.path().join("other_version.bin");
+        let mut bytes = contribution.to_bytes();
+        bytes[0] += 1;
+        std::fs::write(&other_version, bytes).unwrap();
+        assert!(matches!(
+            read_contribution_file(other_version).await,
+            Err(TranscriptIoError::BinaryFormatError(
+                BinaryFormatError::UnsupportedVersion(..)
+            ))
+        ));
+    }
+}
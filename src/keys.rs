@@ -21,9 +21,19 @@ pub struct Options {
     pub signing_key: Option<String>,
 }
 
-#[derive(Serialize)]
+#[derive(Clone, Serialize)]
 pub struct Signature(String);
 
+impl Signature {
+    pub fn from_hex(hex: &str) -> Self {
+        Self(hex.to_owned())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
 #[derive(Debug, Error, IntoStaticStr)]
 pub enum SignatureError {
     #[error("couldn't sign the receipt")]
@@ -89,7 +99,6 @@ impl Keys {
         Ok(Signature(hex::encode::<Vec<u8>>(signature.into())))
     }
 
-    #[allow(unused)]
     pub fn verify(&self, message: &str, signature: &Signature) -> Result<(), SignatureError> {
         let h = hex::decode(&signature.0).map_err(|_| SignatureError::InvalidToken)?;
         let signature = ethers_core::types::Signature::try_from(h.as_ref())
@@ -4,63 +4,106 @@
 #![allow(clippy::module_name_repetitions)]
 
 use crate::{
+    acceptance::{NoopAcceptanceHook, SharedAcceptanceHook},
     api::v1::{
-        auth::{auth_client_link, eth_callback, github_callback},
-        contribute::{contribute, contribute_abort},
-        info::{current_state, status},
-        lobby::try_contribute,
+        admin::{evict_session, inspect_session, pause, resume, revoke_session},
+        auth::{auth_client_link, eth_callback, github_callback, twitter_callback},
+        contribute::{
+            confirm_contribution, contribute, contribute_abort, stage_contribution,
+            ContributeReceipt, SharedStagedContributions,
+        },
+        health::{livez, readyz},
+        info::{
+            ceremony_info, contribution_at, contributors, current_state, export, record_at, status,
+            CeremonyInfo,
+        },
+        link::link_identity,
+        lobby::{
+            enter_with_token, heartbeat, intent_challenge, pow_challenge, submit_intent,
+            submit_pow, try_contribute,
+        },
+        metrics::metrics,
+    },
+    concurrency::{default_max_concurrent_validations, limit_concurrency, ValidationLimiter},
+    engine::EngineKind,
+    idempotency::IdempotencyCache,
+    io::{
+        persist_transcript_on_interval, read_or_create_transcript, read_transcript,
+        reload_transcript_on_interval, transcript_hash, CeremonySizes,
     },
-    io::{read_or_create_transcript, CeremonySizes},
     keys::Keys,
-    lobby::{clear_lobby_on_interval, SharedLobbyState},
+    lobby::{clear_lobby_on_interval, persist_lobby_on_interval, LobbySnapshot, SharedLobbyState},
     oauth::{
-        eth_oauth_client, github_oauth_client, EthAuthOptions, GithubAuthOptions, SharedAuthState,
+        clear_auth_requests_on_interval, eth_oauth_client, github_oauth_client,
+        twitter_oauth_client, EthAuthOptions, GithubAuthOptions, RetryOptions, SharedAuthState,
+        TwitterAuthOptions,
     },
     sessions::{SessionId, SessionInfo},
-    storage::storage_client,
-    util::parse_url,
+    storage::{flush_contributors_on_interval, storage_client, PersistentStorage},
+    util::{duration_from_str, parse_url, CompressionQuality, Secret},
+    verify_only::disable_writes,
 };
 use axum::{
-    extract::{DefaultBodyLimit, Extension},
+    body::Body,
+    extract::{
+        connect_info::IntoMakeServiceWithConnectInfo, ConnectInfo, DefaultBodyLimit, Extension,
+    },
     handler::Handler,
     response::{Html, IntoResponse},
-    routing::{get, post, IntoMakeService},
+    routing::{get, post},
     Router, Server,
 };
 use clap::Parser;
 use cli_batteries::await_shutdown;
-use eyre::Result as EyreResult;
-use http::StatusCode;
+use eyre::{Result as EyreResult, WrapErr};
+use http::{header, HeaderName, HeaderValue, Method, Request, StatusCode};
 use hyper::server::conn::AddrIncoming;
-use kzg_ceremony_crypto::BatchTranscript;
+use kzg_ceremony_crypto::{signature::ContributionDomain, BatchTranscript, VerificationCache};
 use std::{
+    net::SocketAddr,
     path::PathBuf,
     sync::{atomic::AtomicUsize, Arc},
+    time::Duration,
 };
 use tokio::sync::RwLock;
 use tower_http::{
-    cors::CorsLayer,
+    compression::CompressionLayer,
+    cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer},
     limit::RequestBodyLimitLayer,
-    trace::{DefaultMakeSpan, DefaultOnResponse, TraceLayer},
+    trace::{DefaultOnResponse, TraceLayer},
 };
-use tracing::{debug, info, Level};
+use tracing::{debug, info, info_span, warn, Level};
 use url::Url;
 
+pub mod acceptance;
 mod api;
+mod client_ip;
+mod concurrency;
+mod engine;
+mod idempotency;
+mod intent;
 pub mod io;
 mod keys;
 mod lobby;
+mod lobby_token;
 mod oauth;
+mod pow;
 mod receipt;
+mod self_test;
 mod sessions;
 mod storage;
 #[cfg(test)]
 pub mod test_util;
 mod util;
+mod verify_only;
+pub mod verify_transcript;
 
 pub type Engine = kzg_ceremony_crypto::DefaultEngine;
 pub type SharedTranscript = Arc<RwLock<BatchTranscript>>;
 pub type SharedCeremonyStatus = Arc<AtomicUsize>;
+/// Hash of the transcript as of its last write, see [`io::transcript_hash`].
+pub type SharedTranscriptHash = Arc<RwLock<String>>;
+pub type SharedVerificationCache = Arc<VerificationCache>;
 
 pub const DEFAULT_CEREMONY_SIZES: &str = "4096,65:8192,65:16384,65:32768,65";
 pub const MAX_CONTRIBUTION_SIZE: usize = 10_485_760; // 10MB
@@ -81,10 +124,36 @@ pub struct Options {
     #[clap(flatten)]
     pub ethereum: EthAuthOptions,
 
+    #[clap(flatten)]
+    pub twitter: TwitterAuthOptions,
+
+    #[clap(flatten)]
+    pub oauth_retry: RetryOptions,
+
     /// Allow multiple contributions from the same participant.
     #[clap(long, env, default_value = "false")]
     pub multi_contribution: bool,
 
+    /// Only allow contributions from identities that have been linked to an
+    /// identity of another provider through `/auth/link`.
+    #[clap(long, env, default_value = "false")]
+    pub require_linked_identity: bool,
+
+    /// How long a contribution staged through `/contribute/stage` waits for
+    /// its confirmation before it is dropped and the slot freed, in seconds.
+    #[clap(long, env, value_parser=duration_from_str, default_value="30")]
+    pub contribution_confirm_window: Duration,
+
+    /// How long the acceptance hook may take to confirm a contribution before
+    /// it is refused and the slot freed, in seconds.
+    #[clap(long, env, value_parser=duration_from_str, default_value="10")]
+    pub acceptance_hook_timeout: Duration,
+
+    /// How long the result of a contribution is kept for retries with the
+    /// same `Idempotency-Key` header, in seconds.
+    #[clap(long, env, value_parser=duration_from_str, default_value="600")]
+    pub idempotency_key_ttl: Duration,
+
     /// Storage location for the ceremony transcript json file.
     #[clap(long, env, default_value = "./transcript.json")]
     pub transcript_file: PathBuf,
@@ -93,12 +162,147 @@ pub struct Options {
     #[clap(long, env, default_value = "./transcript.json.next")]
     pub transcript_in_progress_file: PathBuf,
 
+    /// Compress the transcript file with zstd at this level, from 1 to 22.
+    /// Uncompressed if not set. Both compressed and uncompressed files are
+    /// read, so this can be changed between restarts.
+    #[clap(long, env, value_parser = clap::value_parser!(i32).range(1..=22))]
+    pub transcript_zstd_level: Option<i32>,
+
+    /// Directory to keep every accepted contribution in, in the compact
+    /// binary format, one file per contribution. Not kept if not set.
+    #[clap(long, env)]
+    pub contribution_archive_dir: Option<PathBuf>,
+
+    /// How often the transcript is persisted independently of contributions,
+    /// in seconds. Zero disables the snapshots.
+    #[clap(long, env, value_parser=duration_from_str, default_value="60")]
+    pub transcript_snapshot_interval: Duration,
+
     /// Size of the ceremony in number of G1 and G2 points. Multiple ceremonies
     /// can be specified by separating them with a colon. The format is
     /// `G1_POINTS,G2_POINTS[:G1_POINTS,G2_POINTS]*`.
     #[clap(long, env, value_parser=CeremonySizes::parse_from_cmd, default_value=DEFAULT_CEREMONY_SIZES)]
     pub ceremony_sizes: CeremonySizes,
 
+    /// Compression level for the transcript and contribution base responses.
+    /// Either `fastest`, `best`, `default` or an algorithm specific number.
+    #[clap(long, env, default_value = "default")]
+    pub compression_level: CompressionQuality,
+
+    /// Origins allowed to make cross-origin requests to the API, separated
+    /// by commas.
+    #[clap(
+        long,
+        env,
+        value_delimiter = ',',
+        default_value = "https://ceremony.ethereum.org"
+    )]
+    pub cors_allowed_origins: Vec<HeaderValue>,
+
+    /// Methods allowed in cross-origin requests, separated by commas.
+    #[clap(long, env, value_delimiter = ',', default_value = "GET,POST")]
+    pub cors_allowed_methods: Vec<Method>,
+
+    /// Request headers allowed in cross-origin requests, separated by commas.
+    #[clap(
+        long,
+        env,
+        value_delimiter = ',',
+        default_value = "authorization,content-type,if-none-match"
+    )]
+    pub cors_allowed_headers: Vec<HeaderName>,
+
+    /// Allow credentialed cross-origin requests to the endpoints that carry a
+    /// session. Only ever granted to the allowed origins.
+    #[clap(long, env, default_value = "false")]
+    pub cors_allow_credentials: bool,
+
+    /// Cryptography backend used to verify contributions.
+    #[clap(long, env, value_enum, default_value = "both")]
+    pub engine: EngineKind,
+
+    /// Run a contribution end-to-end with the configured engine and exit,
+    /// instead of starting the server.
+    #[clap(long, env)]
+    pub self_test: bool,
+
+    /// Serve the transcript and read endpoints only. Sign-in, lobby,
+    /// contribution and admin endpoints answer 405, for read replicas.
+    /// Nothing is written, the transcript file is reloaded instead.
+    #[clap(long, env, default_value = "false")]
+    pub verify_only: bool,
+
+    /// How often the transcript file is reloaded in verify-only mode, in
+    /// seconds.
+    #[clap(long, env, value_parser=duration_from_str, default_value="10")]
+    pub transcript_reload_interval: Duration,
+
+    /// Chain id of the EIP-712 domain Ethereum users sign contributions in.
+    /// Testnet ceremonies must use the chain id of the testnet.
+    #[clap(long, env, default_value = "1")]
+    pub eip712_chain_id: u64,
+
+    /// Name of the EIP-712 domain Ethereum users sign contributions in.
+    #[clap(long, env, default_value = "Ethereum KZG Ceremony")]
+    pub eip712_domain_name: String,
+
+    /// Version of the EIP-712 domain Ethereum users sign contributions in.
+    #[clap(long, env, default_value = "1.0")]
+    pub eip712_domain_version: String,
+
+    /// How long a sign-in request stays valid, in seconds.
+    #[clap(long, env, value_parser=duration_from_str, default_value="600")]
+    pub auth_request_ttl: Duration,
+
+    /// Maximum number of pending sign-in requests. Further requests are
+    /// refused until pending ones are used or expire.
+    #[clap(long, env, default_value = "10000")]
+    pub max_auth_requests: usize,
+
+    /// Maximum number of sign-in requests per client IP and minute. Zero
+    /// disables the limit.
+    #[clap(long, env, default_value = "20")]
+    pub auth_requests_per_ip: u32,
+
+    /// Pretty-print the JSON of the status and export endpoints, for reading
+    /// them by hand during setup. Compact by default to save bandwidth.
+    #[clap(long, env, default_value = "false")]
+    pub pretty_json: bool,
+
+    /// Maximum number of records returned by a single page of
+    /// `/transcript/export` and `/contributors`.
+    #[clap(long, env, default_value = "100")]
+    pub export_max_limit: usize,
+
+    /// Bearer token for the admin endpoints. Admin endpoints are disabled if
+    /// not set.
+    #[clap(long, env)]
+    pub admin_token: Option<Secret>,
+
+    /// Bearer token required to read `/metrics` on the API port. The endpoint
+    /// is not served there if not set, use the internal metrics port instead.
+    #[clap(long, env)]
+    pub metrics_auth: Option<Secret>,
+
+    /// Maximum number of requests handled at once, health checks excluded.
+    /// Zero disables the limit.
+    #[clap(long, env, default_value = "64")]
+    pub max_concurrent_requests: usize,
+
+    /// Number of threads contributions are validated on, so that validation
+    /// doesn't starve the HTTP server. Further validation work waits. Defaults
+    /// to one less than the number of CPUs.
+    #[clap(long, env, default_value_t = default_max_concurrent_validations())]
+    pub max_concurrent_validations: usize,
+
+    /// Number of requests that may wait for one of the
+    /// `max-concurrent-requests` slots. Requests beyond that get a 503.
+    #[clap(long, env, default_value = "256")]
+    pub request_queue_depth: usize,
+
+    #[clap(flatten)]
+    pub proxy: client_ip::Options,
+
     #[clap(flatten)]
     pub lobby: lobby::Options,
 
@@ -106,81 +310,339 @@ pub struct Options {
     pub storage: storage::Options,
 }
 
+impl Options {
+    #[must_use]
+    pub fn contribution_domain(&self) -> ContributionDomain {
+        ContributionDomain {
+            name: self.eip712_domain_name.clone(),
+            version: self.eip712_domain_version.clone(),
+            chain_id: self.eip712_chain_id,
+        }
+    }
+}
+
 #[allow(clippy::missing_errors_doc)]
 pub async fn async_main(options: Options) -> EyreResult<()> {
     debug!(?options, "Options");
+    options.lobby.validate().wrap_err("Invalid lobby options")?;
+
+    if options.self_test {
+        return options.engine.self_test();
+    }
 
     let addr = options.server.clone();
-    let server = start_server(options).await?;
+    let (server, storage) = build_server(options, Arc::new(NoopAcceptanceHook)).await?;
     info!("Listening on http://{}{}", server.local_addr(), addr.path());
     server.with_graceful_shutdown(await_shutdown()).await?;
+    // No more requests, write out everything still buffered.
+    storage
+        .flush_contributors()
+        .await
+        .wrap_err("failed to flush contributors")?;
     Ok(())
 }
 
 #[allow(clippy::missing_errors_doc)]
 pub async fn start_server(
     options: Options,
-) -> EyreResult<Server<AddrIncoming, IntoMakeService<Router>>> {
+) -> EyreResult<Server<AddrIncoming, IntoMakeServiceWithConnectInfo<Router, SocketAddr>>> {
+    start_server_with_hook(options, Arc::new(NoopAcceptanceHook)).await
+}
+
+/// Like [`start_server`], but confirms every contribution with
+/// `acceptance_hook` before adding it to the transcript.
+#[allow(clippy::missing_errors_doc)]
+pub async fn start_server_with_hook(
+    options: Options,
+    acceptance_hook: SharedAcceptanceHook,
+) -> EyreResult<Server<AddrIncoming, IntoMakeServiceWithConnectInfo<Router, SocketAddr>>> {
+    let (server, _) = build_server(options, acceptance_hook).await?;
+    Ok(server)
+}
+
+/// Also returns the storage, so that it can be flushed after the server shut
+/// down.
+async fn build_server(
+    options: Options,
+    acceptance_hook: SharedAcceptanceHook,
+) -> EyreResult<(
+    Server<AddrIncoming, IntoMakeServiceWithConnectInfo<Router, SocketAddr>>,
+    PersistentStorage,
+)> {
+    let (app, storage) = build_app(&options, acceptance_hook).await?;
+    let (addr, _) = parse_url(&options.server)?;
+    // Connect info provides the socket address for the client IP.
+    let server =
+        Server::try_bind(&addr)?.serve(app.into_make_service_with_connect_info::<SocketAddr>());
+    Ok((server, storage))
+}
+
+/// Loads the state, spawns the background tasks and builds the router of
+/// [`build_server`]. In verify-only mode, nothing is written: the lobby is
+/// neither restored nor persisted and the transcript is reloaded from its
+/// file instead of being written to it.
+async fn build_app(
+    options: &Options,
+    acceptance_hook: SharedAcceptanceHook,
+) -> EyreResult<(Router, PersistentStorage)> {
     info!(size=?options.ceremony_sizes, "Starting sequencer for KZG ceremony.");
 
     let keys = Arc::new(Keys::new(&options.keys)?);
 
-    let transcript = read_or_create_transcript(
-        options.transcript_file.clone(),
-        options.transcript_in_progress_file.clone(),
-        &options.ceremony_sizes,
-    )
-    .await?;
+    let transcript = if options.verify_only {
+        read_transcript(options.transcript_file.clone(), &options.ceremony_sizes).await?
+    } else {
+        read_or_create_transcript(
+            options.transcript_file.clone(),
+            options.transcript_in_progress_file.clone(),
+            &options.ceremony_sizes,
+            options.transcript_zstd_level,
+        )
+        .await?
+    };
 
-    let ceremony_status = {
+    let (ceremony_status, shared_hash) = {
         let lock = transcript.read().await;
-        Arc::new(AtomicUsize::new(lock.num_participants()))
+        (
+            Arc::new(AtomicUsize::new(lock.num_participants())),
+            Arc::new(RwLock::new(transcript_hash(&lock))),
+        )
     };
+    let benchmark = options.engine.benchmark();
+    info!(?benchmark, "Benchmarked the crypto engine");
+    let ceremony_info = CeremonyInfo::new(&transcript.read().await.contribution(), &benchmark);
+    let storage = storage_client(&options.storage).await?;
     let lobby_state = SharedLobbyState::new(options.lobby.clone());
+    if storage.is_paused().await? {
+        warn!("Ceremony is paused, resume it through the admin API.");
+        lobby_state.set_paused(true).await;
+    }
+    if !options.verify_only && !options.lobby.lobby_persist_interval.is_zero() {
+        if let Some(json) = storage.load_lobby().await? {
+            match serde_json::from_str::<LobbySnapshot>(&json) {
+                Ok(snapshot) => lobby_state.restore(snapshot, storage.clone()).await,
+                Err(e) => warn!("Ignoring unreadable persisted lobby: {}", e),
+            }
+        }
+    }
     let auth_state = SharedAuthState::default();
-
-    // Spawn automatic queue flusher -- flushes those in the lobby whom have not
-    // pinged in a considerable amount of time
-    tokio::spawn(clear_lobby_on_interval(
-        lobby_state.clone(),
-        options.lobby.clone(),
+    tokio::spawn(clear_auth_requests_on_interval(
+        auth_state.clone(),
+        options.auth_request_ttl,
     ));
 
-    let app = Router::new()
+    if options.verify_only {
+        // Another sequencer writes the transcript, follow its file.
+        tokio::spawn(reload_transcript_on_interval(
+            transcript.clone(),
+            ceremony_status.clone(),
+            shared_hash.clone(),
+            options.transcript_file.clone(),
+            options.ceremony_sizes.clone(),
+            options.transcript_reload_interval,
+        ));
+    } else {
+        spawn_writers(options, &lobby_state, &storage, &transcript, &shared_hash);
+    }
+
+    // Browsers enforce the policy, requests from other origins simply don't get
+    // the `Access-Control-Allow-Origin` header.
+    let cors = CorsLayer::new()
+        .allow_origin(AllowOrigin::list(options.cors_allowed_origins.clone()))
+        .allow_methods(AllowMethods::list(options.cors_allowed_methods.clone()))
+        .allow_headers(AllowHeaders::list(options.cors_allowed_headers.clone()))
+        .expose_headers([header::ETAG]);
+    // The origins are an explicit list, so credentials never go to arbitrary
+    // origins.
+    let session_cors = cors
+        .clone()
+        .allow_credentials(options.cors_allow_credentials);
+
+    // Only compress the routes that return (large) transcripts.
+    let compression = CompressionLayer::new().quality(options.compression_level.into());
+
+    // Routes that carry the session, or the cookies that bind sign-in requests
+    // to the client and carry the lobby entry token.
+    let session_routes = Router::new()
         .route("/auth/request_link", get(auth_client_link))
-        .route("/auth/callback/github", get(github_callback))
-        .route("/auth/callback/eth", get(eth_callback))
-        .route("/lobby/try_contribute", post(try_contribute))
+        .route("/auth/link", post(link_identity))
+        .route(
+            "/lobby/try_contribute",
+            post(try_contribute).layer(compression.clone()),
+        )
+        .route("/lobby/pow", get(pow_challenge).post(submit_pow))
+        .route("/lobby/intent", get(intent_challenge).post(submit_intent))
+        .route("/lobby/enter", post(enter_with_token))
+        .route("/lobby/heartbeat", post(heartbeat))
         .route("/contribute", post(contribute))
         .route("/contribute/abort", post(contribute_abort))
+        .route("/contribute/stage", post(stage_contribution))
+        .route("/contribute/confirm", post(confirm_contribution))
+        .layer(session_cors);
+
+    let write_routes = Router::new()
+        .route("/auth/callback/github", get(github_callback))
+        .route("/auth/callback/eth", get(eth_callback))
+        .route("/admin/revoke/:session_id", post(revoke_session))
+        .route(
+            "/admin/session/:session_id",
+            get(inspect_session).delete(evict_session),
+        )
+        .route("/admin/pause", post(pause))
+        .route("/admin/resume", post(resume))
+        .layer(cors.clone())
+        .merge(session_routes);
+    let twitter_client = twitter_oauth_client(&options.twitter);
+    let write_routes = if twitter_client.is_some() {
+        write_routes.route(
+            "/auth/callback/twitter",
+            get(twitter_callback).layer(cors.clone()),
+        )
+    } else {
+        write_routes
+    };
+    let write_routes = if options.verify_only {
+        disable_writes(write_routes)
+    } else {
+        write_routes
+    };
+
+    let app = Router::new()
         .route("/info/status", get(status))
-        .route("/info/current_state", get(current_state))
-        .layer(CorsLayer::permissive())
-        .layer(Extension(lobby_state))
-        .layer(Extension(auth_state))
-        .layer(Extension(ceremony_status))
-        .layer(Extension(keys))
-        .layer(Extension(eth_oauth_client(&options.ethereum)))
-        .layer(Extension(github_oauth_client(&options.github)))
-        .layer(Extension(reqwest::Client::new()))
-        .layer(Extension(storage_client(&options.storage).await?))
-        .layer(Extension(transcript))
-        .layer(Extension(options.clone()))
-        .layer(DefaultBodyLimit::disable())
-        .layer(RequestBodyLimitLayer::new(MAX_CONTRIBUTION_SIZE));
-
-    // Run the server
-    let (addr, prefix) = parse_url(&options.server)?;
+        .route("/ceremony/info", get(ceremony_info))
+        .route("/contributors", get(contributors))
+        .route(
+            "/info/current_state",
+            get(current_state).layer(compression.clone()),
+        )
+        .route(
+            "/transcript/contribution/:index",
+            get(contribution_at).layer(compression.clone()),
+        )
+        .route("/transcript/record/:index", get(record_at))
+        .route("/transcript/export", get(export).layer(compression))
+        .layer(cors)
+        .merge(write_routes);
+    // Served on the public port, so only with a token.
+    let app = if options.metrics_auth.is_some() {
+        app.route("/metrics", get(metrics))
+    } else {
+        app
+    };
+
+    // Health checks bypass the concurrency limit, so an overloaded sequencer
+    // isn't also restarted.
+    let app = limit_concurrency(
+        app,
+        options.max_concurrent_requests,
+        options.request_queue_depth,
+    )
+    .route("/livez", get(livez))
+    .route("/readyz", get(readyz))
+    .layer(Extension(lobby_state))
+    .layer(Extension(auth_state))
+    .layer(Extension(ceremony_status))
+    .layer(Extension(shared_hash))
+    .layer(Extension(ceremony_info))
+    .layer(Extension(keys))
+    .layer(Extension(acceptance_hook))
+    .layer(Extension(SharedStagedContributions::default()))
+    .layer(Extension(IdempotencyCache::<ContributeReceipt>::new(
+        options.idempotency_key_ttl,
+    )))
+    .layer(Extension(SharedVerificationCache::default()))
+    .layer(Extension(ValidationLimiter::new(
+        options.max_concurrent_validations,
+    )))
+    .layer(Extension(eth_oauth_client(&options.ethereum)))
+    .layer(Extension(github_oauth_client(&options.github)))
+    .layer(Extension(reqwest::Client::new()))
+    .layer(Extension(storage.clone()))
+    .layer(Extension(transcript))
+    .layer(Extension(options.clone()))
+    .layer(DefaultBodyLimit::disable())
+    .layer(RequestBodyLimitLayer::new(MAX_CONTRIBUTION_SIZE));
+
+    let app = match twitter_client {
+        Some(twitter_client) => app.layer(Extension(twitter_client)),
+        None => app,
+    };
+
+    let (_, prefix) = parse_url(&options.server)?;
+    let proxy = options.proxy.clone();
     let app = Router::new()
         .nest(prefix, app)
         .fallback(handle_404.into_service())
         .layer(
             TraceLayer::new_for_http()
-                .make_span_with(DefaultMakeSpan::default().level(Level::INFO))
+                .make_span_with(move |request: &Request<Body>| {
+                    let client_ip = request
+                        .extensions()
+                        .get::<ConnectInfo<SocketAddr>>()
+                        .map(|ConnectInfo(socket)| proxy.client_ip(request.headers(), *socket));
+                    info_span!(
+                        "request",
+                        method = %request.method(),
+                        uri = %request.uri(),
+                        version = ?request.version(),
+                        client_ip = ?client_ip,
+                    )
+                })
                 .on_response(DefaultOnResponse::default().level(Level::INFO)),
         );
-    let server = Server::try_bind(&addr)?.serve(app.into_make_service());
-    Ok(server)
+    Ok((app, storage))
+}
+
+/// Spawns the tasks that flush the lobby and persist the lobby, the buffered
+/// contributors and the transcript.
+fn spawn_writers(
+    options: &Options,
+    lobby_state: &SharedLobbyState,
+    storage: &PersistentStorage,
+    transcript: &SharedTranscript,
+    shared_hash: &SharedTranscriptHash,
+) {
+    // Spawn automatic queue flusher -- flushes those in the lobby whom have not
+    // pinged in a considerable amount of time
+    tokio::spawn(clear_lobby_on_interval(
+        lobby_state.clone(),
+        options.lobby.clone(),
+    ));
+
+    // Persist the lobby, so that queued participants and the active
+    // contributor survive a restart.
+    if !options.lobby.lobby_persist_interval.is_zero() {
+        tokio::spawn(persist_lobby_on_interval(
+            lobby_state.clone(),
+            storage.clone(),
+            options.lobby.lobby_persist_interval,
+        ));
+    }
+
+    // Write buffered contributor inserts in batches.
+    if !options.storage.contributor_flush_interval.is_zero() {
+        tokio::spawn(flush_contributors_on_interval(
+            storage.clone(),
+            options.storage.contributor_flush_interval,
+        ));
+    }
+
+    // Snapshot the transcript in case the contribution flow fails to persist
+    // it. Uses its own work file, and `write_json_file` holds the read lock
+    // until the file is replaced, so a snapshot never replaces a newer
+    // version.
+    if !options.transcript_snapshot_interval.is_zero() {
+        tokio::spawn(persist_transcript_on_interval(
+            transcript.clone(),
+            shared_hash.clone(),
+            options.transcript_file.clone(),
+            options
+                .transcript_in_progress_file
+                .with_extension("snapshot"),
+            options.transcript_zstd_level,
+            options.transcript_snapshot_interval,
+        ));
+    }
 }
 
 #[allow(clippy::unused_async)] // Required for axum function signature
@@ -1,15 +1,21 @@
 use crate::{
-    sessions::{SessionId, SessionInfo},
+    intent,
+    sessions::{IdToken, SessionId, SessionInfo, SessionKey},
     storage::PersistentStorage,
+    util::duration_from_str,
+};
+use clap::{Parser, ValueEnum};
+use kzg_ceremony_crypto::signature::identity::Identity;
+use rand::{rngs::StdRng, seq::IteratorRandom, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
-use clap::Parser;
-use std::{collections::BTreeMap, num::ParseIntError, str::FromStr, sync::Arc, time::Duration};
 use thiserror::Error;
 use tokio::{sync::Mutex, time::Instant};
-
-fn duration_from_str(value: &str) -> Result<Duration, ParseIntError> {
-    Ok(Duration::from_secs(u64::from_str(value)?))
-}
+use tracing::{error, info};
 
 #[derive(Clone, Debug, PartialEq, Eq, Parser)]
 #[group(skip)]
@@ -18,6 +24,11 @@ pub struct Options {
     #[clap(long, env, value_parser=duration_from_str, default_value="180")]
     pub compute_deadline: Duration,
 
+    /// Extra time on top of the compute deadline for the contribution upload
+    /// to reach the server, in seconds.
+    #[clap(long, env, value_parser=duration_from_str, default_value="10")]
+    pub upload_grace: Duration,
+
     /// How often participants should ping the server to keep their session
     /// alive in seconds.
     #[clap(long, env, value_parser=duration_from_str, default_value="30")]
@@ -43,6 +54,71 @@ pub struct Options {
     /// Maximum number of active sessions.
     #[clap(long, env, default_value = "100000")]
     pub max_sessions_count: usize,
+
+    /// Number of leading zero bits required in the proof-of-work that
+    /// sessions have to solve before entering the lobby. Zero disables it.
+    #[clap(long, env, default_value = "0")]
+    pub pow_difficulty: u32,
+
+    /// How long a proof-of-work challenge can be answered, in seconds.
+    #[clap(long, env, value_parser=duration_from_str, default_value="300")]
+    pub pow_challenge_ttl: Duration,
+
+    /// Require sessions of Ethereum identities to sign a challenge from
+    /// `/lobby/intent` with their wallet before entering the lobby.
+    #[clap(long, env)]
+    pub require_intent_signature: bool,
+
+    /// How long an intent challenge can be answered, in seconds.
+    #[clap(long, env, value_parser=duration_from_str, default_value="300")]
+    pub intent_challenge_ttl: Duration,
+
+    /// How long a lobby entry token issued at sign-in can be used to enter
+    /// the lobby, in seconds.
+    #[clap(long, env, value_parser=duration_from_str, default_value="3600")]
+    pub lobby_entry_token_ttl: Duration,
+
+    /// How long a revoked session is blocked from re-entering, in seconds.
+    #[clap(long, env, value_parser=duration_from_str, default_value="3600")]
+    pub revocation_duration: Duration,
+
+    /// How long an identity has to wait before re-entering the lobby after
+    /// aborting, failing or timing out of a contribution, in seconds. Zero
+    /// disables it.
+    #[clap(long, env, value_parser=duration_from_str, default_value="0")]
+    pub contribution_cooldown: Duration,
+
+    /// How the next contributor is picked from the lobby.
+    #[clap(long, env, value_enum, default_value = "first-come")]
+    pub selection_policy: SelectionPolicy,
+
+    /// How much each heartbeat of the active contributor extends the compute
+    /// deadline, in seconds.
+    #[clap(long, env, value_parser=duration_from_str, default_value="30")]
+    pub heartbeat_extension: Duration,
+
+    /// How many times heartbeats can extend the compute deadline. Zero
+    /// disables extensions.
+    #[clap(long, env, default_value = "4")]
+    pub max_heartbeat_extensions: u32,
+
+    /// How often the lobby is persisted to storage, in seconds, so that it is
+    /// restored after a restart. The snapshot contains the identities of all
+    /// signed in participants, zero (the default) disables persisting and
+    /// restoring.
+    #[clap(long, env, value_parser=duration_from_str, default_value="0")]
+    pub lobby_persist_interval: Duration,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SelectionPolicy {
+    /// Whichever participant in the lobby asks for the slot first.
+    FirstCome,
+    /// The participant that entered the lobby first. A participant that
+    /// stopped pinging holds up the lobby until it is flushed.
+    Fifo,
+    /// A uniformly random participant from the lobby.
+    Random,
 }
 
 impl Options {
@@ -50,18 +126,245 @@ impl Options {
         self.lobby_checkin_frequency
             .saturating_sub(self.lobby_checkin_tolerance)
     }
+
+    /// Checks invariants between the options that would otherwise only show up
+    /// as confusing runtime behavior.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first violated invariant.
+    pub fn validate(&self) -> Result<(), OptionsError> {
+        if self.compute_deadline.is_zero() {
+            return Err(OptionsError::ZeroComputeDeadline);
+        }
+        if self.max_lobby_size == 0 {
+            return Err(OptionsError::ZeroLobbySize);
+        }
+        if self.max_sessions_count == 0 {
+            return Err(OptionsError::ZeroSessionsCount);
+        }
+        if self.lobby_flush_interval.is_zero() {
+            return Err(OptionsError::ZeroFlushInterval);
+        }
+        if self.min_checkin_delay() > self.compute_deadline {
+            return Err(OptionsError::CheckinDelayExceedsComputeDeadline {
+                min_checkin_delay: self.min_checkin_delay(),
+                compute_deadline: self.compute_deadline,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum OptionsError {
+    #[error("compute deadline must be greater than zero")]
+    ZeroComputeDeadline,
+    #[error("max lobby size must be greater than zero")]
+    ZeroLobbySize,
+    #[error("max sessions count must be greater than zero")]
+    ZeroSessionsCount,
+    #[error("lobby flush interval must be greater than zero")]
+    ZeroFlushInterval,
+    #[error(
+        "minimum checkin delay ({min_checkin_delay:?}) is larger than the compute deadline \
+         ({compute_deadline:?})"
+    )]
+    CheckinDelayExceedsComputeDeadline {
+        min_checkin_delay: Duration,
+        compute_deadline: Duration,
+    },
 }
 
-#[derive(Default)]
+/// Sessions are stored under their [`SessionKey`].
 pub struct LobbyState {
-    pub sessions_in_lobby: BTreeMap<SessionId, SessionInfo>,
-    pub sessions_out_of_lobby: BTreeMap<SessionId, SessionInfo>,
+    pub sessions_in_lobby: BTreeMap<SessionKey, SessionInfo>,
+    pub sessions_out_of_lobby: BTreeMap<SessionKey, SessionInfo>,
     pub active_contributor: ActiveContributor,
+    /// Sessions revoked by an operator, with the time of revocation.
+    pub revoked_sessions: BTreeMap<SessionKey, Instant>,
+    /// Identities, by [`Identity::unique_id`], that aborted, failed or timed
+    /// out of a contribution, with the time it happened.
+    pub cooldowns: BTreeMap<String, Instant>,
+    /// Counter for [`SessionInfo::lobby_entry`].
+    next_lobby_entry: u64,
+    /// The participant picked by [`SelectionPolicy::Random`], kept until they
+    /// claim the contributor slot or leave the lobby.
+    selected: Option<SessionKey>,
+    rng: StdRng,
+    /// While paused, nobody can claim the contributor slot.
+    paused: bool,
+}
+
+impl LobbyState {
+    fn new(rng: StdRng) -> Self {
+        Self {
+            sessions_in_lobby: BTreeMap::new(),
+            sessions_out_of_lobby: BTreeMap::new(),
+            active_contributor: ActiveContributor::None,
+            revoked_sessions: BTreeMap::new(),
+            cooldowns: BTreeMap::new(),
+            next_lobby_entry: 0,
+            selected: None,
+            rng,
+            paused: false,
+        }
+    }
+
+    /// Keeps `identity` out of the lobby for `cooldown`. Nothing is recorded
+    /// when cooldowns are disabled.
+    fn start_cooldown(&mut self, identity: String, cooldown: Duration) {
+        if !cooldown.is_zero() {
+            self.cooldowns.insert(identity, Instant::now());
+        }
+    }
+
+    /// Whether `participant` may claim the contributor slot under `policy`.
+    fn is_next_contributor(&mut self, policy: SelectionPolicy, participant: &SessionKey) -> bool {
+        match policy {
+            SelectionPolicy::FirstCome => true,
+            SelectionPolicy::Fifo | SelectionPolicy::Random => {
+                self.next_contributor(policy).as_ref() == Some(participant)
+            }
+        }
+    }
+
+    /// The participant picked by `policy`, the head of the lobby for
+    /// [`SelectionPolicy::FirstCome`].
+    fn next_contributor(&mut self, policy: SelectionPolicy) -> Option<SessionKey> {
+        match policy {
+            SelectionPolicy::FirstCome | SelectionPolicy::Fifo => self
+                .sessions_in_lobby
+                .iter()
+                .min_by_key(|(_, info)| info.lobby_entry)
+                .map(|(id, _)| id.clone()),
+            SelectionPolicy::Random => {
+                if self.selected.as_ref().map_or(false, |selected| {
+                    self.sessions_in_lobby.contains_key(selected)
+                }) {
+                    return self.selected.clone();
+                }
+                self.selected = self.sessions_in_lobby.keys().choose(&mut self.rng).cloned();
+                self.selected.clone()
+            }
+        }
+    }
+}
+
+/// A serializable view of the lobby for exports and restarts.
+///
+/// All entries are sorted by session key, so the same logical state always
+/// serializes to the same bytes, regardless of how the maps were filled.
+/// Sessions are only stored by their [`SessionKey`], so a snapshot can't be
+/// used to act as a participant. Timestamps are left out, they are only
+/// meaningful to the running process, except for the contributor deadline,
+/// which is kept as Unix time.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LobbySnapshot {
+    pub sessions_in_lobby: Vec<SessionSnapshot>,
+    pub sessions_out_of_lobby: Vec<SessionSnapshot>,
+    pub active_contributor: Option<ActiveContributorSnapshot>,
+    pub revoked_sessions: Vec<SessionKey>,
+    pub next_lobby_entry: u64,
+    pub paused: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionSnapshot {
+    pub session_key: SessionKey,
+    pub identity: Identity,
+    pub exp: u64,
+    pub pow_solved: bool,
+    #[serde(default)]
+    pub intent_signed: bool,
+    pub lobby_entry: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActiveContributorSnapshot {
+    pub session: SessionSnapshot,
+    /// Unix time in seconds at which the slot expires. `None` while the
+    /// contribution is being verified.
+    pub deadline: Option<u64>,
+    pub extensions: u32,
+}
+
+impl SessionSnapshot {
+    fn new(key: &SessionKey, info: &SessionInfo) -> Self {
+        Self {
+            session_key: key.clone(),
+            identity: info.token.identity.clone(),
+            exp: info.token.exp,
+            pow_solved: info.pow_solved,
+            intent_signed: info.intent_signed,
+            lobby_entry: info.lobby_entry,
+        }
+    }
+
+    /// Restores the session, as if it just pinged.
+    fn into_session(self, now: Instant) -> (SessionKey, SessionInfo) {
+        let info = SessionInfo {
+            token: IdToken {
+                identity: self.identity,
+                exp: self.exp,
+            },
+            last_ping_time: now,
+            is_first_ping_attempt: false,
+            pow_challenge: None,
+            pow_solved: self.pow_solved,
+            intent_challenge: None,
+            intent_signed: self.intent_signed,
+            lobby_entry: self.lobby_entry,
+        };
+        (self.session_key, info)
+    }
+}
+
+impl LobbySnapshot {
+    /// Serializes the snapshot as compact JSON.
+    ///
+    /// # Panics
+    ///
+    /// Panics if serialization fails, which can't happen for these types.
+    #[must_use]
+    pub fn to_canonical_json(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("lobby snapshot is always serializable")
+    }
+}
+
+/// Collects the sessions sorted by key, and by identity between equal keys, so
+/// the order doesn't depend on the map they came from.
+fn sorted_sessions<'a>(
+    sessions: impl Iterator<Item = (&'a SessionKey, &'a SessionInfo)>,
+) -> Vec<SessionSnapshot> {
+    let mut sessions: Vec<_> = sessions
+        .map(|(key, info)| SessionSnapshot::new(key, info))
+        .collect();
+    sessions.sort_by(|a, b| {
+        a.session_key
+            .cmp(&b.session_key)
+            .then_with(|| a.identity.unique_id().cmp(&b.identity.unique_id()))
+    });
+    sessions
+}
+
+/// What an operator sees of a single session, see
+/// [`SharedLobbyState::session_status`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SessionStatus {
+    pub identity: Identity,
+    /// Seconds since the session last pinged the lobby.
+    pub last_ping_secs_ago: u64,
+    /// Number of sessions that entered the lobby before this one, `None` if
+    /// it is not in the lobby. With [`SelectionPolicy::Random`] this is not
+    /// the order of selection.
+    pub queue_position: Option<usize>,
+    pub active_contributor: bool,
 }
 
 #[derive(Clone, Debug)]
 pub struct SessionInfoWithId {
-    id: SessionId,
+    id: SessionKey,
     info: SessionInfo,
 }
 
@@ -73,6 +376,10 @@ pub enum ActiveContributor {
         /// The last time this session requested the contribution base.
         /// This is large, so we only allow them to re-request it infrequently.
         last_contribution_file_request: Instant,
+        /// When the contributor slot expires, heartbeats move it back.
+        deadline: Instant,
+        /// Number of times heartbeats extended the deadline.
+        extensions: u32,
     },
     Contributing(SessionInfoWithId),
 }
@@ -97,8 +404,20 @@ pub enum ActiveContributorError {
     SessionCountLimitExceeded,
     #[error("lobby size limit exceeded")]
     LobbySizeLimitExceeded,
+    /// Carries how long the caller has to wait.
     #[error("call came too early. rate limited")]
-    RateLimited,
+    RateLimited(Duration),
+    #[error("session revoked")]
+    SessionRevoked,
+    #[error("proof of work not solved")]
+    ProofOfWorkRequired,
+    #[error("intent to participate not signed")]
+    IntentSignatureRequired,
+    #[error("ceremony is paused")]
+    CeremonyPaused,
+    /// Carries how long the caller has to wait.
+    #[error("contribution cooldown active")]
+    CooldownActive(Duration),
 }
 
 #[derive(Clone)]
@@ -109,8 +428,14 @@ pub struct SharedLobbyState {
 
 impl SharedLobbyState {
     pub fn new(options: Options) -> Self {
+        Self::with_rng(options, StdRng::from_entropy())
+    }
+
+    /// Uses `rng` for [`SelectionPolicy::Random`], so that tests can make the
+    /// selection reproducible.
+    pub fn with_rng(options: Options, rng: StdRng) -> Self {
         Self {
-            inner: Arc::default(),
+            inner: Arc::new(Mutex::new(LobbyState::new(rng))),
             options,
         }
     }
@@ -121,29 +446,46 @@ impl SharedLobbyState {
         compute_deadline: Duration,
         storage: PersistentStorage,
     ) -> Result<(), ActiveContributorError> {
+        let participant = participant.key();
         let mut state = self.inner.lock().await;
 
+        if state.paused {
+            return Err(ActiveContributorError::CeremonyPaused);
+        }
+
         if matches!(state.active_contributor, ActiveContributor::None) {
+            if !state.sessions_in_lobby.contains_key(&participant) {
+                return Err(ActiveContributorError::UserNotInLobby);
+            }
+            if !state.is_next_contributor(self.options.selection_policy, &participant) {
+                return Err(ActiveContributorError::NotUsersTurn);
+            }
+            state.selected = None;
             let session_info = state
                 .sessions_in_lobby
-                .remove(participant)
+                .remove(&participant)
                 .ok_or(ActiveContributorError::UserNotInLobby)?;
 
+            let now = Instant::now();
+            // The slot is only reclaimed once the upload had time to arrive.
+            let deadline = now + compute_deadline + self.options.upload_grace;
             state.active_contributor = ActiveContributor::AwaitingContribution {
                 session: SessionInfoWithId {
                     id: participant.clone(),
                     info: session_info,
                 },
-                last_contribution_file_request: Instant::now(),
+                last_contribution_file_request: now,
+                deadline,
+                extensions: 0,
             };
 
             let inner = self.inner.clone();
-            let participant = participant.clone();
 
             tokio::spawn(Self::expire_current_contributor(
                 inner,
                 participant,
-                compute_deadline,
+                deadline,
+                self.options.contribution_cooldown,
                 storage,
             ));
 
@@ -157,13 +499,14 @@ impl SharedLobbyState {
         &self,
         participant: &SessionId,
     ) -> Result<SessionInfo, ActiveContributorError> {
+        let participant = participant.key();
         let mut state = self.inner.lock().await;
 
         match &state.active_contributor {
             ActiveContributor::AwaitingContribution {
                 session: info_with_id,
                 ..
-            } if &info_with_id.id == participant => {
+            } if info_with_id.id == participant => {
                 let next_state = ActiveContributor::Contributing(info_with_id.clone());
                 let info = info_with_id.info.clone();
                 state.active_contributor = next_state;
@@ -173,27 +516,79 @@ impl SharedLobbyState {
         }
     }
 
+    /// Frees the contributor slot and starts the cooldown. Returns the
+    /// [`Identity::unique_id`] of the participant, whose contribution is to be
+    /// expired.
     pub async fn abort_contribution(
         &self,
         participant: &SessionId,
-    ) -> Result<(), ActiveContributorError> {
+    ) -> Result<String, ActiveContributorError> {
+        let participant = participant.key();
         let mut state = self.inner.lock().await;
 
-        if !matches!(&state.active_contributor, ActiveContributor::AwaitingContribution { session: x, .. } if &x.id == participant)
-        {
-            return Err(ActiveContributorError::NotUsersTurn);
-        }
-
+        let identity = match &state.active_contributor {
+            ActiveContributor::AwaitingContribution { session, .. }
+            | ActiveContributor::Contributing(session)
+                if session.id == participant =>
+            {
+                session.info.token.identity.unique_id()
+            }
+            _ => return Err(ActiveContributorError::NotUsersTurn),
+        };
         state.active_contributor = ActiveContributor::None;
+        state.start_cooldown(identity.clone(), self.options.contribution_cooldown);
 
-        Ok(())
+        Ok(identity)
     }
 
+    /// Pausing only stops new contributors from claiming the slot, the active
+    /// contributor can still finish.
+    pub async fn set_paused(&self, paused: bool) {
+        self.inner.lock().await.paused = paused;
+    }
+
+    #[cfg(test)]
     pub async fn clear_current_contributor(&self) {
         let mut state = self.inner.lock().await;
         state.active_contributor = ActiveContributor::None;
     }
 
+    /// Frees the contributor slot if `participant` holds it, so that a stale
+    /// task can't free the slot of a later contributor. Returns whether it did.
+    pub async fn release_contributor(&self, participant: &SessionId) -> bool {
+        let participant = participant.key();
+        let mut state = self.inner.lock().await;
+        match &state.active_contributor {
+            ActiveContributor::AwaitingContribution { session, .. }
+            | ActiveContributor::Contributing(session)
+                if session.id == participant =>
+            {
+                state.active_contributor = ActiveContributor::None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Like [`Self::release_contributor`], but also puts the participant into
+    /// the contribution cooldown, for when they didn't finish.
+    pub async fn fail_contributor(&self, participant: &SessionId) -> bool {
+        let participant = participant.key();
+        let mut state = self.inner.lock().await;
+        let identity = match &state.active_contributor {
+            ActiveContributor::AwaitingContribution { session, .. }
+            | ActiveContributor::Contributing(session)
+                if session.id == participant =>
+            {
+                session.info.token.identity.unique_id()
+            }
+            _ => return false,
+        };
+        state.active_contributor = ActiveContributor::None;
+        state.start_cooldown(identity, self.options.contribution_cooldown);
+        true
+    }
+
     #[allow(clippy::needless_collect)]
     pub async fn clear_lobby(&self, predicate: impl Fn(&SessionInfo) -> bool + Copy + Send) {
         let mut lobby_state = self.inner.lock().await;
@@ -217,19 +612,156 @@ impl SharedLobbyState {
             .retain(|_, info| !predicate(info));
     }
 
+    pub async fn clear_revocations(&self, now: Instant) {
+        let mut lobby_state = self.inner.lock().await;
+        let revocation_duration = self.options.revocation_duration;
+        lobby_state
+            .revoked_sessions
+            .retain(|_, revoked_at| now - *revoked_at <= revocation_duration);
+    }
+
+    pub async fn clear_cooldowns(&self, now: Instant) {
+        let mut lobby_state = self.inner.lock().await;
+        let cooldown = self.options.contribution_cooldown;
+        lobby_state
+            .cooldowns
+            .retain(|_, started| now - *started < cooldown);
+    }
+
+    /// Removes the session from the lobby and, if it is the active
+    /// contributor, frees the contributor slot. The session is then blocked
+    /// from re-entering for `revocation_duration`. Returns the session info if
+    /// the session was the active contributor.
+    pub async fn revoke_session(&self, session_id: &SessionId) -> Option<SessionInfo> {
+        let key = session_id.key();
+        let mut state = self.inner.lock().await;
+
+        let active_contributor = match &state.active_contributor {
+            ActiveContributor::None => None,
+            ActiveContributor::AwaitingContribution { session: info, .. }
+            | ActiveContributor::Contributing(info) => (info.id == key).then(|| info.info.clone()),
+        };
+        if active_contributor.is_some() {
+            state.active_contributor = ActiveContributor::None;
+        }
+
+        state.sessions_in_lobby.remove(&key);
+        state.sessions_out_of_lobby.remove(&key);
+        state.revoked_sessions.insert(key, Instant::now());
+
+        active_contributor
+    }
+
+    /// Looks the session up in the lobby, outside of it and in the
+    /// contributor slot.
+    pub async fn session_status(&self, session_id: &SessionId) -> Option<SessionStatus> {
+        let key = session_id.key();
+        let state = self.inner.lock().await;
+        let status = |info: &SessionInfo, queue_position, active_contributor| SessionStatus {
+            identity: info.token.identity.clone(),
+            last_ping_secs_ago: info.last_ping_time.elapsed().as_secs(),
+            queue_position,
+            active_contributor,
+        };
+
+        match &state.active_contributor {
+            ActiveContributor::AwaitingContribution { session, .. }
+            | ActiveContributor::Contributing(session)
+                if session.id == key =>
+            {
+                return Some(status(&session.info, None, true));
+            }
+            _ => {}
+        }
+        if let Some(info) = state.sessions_in_lobby.get(&key) {
+            let position = state
+                .sessions_in_lobby
+                .values()
+                .filter(|other| other.lobby_entry < info.lobby_entry)
+                .count();
+            return Some(status(info, Some(position), false));
+        }
+        state
+            .sessions_out_of_lobby
+            .get(&key)
+            .map(|info| status(info, None, false))
+    }
+
+    /// Removes the session from the lobby and, if it is the active
+    /// contributor, frees the contributor slot. Unlike
+    /// [`Self::revoke_session`], the session may sign in again right away.
+    /// Returns the session info and whether it was the active contributor, or
+    /// `None` if there is no such session.
+    pub async fn evict_session(&self, session_id: &SessionId) -> Option<(SessionInfo, bool)> {
+        let key = session_id.key();
+        let mut state = self.inner.lock().await;
+
+        let active_contributor = match &state.active_contributor {
+            ActiveContributor::None => None,
+            ActiveContributor::AwaitingContribution { session: info, .. }
+            | ActiveContributor::Contributing(info) => (info.id == key).then(|| info.info.clone()),
+        };
+        if let Some(info) = active_contributor {
+            state.active_contributor = ActiveContributor::None;
+            state.sessions_in_lobby.remove(&key);
+            state.sessions_out_of_lobby.remove(&key);
+            return Some((info, true));
+        }
+
+        state
+            .sessions_in_lobby
+            .remove(&key)
+            .or_else(|| state.sessions_out_of_lobby.remove(&key))
+            .map(|info| (info, false))
+    }
+
+    /// Removes the session from the lobby, unless it is the active
+    /// contributor, for it to be replaced by a fresh one.
+    pub async fn remove_idle_session(
+        &self,
+        session_id: &SessionId,
+    ) -> Result<(), ActiveContributorError> {
+        let key = session_id.key();
+        let mut state = self.inner.lock().await;
+
+        let is_active_contributor = match &state.active_contributor {
+            ActiveContributor::None => false,
+            ActiveContributor::AwaitingContribution { session: info, .. }
+            | ActiveContributor::Contributing(info) => info.id == key,
+        };
+        if is_active_contributor {
+            return Err(ActiveContributorError::AnotherContributionInProgress);
+        }
+
+        state.sessions_in_lobby.remove(&key);
+        state.sessions_out_of_lobby.remove(&key);
+        Ok(())
+    }
+
+    pub async fn ensure_not_revoked(
+        &self,
+        session_id: &SessionId,
+    ) -> Result<(), ActiveContributorError> {
+        let state = self.inner.lock().await;
+        match state.revoked_sessions.get(&session_id.key()) {
+            Some(revoked_at) if revoked_at.elapsed() <= self.options.revocation_duration => {
+                Err(ActiveContributorError::SessionRevoked)
+            }
+            _ => Ok(()),
+        }
+    }
+
     pub async fn modify_participant<R>(
         &self,
         session_id: &SessionId,
         fun: impl FnOnce(&mut SessionInfo) -> R + Send,
     ) -> Option<R> {
+        let key = session_id.key();
         let mut lobby_state = self.inner.lock().await;
-        if let Some(lobby_session) = lobby_state.sessions_in_lobby.get_mut(session_id) {
+        if let Some(lobby_session) = lobby_state.sessions_in_lobby.get_mut(&key) {
             return Some(fun(lobby_session));
         }
-        lobby_state
-            .sessions_out_of_lobby
-            .get_mut(session_id)
-            .map(fun)
+        lobby_state.sessions_out_of_lobby.get_mut(&key).map(fun)
     }
 
     pub async fn get_lobby_size(&self) -> usize {
@@ -245,46 +777,174 @@ impl SharedLobbyState {
         session_id: SessionId,
         session_info: SessionInfo,
     ) -> Result<(), ActiveContributorError> {
+        let key = session_id.key();
         let mut state = self.inner.lock().await;
 
         let is_active_contributor = match &state.active_contributor {
             ActiveContributor::None => false,
             ActiveContributor::AwaitingContribution { session: info, .. }
-            | ActiveContributor::Contributing(info) => info.id == session_id,
+            | ActiveContributor::Contributing(info) => info.id == key,
         };
-        let is_in_lobby = state.sessions_in_lobby.contains_key(&session_id);
+        let is_in_lobby = state.sessions_in_lobby.contains_key(&key);
 
         if is_active_contributor || is_in_lobby {
             return Ok(());
         }
 
         let sessions = &mut state.sessions_out_of_lobby;
-        if sessions.len() >= self.options.max_sessions_count && !sessions.contains_key(&session_id)
-        {
+        if sessions.len() >= self.options.max_sessions_count && !sessions.contains_key(&key) {
             return Err(ActiveContributorError::SessionCountLimitExceeded);
         }
-        sessions.insert(session_id, session_info);
+        sessions.insert(key, session_info);
 
         Ok(())
     }
 
     pub async fn enter_lobby(&self, session_id: &SessionId) -> Result<(), ActiveContributorError> {
+        let key = session_id.key();
         let mut state = self.inner.lock().await;
 
+        if self.options.pow_difficulty > 0
+            && matches!(state.sessions_out_of_lobby.get(&key), Some(session) if !session.pow_solved)
+        {
+            return Err(ActiveContributorError::ProofOfWorkRequired);
+        }
+        if self.options.require_intent_signature
+            && state
+                .sessions_out_of_lobby
+                .get(&key)
+                .map_or(false, |session| {
+                    !session.intent_signed && intent::applies_to(&session.token.identity)
+                })
+        {
+            return Err(ActiveContributorError::IntentSignatureRequired);
+        }
+
+        if let Some(session) = state.sessions_out_of_lobby.get(&key) {
+            let identity = session.token.identity.unique_id();
+            if let Some(started) = state.cooldowns.get(&identity) {
+                let elapsed = started.elapsed();
+                if elapsed < self.options.contribution_cooldown {
+                    return Err(ActiveContributorError::CooldownActive(
+                        self.options.contribution_cooldown - elapsed,
+                    ));
+                }
+                state.cooldowns.remove(&identity);
+            }
+        }
+
         // If session is not in sessions_out_of_lobby, it was already moved to lobby or
         // to active contributor state
-        if let Some(session) = state.sessions_out_of_lobby.remove(session_id) {
-            let lobby = &mut state.sessions_in_lobby;
-
-            if lobby.len() >= self.options.max_lobby_size {
+        if let Some(mut session) = state.sessions_out_of_lobby.remove(&key) {
+            if state.sessions_in_lobby.len() >= self.options.max_lobby_size {
                 return Err(ActiveContributorError::LobbySizeLimitExceeded);
             }
-            lobby.insert(session_id.clone(), session);
+            session.lobby_entry = state.next_lobby_entry;
+            state.next_lobby_entry += 1;
+            state.sessions_in_lobby.insert(key, session);
         }
 
         Ok(())
     }
 
+    /// Takes a [`LobbySnapshot`] of the current state.
+    pub async fn snapshot(&self) -> LobbySnapshot {
+        let state = self.inner.lock().await;
+        let active_contributor = match &state.active_contributor {
+            ActiveContributor::None => None,
+            ActiveContributor::AwaitingContribution {
+                session,
+                deadline,
+                extensions,
+                ..
+            } => Some(ActiveContributorSnapshot {
+                session: SessionSnapshot::new(&session.id, &session.info),
+                deadline: Some(
+                    (unix_now() + deadline.saturating_duration_since(Instant::now())).as_secs(),
+                ),
+                extensions: *extensions,
+            }),
+            ActiveContributor::Contributing(session) => Some(ActiveContributorSnapshot {
+                session: SessionSnapshot::new(&session.id, &session.info),
+                deadline: None,
+                extensions: 0,
+            }),
+        };
+        let mut revoked_sessions: Vec<_> = state.revoked_sessions.keys().cloned().collect();
+        revoked_sessions.sort();
+        LobbySnapshot {
+            sessions_in_lobby: sorted_sessions(state.sessions_in_lobby.iter()),
+            sessions_out_of_lobby: sorted_sessions(state.sessions_out_of_lobby.iter()),
+            active_contributor,
+            revoked_sessions,
+            next_lobby_entry: state.next_lobby_entry,
+            paused: state.paused,
+        }
+    }
+
+    /// Restores a snapshot taken by a previous process. Sessions count as
+    /// just pinged. An active contributor whose deadline passed is dropped,
+    /// one whose upload was being verified gets a fresh deadline to upload
+    /// again. Whether the ceremony is paused is not restored, storage keeps
+    /// track of it.
+    pub async fn restore(&self, snapshot: LobbySnapshot, storage: PersistentStorage) {
+        let mut state = self.inner.lock().await;
+        let now = Instant::now();
+
+        state.sessions_in_lobby = snapshot
+            .sessions_in_lobby
+            .into_iter()
+            .map(|session| session.into_session(now))
+            .collect();
+        state.sessions_out_of_lobby = snapshot
+            .sessions_out_of_lobby
+            .into_iter()
+            .map(|session| session.into_session(now))
+            .collect();
+        state.revoked_sessions = snapshot
+            .revoked_sessions
+            .into_iter()
+            .map(|id| (id, now))
+            .collect();
+        state.next_lobby_entry = snapshot.next_lobby_entry;
+        state.selected = None;
+        state.active_contributor = ActiveContributor::None;
+
+        let active = match snapshot.active_contributor {
+            Some(active) => active,
+            None => return,
+        };
+        let time_left = match active.deadline {
+            Some(deadline) => Duration::from_secs(deadline).checked_sub(unix_now()),
+            None => Some(self.options.compute_deadline + self.options.upload_grace),
+        };
+        let time_left = match time_left.filter(|time_left| !time_left.is_zero()) {
+            Some(time_left) => time_left,
+            None => {
+                info!(session_key = %active.session.session_key, "Dropping expired contributor slot");
+                return;
+            }
+        };
+        let (id, info) = active.session.into_session(now);
+        let deadline = now + time_left;
+        state.active_contributor = ActiveContributor::AwaitingContribution {
+            session: SessionInfoWithId {
+                id: id.clone(),
+                info,
+            },
+            last_contribution_file_request: now,
+            deadline,
+            extensions: active.extensions,
+        };
+        tokio::spawn(Self::expire_current_contributor(
+            self.inner.clone(),
+            id,
+            deadline,
+            self.options.contribution_cooldown,
+            storage,
+        ));
+    }
+
     #[cfg(test)]
     pub async fn get_all_participants(&self) -> Vec<SessionInfoWithId> {
         self.inner
@@ -301,20 +961,62 @@ impl SharedLobbyState {
 
     async fn expire_current_contributor(
         inner: Arc<Mutex<LobbyState>>,
-        participant: SessionId,
-        compute_deadline: Duration,
+        participant: SessionKey,
+        mut deadline: Instant,
+        cooldown: Duration,
         storage: PersistentStorage,
     ) {
-        tokio::time::sleep(compute_deadline).await;
-
-        let mut state = inner.lock().await;
-
-        if matches!(&state.active_contributor, ActiveContributor::AwaitingContribution{ session: x, .. } if x.id == participant)
-        {
+        loop {
+            tokio::time::sleep_until(deadline).await;
+
+            let mut state = inner.lock().await;
+            let identity = match &state.active_contributor {
+                ActiveContributor::AwaitingContribution {
+                    session,
+                    deadline: current,
+                    ..
+                } if session.id == participant => {
+                    // Heartbeats moved the deadline while we were sleeping.
+                    if *current > deadline {
+                        deadline = *current;
+                        continue;
+                    }
+                    session.info.token.identity.unique_id()
+                }
+                _ => return,
+            };
+            state.start_cooldown(identity.clone(), cooldown);
             state.active_contributor = ActiveContributor::None;
 
             drop(state);
-            storage.expire_contribution(&participant.0).await.unwrap();
+            storage.expire_contribution(&identity).await.unwrap();
+            return;
+        }
+    }
+
+    /// Extends the compute deadline of the active contributor by
+    /// `heartbeat_extension`, at most `max_heartbeat_extensions` times.
+    /// Returns the time left until the deadline.
+    pub async fn heartbeat(
+        &self,
+        session_id: &SessionId,
+    ) -> Result<Duration, ActiveContributorError> {
+        let key = session_id.key();
+        let mut state = self.inner.lock().await;
+        match &mut state.active_contributor {
+            ActiveContributor::AwaitingContribution {
+                session,
+                deadline,
+                extensions,
+                ..
+            } if session.id == key => {
+                if *extensions < self.options.max_heartbeat_extensions {
+                    *extensions += 1;
+                    *deadline += self.options.heartbeat_extension;
+                }
+                Ok(deadline.saturating_duration_since(Instant::now()))
+            }
+            _ => Err(ActiveContributorError::NotActiveContributor),
         }
     }
 
@@ -322,15 +1024,20 @@ impl SharedLobbyState {
         &self,
         session_id: &SessionId,
     ) -> Result<(), ActiveContributorError> {
+        let key = session_id.key();
         let mut lobby_state = self.inner.lock().await;
         if let ActiveContributor::AwaitingContribution {
             session,
             last_contribution_file_request,
+            ..
         } = &mut lobby_state.active_contributor
         {
-            if &session.id == session_id {
-                if last_contribution_file_request.elapsed() < self.options.min_checkin_delay() {
-                    return Err(ActiveContributorError::RateLimited);
+            if session.id == key {
+                let elapsed = last_contribution_file_request.elapsed();
+                if elapsed < self.options.min_checkin_delay() {
+                    return Err(ActiveContributorError::RateLimited(
+                        self.options.min_checkin_delay() - elapsed,
+                    ));
                 }
                 *last_contribution_file_request = Instant::now();
                 return Ok(());
@@ -340,6 +1047,37 @@ impl SharedLobbyState {
     }
 }
 
+/// Persists a [`LobbySnapshot`] every `interval`, so that it can be restored
+/// after a restart. The write is skipped while the lobby is unchanged.
+pub async fn persist_lobby_on_interval(
+    state: SharedLobbyState,
+    storage: PersistentStorage,
+    interval: Duration,
+) {
+    let mut last = Vec::new();
+    let mut interval = tokio::time::interval(interval);
+
+    loop {
+        interval.tick().await;
+
+        let snapshot = state.snapshot().await.to_canonical_json();
+        if snapshot == last {
+            continue;
+        }
+        let json = String::from_utf8(snapshot.clone()).expect("JSON is valid UTF-8");
+        match storage.save_lobby(&json).await {
+            Ok(()) => last = snapshot,
+            Err(e) => error!("failed to persist lobby: {}", e),
+        }
+    }
+}
+
+pub fn unix_now() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
 pub async fn clear_lobby_on_interval(state: SharedLobbyState, options: Options) {
     let max_lobby_diff = options.lobby_checkin_frequency + options.lobby_checkin_tolerance;
     let max_session_diff = options.session_expiration;
@@ -362,6 +1100,9 @@ pub async fn clear_lobby_on_interval(state: SharedLobbyState, options: Options)
             time_diff > max_session_diff
         };
         state.clear_session(session_predicate).await;
+
+        state.clear_revocations(now).await;
+        state.clear_cooldowns(now).await;
     }
 }
 
@@ -414,3 +1155,455 @@ async fn flush_on_predicate() {
         assert_eq!(participant.info.token.exp % 2, 1);
     }
 }
+
+#[test]
+fn validate_options() {
+    use crate::test_util::test_options;
+
+    let options = test_options().lobby;
+    assert_eq!(options.validate(), Ok(()));
+
+    let mut invalid = options.clone();
+    invalid.compute_deadline = Duration::ZERO;
+    assert_eq!(invalid.validate(), Err(OptionsError::ZeroComputeDeadline));
+
+    let mut invalid = options.clone();
+    invalid.max_lobby_size = 0;
+    assert_eq!(invalid.validate(), Err(OptionsError::ZeroLobbySize));
+
+    let mut invalid = options.clone();
+    invalid.max_sessions_count = 0;
+    assert_eq!(invalid.validate(), Err(OptionsError::ZeroSessionsCount));
+
+    let mut invalid = options.clone();
+    invalid.lobby_flush_interval = Duration::ZERO;
+    assert_eq!(invalid.validate(), Err(OptionsError::ZeroFlushInterval));
+
+    let mut invalid = options;
+    invalid.compute_deadline = Duration::from_secs(10);
+    invalid.lobby_checkin_frequency = Duration::from_secs(30);
+    invalid.lobby_checkin_tolerance = Duration::from_secs(2);
+    assert_eq!(
+        invalid.validate(),
+        Err(OptionsError::CheckinDelayExceedsComputeDeadline {
+            min_checkin_delay: Duration::from_secs(28),
+            compute_deadline: Duration::from_secs(10),
+        })
+    );
+}
+
+#[cfg(test)]
+async fn enter_lobby_in_order(state: &SharedLobbyState, ids: &[&str]) {
+    use crate::test_util::create_test_session_info;
+
+    for id in ids {
+        let id = SessionId((*id).to_string());
+        state
+            .insert_session(id.clone(), create_test_session_info(100))
+            .await
+            .unwrap();
+        state.enter_lobby(&id).await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn fifo_selection_preserves_entry_order() {
+    use crate::{storage::storage_client, test_util::test_options};
+
+    let mut options = test_options();
+    options.lobby.selection_policy = SelectionPolicy::Fifo;
+    let storage = storage_client(&options.storage).await.unwrap();
+    let state = SharedLobbyState::new(options.lobby.clone());
+    // Entry order differs from the order of the session ids.
+    enter_lobby_in_order(&state, &["c", "a", "b"]).await;
+
+    for expected in ["c", "a", "b"] {
+        for id in ["a", "b", "c"].into_iter().filter(|id| *id != expected) {
+            let result = state
+                .set_current_contributor(
+                    &SessionId(id.to_string()),
+                    options.lobby.compute_deadline,
+                    storage.clone(),
+                )
+                .await;
+            assert!(matches!(
+                result,
+                Err(ActiveContributorError::NotUsersTurn | ActiveContributorError::UserNotInLobby)
+            ));
+        }
+        state
+            .set_current_contributor(
+                &SessionId(expected.to_string()),
+                options.lobby.compute_deadline,
+                storage.clone(),
+            )
+            .await
+            .unwrap();
+        state.clear_current_contributor().await;
+    }
+}
+
+#[tokio::test]
+async fn first_come_selection_admits_any_participant() {
+    use crate::{storage::storage_client, test_util::test_options};
+
+    let options = test_options();
+    assert_eq!(options.lobby.selection_policy, SelectionPolicy::FirstCome);
+    let storage = storage_client(&options.storage).await.unwrap();
+    let state = SharedLobbyState::new(options.lobby.clone());
+    enter_lobby_in_order(&state, &["a", "b"]).await;
+
+    // The head of the lobby doesn't hold up the others.
+    state
+        .set_current_contributor(
+            &SessionId("b".to_string()),
+            options.lobby.compute_deadline,
+            storage,
+        )
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn random_selection_can_pick_any_participant() {
+    use crate::test_util::test_options;
+
+    let mut options = test_options().lobby;
+    options.selection_policy = SelectionPolicy::Random;
+
+    let mut selections = Vec::new();
+    for seed in 0..16 {
+        let state = SharedLobbyState::with_rng(options.clone(), StdRng::seed_from_u64(seed));
+        enter_lobby_in_order(&state, &["a", "b", "c"]).await;
+        let mut inner = state.inner.lock().await;
+        let selected = inner.next_contributor(SelectionPolicy::Random).unwrap();
+        // The selection is stable until the participant claims the slot.
+        assert_eq!(
+            inner.next_contributor(SelectionPolicy::Random),
+            Some(selected.clone())
+        );
+        selections.push(selected);
+    }
+    let first = SessionId("a".to_string()).key();
+    assert!(selections.iter().any(|key| *key != first));
+
+    // The same seed reproduces the same selection.
+    let state = SharedLobbyState::with_rng(options, StdRng::seed_from_u64(0));
+    enter_lobby_in_order(&state, &["a", "b", "c"]).await;
+    assert_eq!(
+        state
+            .inner
+            .lock()
+            .await
+            .next_contributor(SelectionPolicy::Random),
+        Some(selections[0].clone())
+    );
+}
+
+#[tokio::test]
+async fn heartbeat_extends_deadline_up_to_cap() {
+    use crate::{storage::storage_client, test_util::test_options};
+
+    let mut options = test_options();
+    options.lobby.compute_deadline = Duration::from_secs(60);
+    options.lobby.heartbeat_extension = Duration::from_secs(30);
+    options.lobby.max_heartbeat_extensions = 2;
+    options.lobby.upload_grace = Duration::ZERO;
+    // Don't rate limit the liveness checks below.
+    options.lobby.lobby_checkin_frequency = Duration::ZERO;
+    let storage = storage_client(&options.storage).await.unwrap();
+    let state = SharedLobbyState::new(options.lobby.clone());
+    enter_lobby_in_order(&state, &["a", "b"]).await;
+    let active = SessionId("a".to_string());
+
+    tokio::time::pause();
+    state
+        .set_current_contributor(&active, options.lobby.compute_deadline, storage)
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        state.heartbeat(&SessionId("b".to_string())).await,
+        Err(ActiveContributorError::NotActiveContributor)
+    ));
+
+    assert_eq!(
+        state.heartbeat(&active).await.unwrap(),
+        Duration::from_secs(90)
+    );
+    assert_eq!(
+        state.heartbeat(&active).await.unwrap(),
+        Duration::from_secs(120)
+    );
+    // The cap is reached, further heartbeats don't move the deadline.
+    assert_eq!(
+        state.heartbeat(&active).await.unwrap(),
+        Duration::from_secs(120)
+    );
+
+    // Still active after the original deadline.
+    tokio::time::sleep(Duration::from_secs(90)).await;
+    state
+        .request_contribution_file_again(&active)
+        .await
+        .unwrap();
+
+    // But expired after the extended one.
+    tokio::time::sleep(Duration::from_secs(31)).await;
+    assert!(matches!(
+        state.request_contribution_file_again(&active).await,
+        Err(ActiveContributorError::NotActiveContributor)
+    ));
+}
+
+#[tokio::test]
+async fn contribution_accepted_within_upload_grace() {
+    use crate::{storage::storage_client, test_util::test_options};
+
+    let mut options = test_options();
+    options.lobby.compute_deadline = Duration::from_secs(60);
+    options.lobby.upload_grace = Duration::from_secs(10);
+    let storage = storage_client(&options.storage).await.unwrap();
+    let state = SharedLobbyState::new(options.lobby.clone());
+    enter_lobby_in_order(&state, &["a", "b"]).await;
+
+    let (first, second) = (SessionId("a".to_string()), SessionId("b".to_string()));
+
+    tokio::time::pause();
+    state
+        .set_current_contributor(&first, options.lobby.compute_deadline, storage.clone())
+        .await
+        .unwrap();
+    // Past the compute deadline, but within the grace period.
+    tokio::time::sleep(Duration::from_secs(65)).await;
+    state.begin_contributing(&first).await.unwrap();
+    state.clear_current_contributor().await;
+
+    state
+        .set_current_contributor(&second, options.lobby.compute_deadline, storage)
+        .await
+        .unwrap();
+    // Past the grace period the slot is reclaimed.
+    tokio::time::sleep(Duration::from_secs(71)).await;
+    assert!(matches!(
+        state.begin_contributing(&second).await,
+        Err(ActiveContributorError::NotUsersTurn)
+    ));
+}
+
+#[tokio::test]
+async fn cooldown_after_abort() {
+    use crate::{
+        storage::storage_client,
+        test_util::{create_test_session_info, test_options},
+    };
+
+    let mut options = test_options();
+    options.lobby.contribution_cooldown = Duration::from_secs(60);
+    let storage = storage_client(&options.storage).await.unwrap();
+    let state = SharedLobbyState::new(options.lobby.clone());
+    enter_lobby_in_order(&state, &["a"]).await;
+    let aborted = SessionId("a".to_string());
+
+    tokio::time::pause();
+    state
+        .set_current_contributor(&aborted, options.lobby.compute_deadline, storage)
+        .await
+        .unwrap();
+    state.abort_contribution(&aborted).await.unwrap();
+
+    // The same identity signs in again with a new session.
+    let rejoin = SessionId("a2".to_string());
+    state
+        .insert_session(rejoin.clone(), create_test_session_info(100))
+        .await
+        .unwrap();
+    tokio::time::advance(Duration::from_secs(20)).await;
+    assert!(matches!(
+        state.enter_lobby(&rejoin).await,
+        Err(ActiveContributorError::CooldownActive(retry_after)) if retry_after == Duration::from_secs(40)
+    ));
+
+    // Other identities are not affected.
+    let other = SessionId("b".to_string());
+    let mut info = create_test_session_info(100);
+    info.token.identity = Identity::Github {
+        id: 2,
+        username: "other".to_string(),
+    };
+    state.insert_session(other.clone(), info).await.unwrap();
+    state.enter_lobby(&other).await.unwrap();
+
+    tokio::time::advance(Duration::from_secs(40)).await;
+    state.enter_lobby(&rejoin).await.unwrap();
+    assert_eq!(state.get_lobby_size().await, 2);
+}
+
+#[tokio::test]
+async fn cooldown_after_abort_while_contributing() {
+    use crate::{storage::storage_client, test_util::test_options};
+
+    let mut options = test_options();
+    options.lobby.contribution_cooldown = Duration::from_secs(60);
+    let storage = storage_client(&options.storage).await.unwrap();
+    let state = SharedLobbyState::new(options.lobby.clone());
+    enter_lobby_in_order(&state, &["a"]).await;
+    let aborted = SessionId("a".to_string());
+
+    state
+        .set_current_contributor(&aborted, options.lobby.compute_deadline, storage)
+        .await
+        .unwrap();
+    state.begin_contributing(&aborted).await.unwrap();
+    state.abort_contribution(&aborted).await.unwrap();
+
+    assert!(!state.release_contributor(&aborted).await);
+    assert_eq!(state.inner.lock().await.cooldowns.len(), 1);
+}
+
+#[tokio::test]
+async fn no_cooldown_when_disabled() {
+    use crate::{storage::storage_client, test_util::test_options};
+
+    let mut options = test_options();
+    options.lobby.contribution_cooldown = Duration::ZERO;
+    let storage = storage_client(&options.storage).await.unwrap();
+    let state = SharedLobbyState::new(options.lobby.clone());
+    enter_lobby_in_order(&state, &["a", "b"]).await;
+    let aborted = SessionId("a".to_string());
+    let failed = SessionId("b".to_string());
+
+    state
+        .set_current_contributor(&aborted, options.lobby.compute_deadline, storage.clone())
+        .await
+        .unwrap();
+    state.abort_contribution(&aborted).await.unwrap();
+    state
+        .set_current_contributor(&failed, options.lobby.compute_deadline, storage)
+        .await
+        .unwrap();
+    assert!(state.fail_contributor(&failed).await);
+
+    assert!(state.inner.lock().await.cooldowns.is_empty());
+}
+
+#[tokio::test]
+async fn expired_cooldowns_are_pruned() {
+    use crate::{storage::storage_client, test_util::test_options};
+
+    let mut options = test_options();
+    options.lobby.contribution_cooldown = Duration::from_secs(60);
+    let storage = storage_client(&options.storage).await.unwrap();
+    let state = SharedLobbyState::new(options.lobby.clone());
+    enter_lobby_in_order(&state, &["a"]).await;
+    let failed = SessionId("a".to_string());
+
+    tokio::time::pause();
+    state
+        .set_current_contributor(&failed, options.lobby.compute_deadline, storage)
+        .await
+        .unwrap();
+    assert!(state.fail_contributor(&failed).await);
+
+    state.clear_cooldowns(Instant::now()).await;
+    assert_eq!(state.inner.lock().await.cooldowns.len(), 1);
+
+    tokio::time::advance(Duration::from_secs(60)).await;
+    state.clear_cooldowns(Instant::now()).await;
+    assert!(state.inner.lock().await.cooldowns.is_empty());
+}
+
+#[tokio::test]
+async fn snapshot_is_deterministic() {
+    use crate::test_util::{create_test_session_info, test_options};
+
+    // Same sessions, inserted in different orders.
+    let first = SharedLobbyState::new(test_options().lobby);
+    let second = SharedLobbyState::new(test_options().lobby);
+    for (state, ids) in [
+        (&first, ["a", "b", "c", "d"]),
+        (&second, ["d", "c", "b", "a"]),
+    ] {
+        for id in ids {
+            let id = SessionId(id.to_string());
+            let mut info = create_test_session_info(100);
+            info.token.identity = Identity::Github {
+                id: 1,
+                username: id.0.clone(),
+            };
+            state.insert_session(id, info).await.unwrap();
+        }
+        state.revoke_session(&SessionId("d".to_string())).await;
+        state.revoke_session(&SessionId("c".to_string())).await;
+    }
+    for state in [&first, &second] {
+        state
+            .enter_lobby(&SessionId("a".to_string()))
+            .await
+            .unwrap();
+    }
+
+    let snapshot = first.snapshot().await;
+    assert_eq!(snapshot, second.snapshot().await);
+    assert_eq!(
+        snapshot.to_canonical_json(),
+        second.snapshot().await.to_canonical_json()
+    );
+    assert_eq!(
+        snapshot.to_canonical_json(),
+        first.snapshot().await.to_canonical_json()
+    );
+    let mut revoked = vec![
+        SessionId("c".to_string()).key(),
+        SessionId("d".to_string()).key(),
+    ];
+    revoked.sort();
+    assert_eq!(snapshot.revoked_sessions, revoked);
+    assert_eq!(snapshot.sessions_in_lobby.len(), 1);
+    assert_eq!(snapshot.sessions_out_of_lobby.len(), 1);
+}
+
+#[tokio::test]
+async fn restore_persisted_lobby() {
+    use crate::{storage::storage_client, test_util::test_options};
+
+    let mut options = test_options();
+    options.lobby.compute_deadline = Duration::from_secs(60);
+    options.lobby.upload_grace = Duration::ZERO;
+    let storage = storage_client(&options.storage).await.unwrap();
+    let state = SharedLobbyState::new(options.lobby.clone());
+    enter_lobby_in_order(&state, &["a", "b"]).await;
+    let active = SessionId("a".to_string());
+    state
+        .set_current_contributor(&active, options.lobby.compute_deadline, storage.clone())
+        .await
+        .unwrap();
+
+    let json = String::from_utf8(state.snapshot().await.to_canonical_json()).unwrap();
+    // Only the session keys are persisted, not the bearer tokens.
+    assert!(!json.contains("\"a\""));
+    assert!(json.contains(&active.key().to_string()));
+    storage.save_lobby(&json).await.unwrap();
+
+    // Restart with the persisted lobby, the active contributor keeps its slot.
+    let loaded: LobbySnapshot =
+        serde_json::from_str(&storage.load_lobby().await.unwrap().unwrap()).unwrap();
+    let restored = SharedLobbyState::new(options.lobby.clone());
+    restored.restore(loaded.clone(), storage.clone()).await;
+    assert_eq!(restored.snapshot().await.sessions_in_lobby.len(), 1);
+    restored
+        .request_contribution_file_again(&active)
+        .await
+        .unwrap();
+
+    // A claim that expired while the sequencer was down is dropped.
+    let mut expired = loaded;
+    expired.active_contributor.as_mut().unwrap().deadline = Some(0);
+    let restored = SharedLobbyState::new(options.lobby.clone());
+    restored.restore(expired, storage).await;
+    assert!(restored.snapshot().await.active_contributor.is_none());
+    assert!(matches!(
+        restored.request_contribution_file_again(&active).await,
+        Err(ActiveContributorError::NotActiveContributor)
+    ));
+}
@@ -0,0 +1,164 @@
+//! Signed, expiring lobby entry tokens. They are issued at sign-in, so that
+//! users can authenticate early and enter the lobby later with a fresh
+//! session, instead of keeping the sign-in session alive until then.
+//!
+//! A token is the base64url encoded JSON of [`LobbyEntryToken`], a `.` and the
+//! hex encoded signature of [`LOBBY_TOKEN_DOMAIN`] followed by the encoded
+//! part, by the sequencer key. The domain keeps receipts and other messages
+//! signed by the same key from passing as tokens. Every token can only be
+//! redeemed once, see [`crate::oauth::AuthState::redeem_lobby_token`].
+
+use crate::{
+    keys::{Keys, Signature, SignatureError},
+    lobby::unix_now,
+};
+use kzg_ceremony_crypto::signature::identity::Identity;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum LobbyTokenError {
+    #[error("malformed or tampered lobby entry token")]
+    Invalid,
+    #[error("lobby entry token expired")]
+    Expired,
+    #[error("lobby entry token already used")]
+    Used,
+}
+
+/// Prefix of every signed lobby entry token payload.
+pub const LOBBY_TOKEN_DOMAIN: &str = "kzg-ceremony-sequencer/lobby-entry-token/v1:";
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LobbyEntryToken {
+    /// Random id, recorded when the token is redeemed.
+    pub id: String,
+    pub identity: Identity,
+    /// Unix time in seconds from which the token is rejected.
+    pub exp: u64,
+}
+
+impl LobbyEntryToken {
+    #[must_use]
+    pub fn new(identity: Identity, ttl: Duration) -> Self {
+        Self {
+            id: hex::encode(rand::random::<[u8; 16]>()),
+            identity,
+            exp: (unix_now() + ttl).as_secs(),
+        }
+    }
+
+    pub async fn sign(&self, keys: &Keys) -> Result<String, SignatureError> {
+        let json = serde_json::to_vec(self).map_err(|_| SignatureError::SignatureCreation)?;
+        let payload = base64::encode_config(json, base64::URL_SAFE_NO_PAD);
+        let signature = keys.sign(&format!("{LOBBY_TOKEN_DOMAIN}{payload}")).await?;
+        Ok(format!("{payload}.{}", signature.as_str()))
+    }
+
+    /// Checks the signature and expiry of `token`.
+    pub fn verify(token: &str, keys: &Keys) -> Result<Self, LobbyTokenError> {
+        let (payload, signature) = token.split_once('.').ok_or(LobbyTokenError::Invalid)?;
+        keys.verify(
+            &format!("{LOBBY_TOKEN_DOMAIN}{payload}"),
+            &Signature::from_hex(signature),
+        )
+        .map_err(|_| LobbyTokenError::Invalid)?;
+        let json = base64::decode_config(payload, base64::URL_SAFE_NO_PAD)
+            .map_err(|_| LobbyTokenError::Invalid)?;
+        let token = serde_json::from_slice::<Self>(&json).map_err(|_| LobbyTokenError::Invalid)?;
+        if unix_now().as_secs() >= token.exp {
+            return Err(LobbyTokenError::Expired);
+        }
+        Ok(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys;
+    use clap::Parser;
+
+    fn test_keys() -> Keys {
+        Keys::new(&keys::Options::parse_from(Vec::<&str>::new())).unwrap()
+    }
+
+    fn identity() -> Identity {
+        Identity::Github {
+            id: 1234,
+            username: "test_user".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn valid_token() {
+        let keys = test_keys();
+        let token = LobbyEntryToken::new(identity(), Duration::from_secs(60));
+        let signed = token.sign(&keys).await.unwrap();
+        assert_eq!(LobbyEntryToken::verify(&signed, &keys), Ok(token));
+    }
+
+    #[tokio::test]
+    async fn expired_token() {
+        let keys = test_keys();
+        let token = LobbyEntryToken {
+            exp: unix_now().as_secs() - 1,
+            ..LobbyEntryToken::new(identity(), Duration::ZERO)
+        };
+        let signed = token.sign(&keys).await.unwrap();
+        assert_eq!(
+            LobbyEntryToken::verify(&signed, &keys),
+            Err(LobbyTokenError::Expired)
+        );
+    }
+
+    #[tokio::test]
+    async fn tampered_token() {
+        let keys = test_keys();
+        let token = LobbyEntryToken::new(identity(), Duration::from_secs(60));
+        let signed = token.sign(&keys).await.unwrap();
+        let (_, signature) = signed.split_once('.').unwrap();
+
+        let forged = LobbyEntryToken {
+            identity: Identity::Github {
+                id: 5678,
+                username: "someone_else".to_string(),
+            },
+            ..token.clone()
+        };
+        let payload = base64::encode_config(
+            serde_json::to_vec(&forged).unwrap(),
+            base64::URL_SAFE_NO_PAD,
+        );
+        assert_eq!(
+            LobbyEntryToken::verify(&format!("{payload}.{signature}"), &keys),
+            Err(LobbyTokenError::Invalid)
+        );
+
+        // Signed by another sequencer.
+        assert_eq!(
+            LobbyEntryToken::verify(&signed, &test_keys()),
+            Err(LobbyTokenError::Invalid)
+        );
+        assert_eq!(
+            LobbyEntryToken::verify("not a token", &keys),
+            Err(LobbyTokenError::Invalid)
+        );
+    }
+
+    #[tokio::test]
+    async fn signature_without_domain() {
+        let keys = test_keys();
+        let token = LobbyEntryToken::new(identity(), Duration::from_secs(60));
+        let signed = token.sign(&keys).await.unwrap();
+        let (payload, _) = signed.split_once('.').unwrap();
+
+        // The plain payload signed like a receipt is not a token.
+        let signature = keys.sign(payload).await.unwrap();
+        assert_eq!(
+            LobbyEntryToken::verify(&format!("{payload}.{}", signature.as_str()), &keys),
+            Err(LobbyTokenError::Invalid)
+        );
+    }
+}
@@ -1,7 +1,7 @@
-use crate::util::Secret;
+use crate::util::{duration_from_str, Secret};
 use clap::Parser;
 use oauth2::{basic::BasicClient, AuthUrl, ClientId, ClientSecret, RedirectUrl, TokenUrl};
-use std::{num::ParseIntError, ops::Deref};
+use std::{num::ParseIntError, ops::Deref, time::Duration};
 
 #[derive(Clone, Debug, PartialEq, Eq, Parser)]
 pub struct EthAuthOptions {
@@ -39,6 +39,16 @@ pub struct EthAuthOptions {
     )]
     pub eth_userinfo_url: String,
 
+    /// Timeout in seconds for a single attempt of the Sign-in-with-Ethereum token exchange and
+    /// the user info and JSON-RPC requests.
+    #[clap(long, env, value_parser=duration_from_str, default_value="10")]
+    pub eth_request_timeout: Duration,
+
+    /// Maximum size in bytes of a JSON-RPC response. Larger responses are
+    /// rejected instead of being read into memory.
+    #[clap(long, env, default_value = "65536")]
+    pub eth_rpc_max_response_size: usize,
+
     /// Sign-in-with-Ethereum OAuth2 callback redirect url.
     #[clap(long, env, default_value = "http://127.0.0.1:3000/auth/callback/eth")]
     pub eth_redirect_url: String,
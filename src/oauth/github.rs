@@ -1,8 +1,8 @@
-use crate::util::Secret;
+use crate::util::{duration_from_str, Secret};
 use chrono::{DateTime, FixedOffset};
 use clap::Parser;
 use oauth2::{basic::BasicClient, AuthUrl, ClientId, ClientSecret, RedirectUrl, TokenUrl};
-use std::ops::Deref;
+use std::{ops::Deref, time::Duration};
 
 #[derive(Clone, Debug, PartialEq, Eq, Parser)]
 pub struct GithubAuthOptions {
@@ -11,6 +11,16 @@ pub struct GithubAuthOptions {
     #[clap(long, env, default_value = "2025-01-16T00:00:00Z")]
     pub gh_max_account_creation_time: DateTime<FixedOffset>,
 
+    /// The minimum number of public repositories a Github account needs in
+    /// order to participate. Not checked if unset.
+    #[clap(long, env)]
+    pub gh_min_public_repos: Option<u64>,
+
+    /// The minimum number of followers a Github account needs in order to
+    /// participate. Not checked if unset.
+    #[clap(long, env)]
+    pub gh_min_followers: Option<u64>,
+
     /// Github OAuth2 authorization url.
     #[clap(long, env, default_value = "https://github.com/login/oauth/authorize")]
     pub gh_auth_url: String,
@@ -27,6 +37,11 @@ pub struct GithubAuthOptions {
     #[clap(long, env, default_value = "https://api.github.com/user")]
     pub gh_userinfo_url: String,
 
+    /// Timeout in seconds for a single attempt of the Github token exchange and
+    /// the user info requests.
+    #[clap(long, env, value_parser=duration_from_str, default_value="10")]
+    pub gh_request_timeout: Duration,
+
     /// Github OAuth2 callback redirect url.
     #[clap(
         long,
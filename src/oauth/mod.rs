@@ -1,13 +1,18 @@
 mod ethereum;
 mod github;
+mod retry;
+mod twitter;
 
-use crate::sessions::SessionId;
-use std::{collections::BTreeMap, sync::Arc};
-use tokio::sync::RwLock;
+use crate::{lobby::unix_now, sessions::SessionId, util::constant_time_eq};
+use oauth2::PkceCodeVerifier;
+use std::{collections::BTreeMap, net::IpAddr, sync::Arc, time::Duration};
+use tokio::{sync::RwLock, time::Instant};
 
 pub use self::{
     ethereum::{eth_oauth_client, EthAuthOptions, EthOAuthClient},
     github::{github_oauth_client, GithubAuthOptions, GithubOAuthClient},
+    retry::RetryOptions,
+    twitter::{twitter_oauth_client, TwitterAuthOptions, TwitterOAuthClient},
 };
 
 pub type SharedAuthState = Arc<RwLock<AuthState>>;
@@ -19,4 +24,193 @@ pub struct AuthState {
     // and their session.
     // We use this to check if a user has already entered the lobby
     pub unique_id_session: BTreeMap<IdTokenSub, SessionId>,
+    // Pending authorization requests, keyed by their CSRF state.
+    // A state can only be used once, to prevent CSRF and replay attacks.
+    auth_requests: BTreeMap<String, AuthRequest>,
+    // Authorization requests per client IP since the counters were last
+    // reset by `clear_auth_requests_on_interval`.
+    requests_per_ip: BTreeMap<IpAddr, u32>,
+    // Ids of the redeemed lobby entry tokens, with their expiry in Unix
+    // seconds. Kept until then, so that every token can only be used once.
+    redeemed_lobby_tokens: BTreeMap<String, u64>,
+}
+
+struct AuthRequest {
+    pkce_verifier: PkceCodeVerifier,
+    // Random value set as a cookie on the client that made the request. The
+    // callback has to come from the same client, so that nobody can sign a
+    // victim in with their own authorization code.
+    binding: String,
+    created_at: Instant,
+}
+
+/// Why [`AuthState::insert_auth_request`] refused a new request.
+#[derive(Debug, PartialEq, Eq)]
+pub enum AuthRequestRefused {
+    TooManyPending,
+    RateLimited,
+}
+
+/// How often expired authorization requests are dropped and the per-IP
+/// counters are reset.
+pub const AUTH_REQUEST_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+impl AuthState {
+    /// Records a new authorization request from `client_ip`, bound to the
+    /// client that holds `binding`, unless there are `max_pending` requests
+    /// already, or the IP made `max_per_ip` requests since the last sweep. A
+    /// `max_per_ip` of zero disables the per-IP limit.
+    pub fn insert_auth_request(
+        &mut self,
+        state: String,
+        pkce_verifier: PkceCodeVerifier,
+        binding: String,
+        client_ip: IpAddr,
+        max_pending: usize,
+        max_per_ip: u32,
+    ) -> Result<(), AuthRequestRefused> {
+        // Checked first, so that refused requests don't add counters.
+        if self.auth_requests.len() >= max_pending {
+            return Err(AuthRequestRefused::TooManyPending);
+        }
+        let count = self.requests_per_ip.entry(client_ip).or_default();
+        if max_per_ip > 0 && *count >= max_per_ip {
+            return Err(AuthRequestRefused::RateLimited);
+        }
+        *count += 1;
+        self.auth_requests.insert(
+            state,
+            AuthRequest {
+                pkce_verifier,
+                binding,
+                created_at: Instant::now(),
+            },
+        );
+        Ok(())
+    }
+
+    /// Drops the authorization requests older than `ttl`, the ids of expired
+    /// lobby entry tokens, and resets the per-IP counters.
+    pub fn clear_expired_auth_requests(&mut self, ttl: Duration) {
+        self.auth_requests
+            .retain(|_, request| request.created_at.elapsed() <= ttl);
+        self.requests_per_ip.clear();
+        let now = unix_now().as_secs();
+        self.redeemed_lobby_tokens.retain(|_, exp| *exp > now);
+    }
+
+    /// Records the lobby entry token `id`, which is valid until `exp`.
+    /// Returns `false` if it was redeemed before.
+    pub fn redeem_lobby_token(&mut self, id: &str, exp: u64) -> bool {
+        if self.redeemed_lobby_tokens.contains_key(id) {
+            return false;
+        }
+        self.redeemed_lobby_tokens.insert(id.to_string(), exp);
+        true
+    }
+
+    /// Removes the authorization request for `state` and returns its PKCE
+    /// verifier, or `None` if the state is unknown, expired or was issued to
+    /// a client that doesn't hold `binding`.
+    pub fn take_auth_request(
+        &mut self,
+        state: &str,
+        binding: Option<&str>,
+        ttl: Duration,
+    ) -> Option<PkceCodeVerifier> {
+        self.auth_requests
+            .remove(state)
+            .filter(|request| request.created_at.elapsed() <= ttl)
+            .filter(|request| binding.map_or(false, |b| constant_time_eq(&request.binding, b)))
+            .map(|request| request.pkce_verifier)
+    }
+}
+
+/// Calls [`AuthState::clear_expired_auth_requests`] every
+/// [`AUTH_REQUEST_SWEEP_INTERVAL`].
+pub async fn clear_auth_requests_on_interval(auth_state: SharedAuthState, ttl: Duration) {
+    let mut interval = tokio::time::interval(AUTH_REQUEST_SWEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+        auth_state.write().await.clear_expired_auth_requests(ttl);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(600);
+    const IP: IpAddr = IpAddr::V4(std::net::Ipv4Addr::LOCALHOST);
+
+    fn verifier() -> PkceCodeVerifier {
+        PkceCodeVerifier::new("verifier".to_string())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn auth_request_state() {
+        let mut state = AuthState::default();
+        for name in ["issued", "unbound", "stale"] {
+            state
+                .insert_auth_request(
+                    name.to_string(),
+                    verifier(),
+                    "cookie".to_string(),
+                    IP,
+                    10,
+                    0,
+                )
+                .unwrap();
+        }
+        let mut take = |name: &str, binding| state.take_auth_request(name, binding, TTL).is_some();
+
+        assert!(!take("mismatched", Some("cookie")));
+        assert!(take("issued", Some("cookie")));
+        // Every state can only be used once.
+        assert!(!take("issued", Some("cookie")));
+        // Only by the client it was issued to.
+        assert!(!take("unbound", None));
+
+        tokio::time::advance(TTL + Duration::from_secs(1)).await;
+        assert!(!take("stale", Some("cookie")));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn auth_requests_are_bounded() {
+        let mut state = AuthState::default();
+        let other_ip = IpAddr::V4(std::net::Ipv4Addr::new(10, 0, 0, 1));
+        let mut insert = |name: &str, ip| {
+            state.insert_auth_request(name.to_string(), verifier(), String::new(), ip, 3, 2)
+        };
+        assert_eq!(insert("a", IP), Ok(()));
+        assert_eq!(insert("b", IP), Ok(()));
+        assert_eq!(insert("c", IP), Err(AuthRequestRefused::RateLimited));
+        assert_eq!(insert("d", other_ip), Ok(()));
+        assert_eq!(
+            insert("e", other_ip),
+            Err(AuthRequestRefused::TooManyPending)
+        );
+
+        // The sweep drops expired requests and resets the counters.
+        tokio::time::advance(TTL + Duration::from_secs(1)).await;
+        state.clear_expired_auth_requests(TTL);
+        assert!(state.auth_requests.is_empty());
+        assert!(state
+            .insert_auth_request("f".to_string(), verifier(), String::new(), IP, 3, 2)
+            .is_ok());
+    }
+
+    #[test]
+    fn lobby_tokens_are_redeemed_once() {
+        let mut state = AuthState::default();
+        let now = unix_now().as_secs();
+        assert!(state.redeem_lobby_token("valid", now + 60));
+        assert!(!state.redeem_lobby_token("valid", now + 60));
+        assert!(state.redeem_lobby_token("expired", now - 1));
+
+        // Expired tokens are rejected anyway, so their ids are dropped.
+        state.clear_expired_auth_requests(TTL);
+        assert!(!state.redeem_lobby_token("valid", now + 60));
+        assert!(state.redeem_lobby_token("expired", now - 1));
+    }
 }
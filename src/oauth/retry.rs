@@ -0,0 +1,258 @@
+use crate::util::{duration_from_millis_str, duration_from_str};
+use clap::Parser;
+use http::StatusCode;
+use oauth2::{reqwest::async_http_client, HttpRequest, HttpResponse};
+use once_cell::sync::Lazy;
+use prometheus::{register_int_counter_vec, IntCounterVec};
+use rand::Rng;
+use std::{future::Future, io, time::Duration};
+use tokio::time::{sleep, timeout, Instant};
+use tracing::warn;
+
+static RETRIES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "oauth_request_retries",
+        "Number of retried requests to OAuth providers.",
+        &["request"]
+    )
+    .unwrap()
+});
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+pub struct RetryOptions {
+    /// Maximum number of attempts for token exchange and user info requests
+    /// to OAuth providers.
+    #[clap(long, env, default_value = "3")]
+    pub oauth_retry_max_attempts: u32,
+
+    /// Backoff before the first retry in milliseconds. It doubles with every
+    /// further retry.
+    #[clap(long, env, value_parser=duration_from_millis_str, default_value="200")]
+    pub oauth_retry_backoff: Duration,
+
+    /// Stop retrying once this many seconds have passed since the first
+    /// attempt.
+    #[clap(long, env, value_parser=duration_from_str, default_value="10")]
+    pub oauth_retry_deadline: Duration,
+}
+
+/// Only gateway errors are assumed to be transient, any other status is
+/// returned as-is.
+const fn is_transient_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+fn is_transient_error(error: &reqwest::Error) -> bool {
+    error.is_timeout() || error.is_connect()
+}
+
+impl RetryOptions {
+    /// Runs `attempt` until it returns a result that is not transient, or the
+    /// attempts or the deadline are exhausted. Retries back off exponentially
+    /// with jitter.
+    pub async fn retry<T, E, F, Fut>(
+        &self,
+        request: &'static str,
+        mut attempt: F,
+        is_transient: impl Fn(&Result<T, E>) -> bool,
+    ) -> Result<T, E>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let start = Instant::now();
+        let mut backoff = self.oauth_retry_backoff;
+        let mut attempts = 1;
+        loop {
+            let result = attempt().await;
+            if attempts >= self.oauth_retry_max_attempts || !is_transient(&result) {
+                return result;
+            }
+            let delay = rand::thread_rng().gen_range(backoff / 2..=backoff);
+            if start.elapsed() + delay > self.oauth_retry_deadline {
+                return result;
+            }
+            warn!(
+                request,
+                attempts,
+                ?delay,
+                "Retrying transient OAuth provider failure"
+            );
+            RETRIES.with_label_values(&[request]).inc();
+            sleep(delay).await;
+            backoff = backoff.saturating_mul(2);
+            attempts += 1;
+        }
+    }
+
+    /// HTTP client for [`oauth2`] token exchanges that retries transient
+    /// failures. Attempts taking longer than `request_timeout` count as
+    /// transient failures.
+    pub async fn http_client(
+        &self,
+        request_timeout: Duration,
+        request: HttpRequest,
+    ) -> Result<HttpResponse, oauth2::reqwest::Error<reqwest::Error>> {
+        self.retry(
+            "token_exchange",
+            || {
+                let request = request.clone();
+                async move {
+                    timeout(request_timeout, async_http_client(request))
+                        .await
+                        .unwrap_or_else(|_| {
+                            Err(oauth2::reqwest::Error::Io(io::Error::new(
+                                io::ErrorKind::TimedOut,
+                                "token exchange timed out",
+                            )))
+                        })
+                }
+            },
+            |result| match result {
+                Ok(response) => is_transient_status(response.status_code),
+                Err(oauth2::reqwest::Error::Reqwest(error)) => is_transient_error(error),
+                Err(oauth2::reqwest::Error::Io(error)) => error.kind() == io::ErrorKind::TimedOut,
+                Err(_) => false,
+            },
+        )
+        .await
+    }
+
+    /// Sends the request built by `request` and retries transient failures.
+    /// Attempts taking longer than `request_timeout` count as transient
+    /// failures.
+    pub async fn send(
+        &self,
+        name: &'static str,
+        request_timeout: Duration,
+        request: impl Fn() -> reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response, reqwest::Error> {
+        self.retry(
+            name,
+            || request().timeout(request_timeout).send(),
+            |result| match result {
+                Ok(response) => is_transient_status(response.status()),
+                Err(error) => is_transient_error(error),
+            },
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::test_options;
+    use axum::{routing::any, Router, Server};
+    use std::sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    };
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_only_transient_failures() {
+        let options = test_options().oauth_retry;
+        let attempts = AtomicU32::new(0);
+        let attempt = |failures: u32| {
+            let attempts = &attempts;
+            move || async move {
+                let attempt = attempts.fetch_add(1, Ordering::SeqCst);
+                if attempt < failures {
+                    Err(StatusCode::SERVICE_UNAVAILABLE)
+                } else {
+                    Ok(())
+                }
+            }
+        };
+        let is_transient = |result: &Result<(), StatusCode>| matches!(result, Err(status) if is_transient_status(*status));
+
+        assert_eq!(
+            options.retry("test", attempt(2), is_transient).await,
+            Ok(())
+        );
+        assert_eq!(attempts.swap(0, Ordering::SeqCst), 3);
+
+        // Gives up after the maximum number of attempts.
+        assert_eq!(
+            options.retry("test", attempt(5), is_transient).await,
+            Err(StatusCode::SERVICE_UNAVAILABLE)
+        );
+        assert_eq!(attempts.swap(0, Ordering::SeqCst), 3);
+
+        // Permanent failures are not retried.
+        assert_eq!(
+            options.retry("test", attempt(5), |_| false).await,
+            Err(StatusCode::SERVICE_UNAVAILABLE)
+        );
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+
+    /// Serves a provider that takes `delay` to answer with `status`, and
+    /// counts the requests it receives.
+    fn mock_provider(delay: Duration, status: StatusCode) -> (String, Arc<AtomicU32>) {
+        let requests = Arc::new(AtomicU32::new(0));
+        let counter = requests.clone();
+        let app = Router::new().route(
+            "/",
+            any(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    sleep(delay).await;
+                    status
+                }
+            }),
+        );
+        let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(app.into_make_service());
+        let url = format!("http://{}/", server.local_addr());
+        tokio::spawn(server);
+        (url, requests)
+    }
+
+    #[tokio::test]
+    async fn hung_provider_times_out() {
+        let mut options = test_options().oauth_retry;
+        options.oauth_retry_backoff = Duration::from_millis(10);
+        let (url, requests) = mock_provider(Duration::from_secs(30), StatusCode::OK);
+        let request_timeout = Duration::from_millis(100);
+
+        let error = options
+            .send("test", request_timeout, || reqwest::Client::new().get(&url))
+            .await
+            .unwrap_err();
+        assert!(error.is_timeout());
+        assert_eq!(requests.swap(0, Ordering::SeqCst), 3);
+
+        let request = HttpRequest {
+            url: url.parse().unwrap(),
+            method: http::Method::POST,
+            headers: http::HeaderMap::new(),
+            body: Vec::new(),
+        };
+        let error = options
+            .http_client(request_timeout, request)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(error, oauth2::reqwest::Error::Io(error) if error.kind() == io::ErrorKind::TimedOut)
+        );
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_not_retried() {
+        let options = test_options().oauth_retry;
+        let (url, requests) = mock_provider(Duration::ZERO, StatusCode::UNAUTHORIZED);
+
+        let response = options
+            .send("test", Duration::from_secs(10), || {
+                reqwest::Client::new().get(&url)
+            })
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,80 @@
+use crate::util::{duration_from_str, Secret};
+use clap::Parser;
+use oauth2::{basic::BasicClient, AuthUrl, ClientId, ClientSecret, RedirectUrl, TokenUrl};
+use std::{ops::Deref, time::Duration};
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+pub struct TwitterAuthOptions {
+    /// The minimum number of followers a Twitter account needs in order to
+    /// participate.
+    #[clap(long, env, default_value = "50")]
+    pub tw_min_followers: u64,
+
+    /// Twitter OAuth2 authorization url.
+    #[clap(long, env, default_value = "https://twitter.com/i/oauth2/authorize")]
+    pub tw_auth_url: String,
+
+    /// Twitter OAuth2 token url.
+    #[clap(long, env, default_value = "https://api.twitter.com/2/oauth2/token")]
+    pub tw_token_url: String,
+
+    /// Twitter OAuth2 user info url.
+    #[clap(
+        long,
+        env,
+        default_value = "https://api.twitter.com/2/users/me?user.fields=public_metrics"
+    )]
+    pub tw_userinfo_url: String,
+
+    /// Timeout in seconds for a single attempt of the Twitter token exchange and
+    /// the user info requests.
+    #[clap(long, env, value_parser=duration_from_str, default_value="10")]
+    pub tw_request_timeout: Duration,
+
+    /// Twitter OAuth2 callback redirect url.
+    #[clap(
+        long,
+        env,
+        default_value = "http://127.0.0.1:3000/auth/callback/twitter"
+    )]
+    pub tw_redirect_url: String,
+
+    /// Twitter OAuth2 client access id. Sign-in with Twitter is disabled if
+    /// not set.
+    #[clap(long, env, requires = "tw_client_secret")]
+    pub tw_client_id: Option<Secret>,
+
+    /// Twitter OAuth2 client access key.
+    #[clap(long, env, requires = "tw_client_id")]
+    pub tw_client_secret: Option<Secret>,
+}
+
+#[derive(Clone)]
+pub struct TwitterOAuthClient {
+    pub client: BasicClient,
+}
+
+impl Deref for TwitterOAuthClient {
+    type Target = BasicClient;
+
+    fn deref(&self) -> &Self::Target {
+        &self.client
+    }
+}
+
+/// The Twitter client, or `None` if sign-in with Twitter is disabled.
+pub fn twitter_oauth_client(options: &TwitterAuthOptions) -> Option<TwitterOAuthClient> {
+    let (client_id, client_secret) = options
+        .tw_client_id
+        .as_ref()
+        .zip(options.tw_client_secret.as_ref())?;
+    Some(TwitterOAuthClient {
+        client: BasicClient::new(
+            ClientId::new(client_id.get_secret().to_owned()),
+            Some(ClientSecret::new(client_secret.get_secret().to_owned())),
+            AuthUrl::new(options.tw_auth_url.clone()).unwrap(),
+            Some(TokenUrl::new(options.tw_token_url.clone()).unwrap()),
+        )
+        .set_redirect_uri(RedirectUrl::new(options.tw_redirect_url.clone()).unwrap()),
+    })
+}
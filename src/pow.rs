@@ -0,0 +1,132 @@
+//! Proof-of-work challenge that makes mass session creation costly.
+//!
+//! A solution is a `nonce` such that `argon2id(nonce, challenge)` (nonce as
+//! big-endian `u64`, challenge as the salt) has at least `difficulty` leading
+//! zero bits. Argon2 is memory-hard, so solving can't be sped up much with
+//! GPUs, while verifying a solution costs a single evaluation.
+//!
+//! The server issues a random, expiring challenge per session.
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Memory cost of a single evaluation in KiB.
+const ARGON2_MEMORY_KIB: u32 = 1024;
+const ARGON2_ITERATIONS: u32 = 1;
+const ARGON2_PARALLELISM: u32 = 1;
+
+#[derive(Clone, Debug)]
+pub struct PowChallenge {
+    challenge: String,
+    difficulty: u32,
+    expires: Instant,
+}
+
+impl PowChallenge {
+    #[must_use]
+    pub fn new(difficulty: u32, ttl: Duration) -> Self {
+        Self {
+            challenge: hex::encode(rand::random::<[u8; 16]>()),
+            difficulty,
+            expires: Instant::now() + ttl,
+        }
+    }
+
+    #[must_use]
+    pub fn challenge(&self) -> &str {
+        &self.challenge
+    }
+
+    #[must_use]
+    pub const fn difficulty(&self) -> u32 {
+        self.difficulty
+    }
+
+    #[must_use]
+    pub fn is_expired(&self) -> bool {
+        Instant::now() >= self.expires
+    }
+
+    #[must_use]
+    pub fn verify(&self, nonce: u64) -> bool {
+        leading_zero_bits(&pow_hash(&self.challenge, nonce)) >= self.difficulty
+    }
+}
+
+fn pow_hash(challenge: &str, nonce: u64) -> [u8; 32] {
+    let params = Params::new(
+        ARGON2_MEMORY_KIB,
+        ARGON2_ITERATIONS,
+        ARGON2_PARALLELISM,
+        Some(32),
+    )
+    .expect("argon2 parameters are valid");
+    let mut hash = [0; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(&nonce.to_be_bytes(), challenge.as_bytes(), &mut hash)
+        .expect("challenge is a valid argon2 salt");
+    hash
+}
+
+fn leading_zero_bits(bytes: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in bytes {
+        bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+#[cfg(test)]
+pub fn solve_pow(challenge: &PowChallenge) -> u64 {
+    (0..).find(|nonce| challenge.verify(*nonce)).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn test_leading_zero_bits() {
+        assert_eq!(leading_zero_bits(&[0xff, 0x00]), 0);
+        assert_eq!(leading_zero_bits(&[0x00, 0x10, 0x00]), 11);
+        assert_eq!(leading_zero_bits(&[0x00, 0x00]), 16);
+    }
+
+    #[test]
+    fn test_valid_solution() {
+        let challenge = PowChallenge::new(6, TTL);
+        let nonce = solve_pow(&challenge);
+        assert!(challenge.verify(nonce));
+        assert!(!challenge.is_expired());
+        // The solution is bound to the challenge.
+        let other = PowChallenge::new(6, TTL);
+        assert_ne!(
+            pow_hash(challenge.challenge(), nonce),
+            pow_hash(other.challenge(), nonce)
+        );
+    }
+
+    #[test]
+    fn test_insufficient_difficulty() {
+        let easy = PowChallenge::new(4, TTL);
+        let nonce = solve_pow(&easy);
+        let achieved = leading_zero_bits(&pow_hash(easy.challenge(), nonce));
+        let hard = PowChallenge {
+            difficulty: achieved + 1,
+            ..easy
+        };
+        assert!(!hard.verify(nonce));
+    }
+
+    #[test]
+    fn test_stale_challenge() {
+        let challenge = PowChallenge::new(4, Duration::ZERO);
+        assert!(challenge.is_expired());
+    }
+}
@@ -1,11 +1,12 @@
 use crate::keys::{Keys, Signature, SignatureError};
 use kzg_ceremony_crypto::{signature::identity::Identity, G2};
-use serde::Serialize;
+use serde::{Serialize, Serializer};
 
 // Receipt for contributor that sequencer has
 // included their contribution
 #[derive(Serialize)]
 pub struct Receipt {
+    #[serde(serialize_with = "serialize_checksummed")]
     pub(crate) identity: Identity,
     pub witness: Vec<G2>,
 }
@@ -19,3 +20,10 @@ impl Receipt {
             .map(|sig| (receipt_message, sig))
     }
 }
+
+fn serialize_checksummed<S: Serializer>(
+    identity: &Identity,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&identity.display_checksummed())
+}
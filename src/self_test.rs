@@ -0,0 +1,65 @@
+//! Runs a tiny contribution end-to-end, so that crypto or configuration
+//! problems with the selected [`Engine`] are caught before going live.
+
+use eyre::{ensure, Result as EyreResult};
+use kzg_ceremony_crypto::{
+    signature::{identity::Identity, SigningContext},
+    Engine, Entropy, EntropySource, Transcript,
+};
+use std::time::Instant;
+use tracing::info;
+
+/// Number of G1 and G2 powers in the self-test transcript.
+const SELF_TEST_SIZE: (usize, usize) = (8, 3);
+
+fn stage<T>(name: &str, f: impl FnOnce() -> EyreResult<T>) -> EyreResult<T> {
+    let start = Instant::now();
+    let result = f();
+    info!(stage = name, elapsed = ?start.elapsed(), ok = result.is_ok(), "Self-test stage");
+    result
+}
+
+/// Generates a tau, adds it to a fresh contribution, validates and verifies
+/// the result and checks that the BLS signature survives pruning.
+///
+/// # Errors
+///
+/// Returns an error describing the first stage that failed.
+pub fn run<E: Engine>() -> EyreResult<()> {
+    let start = Instant::now();
+    let identity = Identity::Github {
+        id: 0,
+        username: "self-test".to_string(),
+    };
+    let transcript = Transcript::new(SELF_TEST_SIZE.0, SELF_TEST_SIZE.1);
+    let mut contribution = transcript.contribution();
+
+    let tau = stage("generate_tau", || Ok(E::generate_tau(&Entropy::random())))?;
+    stage("add_tau", || {
+        contribution.add_tau::<E>(&tau, &identity)?;
+        Ok(())
+    })?;
+    drop(tau);
+    stage("validate", || {
+        contribution.validate::<E>()?;
+        Ok(())
+    })?;
+    stage("verify", || {
+        transcript.verify::<E>(&contribution)?;
+        Ok(())
+    })?;
+    stage("sign_and_prune", || {
+        let message = SigningContext::default().message(&identity);
+        let pruned = contribution
+            .bls_signature
+            .prune::<E>(&message, contribution.pot_pubkey);
+        ensure!(
+            pruned.0.is_some(),
+            "BLS signature does not verify against the contribution pubkey"
+        );
+        Ok(())
+    })?;
+
+    info!(elapsed = ?start.elapsed(), "Self-test passed");
+    Ok(())
+}
@@ -1,26 +1,52 @@
+use crate::{intent::IntentChallenge, pow::PowChallenge, util::constant_time_eq};
 use async_session::async_trait;
-use axum::{
-    extract::{FromRequest, RequestParts},
-    TypedHeader,
-};
-use headers::{authorization::Bearer, Authorization};
+use axum::extract::{FromRequest, RequestParts};
+use http::{header::AUTHORIZATION, HeaderValue};
 use kzg_ceremony_crypto::{signature::identity::Identity, ErrorCode};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::fmt::{Display, Formatter};
 use strum::IntoStaticStr;
 use thiserror::Error;
 use tokio::time::Instant;
 use uuid::Uuid;
 
-#[derive(Debug, Hash, Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+/// Session ids are bearer tokens. They are compared in constant time and are
+/// not `Ord` or `Hash`, so that they can't be used as map keys, see
+/// [`SessionKey`].
+#[derive(Debug, Serialize, Deserialize, Clone, Eq)]
 #[serde(rename = "session_id")]
 pub struct SessionId(pub String);
 
+impl PartialEq for SessionId {
+    fn eq(&self, other: &Self) -> bool {
+        constant_time_eq(&self.0, &other.0)
+    }
+}
+
 impl SessionId {
     // Create a random session id
     pub fn new() -> Self {
         Self(Uuid::new_v4().to_string())
     }
+
+    /// The key the session is stored under, see [`SessionKey`].
+    #[must_use]
+    pub fn key(&self) -> SessionKey {
+        SessionKey(hex::encode(Sha256::digest(self.0.as_bytes())))
+    }
+}
+
+/// Hex encoded SHA-256 of a [`SessionId`]. Sessions are stored under it, so
+/// that map lookups don't compare the secret id itself and persisted state
+/// doesn't contain ids that could be used as bearer tokens.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SessionKey(String);
+
+impl Display for SessionKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
 }
 
 impl Default for SessionId {
@@ -38,7 +64,7 @@ impl Display for SessionId {
 #[derive(Debug, Error, IntoStaticStr)]
 pub enum SessionError {
     #[error("unknown session id")]
-    InvalidSessionId,
+    UnknownSessionId,
 }
 
 impl ErrorCode for SessionError {
@@ -71,6 +97,17 @@ pub struct SessionInfo {
     // Indicates whether an early /lobby/try_contribute call is accepted.
     // (only allowed right after authentication)
     pub is_first_ping_attempt: bool,
+    // The last proof-of-work challenge issued to this session, until it is
+    // answered.
+    pub pow_challenge: Option<PowChallenge>,
+    // Whether the proof-of-work challenge for this session has been solved.
+    pub pow_solved: bool,
+    // The last intent challenge issued to this session, until it is answered.
+    pub intent_challenge: Option<IntentChallenge>,
+    // Whether the session signed its intent to participate.
+    pub intent_signed: bool,
+    // Position in the order of lobby entries, used for FIFO selection.
+    pub lobby_entry: u64,
 }
 
 #[async_trait]
@@ -82,11 +119,74 @@ where
 
     async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
         // Extract the token from the authorization header
-        let TypedHeader(Authorization(bearer)) =
-            TypedHeader::<Authorization<Bearer>>::from_request(req)
-                .await
-                .map_err(|_| SessionError::InvalidSessionId)?;
+        req.headers()
+            .get(AUTHORIZATION)
+            .and_then(bearer_token)
+            .map(|token| Self(token.to_owned()))
+            .ok_or(SessionError::UnknownSessionId)
+    }
+}
+
+/// Parses an `Authorization: Bearer <token>` header value. The scheme is
+/// case-insensitive, anything malformed yields `None`.
+fn bearer_token(value: &HeaderValue) -> Option<&str> {
+    let (scheme, token) = value.to_str().ok()?.split_once(' ')?;
+    let token = token.trim();
+    (scheme.eq_ignore_ascii_case("Bearer") && !token.is_empty()).then_some(token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_id_equality() {
+        let id = SessionId::new();
+        assert_eq!(id, id.clone());
+        assert_ne!(id, SessionId::new());
+        assert_ne!(SessionId("abc".to_string()), SessionId("abcd".to_string()));
+
+        assert_eq!(id.key(), SessionId(id.0.clone()).key());
+        assert_ne!(id.key(), SessionId::new().key());
+    }
+
+    #[test]
+    fn parse_bearer_token() {
+        let parse = |value: &'static str| bearer_token(&HeaderValue::from_static(value));
+        assert_eq!(parse("Bearer abc"), Some("abc"));
+        assert_eq!(parse("bearer abc"), Some("abc"));
+        assert_eq!(parse("BEARER  abc "), Some("abc"));
+
+        assert_eq!(parse("Bearer"), None);
+        assert_eq!(parse("Bearer "), None);
+        assert_eq!(parse("Basic abc"), None);
+        assert_eq!(parse("Bearerabc"), None);
+        assert_eq!(
+            bearer_token(&HeaderValue::from_bytes(b"Bearer \xff").unwrap()),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn extract_session_id() {
+        let extract = |value: Option<&'static str>| async move {
+            let mut request = http::Request::builder();
+            if let Some(value) = value {
+                request = request.header(AUTHORIZATION, value);
+            }
+            let mut parts = RequestParts::new(request.body(()).unwrap());
+            SessionId::from_request(&mut parts).await
+        };
 
-        Ok(Self(bearer.token().to_owned()))
+        assert_eq!(
+            extract(Some("Bearer abc")).await.unwrap(),
+            SessionId("abc".to_string())
+        );
+        for value in [None, Some("bearer"), Some("Token abc")] {
+            assert!(matches!(
+                extract(value).await,
+                Err(SessionError::UnknownSessionId)
+            ));
+        }
     }
 }
@@ -1,20 +1,19 @@
-use axum::{
-    response::{IntoResponse, Response},
-    Json,
-};
-use chrono::Utc;
+use crate::util::duration_from_str;
+use chrono::{DateTime, Utc};
 use clap::Parser;
 use eyre::{eyre, WrapErr};
-use http::StatusCode;
-use serde_json::json;
+use kzg_ceremony_crypto::{signature::identity::Identity, ErrorCode};
 use sqlx::{
     any::{AnyConnectOptions, AnyKind},
     migrate::{Migrate, MigrateDatabase, Migrator},
-    Any, AnyConnection, ConnectOptions, Executor, Row,
+    Any, AnyConnection, ConnectOptions, Connection, Executor, Row,
 };
-use std::{str::FromStr, sync::Arc};
+use std::{str::FromStr, sync::Arc, time::Duration};
+use strum::IntoStaticStr;
 use thiserror::Error;
 use tokio::sync::Mutex;
+#[cfg(test)]
+use tokio::sync::MutexGuard;
 use tracing::{error, info, warn};
 
 // Statically link in migration files
@@ -39,12 +38,58 @@ pub struct Options {
     /// up to date.
     #[clap(long, env, default_value = "true")]
     pub database_migrate: bool,
+
+    /// Buffer contributor inserts and write them in one transaction at this
+    /// interval, in seconds. Zero writes every insert immediately.
+    #[clap(long, env, value_parser=duration_from_str, default_value="0")]
+    pub contributor_flush_interval: Duration,
+
+    /// Number of buffered contributor inserts that triggers a flush before
+    /// the interval elapses.
+    #[clap(long, env, default_value = "100")]
+    pub contributor_buffer_size: usize,
 }
 
 #[derive(Clone, Debug)]
-pub struct PersistentStorage(Arc<Mutex<AnyConnection>>);
+pub struct PersistentStorage {
+    connection: Arc<Mutex<AnyConnection>>,
+    buffer: Option<Arc<ContributorBuffer>>,
+}
+
+/// A contributor row that hasn't been written to the database yet.
+#[derive(Clone, Debug)]
+struct BufferedContributor {
+    uid: String,
+    started_at: DateTime<Utc>,
+    finished_at: Option<DateTime<Utc>>,
+    expired_at: Option<DateTime<Utc>>,
+}
+
+/// Write-behind buffer of contributor inserts. Everything but plain inserts
+/// holds the connection lock while touching it, so a flush in progress is
+/// never observed half done.
+#[derive(Debug)]
+struct ContributorBuffer {
+    capacity: usize,
+    pending: std::sync::Mutex<Vec<BufferedContributor>>,
+}
 
-#[derive(Debug, Error)]
+impl ContributorBuffer {
+    fn pending(&self) -> std::sync::MutexGuard<'_, Vec<BufferedContributor>> {
+        self.pending.lock().expect("buffer lock is never poisoned")
+    }
+
+    /// Applies `update` to the buffered rows of `uid`, like an `UPDATE ..
+    /// WHERE uid = ?` would.
+    fn update(&self, uid: &str, update: impl Fn(&mut BufferedContributor)) {
+        self.pending()
+            .iter_mut()
+            .filter(|entry| entry.uid == uid)
+            .for_each(update);
+    }
+}
+
+#[derive(Debug, Error, IntoStaticStr)]
 pub enum StorageError {
     #[error("Database error: {0}")]
     DatabaseError(#[from] sqlx::error::Error),
@@ -133,38 +178,231 @@ pub async fn storage_client(options: &Options) -> eyre::Result<PersistentStorage
         return Err(eyre!("Could not get database version."));
     }
 
-    Ok(PersistentStorage(Arc::new(Mutex::new(connection))))
+    let buffer = (!options.contributor_flush_interval.is_zero()).then(|| {
+        Arc::new(ContributorBuffer {
+            capacity: options.contributor_buffer_size,
+            pending: std::sync::Mutex::default(),
+        })
+    });
+    Ok(PersistentStorage {
+        connection: Arc::new(Mutex::new(connection)),
+        buffer,
+    })
 }
 
-impl IntoResponse for StorageError {
-    fn into_response(self) -> Response {
-        let message = match &self {
-            Self::DatabaseError(error) => error.to_string(),
-        };
-        let body = Json(json!({
-            "code": "StorageError::DatabaseError",
-            "error": message
-        }));
-        (StatusCode::INTERNAL_SERVER_ERROR, body).into_response()
+/// Flushes buffered contributor inserts every `interval`.
+pub async fn flush_contributors_on_interval(storage: PersistentStorage, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = storage.flush_contributors().await {
+            error!("failed to flush contributors: {}", e);
+        }
+    }
+}
+
+impl ErrorCode for StorageError {
+    fn to_error_code(&self) -> String {
+        format!("StorageError::{}", <&str>::from(self))
     }
 }
 
 impl PersistentStorage {
-    pub async fn has_contributed(&self, uid: &str) -> Result<bool, StorageError> {
-        let sql = "SELECT EXISTS(SELECT 1 FROM contributors WHERE uid = ?1)";
+    /// Checks that the database connection is still alive.
+    pub async fn ping(&self) -> Result<(), StorageError> {
+        self.connection.lock().await.ping().await?;
+        Ok(())
+    }
+
+    /// Holds on to the connection, so other users of the storage block as if
+    /// the database stopped responding.
+    #[cfg(test)]
+    pub async fn lock_connection(&self) -> MutexGuard<'_, AnyConnection> {
+        self.connection.lock().await
+    }
+
+    /// Whether the identity, or any identity linked to it, has contributed.
+    pub async fn has_contributed(&self, identity: &Identity) -> Result<bool, StorageError> {
+        let uid = identity.unique_id();
+        let mut connection = self.connection.lock().await;
+
+        if let Some(buffer) = &self.buffer {
+            let pending = buffer
+                .pending()
+                .iter()
+                .map(|entry| entry.uid.clone())
+                .collect::<Vec<_>>();
+            if pending.contains(&uid) {
+                return Ok(true);
+            }
+            if !pending.is_empty() {
+                let sql = "SELECT linked.uid FROM linked_identities linked JOIN \
+                           linked_identities own ON linked.principal = own.principal WHERE \
+                           own.uid = ?1";
+                let linked: Vec<String> = connection
+                    .fetch_all(sqlx::query(sql).bind(&uid))
+                    .await?
+                    .iter()
+                    .map(|row| row.get(0))
+                    .collect();
+                if linked.iter().any(|uid| pending.contains(uid)) {
+                    return Ok(true);
+                }
+            }
+        }
+
+        let sql = "SELECT EXISTS(SELECT 1 FROM contributors WHERE uid = ?1 OR uid IN (SELECT \
+                   linked.uid FROM linked_identities linked JOIN linked_identities own ON \
+                   linked.principal = own.principal WHERE own.uid = ?1))";
+        let result = connection
+            .fetch_one(sqlx::query(sql).bind(&uid))
+            .await
+            .map(|row| row.get(0))?;
+        Ok(result)
+    }
+
+    pub async fn is_linked(&self, identity: &Identity) -> Result<bool, StorageError> {
+        let sql = "SELECT EXISTS(SELECT 1 FROM linked_identities WHERE uid = ?1)";
         let result = self
-            .0
+            .connection
             .lock()
             .await
-            .fetch_one(sqlx::query(sql).bind(uid))
+            .fetch_one(sqlx::query(sql).bind(identity.unique_id()))
             .await
             .map(|row| row.get(0))?;
         Ok(result)
     }
 
+    /// Records both identities as a single principal. Returns `false` if
+    /// either of them is already linked.
+    pub async fn link_identities(
+        &self,
+        first: &Identity,
+        second: &Identity,
+    ) -> Result<bool, StorageError> {
+        let (first, second) = (first.unique_id(), second.unique_id());
+        let mut connection = self.connection.lock().await;
+
+        let sql = "SELECT EXISTS(SELECT 1 FROM linked_identities WHERE uid = ?1 OR uid = ?2)";
+        let already_linked: bool = connection
+            .fetch_one(sqlx::query(sql).bind(&first).bind(&second))
+            .await
+            .map(|row| row.get(0))?;
+        if already_linked {
+            return Ok(false);
+        }
+
+        let sql = "INSERT INTO linked_identities (uid, principal) VALUES (?1, ?1), (?2, ?1)";
+        connection
+            .execute(sqlx::query(sql).bind(&first).bind(&second))
+            .await?;
+        Ok(true)
+    }
+
+    /// Returns the identities that finished a contribution, in the order of
+    /// their first contribution. Identities are listed once, even if they
+    /// contributed multiple times.
+    pub async fn list_contributors(
+        &self,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<Identity>, StorageError> {
+        let mut connection = self.connection.lock().await;
+        // The order depends on the row ids, so buffered rows need them first.
+        self.flush_locked(&mut connection).await?;
+
+        let sql = "SELECT uid FROM contributors WHERE finished_at IS NOT NULL GROUP BY uid ORDER \
+                   BY MIN(id) LIMIT ?1 OFFSET ?2";
+        let uids: Vec<String> = connection
+            .fetch_all(
+                sqlx::query(sql)
+                    .bind(i64::try_from(limit).unwrap_or(i64::MAX))
+                    .bind(i64::try_from(offset).unwrap_or(i64::MAX)),
+            )
+            .await?
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+        Ok(uids
+            .iter()
+            .filter_map(|uid| match uid.parse() {
+                Ok(identity) => Some(identity),
+                Err(error) => {
+                    warn!(%uid, ?error, "skipping contributor with malformed uid");
+                    None
+                }
+            })
+            .collect())
+    }
+
+    pub async fn is_paused(&self) -> Result<bool, StorageError> {
+        let sql = "SELECT EXISTS(SELECT 1 FROM settings WHERE name = 'paused' AND value = 'true')";
+        let result = self
+            .connection
+            .lock()
+            .await
+            .fetch_one(sqlx::query(sql))
+            .await
+            .map(|row| row.get(0))?;
+        Ok(result)
+    }
+
+    pub async fn set_paused(&self, paused: bool) -> Result<(), StorageError> {
+        let sql = "INSERT INTO settings (name, value) VALUES ('paused', ?1) ON CONFLICT (name) DO \
+                   UPDATE SET value = excluded.value";
+        self.connection
+            .lock()
+            .await
+            .execute(sqlx::query(sql).bind(paused.to_string()))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn load_lobby(&self) -> Result<Option<String>, StorageError> {
+        let sql = "SELECT value FROM settings WHERE name = 'lobby'";
+        let result = self
+            .connection
+            .lock()
+            .await
+            .fetch_optional(sqlx::query(sql))
+            .await?
+            .map(|row| row.get(0));
+        Ok(result)
+    }
+
+    pub async fn save_lobby(&self, lobby: &str) -> Result<(), StorageError> {
+        let sql = "INSERT INTO settings (name, value) VALUES ('lobby', ?1) ON CONFLICT (name) DO \
+                   UPDATE SET value = excluded.value";
+        self.connection
+            .lock()
+            .await
+            .execute(sqlx::query(sql).bind(lobby))
+            .await?;
+        Ok(())
+    }
+
     pub async fn insert_contributor(&self, uid: &str) -> Result<(), StorageError> {
+        if let Some(buffer) = &self.buffer {
+            let full = {
+                let mut pending = buffer.pending();
+                pending.push(BufferedContributor {
+                    uid: uid.to_owned(),
+                    started_at: Utc::now(),
+                    finished_at: None,
+                    expired_at: None,
+                });
+                pending.len() >= buffer.capacity
+            };
+            if full {
+                self.flush_contributors().await?;
+            }
+            return Ok(());
+        }
+
         let sql = "INSERT INTO contributors (uid, started_at) VALUES (?1, ?2)";
-        self.0
+        self.connection
             .lock()
             .await
             .execute(sqlx::query(sql).bind(uid).bind(Utc::now()))
@@ -173,22 +411,260 @@ impl PersistentStorage {
     }
 
     pub async fn finish_contribution(&self, uid: &str) -> Result<(), StorageError> {
+        let now = Utc::now();
+        let mut connection = self.connection.lock().await;
+        if let Some(buffer) = &self.buffer {
+            buffer.update(uid, |entry| entry.finished_at = Some(now));
+        }
         let sql = "UPDATE contributors SET finished_at = ?1 WHERE uid = ?2";
-        self.0
-            .lock()
-            .await
-            .execute(sqlx::query(sql).bind(Utc::now()).bind(uid))
+        connection
+            .execute(sqlx::query(sql).bind(now).bind(uid))
             .await?;
         Ok(())
     }
 
+    /// Writes the buffered contributor inserts in one transaction. Must be
+    /// called before shutting down, otherwise they are lost.
+    pub async fn flush_contributors(&self) -> Result<(), StorageError> {
+        let mut connection = self.connection.lock().await;
+        self.flush_locked(&mut connection).await
+    }
+
+    async fn flush_locked(&self, connection: &mut AnyConnection) -> Result<(), StorageError> {
+        let buffer = match &self.buffer {
+            Some(buffer) => buffer,
+            None => return Ok(()),
+        };
+        let pending = std::mem::take(&mut *buffer.pending());
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let result = async {
+            let sql = "INSERT INTO contributors (uid, started_at, finished_at, expired_at) \
+                       VALUES (?1, ?2, ?3, ?4)";
+            let mut transaction = connection.begin().await?;
+            for entry in &pending {
+                transaction
+                    .execute(
+                        sqlx::query(sql)
+                            .bind(&entry.uid)
+                            .bind(entry.started_at)
+                            .bind(entry.finished_at)
+                            .bind(entry.expired_at),
+                    )
+                    .await?;
+            }
+            transaction.commit().await
+        }
+        .await;
+
+        if let Err(e) = result {
+            // Keep the rows for the next flush, ahead of newer inserts.
+            let mut current = buffer.pending();
+            let newer = std::mem::replace(&mut *current, pending);
+            current.extend(newer);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
     pub async fn expire_contribution(&self, uid: &str) -> Result<(), StorageError> {
+        let now = Utc::now();
+        let mut connection = self.connection.lock().await;
+        if let Some(buffer) = &self.buffer {
+            buffer.update(uid, |entry| entry.expired_at = Some(now));
+        }
         let sql = "UPDATE contributors SET expired_at = ?1 WHERE uid = ?2";
-        self.0
-            .lock()
-            .await
-            .execute(sqlx::query(sql).bind(Utc::now()).bind(uid))
+        connection
+            .execute(sqlx::query(sql).bind(now).bind(uid))
             .await?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::test_options;
+
+    #[tokio::test]
+    async fn has_contributed_by_identity() {
+        let storage = storage_client(&test_options().storage).await.unwrap();
+        let contributor = Identity::Github {
+            id: 1234,
+            username: "test_user".to_string(),
+        };
+        let other = Identity::Github {
+            id: 5678,
+            username: "other_user".to_string(),
+        };
+
+        storage
+            .insert_contributor(&contributor.unique_id())
+            .await
+            .unwrap();
+
+        assert!(storage.has_contributed(&contributor).await.unwrap());
+        assert!(!storage.has_contributed(&other).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn has_contributed_ignores_github_username_case() {
+        let storage = storage_client(&test_options().storage).await.unwrap();
+        let contributor = Identity::Github {
+            id: 1234,
+            username: "Test_User".to_string(),
+        };
+        let case_variant = Identity::Github {
+            id: 1234,
+            username: "test_user".to_string(),
+        };
+
+        storage
+            .insert_contributor(&contributor.unique_id())
+            .await
+            .unwrap();
+
+        assert!(storage.has_contributed(&case_variant).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn list_finished_contributors() {
+        let storage = storage_client(&test_options().storage).await.unwrap();
+        let identities = (1..=5)
+            .map(|id| Identity::Github {
+                id,
+                username: format!("user_{id}"),
+            })
+            .collect::<Vec<_>>();
+        for identity in &identities {
+            storage
+                .insert_contributor(&identity.unique_id())
+                .await
+                .unwrap();
+        }
+        // Only finished contributions are listed, and only once.
+        for index in [0, 1, 3, 4] {
+            storage
+                .finish_contribution(&identities[index].unique_id())
+                .await
+                .unwrap();
+        }
+        storage
+            .insert_contributor(&identities[0].unique_id())
+            .await
+            .unwrap();
+        storage
+            .finish_contribution(&identities[0].unique_id())
+            .await
+            .unwrap();
+
+        let expected = [0, 1, 3, 4].map(|index| identities[index].clone());
+        assert_eq!(storage.list_contributors(0, 10).await.unwrap(), expected);
+        assert_eq!(
+            storage.list_contributors(1, 2).await.unwrap(),
+            expected[1..3]
+        );
+        assert!(storage.list_contributors(4, 2).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn linked_identities_share_contribution() {
+        let storage = storage_client(&test_options().storage).await.unwrap();
+        let github = Identity::Github {
+            id: 1234,
+            username: "test_user".to_string(),
+        };
+        let eth = Identity::Ethereum { address: [1; 20] };
+        let other_eth = Identity::Ethereum { address: [2; 20] };
+
+        assert!(!storage.is_linked(&github).await.unwrap());
+        assert!(storage.link_identities(&github, &eth).await.unwrap());
+        assert!(storage.is_linked(&eth).await.unwrap());
+        // Either identity can only be part of one link.
+        assert!(!storage.link_identities(&other_eth, &github).await.unwrap());
+
+        storage
+            .insert_contributor(&github.unique_id())
+            .await
+            .unwrap();
+        assert!(storage.has_contributed(&eth).await.unwrap());
+        assert!(!storage.has_contributed(&other_eth).await.unwrap());
+    }
+
+    async fn buffered_storage(capacity: usize) -> PersistentStorage {
+        let mut options = test_options().storage;
+        options.contributor_flush_interval = Duration::from_secs(3600);
+        options.contributor_buffer_size = capacity;
+        storage_client(&options).await.unwrap()
+    }
+
+    async fn stored_uids(storage: &PersistentStorage) -> Vec<String> {
+        storage
+            .lock_connection()
+            .await
+            .fetch_all("SELECT uid FROM contributors ORDER BY id")
+            .await
+            .unwrap()
+            .iter()
+            .map(|row| row.get(0))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn buffered_contributors_flushed() {
+        let storage = buffered_storage(100).await;
+        let identities = (1..=5)
+            .map(|id| Identity::Github {
+                id,
+                username: format!("user_{id}"),
+            })
+            .collect::<Vec<_>>();
+        let eth = Identity::Ethereum { address: [1; 20] };
+        assert!(storage.link_identities(&identities[4], &eth).await.unwrap());
+
+        for identity in &identities {
+            storage
+                .insert_contributor(&identity.unique_id())
+                .await
+                .unwrap();
+        }
+        storage
+            .finish_contribution(&identities[1].unique_id())
+            .await
+            .unwrap();
+
+        // Nothing written yet, but reads see the buffered rows.
+        assert!(stored_uids(&storage).await.is_empty());
+        assert!(storage.has_contributed(&identities[0]).await.unwrap());
+        assert!(storage.has_contributed(&eth).await.unwrap());
+
+        storage.flush_contributors().await.unwrap();
+        let expected = identities
+            .iter()
+            .map(Identity::unique_id)
+            .collect::<Vec<_>>();
+        assert_eq!(stored_uids(&storage).await, expected);
+        assert_eq!(
+            storage.list_contributors(0, 10).await.unwrap(),
+            [identities[1].clone()]
+        );
+    }
+
+    #[tokio::test]
+    async fn buffered_contributors_flushed_when_full() {
+        let storage = buffered_storage(3).await;
+        for id in 1..=4 {
+            let identity = Identity::Github {
+                id,
+                username: format!("user_{id}"),
+            };
+            storage
+                .insert_contributor(&identity.unique_id())
+                .await
+                .unwrap();
+        }
+        assert_eq!(stored_uids(&storage).await.len(), 3);
+    }
+}
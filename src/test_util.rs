@@ -25,6 +25,11 @@ pub fn create_test_session_info(exp: u64) -> SessionInfo {
         token: test_jwt(exp),
         last_ping_time: Instant::now(),
         is_first_ping_attempt: true,
+        pow_challenge: None,
+        pow_solved: false,
+        intent_challenge: None,
+        intent_signed: false,
+        lobby_entry: 0,
     }
 }
 
@@ -42,6 +47,10 @@ pub fn test_options() -> Options {
         "INVALID",
         "--eth-client-id",
         "INVALID",
+        "--tw-client-secret",
+        "INVALID",
+        "--tw-client-id",
+        "INVALID",
         "--database-url",
         "sqlite://:memory:",
     ];
@@ -3,8 +3,13 @@ use std::{
     convert::Infallible,
     fmt,
     net::{IpAddr, Ipv4Addr, SocketAddr},
+    num::ParseIntError,
     str,
+    str::FromStr,
+    time::Duration,
 };
+use subtle::ConstantTimeEq;
+use tower_http::CompressionLevel;
 use url::{Host, Url};
 
 pub fn parse_url(url: &Url) -> EyreResult<(SocketAddr, &str)> {
@@ -25,6 +30,23 @@ pub fn parse_url(url: &Url) -> EyreResult<(SocketAddr, &str)> {
     Ok((addr, prefix))
 }
 
+/// Compares two tokens in constant time, so the comparison doesn't leak how
+/// many leading bytes match. Only the lengths are compared in variable time.
+#[must_use]
+pub fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.as_bytes().ct_eq(b.as_bytes()).into()
+}
+
+/// Parses a duration in seconds.
+pub fn duration_from_str(value: &str) -> Result<Duration, ParseIntError> {
+    Ok(Duration::from_secs(u64::from_str(value)?))
+}
+
+/// Parses a duration in milliseconds.
+pub fn duration_from_millis_str(value: &str) -> Result<Duration, ParseIntError> {
+    Ok(Duration::from_millis(u64::from_str(value)?))
+}
+
 #[derive(Clone, PartialEq, Eq)]
 pub struct Secret(String);
 
@@ -33,11 +55,17 @@ impl Secret {
     pub fn get_secret(&self) -> &str {
         &self.0
     }
+
+    /// Checks `candidate` against the secret in constant time.
+    #[must_use]
+    pub fn matches(&self, candidate: &str) -> bool {
+        constant_time_eq(&self.0, candidate)
+    }
 }
 
 impl fmt::Debug for Secret {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        formatter.write_str("[REDACTED]")
+        formatter.write_str("<redacted>")
     }
 }
 
@@ -48,3 +76,90 @@ impl str::FromStr for Secret {
         Ok(Self(str.to_owned()))
     }
 }
+
+/// Compression level for response bodies. Either `fastest`, `best`, `default`
+/// or an algorithm specific number.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompressionQuality {
+    Fastest,
+    Best,
+    Default,
+    Precise(u32),
+}
+
+impl str::FromStr for CompressionQuality {
+    type Err = ParseIntError;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        match str {
+            "fastest" => Ok(Self::Fastest),
+            "best" => Ok(Self::Best),
+            "default" => Ok(Self::Default),
+            level => level.parse().map(Self::Precise),
+        }
+    }
+}
+
+impl From<CompressionQuality> for CompressionLevel {
+    fn from(quality: CompressionQuality) -> Self {
+        match quality {
+            CompressionQuality::Fastest => Self::Fastest,
+            CompressionQuality::Best => Self::Best,
+            CompressionQuality::Default => Self::Default,
+            CompressionQuality::Precise(level) => Self::Precise(level),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oauth::{EthAuthOptions, GithubAuthOptions};
+    use clap::Parser;
+
+    #[test]
+    fn constant_time_eq_semantics() {
+        assert!(constant_time_eq("", ""));
+        assert!(constant_time_eq("token", "token"));
+        assert!(!constant_time_eq("token", "tokem"));
+        assert!(!constant_time_eq("token", "token2"));
+        assert!(!constant_time_eq("token", ""));
+
+        let secret: Secret = "admin".parse().unwrap();
+        assert!(secret.matches("admin"));
+        assert!(!secret.matches("Admin"));
+        assert!(!secret.matches("admin "));
+    }
+
+    #[test]
+    fn options_debug_redacts_secrets() {
+        let eth = EthAuthOptions::parse_from([
+            "test",
+            "--eth-rpc-url",
+            "https://rpc.example/rpc-url-secret",
+            "--eth-client-id",
+            "eth-id-secret",
+            "--eth-client-secret",
+            "eth-key-secret",
+        ]);
+        let github = GithubAuthOptions::parse_from([
+            "test",
+            "--gh-client-id",
+            "gh-id-secret",
+            "--gh-client-secret",
+            "gh-key-secret",
+        ]);
+
+        let formatted = format!("{eth:?} {github:?} {eth:#?} {github:#?}");
+        assert!(formatted.contains("<redacted>"));
+        for secret in [
+            "rpc-url-secret",
+            "eth-id-secret",
+            "eth-key-secret",
+            "gh-id-secret",
+            "gh-key-secret",
+        ] {
+            assert!(!formatted.contains(secret), "{secret} leaked");
+        }
+    }
+}
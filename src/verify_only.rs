@@ -0,0 +1,95 @@
+//! Verify-only mode, for read replicas that serve the transcript of another
+//! sequencer without accepting contributions.
+
+use axum::{
+    body::Body,
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Router,
+};
+use http::Request;
+use kzg_ceremony_crypto::ErrorCode;
+use strum::IntoStaticStr;
+use thiserror::Error;
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum VerifyOnlyError {
+    #[error("this sequencer only serves the transcript, contribute through the main sequencer")]
+    WritesDisabled,
+}
+
+impl ErrorCode for VerifyOnlyError {
+    fn to_error_code(&self) -> String {
+        format!("VerifyOnlyError::{}", <&str>::from(self))
+    }
+}
+
+/// Answers every request to the routes of `router` with a 405 instead of
+/// handling it.
+pub fn disable_writes(router: Router) -> Router {
+    router.layer(middleware::from_fn(
+        |_request: Request<Body>, _next: Next<Body>| async {
+            VerifyOnlyError::WritesDisabled.into_response()
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        acceptance::NoopAcceptanceHook,
+        build_app,
+        io::{write_json_file, CeremonySizes},
+        test_util::test_options,
+        tests::test_transcript,
+    };
+    use http::StatusCode;
+    use std::sync::Arc;
+    use tempfile::tempdir;
+    use tokio::sync::RwLock;
+    use tower::ServiceExt;
+
+    fn request(method: &str, uri: &str) -> Request<Body> {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn rejects_writes_only() {
+        let dir = tempdir().unwrap();
+        let mut options = test_options();
+        options.verify_only = true;
+        options.ceremony_sizes = CeremonySizes::parse_from_cmd("4,2").unwrap();
+        options.transcript_file = dir.path().join("transcript.json");
+        options.transcript_in_progress_file = dir.path().join("transcript.json.next");
+        // A replica never creates the transcript, the main sequencer does.
+        write_json_file(
+            options.transcript_file.clone(),
+            options.transcript_in_progress_file.clone(),
+            Arc::new(RwLock::new(test_transcript())),
+            None,
+        )
+        .await
+        .unwrap();
+        let (app, _) = build_app(&options, Arc::new(NoopAcceptanceHook))
+            .await
+            .unwrap();
+
+        let response = app
+            .clone()
+            .oneshot(request("POST", "/lobby/try_contribute"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+        let response = app
+            .oneshot(request("GET", "/info/current_state"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
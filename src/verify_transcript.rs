@@ -0,0 +1,127 @@
+//! Checks a complete transcript file offline, e.g. by an auditor after the
+//! ceremony ended. Run through the `verify-transcript` binary, which doesn't
+//! need any of the server options.
+
+use crate::{engine::EngineKind, io::read_json_file};
+use clap::Parser;
+use eyre::{eyre, Result as EyreResult, WrapErr};
+use kzg_ceremony_crypto::{BatchTranscript, CeremoniesError};
+use std::path::PathBuf;
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Transcript file to verify.
+    #[clap(env = "TRANSCRIPT_FILE", default_value = "./transcript.json")]
+    pub transcript_file: PathBuf,
+
+    /// Cryptography backend used to verify the transcript.
+    #[clap(long, env, value_enum, default_value = "both")]
+    pub engine: EngineKind,
+}
+
+/// Outcome of [`verify`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Summary {
+    pub num_contributions: usize,
+    pub first_failure: Option<CeremoniesError>,
+}
+
+/// Verifies the chain and the signatures of every sub-ceremony.
+pub fn verify(transcript: &BatchTranscript, engine: EngineKind) -> Summary {
+    Summary {
+        num_contributions: transcript.num_participants(),
+        first_failure: engine.verify_history(transcript).err(),
+    }
+}
+
+/// Reads the transcript file, verifies it and prints a summary.
+///
+/// # Errors
+///
+/// Returns an error if the file can't be read or the transcript doesn't
+/// verify.
+pub async fn run(options: Options) -> EyreResult<()> {
+    let path = options.transcript_file;
+    let transcript: BatchTranscript = read_json_file(path.clone())
+        .await
+        .wrap_err_with(|| format!("Failed to read transcript {}", path.display()))?;
+    let summary = verify(&transcript, options.engine);
+
+    println!("Contributions: {}", summary.num_contributions);
+    match summary.first_failure {
+        None => {
+            println!("Transcript is valid");
+            Ok(())
+        }
+        Some(error) => {
+            println!("First failure: {error}");
+            Err(eyre!("Transcript {} is invalid: {}", path.display(), error))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{test_transcript, valid_contribution};
+    use kzg_ceremony_crypto::{signature::ContributionDomain, CeremonyError, Identity, G2};
+    use std::fs;
+
+    fn write_fixture(transcript: &BatchTranscript) -> tempfile::NamedTempFile {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        fs::write(file.path(), serde_json::to_vec(transcript).unwrap()).unwrap();
+        file
+    }
+
+    fn options(file: &tempfile::NamedTempFile) -> Options {
+        Options {
+            transcript_file: file.path().to_path_buf(),
+            engine: EngineKind::Both,
+        }
+    }
+
+    #[tokio::test]
+    async fn verify_transcript_files() {
+        let engines = [EngineKind::Both, EngineKind::Blst, EngineKind::Arkworks];
+        let mut transcript = test_transcript();
+        for no in 1..=3 {
+            let contribution = valid_contribution(&transcript, no);
+            EngineKind::Both
+                .verify_add_in_domain(
+                    &mut transcript,
+                    contribution,
+                    Identity::None,
+                    &ContributionDomain::default(),
+                )
+                .unwrap();
+        }
+        for engine in engines {
+            assert_eq!(
+                verify(&transcript, engine),
+                Summary {
+                    num_contributions: 3,
+                    first_failure: None,
+                }
+            );
+        }
+        let valid = write_fixture(&transcript);
+        run(options(&valid)).await.unwrap();
+
+        transcript.transcripts[0].witness.pubkeys[2] = G2::one();
+        for engine in engines {
+            assert_eq!(
+                verify(&transcript, engine),
+                Summary {
+                    num_contributions: 3,
+                    first_failure: Some(CeremoniesError::InvalidCeremony(
+                        0,
+                        CeremonyError::InvalidChainLink(2)
+                    )),
+                }
+            );
+        }
+        let tampered = write_fixture(&transcript);
+        assert!(run(options(&tampered)).await.is_err());
+    }
+}
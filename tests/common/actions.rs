@@ -10,9 +10,45 @@ use serde_json::Value;
 use std::collections::HashMap;
 use url::Url;
 
+/// The state of a sign-in request, with the cookie that binds it to the
+/// client that made the request.
+pub struct AuthRequest {
+    pub state: String,
+    pub cookie: Option<String>,
+}
+
+impl AuthRequest {
+    /// A state sent without the cookie, like from another client.
+    pub fn unbound(state: &str) -> Self {
+        Self {
+            state: state.to_string(),
+            cookie: None,
+        }
+    }
+}
+
+/// The `name=value` part of the cookie set by a `/auth/request_link` response.
+pub fn binding_cookie(response: &reqwest::Response) -> Option<String> {
+    response
+        .headers()
+        .get(http::header::SET_COOKIE)
+        .map(|value| {
+            value
+                .to_str()
+                .unwrap()
+                .split(';')
+                .next()
+                .unwrap()
+                .to_string()
+        })
+}
+
 /// This function acts both as a test and a utility. This way, we'll test the
 /// behavior in a variety of different app states.
-pub async fn get_and_validate_csrf_token(harness: &Harness, redirect_url: Option<&str>) -> String {
+pub async fn get_and_validate_csrf_token(
+    harness: &Harness,
+    redirect_url: Option<&str>,
+) -> AuthRequest {
     let client = reqwest::Client::new();
 
     let mut url = harness.app_path("auth/request_link");
@@ -20,14 +56,13 @@ pub async fn get_and_validate_csrf_token(harness: &Harness, redirect_url: Option
         url.query_pairs_mut().append_pair("redirect_to", redirect);
     });
 
-    let response = client
-        .get(url)
-        .send()
-        .await
-        .unwrap()
-        .json::<Value>()
-        .await
-        .unwrap();
+    let response = client.get(url).send().await.unwrap();
+    let cookie = binding_cookie(&response);
+    assert!(
+        cookie.is_some(),
+        "/auth/request_link response must set the binding cookie"
+    );
+    let response = response.json::<Value>().await.unwrap();
 
     let csrf_for_gh = Url::parse(
         response
@@ -63,7 +98,10 @@ pub async fn get_and_validate_csrf_token(harness: &Harness, redirect_url: Option
         csrf_for_eth, csrf_for_gh
     );
 
-    csrf_for_eth
+    AuthRequest {
+        state: csrf_for_eth,
+        cookie,
+    }
 }
 
 pub fn entropy_from_str(seed: &str) -> Secret<[u8; 32]> {
@@ -81,18 +119,20 @@ pub async fn request_auth_callback(
     harness: &Harness,
     http_client: &reqwest::Client,
     user: &TestUser,
-    csrf: &str,
+    csrf: &AuthRequest,
 ) -> reqwest::Response {
     let url_ext = match user.user {
         AnyTestUser::Eth(_) => "auth/callback/eth",
         AnyTestUser::Gh(_) => "auth/callback/github",
+        AnyTestUser::Tw(_) => "auth/callback/twitter",
     };
-    http_client
+    let mut request = http_client
         .get(harness.options.server.join(url_ext).unwrap())
-        .query(&[("state", csrf), ("code", &user.id.to_string())])
-        .send()
-        .await
-        .expect("Could not call the endpoint")
+        .query(&[("state", &csrf.state), ("code", &user.id.to_string())]);
+    if let Some(cookie) = &csrf.cookie {
+        request = request.header(http::header::COOKIE, cookie);
+    }
+    request.send().await.expect("Could not call the endpoint")
 }
 
 pub async fn extract_session_id_from_auth_response(response: reqwest::Response) -> String {
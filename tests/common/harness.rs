@@ -1,6 +1,6 @@
 use crate::common::{
     mock_auth_service,
-    mock_auth_service::{AuthState, EthUser, GhUser, TestUser},
+    mock_auth_service::{AuthState, EthUser, GhUser, TestUser, TwUser},
 };
 use chrono::{DateTime, FixedOffset};
 use clap::Parser;
@@ -13,7 +13,7 @@ use tempfile::{tempdir, TempDir};
 use tokio::sync::{broadcast, oneshot, Mutex, MutexGuard, OnceCell};
 use url::Url;
 
-fn test_options() -> Options {
+pub fn test_options() -> Options {
     let args: Vec<&str> = vec![
         "kzg-ceremony-sequencer",
         "--ceremony-sizes",
@@ -38,8 +38,21 @@ fn test_options() -> Options {
         "INVALID",
         "--eth-client-id",
         "INVALID",
+        "--tw-token-url",
+        "http://127.0.0.1:3001/twitter/oauth/token",
+        "--tw-userinfo-url",
+        "http://127.0.0.1:3001/twitter/user",
+        "--tw-min-followers",
+        "10",
+        "--tw-client-secret",
+        "INVALID",
+        "--tw-client-id",
+        "INVALID",
         "--database-url",
         "sqlite::memory:",
+        // Every test user signs in from localhost.
+        "--auth-requests-per-ip",
+        "0",
     ];
     Options::parse_from(args)
 }
@@ -71,13 +84,42 @@ impl Harness {
             .register_gh_user(GhUser {
                 created_at: "2022-01-01T00:00:00Z".to_string(),
                 name,
+                public_repos: 10,
+                followers: 10,
             })
             .await
     }
 
     pub async fn create_gh_user_with_time(&self, name: String, created_at: String) -> TestUser {
         self.auth_state
-            .register_gh_user(GhUser { created_at, name })
+            .register_gh_user(GhUser {
+                created_at,
+                name,
+                public_repos: 10,
+                followers: 10,
+            })
+            .await
+    }
+
+    pub async fn create_gh_user_with_activity(
+        &self,
+        name: String,
+        public_repos: u64,
+        followers: u64,
+    ) -> TestUser {
+        self.auth_state
+            .register_gh_user(GhUser {
+                created_at: "2022-01-01T00:00:00Z".to_string(),
+                name,
+                public_repos,
+                followers,
+            })
+            .await
+    }
+
+    pub async fn create_tw_user(&self, handle: String, followers: u64) -> TestUser {
+        self.auth_state
+            .register_tw_user(TwUser { handle, followers })
             .await
     }
 
@@ -209,6 +251,8 @@ impl Builder {
         let mut options = test_options();
         options.lobby.lobby_checkin_frequency = Duration::from_millis(2000);
         options.lobby.lobby_checkin_tolerance = Duration::from_millis(2000);
+        // Tests time out contributors on the compute deadline itself.
+        options.lobby.upload_grace = Duration::ZERO;
         options.lobby.compute_deadline = Duration::from_millis(800);
         Self { options }
     }
@@ -248,6 +292,21 @@ impl Builder {
         self
     }
 
+    pub fn set_gh_min_public_repos(mut self, min_public_repos: u64) -> Self {
+        self.options.github.gh_min_public_repos = Some(min_public_repos);
+        self
+    }
+
+    pub fn set_gh_min_followers(mut self, min_followers: u64) -> Self {
+        self.options.github.gh_min_followers = Some(min_followers);
+        self
+    }
+
+    pub fn allow_cors_credentials(mut self) -> Self {
+        self.options.cors_allow_credentials = true;
+        self
+    }
+
     pub fn set_eth_min_nonce(mut self, min_nonce: u64) -> Self {
         self.options.ethereum.eth_min_nonce = min_nonce;
         self
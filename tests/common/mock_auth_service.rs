@@ -10,11 +10,15 @@ use hyper::{server::conn::AddrIncoming, Server};
 use kzg_ceremony_crypto::signature::identity::Identity;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
 use std::{
     collections::HashMap,
     net::{Ipv4Addr, SocketAddr},
     str::FromStr,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
 };
 use tokio::sync::RwLock;
 
@@ -25,6 +29,8 @@ pub fn start_server(auth_state: AuthState) -> Server<AddrIncoming, IntoMakeServi
         .route("/eth/oauth/token", post(exchange_eth_token))
         .route("/eth/user", get(eth_userinfo))
         .route("/eth/rpc", post(eth_rpc))
+        .route("/twitter/oauth/token", post(exchange_tw_token))
+        .route("/twitter/user", get(tw_userinfo))
         .layer(Extension(auth_state));
     Server::try_bind(&SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 3001))
         .unwrap()
@@ -46,6 +52,21 @@ impl GhUsersState {
     }
 }
 
+#[derive(Default)]
+struct TwUsersState {
+    users: HashMap<u64, TwUser>,
+    next_id: u64,
+}
+
+impl TwUsersState {
+    fn register(&mut self, user: TwUser) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.users.insert(id, user);
+        id
+    }
+}
+
 #[derive(Default)]
 struct EthUsersState {
     users: HashMap<u64, EthUser>,
@@ -71,6 +92,14 @@ impl EthUsersState {
 pub struct GhUser {
     pub name: String,
     pub created_at: String,
+    pub public_repos: u64,
+    pub followers: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct TwUser {
+    pub handle: String,
+    pub followers: u64,
 }
 
 #[derive(Clone, Debug)]
@@ -89,6 +118,7 @@ impl EthUser {
 pub enum AnyTestUser {
     Eth(EthUser),
     Gh(GhUser),
+    Tw(TwUser),
 }
 
 #[derive(Clone, Debug)]
@@ -106,6 +136,9 @@ impl TestUser {
             AnyTestUser::Gh(user) => {
                 Identity::from_str(&format!("git|{}|{}", self.id, user.name)).unwrap()
             }
+            AnyTestUser::Tw(user) => {
+                Identity::from_str(&format!("twt|{}|{}", self.id, user.handle)).unwrap()
+            }
         }
     }
 
@@ -118,6 +151,12 @@ impl TestUser {
 pub struct AuthState {
     github_users: Arc<RwLock<GhUsersState>>,
     eth_users: Arc<RwLock<EthUsersState>>,
+    twitter_users: Arc<RwLock<TwUsersState>>,
+    // PKCE challenges by authorization code. Codes without a registered
+    // challenge are exchanged without checking the verifier.
+    pkce_challenges: Arc<RwLock<HashMap<u64, String>>>,
+    // Number of upcoming GitHub token requests to answer with a 503.
+    gh_token_failures: Arc<AtomicUsize>,
 }
 
 impl AuthState {
@@ -137,6 +176,39 @@ impl AuthState {
         }
     }
 
+    pub async fn register_tw_user(&self, user: TwUser) -> TestUser {
+        let id = self.twitter_users.write().await.register(user.clone());
+        TestUser {
+            id,
+            user: AnyTestUser::Tw(user),
+        }
+    }
+
+    pub async fn register_pkce_challenge(&self, auth_code: u64, challenge: String) {
+        self.pkce_challenges
+            .write()
+            .await
+            .insert(auth_code, challenge);
+    }
+
+    pub fn fail_next_gh_token_requests(&self, count: usize) {
+        self.gh_token_failures.store(count, Ordering::SeqCst);
+    }
+
+    pub fn remaining_gh_token_failures(&self) -> usize {
+        self.gh_token_failures.load(Ordering::SeqCst)
+    }
+
+    async fn verify_pkce(&self, auth_code: u64, verifier: Option<&str>) -> bool {
+        match self.pkce_challenges.read().await.get(&auth_code) {
+            None => true,
+            Some(challenge) => verifier.map_or(false, |verifier| {
+                let hash = Sha256::digest(verifier.as_bytes());
+                base64::encode_config(hash, base64::URL_SAFE_NO_PAD) == *challenge
+            }),
+        }
+    }
+
     pub async fn get_gh_user(&self, auth_code: u64) -> Option<GhUser> {
         self.github_users
             .read()
@@ -146,6 +218,15 @@ impl AuthState {
             .map(Clone::clone)
     }
 
+    pub async fn get_tw_user(&self, auth_code: u64) -> Option<TwUser> {
+        self.twitter_users
+            .read()
+            .await
+            .users
+            .get(&auth_code)
+            .map(Clone::clone)
+    }
+
     pub async fn get_eth_user(&self, auth_code: u64) -> Option<EthUser> {
         self.eth_users
             .read()
@@ -159,13 +240,33 @@ impl AuthState {
 #[derive(Debug, Deserialize)]
 struct ExchangeRequest {
     code: u64,
+    code_verifier: Option<String>,
 }
 
 async fn exchange_gh_token(
     Form(req): Form<ExchangeRequest>,
     Extension(state): Extension<AuthState>,
 ) -> (StatusCode, Json<Value>) {
+    if state
+        .gh_token_failures
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+        .is_ok()
+    {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({"error": "temporarily_unavailable"})),
+        );
+    }
     let user = state.get_gh_user(req.code).await;
+    if !state
+        .verify_pkce(req.code, req.code_verifier.as_deref())
+        .await
+    {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "invalid_grant"})),
+        );
+    }
     match user {
         Some(_) => (
             StatusCode::OK,
@@ -188,6 +289,15 @@ async fn exchange_eth_token(
     Extension(state): Extension<AuthState>,
 ) -> (StatusCode, Json<Value>) {
     let user = state.get_eth_user(req.code).await;
+    if !state
+        .verify_pkce(req.code, req.code_verifier.as_deref())
+        .await
+    {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "invalid_grant"})),
+        );
+    }
     match user {
         Some(_) => (
             StatusCode::OK,
@@ -205,6 +315,37 @@ async fn exchange_eth_token(
     }
 }
 
+async fn exchange_tw_token(
+    Form(req): Form<ExchangeRequest>,
+    Extension(state): Extension<AuthState>,
+) -> (StatusCode, Json<Value>) {
+    let user = state.get_tw_user(req.code).await;
+    if !state
+        .verify_pkce(req.code, req.code_verifier.as_deref())
+        .await
+    {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "invalid_grant"})),
+        );
+    }
+    match user {
+        Some(_) => (
+            StatusCode::OK,
+            Json(json!({
+                "access_token": format!("token_of::{}", req.code),
+                "token_type": "bearer",
+                "expires_in": 60,
+                "scope": "users.read tweet.read"
+            })),
+        ),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Invalid code"})),
+        ),
+    }
+}
+
 async fn gh_userinfo(
     TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
     Extension(state): Extension<AuthState>,
@@ -220,7 +361,49 @@ async fn gh_userinfo(
     match user {
         Some(user) => (
             StatusCode::OK,
-            Json(json!({"login": user.name, "created_at": user.created_at, "id": code})),
+            Json(json!({
+                "login": user.name,
+                "created_at": user.created_at,
+                "public_repos": user.public_repos,
+                "followers": user.followers,
+                "id": code,
+            })),
+        ),
+        None => (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"error": "Invalid auth token"})),
+        ),
+    }
+}
+
+async fn tw_userinfo(
+    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    Extension(state): Extension<AuthState>,
+) -> (StatusCode, Json<Value>) {
+    let token = auth.0.token();
+    let code_str = *token
+        .split("::")
+        .collect::<Vec<_>>()
+        .get(1)
+        .expect("invalid auth token");
+    let code = u64::from_str(code_str).expect("invalid auth token");
+    let user = state.get_tw_user(code).await;
+    match user {
+        Some(user) => (
+            StatusCode::OK,
+            Json(json!({
+                "data": {
+                    "id": code.to_string(),
+                    "name": user.handle,
+                    "username": user.handle,
+                    "public_metrics": {
+                        "followers_count": user.followers,
+                        "following_count": 0,
+                        "tweet_count": 0,
+                        "listed_count": 0
+                    }
+                }
+            })),
         ),
         None => (
             StatusCode::UNAUTHORIZED,
@@ -9,14 +9,16 @@ use chrono::DateTime;
 use common::participants;
 use ethers_core::types::Address;
 use ethers_signers::{LocalWallet, Signer};
-use http::StatusCode;
+use flate2::read::GzDecoder;
+use http::{Method, StatusCode};
 use kzg_ceremony_crypto::{
     signature::{BlsSignature, ContributionTypedData, EcdsaSignature},
-    Arkworks, DefaultEngine, G1,
+    Arkworks, BatchTranscript, DefaultEngine, G1,
 };
+use kzg_ceremony_sequencer::async_main;
 use rand::thread_rng;
 use secrecy::Secret;
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use std::{collections::HashMap, io::Read, sync::Arc, time::Duration};
 use tokio::sync::RwLock;
 use url::Url;
 
@@ -35,6 +37,46 @@ async fn test_gh_auth_happy_path() {
     actions::create_and_login_gh_user(&harness, &http_client, "kustosz".to_string()).await;
 }
 
+#[tokio::test]
+async fn test_gh_auth_retries_transient_failures() {
+    let harness = run_test_harness().await;
+    let http_client = reqwest::Client::new();
+    harness.auth_state.fail_next_gh_token_requests(2);
+    actions::create_and_login_gh_user(&harness, &http_client, "kustosz".to_string()).await;
+    assert_eq!(harness.auth_state.remaining_gh_token_failures(), 0);
+}
+
+#[tokio::test]
+async fn test_twitter_auth_happy_path() {
+    let harness = run_test_harness().await;
+    let http_client = reqwest::Client::new();
+    let user = harness.create_tw_user("kustosz".to_string(), 10).await;
+    let csrf = actions::get_and_validate_csrf_token(&harness, None).await;
+    let response = actions::request_auth_callback(&harness, &http_client, &user, &csrf).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(
+        body["id_token"]["sub"].as_str(),
+        Some(user.identity().unique_id().as_str())
+    );
+    assert_eq!(body["id_token"]["provider"].as_str(), Some("Twitter"));
+}
+
+#[tokio::test]
+async fn test_twitter_auth_not_enough_followers() {
+    let harness = run_test_harness().await;
+    let http_client = reqwest::Client::new();
+    let user = harness.create_tw_user("kustosz".to_string(), 9).await;
+    let csrf = actions::get_and_validate_csrf_token(&harness, None).await;
+    let response = actions::request_auth_callback(&harness, &http_client, &user, &csrf).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("AuthErrorPayload::NotEnoughFollowers"));
+}
+
 #[tokio::test]
 async fn test_eth_auth_happy_path() {
     let harness = run_test_harness().await;
@@ -75,6 +117,126 @@ async fn test_malformed_auth_request() {
         .contains("invalid json in state parameter"));
 }
 
+#[tokio::test]
+async fn test_auth_callback_state_validation() {
+    let harness = run_test_harness().await;
+    let http_client = reqwest::Client::new();
+    let user = harness.create_gh_user("kustosz".to_string()).await;
+    let eth_user = harness.create_eth_user().await;
+
+    // A state that was never issued by the server, on every provider.
+    let forged_state = base64::encode_config(
+        r#"{"nonce":"forged","redirect":null}"#,
+        base64::URL_SAFE_NO_PAD,
+    );
+    for user in [&user, &eth_user] {
+        let response = actions::request_auth_callback(
+            &harness,
+            &http_client,
+            user,
+            &actions::AuthRequest::unbound(&forged_state),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(response
+            .text()
+            .await
+            .unwrap()
+            .contains("AuthErrorPayload::InvalidCsrfState"));
+    }
+
+    // A missing state.
+    let response = http_client
+        .get(harness.app_path("auth/callback/github"))
+        .query(&[("code", user.id.to_string())])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    // A valid state is refused from a client without the cookie it was
+    // issued with, so that nobody can sign a victim in to their account.
+    let csrf = actions::get_and_validate_csrf_token(&harness, None).await;
+    let response = actions::request_auth_callback(
+        &harness,
+        &http_client,
+        &user,
+        &actions::AuthRequest::unbound(&csrf.state),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("AuthErrorPayload::InvalidCsrfState"));
+
+    // A valid state can only be used once.
+    let csrf = actions::get_and_validate_csrf_token(&harness, None).await;
+    let response = actions::request_auth_callback(&harness, &http_client, &user, &csrf).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = actions::request_auth_callback(&harness, &http_client, &user, &csrf).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("AuthErrorPayload::InvalidCsrfState"));
+}
+
+#[tokio::test]
+async fn test_pkce_verifier_mismatch() {
+    let harness = run_test_harness().await;
+    let http_client = reqwest::Client::new();
+
+    let response = http_client
+        .get(harness.app_path("auth/request_link"))
+        .send()
+        .await
+        .unwrap();
+    let cookie = actions::binding_cookie(&response);
+    let auth_urls = response.json::<serde_json::Value>().await.unwrap();
+    let query = |provider: &str| -> HashMap<String, String> {
+        Url::parse(auth_urls[provider].as_str().unwrap())
+            .unwrap()
+            .query_pairs()
+            .into_owned()
+            .collect()
+    };
+    for provider in ["eth_auth_url", "github_auth_url", "twitter_auth_url"] {
+        assert_eq!(
+            query(provider)
+                .get("code_challenge_method")
+                .map(String::as_str),
+            Some("S256")
+        );
+    }
+
+    let params = query("github_auth_url");
+    let user = harness.create_gh_user("kustosz".to_string()).await;
+    harness
+        .auth_state
+        .register_pkce_challenge(user.id, params["code_challenge"].clone())
+        .await;
+
+    // The state of another authorization request comes with another verifier.
+    let other_csrf = actions::get_and_validate_csrf_token(&harness, None).await;
+    let response = actions::request_auth_callback(&harness, &http_client, &user, &other_csrf).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("AuthErrorPayload::InvalidAuthCode"));
+
+    let csrf = actions::AuthRequest {
+        state: params["state"].clone(),
+        cookie,
+    };
+    let response = actions::request_auth_callback(&harness, &http_client, &user, &csrf).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
 #[tokio::test]
 async fn test_sessions_limit() {
     let harness = harness::Builder::new()
@@ -140,6 +302,7 @@ async fn test_too_new_accounts() {
         .unwrap()
         .contains("AuthErrorPayload::UserCreatedAfterDeadline"));
 
+    let csrf = actions::get_and_validate_csrf_token(&harness, None).await;
     let user = harness.create_eth_user_with_nonce(10).await;
     let response = actions::request_auth_callback(&harness, &http_client, &user, &csrf).await;
     assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
@@ -150,6 +313,60 @@ async fn test_too_new_accounts() {
         .contains("AuthErrorPayload::UserCreatedAfterDeadline"));
 }
 
+#[tokio::test]
+async fn test_gh_activity_thresholds() {
+    let harness = harness::Builder::new()
+        .set_gh_min_public_repos(5)
+        .set_gh_min_followers(3)
+        .run()
+        .await;
+    let http_client = reqwest::Client::new();
+
+    let csrf = actions::get_and_validate_csrf_token(&harness, None).await;
+    let user = harness
+        .create_gh_user_with_activity("kustosz".to_string(), 4, 3)
+        .await;
+    let response = actions::request_auth_callback(&harness, &http_client, &user, &csrf).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("AuthErrorPayload::NotEnoughPublicRepos"));
+
+    let csrf = actions::get_and_validate_csrf_token(&harness, None).await;
+    let user = harness
+        .create_gh_user_with_activity("kustosz".to_string(), 5, 2)
+        .await;
+    let response = actions::request_auth_callback(&harness, &http_client, &user, &csrf).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("AuthErrorPayload::NotEnoughFollowers"));
+
+    let csrf = actions::get_and_validate_csrf_token(&harness, None).await;
+    let user = harness
+        .create_gh_user_with_activity("kustosz".to_string(), 5, 3)
+        .await;
+    let response = actions::request_auth_callback(&harness, &http_client, &user, &csrf).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_gh_activity_thresholds_unset() {
+    let harness = run_test_harness().await;
+    let http_client = reqwest::Client::new();
+
+    let csrf = actions::get_and_validate_csrf_token(&harness, None).await;
+    let user = harness
+        .create_gh_user_with_activity("kustosz".to_string(), 0, 0)
+        .await;
+    let response = actions::request_auth_callback(&harness, &http_client, &user, &csrf).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
 #[tokio::test]
 async fn test_nonexistent_eth_user() {
     let harness = run_test_harness().await;
@@ -157,11 +374,17 @@ async fn test_nonexistent_eth_user() {
     let csrf = actions::get_and_validate_csrf_token(&harness, None).await;
     let response = http_client
         .get(harness.app_path("auth/callback/eth"))
-        .query(&[("state", csrf), ("code", "1234".to_string())])
+        .query(&[("state", csrf.state), ("code", "1234".to_string())])
+        .header(http::header::COOKIE, csrf.cookie.unwrap())
         .send()
         .await
         .expect("Could not call the endpoint");
     assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("AuthErrorPayload::InvalidAuthCode"));
 }
 
 #[tokio::test]
@@ -214,6 +437,8 @@ async fn test_gh_auth_errors_with_custom_frontend_redirect() {
         user: AnyTestUser::Gh(GhUser {
             name: "foo".to_string(),
             created_at: "2022-01-01T00:00:00Z".to_string(),
+            public_repos: 10,
+            followers: 10,
         }),
     };
     let auth_response =
@@ -330,7 +555,7 @@ async fn test_double_contribution() {
         &http_client,
         &session_id,
         &contribution,
-        &user_id.to_string(),
+        &user_id.display_checksummed(),
     )
     .await;
     let transcript_with_first_contrib = harness.read_transcript_file().await;
@@ -402,7 +627,7 @@ async fn test_double_contribution_when_allowed() {
         &http_client,
         &session_id,
         &contribution1,
-        &user_id.to_string(),
+        &user_id.display_checksummed(),
     )
     .await;
 
@@ -425,7 +650,7 @@ async fn test_double_contribution_when_allowed() {
         &http_client,
         &session_id,
         &contribution2,
-        &user_id.to_string(),
+        &user_id.display_checksummed(),
     )
     .await;
     let transcript = harness.read_transcript_file().await;
@@ -701,3 +926,179 @@ async fn test_graceful_restart() {
         .map(|r| r.expect("must terminate successfully"))
         .for_each(|check| check(&final_transcript));
 }
+
+#[tokio::test]
+async fn test_current_state_not_modified() {
+    let harness = run_test_harness().await;
+    let http_client = reqwest::Client::new();
+
+    let response = http_client
+        .get(harness.app_path("info/current_state"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let etag = response
+        .headers()
+        .get("ETag")
+        .expect("response must contain an ETag")
+        .clone();
+
+    let response = http_client
+        .get(harness.app_path("info/current_state"))
+        .header("If-None-Match", etag.clone())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers().get("ETag"), Some(&etag));
+
+    // The ETag changes once a contribution is written.
+    let (user, session_id) =
+        actions::create_and_login_gh_user(&harness, &http_client, "kustosz".to_string()).await;
+    let mut contribution = actions::try_contribute(&harness, &http_client, &session_id).await;
+    let entropy = Secret::new([7; 32]);
+    contribution
+        .add_entropy::<Arkworks>(&entropy, &user.identity())
+        .expect("Adding entropy must be possible");
+    actions::contribute_successfully(
+        &harness,
+        &http_client,
+        &session_id,
+        &contribution,
+        &user.identity().to_string(),
+    )
+    .await;
+
+    let response = http_client
+        .get(harness.app_path("info/current_state"))
+        .header("If-None-Match", etag.clone())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_ne!(response.headers().get("ETag"), Some(&etag));
+}
+
+#[tokio::test]
+async fn test_current_state_gzip() {
+    let harness = run_test_harness().await;
+    let http_client = reqwest::Client::new();
+
+    let response = http_client
+        .get(harness.app_path("info/current_state"))
+        .header("Accept-Encoding", "gzip")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response
+            .headers()
+            .get("Content-Encoding")
+            .map(|value| value.to_str().unwrap()),
+        Some("gzip")
+    );
+
+    let compressed = response.bytes().await.unwrap();
+    let mut decompressed = Vec::new();
+    GzDecoder::new(&compressed[..])
+        .read_to_end(&mut decompressed)
+        .unwrap();
+    let transcript: BatchTranscript = serde_json::from_slice(&decompressed).unwrap();
+    assert_eq!(transcript, harness.read_transcript_file().await);
+}
+
+#[tokio::test]
+async fn test_cors_preflight() {
+    let harness = run_test_harness().await;
+    let http_client = reqwest::Client::new();
+
+    let preflight = |origin: &'static str| {
+        http_client
+            .request(Method::OPTIONS, harness.app_path("lobby/try_contribute"))
+            .header("Origin", origin)
+            .header("Access-Control-Request-Method", "POST")
+            .header("Access-Control-Request-Headers", "authorization")
+            .send()
+    };
+
+    let response = preflight("https://ceremony.ethereum.org").await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response
+            .headers()
+            .get("Access-Control-Allow-Origin")
+            .map(|value| value.to_str().unwrap()),
+        Some("https://ceremony.ethereum.org")
+    );
+
+    // Credentials are off by default.
+    assert!(response
+        .headers()
+        .get("Access-Control-Allow-Credentials")
+        .is_none());
+
+    let response = preflight("https://evil.example.com").await.unwrap();
+    assert!(response
+        .headers()
+        .get("Access-Control-Allow-Origin")
+        .is_none());
+}
+
+#[tokio::test]
+async fn test_cors_credentials_only_on_session_routes() {
+    let harness = Builder::new().allow_cors_credentials().run().await;
+    let http_client = reqwest::Client::new();
+
+    let preflight = |path: &'static str, origin: &'static str| {
+        http_client
+            .request(Method::OPTIONS, harness.app_path(path))
+            .header("Origin", origin)
+            .header("Access-Control-Request-Method", "POST")
+            .header("Access-Control-Request-Headers", "authorization")
+            .send()
+    };
+    let header = |response: &reqwest::Response, name: &str| {
+        response
+            .headers()
+            .get(name)
+            .map(|value| value.to_str().unwrap().to_owned())
+    };
+
+    let response = preflight("lobby/try_contribute", "https://ceremony.ethereum.org")
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        header(&response, "Access-Control-Allow-Origin").as_deref(),
+        Some("https://ceremony.ethereum.org")
+    );
+    assert_eq!(
+        header(&response, "Access-Control-Allow-Credentials").as_deref(),
+        Some("true")
+    );
+
+    // Without a matching origin the browser rejects the response.
+    let response = preflight("lobby/try_contribute", "https://evil.example.com")
+        .await
+        .unwrap();
+    assert!(header(&response, "Access-Control-Allow-Origin").is_none());
+
+    // Routes without a session never allow credentials.
+    let response = preflight("info/status", "https://ceremony.ethereum.org")
+        .await
+        .unwrap();
+    assert_eq!(
+        header(&response, "Access-Control-Allow-Origin").as_deref(),
+        Some("https://ceremony.ethereum.org")
+    );
+    assert!(header(&response, "Access-Control-Allow-Credentials").is_none());
+}
+
+#[tokio::test]
+async fn test_self_test() {
+    let mut options = harness::test_options();
+    options.self_test = true;
+    async_main(options).await.unwrap();
+}